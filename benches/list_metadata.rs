@@ -0,0 +1,63 @@
+//! Measures listing throughput on an archive made up of entries without any
+//! data: directories, symlinks and empty files.
+//!
+//! Run with `cargo bench --bench list_metadata`.
+
+use std::time::Instant;
+
+use async_tar::{Archive, EntryType, Header};
+use futures_util::StreamExt;
+
+const ENTRIES: usize = 100_000;
+const ROUNDS: usize = 10;
+
+fn archive() -> Vec<u8> {
+    let mut data = Vec::with_capacity((ENTRIES + 2) * 512);
+    for i in 0..ENTRIES {
+        let mut header = Header::new_gnu();
+        let kind = match i % 3 {
+            0 => EntryType::Directory,
+            1 => EntryType::Symlink,
+            _ => EntryType::Regular,
+        };
+        header
+            .set_path(format!("dir{}/entry{}", i / 100, i))
+            .unwrap();
+        header.set_entry_type(kind);
+        if kind == EntryType::Symlink {
+            header.set_link_name("target").unwrap();
+        }
+        header.set_size(0);
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+    }
+    data.resize(data.len() + 1024, 0);
+    data
+}
+
+fn main() {
+    let data = archive();
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            let mut entries = Archive::new(&data[..]).entries().unwrap();
+            let mut count = 0;
+            while let Some(entry) = entries.next().await {
+                let entry = entry.unwrap();
+                assert!(!entry.path_bytes().is_empty());
+                count += 1;
+            }
+            assert_eq!(count, ENTRIES);
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "listed {} entries in {:?} ({:.0} entries/s)",
+            ENTRIES * ROUNDS,
+            elapsed,
+            (ENTRIES * ROUNDS) as f64 / elapsed.as_secs_f64()
+        );
+    });
+}
@@ -0,0 +1,76 @@
+use std::{cmp, mem};
+
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+use crate::{EntryType, GnuHeader, Header, UstarHeader};
+
+// Headers are generated field by field rather than from raw bytes, so that
+// they carry a valid checksum and mostly well-formed numbers, which gets
+// fuzzed parsers past the header checks.
+impl<'a> Arbitrary<'a> for Header {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Header> {
+        let mut header = match u.int_in_range(0..=2)? {
+            0 => Header::new_old(),
+            1 => Header::new_ustar(),
+            _ => Header::new_gnu(),
+        };
+        fill(&mut header, u)?;
+        Ok(header)
+    }
+}
+
+impl<'a> Arbitrary<'a> for GnuHeader {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<GnuHeader> {
+        let mut header = Header::new_gnu();
+        fill(&mut header, u)?;
+        // Every field of the header is a byte array, so any bytes are valid.
+        Ok(unsafe { mem::transmute::<[u8; 512], GnuHeader>(*header.as_bytes()) })
+    }
+}
+
+impl<'a> Arbitrary<'a> for UstarHeader {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<UstarHeader> {
+        let mut header = Header::new_ustar();
+        fill(&mut header, u)?;
+        Ok(unsafe { mem::transmute::<[u8; 512], UstarHeader>(*header.as_bytes()) })
+    }
+}
+
+impl<'a> Arbitrary<'a> for EntryType {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<EntryType> {
+        Ok(EntryType::new(u.arbitrary()?))
+    }
+
+    fn size_hint(depth: usize) -> (usize, Option<usize>) {
+        <u8 as Arbitrary>::size_hint(depth)
+    }
+}
+
+/// Sets the fields of `header` to arbitrary values, keeping its format.
+fn fill(header: &mut Header, u: &mut Unstructured) -> Result<()> {
+    header.set_entry_type(u.arbitrary()?);
+    fill_name(&mut header.as_old_mut().name, u)?;
+    fill_name(&mut header.as_old_mut().linkname, u)?;
+    header.set_mode(u.arbitrary::<u32>()? & 0o7777);
+    header.set_uid(u.arbitrary()?);
+    header.set_gid(u.arbitrary()?);
+    header.set_size(u.arbitrary()?);
+    header.set_mtime(u.arbitrary()?);
+    if let Some(ustar) = header.as_ustar_mut() {
+        fill_name(&mut ustar.uname, u)?;
+        fill_name(&mut ustar.gname, u)?;
+        fill_name(&mut ustar.prefix, u)?;
+    } else if let Some(gnu) = header.as_gnu_mut() {
+        fill_name(&mut gnu.uname, u)?;
+        fill_name(&mut gnu.gname, u)?;
+    }
+    header.set_cksum();
+    Ok(())
+}
+
+fn fill_name(field: &mut [u8], u: &mut Unstructured) -> Result<()> {
+    let name: &[u8] = u.arbitrary()?;
+    let len = cmp::min(name.len(), field.len());
+    field[..len].copy_from_slice(&name[..len]);
+    Ok(())
+}
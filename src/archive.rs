@@ -1,21 +1,45 @@
 use std::{
-    cmp, io,
-    path::Path,
+    cmp,
+    collections::{HashMap, HashSet},
+    ffi::{CString, OsStr},
+    future::Future,
+    io,
+    mem::MaybeUninit,
+    os::{
+        fd::{AsRawFd, RawFd},
+        unix::ffi::OsStrExt,
+    },
+    path::{Component, Path, PathBuf},
     pin::Pin,
+    rc::Rc,
+    str,
     sync::{Arc, Mutex},
     task::{Context, Poll},
+    time::Duration,
 };
 
-use futures_core::Stream;
+use bytes::Bytes;
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::Encoding;
+use futures_core::{FusedStream, Stream};
 use futures_util::StreamExt;
 use pin_project::pin_project;
-use tokio::io::{AsyncRead, AsyncReadExt, ReadBuf};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncWrite, AsyncWriteExt, ReadBuf, SeekFrom};
 use tokio_uring::fs;
 
 use crate::{
-    entry::{EntryFields, EntryIo},
+    clock::SharedClock,
+    digest::DigestState,
+    entry::{EntryFields, EntryIo, FixedBuffers, UnknownEntryCallback},
     error::TarError,
-    other, Entry, GnuExtSparseHeader, GnuSparseHeader, Header,
+    limit::poll_read_limited,
+    other,
+    pax::{apply_global_extensions, merge_global_extensions, pax_extensions, PaxParser},
+    throttle::Throttle,
+    timeout::ReadTimeout,
+    ArchiveIndex, ArchiveTree, Clock, DigestAlgorithm, Entry, EntryType, ExtractSink, FsSink,
+    GnuExtSparseHeader, GnuSparseHeader, Header, InputLimitExceeded, MetadataOrder, PaxExtensions,
+    UnknownEntryTypes, UnpackStream, UnsupportedMetadata,
 };
 
 /// A top-level representation of an archive file.
@@ -38,10 +62,48 @@ impl<R: AsyncRead + Unpin> Clone for Archive<R> {
 #[derive(Debug)]
 pub struct ArchiveInner<R: AsyncRead + Unpin> {
     pos: u64,
+    /// Number of consecutive zero blocks read just before `pos`.
+    zero_blocks: u64,
+    termination: Option<Termination>,
+    /// Number of bytes skipped so far while looking for the next header.
+    skipped: u64,
     unpack_xattrs: bool,
+    unpack_file_flags: bool,
     preserve_permissions: bool,
+    mask: u32,
     preserve_mtime: bool,
+    preserve_atime: bool,
     ignore_zeros: bool,
+    resync_headers: bool,
+    fixed_buffers: Option<(usize, usize)>,
+    preallocate: bool,
+    direct_io: bool,
+    fadvise: Option<Fadvise>,
+    max_symlink_depth: Option<u32>,
+    mangle_names: bool,
+    metadata_order: MetadataOrder,
+    unsupported_metadata: UnsupportedMetadata,
+    clamp_future_mtime: bool,
+    star_compat: bool,
+    skip_unchanged: bool,
+    unknown_entry_types: UnknownEntryTypes,
+    unknown_entry_callback: Option<UnknownEntryCallback>,
+    #[cfg(feature = "encoding_rs")]
+    name_encoding: Option<&'static Encoding>,
+    duplicate_entries: DuplicateEntries,
+    delay_directory_restore: bool,
+    continue_on_error: bool,
+    clock: SharedClock,
+    max_input_bytes: Option<u64>,
+    max_metadata_size: u64,
+    max_pax_record_size: Option<u64>,
+    digest: Option<DigestAlgorithm>,
+    seek: Option<SeekFn<R>>,
+    check_free_space: Option<SeekFn<R>>,
+    /// Whether a seek has been started but not completed yet.
+    seeking: bool,
+    throttle: Option<Throttle>,
+    read_timeout: Option<ReadTimeout>,
     #[pin]
     obj: R,
 }
@@ -50,19 +112,85 @@ pub struct ArchiveInner<R: AsyncRead + Unpin> {
 pub struct ArchiveBuilder<R: AsyncRead + Unpin> {
     obj: R,
     unpack_xattrs: bool,
+    unpack_file_flags: bool,
     preserve_permissions: bool,
+    mask: u32,
     preserve_mtime: bool,
+    preserve_atime: bool,
     ignore_zeros: bool,
+    resync_headers: bool,
+    fixed_buffers: Option<(usize, usize)>,
+    preallocate: bool,
+    direct_io: bool,
+    fadvise: Option<RawFd>,
+    max_symlink_depth: Option<u32>,
+    mangle_names: bool,
+    metadata_order: MetadataOrder,
+    unsupported_metadata: UnsupportedMetadata,
+    clamp_future_mtime: bool,
+    star_compat: bool,
+    skip_unchanged: bool,
+    unknown_entry_types: UnknownEntryTypes,
+    unknown_entry_callback: Option<UnknownEntryCallback>,
+    #[cfg(feature = "encoding_rs")]
+    name_encoding: Option<&'static Encoding>,
+    duplicate_entries: DuplicateEntries,
+    delay_directory_restore: bool,
+    continue_on_error: bool,
+    clock: SharedClock,
+    max_input_bytes: Option<u64>,
+    max_metadata_size: u64,
+    max_pax_record_size: Option<u64>,
+    digest: Option<DigestAlgorithm>,
+    seek: Option<SeekFn<R>>,
+    check_free_space: Option<SeekFn<R>>,
+    max_read_rate: Option<u64>,
+    read_timeout: Option<Duration>,
 }
 
+/// Moves a reader by the given number of bytes, tracking whether a seek is
+/// in progress in the flag.
+type SeekFn<R> = fn(&mut R, &mut Context<'_>, u64, &mut bool) -> Poll<io::Result<()>>;
+
 impl<R: AsyncRead + Unpin> ArchiveBuilder<R> {
     /// Create a new builder.
     pub fn new(obj: R) -> Self {
         ArchiveBuilder {
             unpack_xattrs: false,
+            unpack_file_flags: false,
             preserve_permissions: false,
+            mask: 0,
             preserve_mtime: true,
+            preserve_atime: false,
             ignore_zeros: false,
+            resync_headers: false,
+            fixed_buffers: None,
+            preallocate: false,
+            direct_io: false,
+            fadvise: None,
+            max_symlink_depth: None,
+            mangle_names: false,
+            metadata_order: MetadataOrder::default(),
+            unsupported_metadata: UnsupportedMetadata::default(),
+            clamp_future_mtime: false,
+            star_compat: false,
+            skip_unchanged: false,
+            unknown_entry_types: UnknownEntryTypes::default(),
+            unknown_entry_callback: None,
+            #[cfg(feature = "encoding_rs")]
+            name_encoding: None,
+            duplicate_entries: DuplicateEntries::default(),
+            delay_directory_restore: true,
+            continue_on_error: false,
+            clock: SharedClock::default(),
+            max_input_bytes: None,
+            max_metadata_size: DEFAULT_MAX_METADATA_SIZE,
+            max_pax_record_size: None,
+            digest: None,
+            seek: None,
+            check_free_space: None,
+            max_read_rate: None,
+            read_timeout: None,
             obj,
         }
     }
@@ -79,6 +207,16 @@ impl<R: AsyncRead + Unpin> ArchiveBuilder<R> {
         self
     }
 
+    /// Indicate whether BSD file flags recorded in `SCHILY.fflags` pax
+    /// records are restored when unpacking this archive, see
+    /// `Entry::set_unpack_file_flags`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_unpack_file_flags(mut self, unpack: bool) -> Self {
+        self.unpack_file_flags = unpack;
+        self
+    }
+
     /// Indicate whether extended permissions (like suid on Unix) are preserved
     /// when unpacking this entry.
     ///
@@ -89,8 +227,17 @@ impl<R: AsyncRead + Unpin> ArchiveBuilder<R> {
         self
     }
 
-    /// Indicate whether access time information is preserved when unpacking
-    /// this entry.
+    /// Set the mask of the permission bits when unpacking, see
+    /// `Entry::set_mask`.
+    ///
+    /// The mask is 0 by default, so no bits are cleared.
+    pub fn set_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Indicate whether modification time information is preserved when
+    /// unpacking entries.
     ///
     /// This flag is enabled by default.
     pub fn set_preserve_mtime(mut self, preserve: bool) -> Self {
@@ -98,6 +245,19 @@ impl<R: AsyncRead + Unpin> ArchiveBuilder<R> {
         self
     }
 
+    /// Indicate whether access times recorded in GNU headers, or in `star`
+    /// headers with `set_star_compat`, are restored along with modification
+    /// times when unpacking, see `Entry::atime`.
+    ///
+    /// Entries without an access time get theirs set to the modification
+    /// time, just like when this is disabled.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_preserve_atime(mut self, preserve: bool) -> Self {
+        self.preserve_atime = preserve;
+        self
+    }
+
     /// Ignore zeroed headers, which would otherwise indicate to the archive that it has no more
     /// entries.
     ///
@@ -107,164 +267,1497 @@ impl<R: AsyncRead + Unpin> ArchiveBuilder<R> {
         self
     }
 
+    /// Set the order in which metadata is applied to unpacked files, see
+    /// `MetadataOrder`.
+    pub fn set_metadata_order(mut self, order: MetadataOrder) -> Self {
+        self.metadata_order = order;
+        self
+    }
+
+    /// Set what happens when the filesystem rejects metadata of unpacked
+    /// entries, see `UnsupportedMetadata`.
+    pub fn set_unsupported_metadata(mut self, policy: UnsupportedMetadata) -> Self {
+        self.unsupported_metadata = policy;
+        self
+    }
+
+    /// Set what `Archive::unpack` does with entries whose path was already
+    /// unpacked from an earlier entry, see `DuplicateEntries`.
+    pub fn set_duplicate_entries(mut self, policy: DuplicateEntries) -> Self {
+        self.duplicate_entries = policy;
+        self
+    }
+
+    /// Indicate whether `Archive::unpack` restores directories in a final
+    /// pass, like GNU tar's `--delay-directory-restore`.
+    ///
+    /// Directories are then unpacked after all other entries, subdirectories
+    /// before their parents, so that read-only modes do not prevent writing
+    /// their contents and writing their contents does not change their
+    /// modification time. Otherwise directories are unpacked in archive
+    /// order like all other entries.
+    ///
+    /// This flag is enabled by default.
+    pub fn set_delay_directory_restore(mut self, delay: bool) -> Self {
+        self.delay_directory_restore = delay;
+        self
+    }
+
+    /// Indicate whether `Archive::unpack` carries on with the next entry when
+    /// unpacking an entry fails.
+    ///
+    /// The failed entries are then listed by `UnpackReport::failures`
+    /// instead of the first failure being returned. Errors reading the
+    /// archive itself still end unpacking.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_continue_on_error(mut self, keep_going: bool) -> Self {
+        self.continue_on_error = keep_going;
+        self
+    }
+
+    /// Indicate whether modification times lying in the future are clamped
+    /// to the current time when unpacking.
+    ///
+    /// The current time is taken from the clock set with `set_clock`. This
+    /// flag is disabled by default.
+    pub fn set_clamp_future_mtime(mut self, clamp: bool) -> Self {
+        self.clamp_future_mtime = clamp;
+        self
+    }
+
+    /// Indicate whether headers written by `star` are recognized.
+    ///
+    /// The xstar and xustar dialects of `star` shorten the ustar `prefix`
+    /// field to 131 bytes and store access and change times after it. Without
+    /// this flag those times end up in the paths of entries whose prefix
+    /// fills all of those 131 bytes.
+    /// With it, such headers are detected the same way GNU tar does and their
+    /// access times are restored when unpacking with `set_preserve_atime`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_star_compat(mut self, star: bool) -> Self {
+        self.star_compat = star;
+        self
+    }
+
+    /// Indicate whether regular files which already exist in the destination
+    /// with the same size and modification time as in the archive are left
+    /// alone when unpacking, instead of being written again.
+    ///
+    /// This makes extracting an archive over a previous extraction of a
+    /// mostly unchanged version of it cheap. Only the metadata of existing
+    /// files is compared, not their contents.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_skip_unchanged(mut self, skip: bool) -> Self {
+        self.skip_unchanged = skip;
+        self
+    }
+
+    /// Set what happens when unpacking entries whose type is not known to
+    /// this crate, see `UnknownEntryTypes`.
+    pub fn set_unknown_entry_types(mut self, policy: UnknownEntryTypes) -> Self {
+        self.unknown_entry_types = policy;
+        self
+    }
+
+    /// Set a function called with the path and type of every entry of an
+    /// unknown type before it is unpacked, for example to log them.
+    ///
+    /// It is called whatever `set_unknown_entry_types` says, including
+    /// before failing with `UnknownEntryTypes::Error`.
+    pub fn set_unknown_entry_callback<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&Path, EntryType) + Send + Sync + 'static,
+    {
+        self.unknown_entry_callback = Some(UnknownEntryCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Set the character encoding of the names stored in headers and GNU
+    /// long name entries, such as Shift_JIS or windows-1252 for archives
+    /// created on older systems.
+    ///
+    /// Names are decoded to UTF-8 before paths are built from them. Names
+    /// in pax records are UTF-8 already and are not affected. By default
+    /// names are used as they are.
+    #[cfg(feature = "encoding_rs")]
+    pub fn set_name_encoding(mut self, encoding: &'static Encoding) -> Self {
+        self.name_encoding = Some(encoding);
+        self
+    }
+
+    /// Set the clock consulted for the current time, which is the system
+    /// clock by default.
+    pub fn set_clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.clock = SharedClock(Arc::new(clock));
+        self
+    }
+
+    /// Scan forward for the next plausible header when a header with a bad
+    /// checksum is found, instead of failing.
+    ///
+    /// The archive is searched in steps of 512 bytes for a block with a valid
+    /// checksum and a ustar or GNU magic, and iteration resumes from there.
+    /// The number of bytes thrown away is available from
+    /// `Entry::skipped_bytes` on the next entry, or from
+    /// `Termination::DamagedAfter` if no header follows. This allows
+    /// salvaging what is left of partially damaged archives.
+    ///
+    /// This is disabled by default.
+    pub fn set_resync_headers(mut self, resync: bool) -> Self {
+        self.resync_headers = resync;
+        self
+    }
+
+    /// Write the data of extracted files through a pool of `count` buffers of
+    /// `size` bytes each, registered with io_uring once when unpacking starts.
+    ///
+    /// Registered buffers spare the kernel from mapping user memory on every
+    /// write, which reduces overhead for large extractions. If registration
+    /// fails, for example because it would exceed `RLIMIT_MEMLOCK`, unpacking
+    /// falls back to regular buffers.
+    ///
+    /// This is disabled by default.
+    pub fn set_fixed_buffers(mut self, count: usize, size: usize) -> Self {
+        self.fixed_buffers = Some((count, size));
+        self
+    }
+
+    /// Indicate whether space for extracted files is allocated up front,
+    /// using the size recorded in the archive, before their data is written.
+    /// Only the data of sparse files is allocated, not their holes.
+    ///
+    /// Preallocation lets the filesystem lay files out in contiguous extents.
+    /// Filesystems which do not support `fallocate` are silently skipped.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_preallocate(mut self, preallocate: bool) -> Self {
+        self.preallocate = preallocate;
+        self
+    }
+
+    /// Limit the number of symbolic links followed while checking that an
+    /// entry is unpacked inside the destination directory.
+    ///
+    /// Archives can create symlinks which are then traversed by later
+    /// entries. A crafted chain of such links is rejected with an error once
+    /// it is longer than `depth`, instead of only being caught by the
+    /// operating system's own limit.
+    ///
+    /// By default only the operating system's limit applies.
+    pub fn set_max_symlink_depth(mut self, depth: u32) -> Self {
+        self.max_symlink_depth = Some(depth);
+        self
+    }
+
+    /// Indicate whether names which cannot be created on the destination
+    /// filesystem are escaped instead of failing the extraction.
+    ///
+    /// On Linux a file name may contain any byte except `/` and NUL, so only
+    /// NUL bytes, which can be smuggled in through PAX records or GNU long
+    /// names, need escaping. They are written as `%00`. To keep distinct
+    /// names apart, `%` is written as `%25` in every name while this is
+    /// enabled. The escaping does not depend on the locale. Link targets are
+    /// escaped the same way, so links keep pointing at renamed entries.
+    ///
+    /// `Archive::unpack_with_report` lists every entry which was renamed.
+    ///
+    /// This is disabled by default.
+    pub fn set_mangle_names(mut self, mangle: bool) -> Self {
+        self.mangle_names = mangle;
+        self
+    }
+
+    /// Limit the total number of bytes read from the underlying reader.
+    ///
+    /// Reading fails with an `InputLimitExceeded` error once the reader
+    /// produces more than `max` bytes. This protects against endless or
+    /// oversized streams, in particular with `set_ignore_zeros` enabled,
+    /// where a stream of zeros would otherwise be consumed forever.
+    ///
+    /// By default the input is not limited.
+    pub fn set_max_input_bytes(mut self, max: u64) -> Self {
+        self.max_input_bytes = Some(max);
+        self
+    }
+
+    /// Limit the size of GNU long name and long link entries and of pax
+    /// extension entries, which are read into memory in full.
+    ///
+    /// Iteration fails once such an entry is larger than `max` bytes,
+    /// instead of allocating whatever an archive claims to need. The default
+    /// is 4 MiB, far above what legitimate archives use.
+    pub fn set_max_metadata_size(mut self, max: u64) -> Self {
+        self.max_metadata_size = max;
+        self
+    }
+
+    /// Limit the size of a single record in pax extension entries, such as
+    /// an extended attribute.
+    ///
+    /// Records are parsed as they are read, so iteration fails as soon as a
+    /// record announces a larger size, before any of its value is buffered.
+    /// By default only the limit of `set_max_metadata_size` applies.
+    pub fn set_max_pax_record_size(mut self, max: u64) -> Self {
+        self.max_pax_record_size = Some(max);
+        self
+    }
+
+    /// Cap the rate at which the underlying reader is read, in bytes per
+    /// second.
+    ///
+    /// This keeps unpacking a huge archive from starving other I/O on the
+    /// same device. Reads are throttled like with a `ThrottledReader`, which
+    /// allows short bursts of up to one second worth of bytes.
+    ///
+    /// By default the rate is not limited.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn set_max_read_rate(mut self, bytes_per_sec: u64) -> Self {
+        assert!(bytes_per_sec > 0, "read rate must be positive");
+        self.max_read_rate = Some(bytes_per_sec);
+        self
+    }
+
+    /// Fail reads of the underlying reader which make no progress for
+    /// longer than `timeout` with an error of kind `ErrorKind::TimedOut`.
+    ///
+    /// This keeps an archive read from a stalled network peer from hanging
+    /// forever, see `TimeoutReader`. Time spent waiting because of
+    /// `set_max_read_rate` does not count towards the timeout.
+    ///
+    /// By default reads do not time out.
+    pub fn set_read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// Compute a digest of the contents of every entry as it is read or
+    /// unpacked.
+    ///
+    /// Once all of its contents have been consumed, the digest is available
+    /// from `Entry::digest`, so verifying an archive does not need a second
+    /// pass over the data.
+    ///
+    /// This is disabled by default.
+    pub fn set_digest(mut self, algorithm: DigestAlgorithm) -> Self {
+        self.digest = Some(algorithm);
+        self
+    }
+
+    /// Indicate whether extracted files are written with `O_DIRECT`,
+    /// bypassing the page cache.
+    ///
+    /// This is useful when restoring large backups whose contents are not
+    /// going to be read back soon. Data is written in aligned blocks; the
+    /// unaligned tail of a file, and whole files on filesystems without
+    /// `O_DIRECT` support, are written through the page cache as usual.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
     /// Construct the archive, ready to accept inputs.
     pub fn build(self) -> Archive<R> {
         let Self {
             unpack_xattrs,
+            unpack_file_flags,
             preserve_permissions,
+            mask,
             preserve_mtime,
+            preserve_atime,
             ignore_zeros,
+            resync_headers,
+            fixed_buffers,
+            preallocate,
+            direct_io,
+            fadvise,
+            max_symlink_depth,
+            mangle_names,
+            metadata_order,
+            unsupported_metadata,
+            clamp_future_mtime,
+            star_compat,
+            skip_unchanged,
+            unknown_entry_types,
+            unknown_entry_callback,
+            #[cfg(feature = "encoding_rs")]
+            name_encoding,
+            duplicate_entries,
+            delay_directory_restore,
+            continue_on_error,
+            clock,
+            max_input_bytes,
+            max_metadata_size,
+            max_pax_record_size,
+            digest,
+            seek,
+            check_free_space,
+            max_read_rate,
+            read_timeout,
             obj,
         } = self;
 
         Archive {
             inner: Arc::new(Mutex::new(ArchiveInner {
                 unpack_xattrs,
+                unpack_file_flags,
                 preserve_permissions,
+                mask,
                 preserve_mtime,
+                preserve_atime,
                 ignore_zeros,
+                resync_headers,
+                fixed_buffers,
+                preallocate,
+                direct_io,
+                fadvise: fadvise.map(Fadvise::new),
+                max_symlink_depth,
+                mangle_names,
+                metadata_order,
+                unsupported_metadata,
+                clamp_future_mtime,
+                star_compat,
+                skip_unchanged,
+                unknown_entry_types,
+                unknown_entry_callback,
+                #[cfg(feature = "encoding_rs")]
+                name_encoding,
+                duplicate_entries,
+                delay_directory_restore,
+                continue_on_error,
+                clock,
+                max_input_bytes,
+                max_metadata_size,
+                max_pax_record_size,
+                digest,
+                seek,
+                check_free_space,
+                seeking: false,
+                throttle: max_read_rate.map(Throttle::new),
+                read_timeout: read_timeout.map(ReadTimeout::new),
                 obj,
                 pos: 0,
+                zero_blocks: 0,
+                termination: None,
+                skipped: 0,
             })),
         }
     }
-}
+}
+
+impl<R: AsyncRead + Unpin + AsRawFd> ArchiveBuilder<R> {
+    /// Indicate whether page cache hints are given to the kernel while
+    /// reading the archive from a file.
+    ///
+    /// When enabled, the file is marked for sequential access and read ahead
+    /// of the cursor, while pages that have already been consumed are
+    /// released, so that reading a huge archive does not evict the rest of
+    /// the page cache. The archive is assumed to start at the current offset
+    /// of the file.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_fadvise(mut self, fadvise: bool) -> Self {
+        self.fadvise = if fadvise {
+            Some(self.obj.as_raw_fd())
+        } else {
+            None
+        };
+        self
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> ArchiveBuilder<R> {
+    /// Indicate whether the data of entries which are not read is skipped by
+    /// seeking the underlying reader rather than reading through it.
+    ///
+    /// This makes listing the entries of a large archive only touch its
+    /// headers. Bytes which are seeked over are not checked against the
+    /// limit set with `set_max_input_bytes`, except that no seek goes past
+    /// it.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_seek_skips(mut self, seek: bool) -> Self {
+        self.seek = if seek { Some(poll_seek_forward) } else { None };
+        self
+    }
+
+    /// Indicate whether `Archive::unpack` checks that the destination has
+    /// enough free space before unpacking anything.
+    ///
+    /// The archive is read once to add up the sizes of its files, see
+    /// `Archive::total_size`, and then seeked back to the start. If the
+    /// filesystem of the destination has less space available to
+    /// unprivileged users, unpacking fails right away instead of running out
+    /// of space halfway through.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_check_free_space(mut self, check: bool) -> Self {
+        self.check_free_space = if check { Some(poll_seek_back) } else { None };
+        self
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Archive<R> {
+    /// Starts moving the reader to `pos` in the archive, to read the contents
+    /// of an entry from there.
+    pub(crate) fn start_seek_to(&self, pos: u64) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        let delta = pos as i128 - inner.pos as i128;
+        if delta < i64::MIN as i128 || delta > i64::MAX as i128 {
+            return Err(other("seek is too large"));
+        }
+        Pin::new(&mut inner.obj).start_seek(SeekFrom::Current(delta as i64))?;
+        inner.pos = pos;
+        if let Some(fadvise) = inner.fadvise.as_mut() {
+            fadvise.released = fadvise.released.min(pos);
+        }
+        Ok(())
+    }
+
+    /// Waits for the seek started with `start_seek_to` to complete.
+    pub(crate) fn poll_seek_complete(&self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut inner = self.inner.lock().unwrap();
+        Pin::new(&mut inner.obj).poll_complete(cx).map_ok(|_| ())
+    }
+}
+
+fn poll_seek_forward<R: AsyncSeek + Unpin>(
+    obj: &mut R,
+    cx: &mut Context<'_>,
+    amt: u64,
+    seeking: &mut bool,
+) -> Poll<io::Result<()>> {
+    if !*seeking {
+        if amt > i64::MAX as u64 {
+            return Poll::Ready(Err(other("skip is too large to seek")));
+        }
+        Pin::new(&mut *obj).start_seek(SeekFrom::Current(amt as i64))?;
+        *seeking = true;
+    }
+    let res = std::task::ready!(Pin::new(obj).poll_complete(cx));
+    *seeking = false;
+    Poll::Ready(res.map(|_| ()))
+}
+
+fn poll_seek_back<R: AsyncSeek + Unpin>(
+    obj: &mut R,
+    cx: &mut Context<'_>,
+    amt: u64,
+    seeking: &mut bool,
+) -> Poll<io::Result<()>> {
+    if !*seeking {
+        if amt > i64::MAX as u64 {
+            return Poll::Ready(Err(other("rewind is too large to seek")));
+        }
+        Pin::new(&mut *obj).start_seek(SeekFrom::Current(-(amt as i64)))?;
+        *seeking = true;
+    }
+    let res = std::task::ready!(Pin::new(obj).poll_complete(cx));
+    *seeking = false;
+    Poll::Ready(res.map(|_| ()))
+}
+
+/// Amount of data read ahead of, and released behind, the read cursor when
+/// page cache hints are enabled.
+const FADVISE_WINDOW: u64 = 8 * 1024 * 1024;
+
+/// Page cache hints for an archive read from a file.
+#[derive(Debug)]
+struct Fadvise {
+    fd: RawFd,
+    /// Offset in the file at which the archive starts.
+    base: u64,
+    /// Position in the archive up to which pages have been released.
+    released: u64,
+}
+
+impl Fadvise {
+    fn new(fd: RawFd) -> Fadvise {
+        let base = unsafe { libc::lseek(fd, 0, libc::SEEK_CUR) }.max(0) as u64;
+        let fadvise = Fadvise {
+            fd,
+            base,
+            released: 0,
+        };
+        fadvise.advise(0, 0, libc::POSIX_FADV_SEQUENTIAL);
+        fadvise.advise(0, FADVISE_WINDOW, libc::POSIX_FADV_WILLNEED);
+        fadvise
+    }
+
+    /// Called whenever the archive has been read up to `pos`.
+    fn advance(&mut self, pos: u64) {
+        if pos.saturating_sub(self.released) < FADVISE_WINDOW {
+            return;
+        }
+        let end = pos - pos % FADVISE_WINDOW;
+        self.advise(
+            self.released,
+            end - self.released,
+            libc::POSIX_FADV_DONTNEED,
+        );
+        self.advise(pos, FADVISE_WINDOW, libc::POSIX_FADV_WILLNEED);
+        self.released = end;
+    }
+
+    fn advise(&self, pos: u64, len: u64, advice: libc::c_int) {
+        // These are only hints, so failures are deliberately ignored.
+        unsafe {
+            libc::posix_fadvise(
+                self.fd,
+                (self.base + pos) as libc::off_t,
+                len as libc::off_t,
+                advice,
+            );
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> Archive<R> {
+    /// Create a new archive with the underlying object as the reader.
+    pub fn new(obj: R) -> Archive<R> {
+        ArchiveBuilder::new(obj).build()
+    }
+
+    /// Unwrap this archive, returning the underlying object.
+    pub fn into_inner(self) -> Result<R, Self> {
+        match Arc::try_unwrap(self.inner) {
+            Ok(inner) => Ok(inner.into_inner().unwrap().obj),
+            Err(inner) => Err(Self { inner }),
+        }
+    }
+
+    /// Construct an stream over the entries in this archive.
+    ///
+    /// Note that care must be taken to consider each entry within an archive in
+    /// sequence. If entries are processed out of sequence (from what the
+    /// stream returns), then the contents read for each entry may be
+    /// corrupted.
+    pub fn entries(self) -> io::Result<Entries<R>> {
+        self.entries_from(0)
+    }
+
+    /// Construct a stream over the entries in this archive, resuming at the
+    /// header at `offset`.
+    ///
+    /// The underlying reader must already be positioned `offset` bytes into
+    /// the archive, for example by seeking it to a position returned by
+    /// `Entries::position` before a restart. Positions reported by entries
+    /// stay relative to the start of the archive, and the limit set with
+    /// `ArchiveBuilder::set_max_input_bytes` counts the bytes before `offset`
+    /// as read.
+    ///
+    /// Global pax extensions and volume labels from before `offset` are not
+    /// seen. Resuming at an entry whose name or pax metadata was stored in
+    /// preceding extension headers loses that metadata too, so `offset`
+    /// should be the position right after a previous entry.
+    pub fn entries_from(self, offset: u64) -> io::Result<Entries<R>> {
+        self.start_at(offset, "entries")?;
+
+        Ok(Entries {
+            archive: self,
+            current: (offset, None, 0, None),
+            fields: None,
+            gnu_longlink: None,
+            gnu_longname: None,
+            pax_extensions: None,
+            skipped: 0,
+            extension_pos: None,
+            volume_label: None,
+            global_extensions: Vec::new(),
+            sparse_map: Vec::new(),
+            pax_parser: None,
+            peeked: None,
+            done: false,
+        })
+    }
+
+    /// Construct an stream over the raw entries in this archive.
+    ///
+    /// Note that care must be taken to consider each entry within an archive in
+    /// sequence. If entries are processed out of sequence (from what the
+    /// stream returns), then the contents read for each entry may be
+    /// corrupted.
+    ///
+    /// Headers are yielded exactly as they appear in the archive, including
+    /// any data in `Header::padding`, which makes this suitable for copying
+    /// entries through without altering them.
+    pub fn entries_raw(self) -> io::Result<RawEntries<R>> {
+        self.entries_raw_from(0)
+    }
+
+    /// Construct a stream over the raw entries in this archive, resuming at
+    /// the header at `offset`.
+    ///
+    /// The underlying reader must already be positioned `offset` bytes into
+    /// the archive, see `entries_from`.
+    pub fn entries_raw_from(self, offset: u64) -> io::Result<RawEntries<R>> {
+        self.start_at(offset, "entries_raw")?;
+
+        Ok(RawEntries {
+            archive: self,
+            current: (offset, None, 0),
+            done: false,
+        })
+    }
+
+    /// Marks the archive as read up to `offset`, where its reader is
+    /// positioned.
+    fn start_at(&self, offset: u64, what: &str) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.pos != 0 {
+            return Err(other(&format!(
+                "cannot call {} unless archive is at position 0",
+                what
+            )));
+        }
+        inner.pos = offset;
+        if let Some(fadvise) = inner.fadvise.as_mut() {
+            fadvise.base = fadvise.base.saturating_sub(offset);
+            fadvise.released = offset;
+        }
+        Ok(())
+    }
+
+    /// Unpacks the contents tarball into the specified `dst`.
+    ///
+    /// This function will iterate over the entire contents of this tarball,
+    /// extracting each file in turn to the location specified by the entry's
+    /// path name. The entries are written through an `FsSink` rooted at
+    /// `dst`; use `unpack_into` to extract elsewhere.
+    ///
+    /// Entries are written one after another, so at most one destination
+    /// file is open at a time, plus its `O_DIRECT` handle if
+    /// `ArchiveBuilder::set_direct_io` is enabled.
+    ///
+    /// This operation is relatively sensitive in that it will not write files
+    /// outside of the path specified by `dst`. Files in the archive which have
+    /// a '..' in their path are skipped during the unpacking process, see
+    /// `UnpackReport::skipped`.
+    ///
+    /// If a path occurs more than once, the last entry with it ends up on
+    /// disk, unless `ArchiveBuilder::set_duplicate_entries` says otherwise.
+    ///
+    /// Directories are unpacked last, deepest first, so that read-only
+    /// directories can be extracted, see
+    /// `ArchiveBuilder::set_delay_directory_restore`.
+    ///
+    /// With `ArchiveBuilder::set_continue_on_error`, entries which fail do
+    /// not stop the remaining ones from being unpacked, but the first failure
+    /// is still returned at the end. Use `unpack_with_report` to see all of
+    /// them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { tokio_uring::start(async {
+    /// #
+    /// use async_tar::Archive;
+    ///
+    /// let ar = Archive::new(tokio::fs::File::open("foo.tar").await?);
+    /// ar.unpack("foo").await?;
+    /// #
+    /// # Ok(()) }) }
+    /// ```
+    pub async fn unpack<P: AsRef<Path>>(self, dst: P) -> io::Result<()> {
+        let report = self.unpack_with_report(dst).await?;
+        let failed = report.failures.len();
+        match report.failures.into_iter().next() {
+            Some((_, err)) => {
+                Err(TarError::new(&format!("failed to unpack {} entries", failed), err).into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Unpacks the contents of this tarball into `dst` like `unpack`, while
+    /// reporting progress as a stream of `UnpackEvent`s.
+    ///
+    /// Nothing is unpacked until the stream is polled. The stream ends after
+    /// the last entry has been unpacked, or after the first
+    /// `UnpackEvent::Error`. What `unpack_with_report` would return is
+    /// available from `UnpackStream::report` as the stream goes.
+    pub fn unpack_stream<'a, P: AsRef<Path>>(self, dst: P) -> io::Result<UnpackStream<'a, R>>
+    where
+        R: 'a,
+    {
+        self.start_at(0, "unpack_stream")?;
+        Ok(UnpackStream::new(self, dst.as_ref().to_path_buf()))
+    }
+
+    /// Unpacks the contents of this tarball into `dst` like `unpack`, and
+    /// returns a report describing how the entries were extracted.
+    pub async fn unpack_with_report<P: AsRef<Path>>(self, dst: P) -> io::Result<UnpackReport> {
+        let mut unpacker = Unpacker::new(&self, dst.as_ref()).await?;
+        let mut entries = self.entries()?;
+        let mut pinned = Pin::new(&mut entries);
+        while let Some(entry) = pinned.next().await {
+            let entry = entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            if let Admission::Unpack(file) = unpacker.admit(entry)? {
+                let (mut file, res) = unpacker.unpack(*file).await;
+                unpacker.finish(&mut file, res)?;
+            }
+        }
+        for dir in unpacker.delayed_directories() {
+            let (mut dir, res) = unpacker.unpack(dir).await;
+            unpacker.finish(&mut dir, res)?;
+        }
+
+        Ok(unpacker.report)
+    }
+
+    /// Writes the contents of the regular files in this tarball to `writer`,
+    /// one after another in archive order, like `tar -xO`.
+    ///
+    /// Only members whose path equals one of `paths`, or lies below one of
+    /// them, are written. If `paths` is empty, every regular file is written.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { tokio_uring::start(async {
+    /// #
+    /// use async_tar::Archive;
+    ///
+    /// let ar = Archive::new(tokio::fs::File::open("foo.tar").await?);
+    /// let mut hostname = Vec::new();
+    /// ar.cat(&["etc/hostname"], &mut hostname).await?;
+    /// #
+    /// # Ok(()) }) }
+    /// ```
+    pub async fn cat<P, W>(self, paths: &[P], writer: &mut W) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        self.cat_with(paths, writer, |_, _| None).await
+    }
+
+    /// Like `cat`, but writes the bytes returned by `delimiter` before the
+    /// contents of each member.
+    ///
+    /// `delimiter` is called with the path and header of every selected
+    /// member and can return e.g. a line naming the member, or `None` to
+    /// write nothing.
+    pub async fn cat_with<P, W, F>(
+        self,
+        paths: &[P],
+        writer: &mut W,
+        mut delimiter: F,
+    ) -> io::Result<()>
+    where
+        P: AsRef<Path>,
+        W: AsyncWrite + Unpin + ?Sized,
+        F: FnMut(&Path, &Header) -> Option<Vec<u8>>,
+    {
+        let selected = paths
+            .iter()
+            .filter_map(|p| relative_path(p.as_ref()))
+            .collect::<Vec<_>>();
+        let mut entries = self.entries()?;
+        while let Some(entry) = entries.next().await {
+            let mut entry =
+                entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let path = match relative_path(&entry.path()?) {
+                Some(path) => path,
+                None => continue,
+            };
+            if !paths.is_empty() && !selected.iter().any(|s| path.starts_with(s)) {
+                continue;
+            }
+
+            if let Some(delimiter) = delimiter(&path, entry.header()) {
+                writer.write_all(&delimiter).await?;
+            }
+            tokio::io::copy(&mut entry, writer)
+                .await
+                .map_err(|e| TarError::new(&format!("failed to read `{}`", path.display()), e))?;
+        }
+        writer.flush().await
+    }
+
+    /// Reads all entries of this tarball and arranges them as a directory
+    /// tree, see `ArchiveTree`.
+    ///
+    /// The contents of the entries are skipped, so this only needs a single
+    /// sequential pass over the archive.
+    pub async fn tree(self) -> io::Result<ArchiveTree> {
+        let index = ArchiveIndex::build(self).await?;
+        Ok(ArchiveTree::from_index(&index))
+    }
+
+    /// Fails if the filesystem of `dst` has less space available than
+    /// unpacking this tarball needs, leaving the archive at its start.
+    async fn check_free_space(&self, rewind: SeekFn<R>, dst: &Path) -> io::Result<()> {
+        let needed = self.clone().total_size().await?;
+        futures_util::future::poll_fn(|cx| {
+            let mut lock = self.inner.lock().unwrap();
+            let inner = &mut *lock;
+            std::task::ready!(rewind(&mut inner.obj, cx, inner.pos, &mut inner.seeking))?;
+            inner.pos = 0;
+            inner.zero_blocks = 0;
+            inner.termination = None;
+            inner.skipped = 0;
+            if let Some(fadvise) = inner.fadvise.as_mut() {
+                fadvise.released = 0;
+            }
+            Poll::Ready(Ok::<_, io::Error>(()))
+        })
+        .await?;
+
+        let available = free_space(dst)
+            .map_err(|e| TarError::new(&format!("failed to stat `{}`", dst.display()), e))?;
+        if needed > available {
+            return Err(other(&format!(
+                "not enough space to unpack into `{}`: {} bytes needed, {} available",
+                dst.display(),
+                needed,
+                available
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the total size of the files unpacking this tarball would
+    /// write.
+    ///
+    /// This is the sum of `Entry::logical_size` over all regular, contiguous
+    /// and sparse files, so sparse files count with their holes filled in.
+    /// It can be used to check for available disk space or to show the
+    /// progress of a later unpack. The contents of the entries are skipped,
+    /// which only takes a single sequential pass over the archive, or seeks
+    /// over the contents with `ArchiveBuilder::set_seek_skips`.
+    pub async fn total_size(self) -> io::Result<u64> {
+        let mut total = 0u64;
+        let mut entries = self.entries()?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            let kind = entry.header().entry_type();
+            if kind.is_file() || kind.is_contiguous() || kind.is_gnu_sparse() {
+                total = total.saturating_add(entry.logical_size()?);
+            }
+        }
+        Ok(total)
+    }
+
+    /// Returns the number of members of this tarball.
+    ///
+    /// Only the headers are looked at: long names, PAX extensions and volume
+    /// labels are skipped without being parsed, and so are the contents of
+    /// the entries, which are seeked over with
+    /// `ArchiveBuilder::set_seek_skips`. This makes it a quick way to
+    /// validate an archive or to size a listing before reading it with
+    /// `entries`.
+    pub async fn count(self) -> io::Result<usize> {
+        let mut archive = self.clone();
+        let mut entries = self.entries_raw()?;
+        let mut count = 0;
+        while let Some(entry) = entries.next().await {
+            let entry = entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            let header = entry.header();
+            let kind = header.entry_type();
+            let is_recognized_header = header.as_gnu().is_some() || header.as_ustar().is_some();
+            if is_recognized_header
+                && (kind.is_gnu_longname()
+                    || kind.is_gnu_longlink()
+                    || kind.is_pax_global_extensions()
+                    || kind.is_pax_local_extensions())
+            {
+                continue;
+            }
+            if header.as_gnu().is_some() && kind.is_gnu_volume_label() {
+                continue;
+            }
+            count += 1;
+
+            // Extended sparse headers follow the header, but are not part of
+            // the size of the entry.
+            let extended =
+                kind.is_gnu_sparse() && matches!(header.as_gnu(), Some(gnu) if gnu.is_extended());
+            drop(entry);
+            if extended {
+                let mut ext = GnuExtSparseHeader::new();
+                loop {
+                    archive.read_exact(ext.as_mut_bytes()).await?;
+                    entries.current.0 += 512;
+                    if !ext.is_extended() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(count)
+    }
+
+    /// Checks that this tarball is well-formed without writing anything.
+    ///
+    /// Every entry is walked, which validates header checksums and sparse
+    /// maps, the PAX records applying to each entry are parsed, and all data
+    /// is read to make sure that nothing is missing. An archive which ends
+    /// after a complete entry but without the terminating zero blocks is also
+    /// reported as truncated. This is useful to validate an upload before
+    /// accepting it.
+    pub async fn verify(self) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        while let Some(entry) = entries.next().await {
+            let mut entry =
+                entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            let path = String::from_utf8_lossy(&entry.path_bytes()).into_owned();
+            verify_entry(&mut entry)
+                .await
+                .map_err(|e| TarError::new(&format!("invalid entry `{}`", path), e))?;
+        }
+        match entries.termination() {
+            Some(Termination::TruncatedAfter { offset }) => Err(other(&format!(
+                "archive is truncated after {} bytes",
+                offset
+            ))),
+            Some(Termination::DamagedAfter { offset, .. }) => {
+                Err(other(&format!("archive is damaged after {} bytes", offset)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Unpacks the contents of this tarball into `sink`.
+    ///
+    /// This allows extracting into destinations other than the local
+    /// filesystem, see `ExtractSink` for details. Entries which have a `..` in
+    /// their path are skipped, just like with `unpack`, while hard links to
+    /// such paths are an error.
+    pub async fn unpack_into<S: ExtractSink>(self, sink: &mut S) -> io::Result<()> {
+        let mut entries = self.entries()?;
+        let mut directories = Vec::new();
+        while let Some(entry) = entries.next().await {
+            let mut entry =
+                entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            let path = match relative_path(&entry.path()?) {
+                Some(path) => path,
+                None => continue,
+            };
+            let header = entry.header().clone();
+            let kind = header.entry_type();
+            let res = if kind.is_dir() || kind.is_gnu_dumpdir() {
+                // Directories are created right away so that their children
+                // can be written, but their metadata is only applied at the
+                // end.
+                sink.create_dir(&path, &header).await.map_err(|e| {
+                    TarError::new(&format!("failed to create `{}`", path.display()), e)
+                })?;
+                directories.push((path, header));
+                continue;
+            } else if kind.is_symlink() || kind.is_hard_link() {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| {
+                        other(&format!(
+                            "link listed for {} but no link name found",
+                            path.display()
+                        ))
+                    })?
+                    .into_owned();
+                if kind.is_symlink() {
+                    sink.symlink(&path, &target, &header).await
+                } else {
+                    match relative_path(&target) {
+                        Some(target) => sink.hard_link(&path, &target, &header).await,
+                        None => Err(other(&format!(
+                            "hard link target `{}` is outside of the destination",
+                            target.display()
+                        ))),
+                    }
+                }
+            } else if kind.is_pax_global_extensions()
+                || kind.is_pax_local_extensions()
+                || kind.is_gnu_longname()
+                || kind.is_gnu_longlink()
+            {
+                continue;
+            } else {
+                // As with `unpack`, unknown entry types are treated as
+                // regular files.
+                sink.write_file(&path, &header, &mut entry).await
+            };
+            match res {
+                Ok(()) => sink.set_metadata(&path, &header).await,
+                Err(e) => Err(e),
+            }
+            .map_err(|e| TarError::new(&format!("failed to unpack `{}`", path.display()), e))?;
+        }
+        // Apply directory metadata deepest first, so that read-only parents
+        // are locked down last.
+        for (path, header) in directories.iter().rev() {
+            sink.set_metadata(path, header)
+                .await
+                .map_err(|e| TarError::new(&format!("failed to unpack `{}`", path.display()), e))?;
+        }
+        Ok(())
+    }
+
+    /// Unpacks the contents of this tarball into memory instead of the
+    /// filesystem.
+    ///
+    /// The returned map is keyed by the path of each entry, with leading `/`
+    /// and `.` components removed. As with `unpack`, entries which have a `..`
+    /// in their path are skipped, and when a path is listed more than once the
+    /// last occurrence wins. Directories, links and other special entries are
+    /// included with empty data; their headers describe what they are.
+    pub async fn unpack_to_memory(self) -> io::Result<HashMap<PathBuf, InMemoryEntry>> {
+        let mut entries = self.entries()?;
+        let mut map = HashMap::new();
+        while let Some(entry) = entries.next().await {
+            let mut entry =
+                entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+            let path = match relative_path(&entry.path()?) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let mut data = Vec::new();
+            entry
+                .read_to_end(&mut data)
+                .await
+                .map_err(|e| TarError::new(&format!("failed to read `{}`", path.display()), e))?;
+            let header = entry.header().clone();
+            map.insert(
+                path,
+                InMemoryEntry {
+                    data: data.into(),
+                    header,
+                },
+            );
+        }
+        Ok(map)
+    }
+}
+
+/// Strips leading `/` and `.` components from an entry path.
+///
+/// Returns `None` for paths which are empty afterwards, or which contain a
+/// `..` component and could therefore escape the destination.
+fn relative_path(path: &Path) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::Prefix(..) | Component::RootDir | Component::CurDir => continue,
+            Component::ParentDir => return None,
+            Component::Normal(part) => relative.push(part),
+        }
+    }
+    if relative.as_os_str().is_empty() {
+        None
+    } else {
+        Some(relative)
+    }
+}
+
+/// An entry unpacked into memory by `Archive::unpack_to_memory`.
+#[derive(Clone, Debug)]
+pub struct InMemoryEntry {
+    /// The contents of the entry, with any sparse holes filled in.
+    pub data: Bytes,
+    /// The header describing the entry.
+    pub header: Header,
+}
+
+/// Checks the PAX records and the size of a single entry for `Archive::verify`.
+async fn verify_entry<R: AsyncRead + Unpin>(entry: &mut Entry<Archive<R>>) -> io::Result<()> {
+    if let Some(extensions) = entry.pax_extensions().await? {
+        for extension in extensions {
+            extension?;
+        }
+    }
+    let expected = if entry.header().entry_type().is_gnu_sparse() {
+        entry.logical_size()?
+    } else {
+        entry.stored_data_size()?
+    };
+    let read = tokio::io::copy(entry, &mut tokio::io::sink()).await?;
+    if read != expected {
+        return Err(other(&format!(
+            "entry has {} bytes of data but {} were expected",
+            read, expected
+        )));
+    }
+    Ok(())
+}
+
+/// The per-entry part of unpacking, shared by `Archive::unpack_with_report`
+/// and `UnpackStream` so that both honor the same options and produce the
+/// same report.
+pub(crate) struct Unpacker<R: AsyncRead + Unpin> {
+    sink: Rc<FsSink>,
+    buffers: Rc<Option<FixedBuffers>>,
+    duplicate_entries: DuplicateEntries,
+    delay_directories: bool,
+    keep_going: bool,
+    unpacked: HashSet<PathBuf>,
+    directories: Vec<Entry<Archive<R>>>,
+    pub(crate) report: UnpackReport,
+}
+
+/// What `Unpacker::admit` decided to do with an entry.
+pub(crate) enum Admission<R: AsyncRead + Unpin> {
+    /// The entry is to be unpacked right away.
+    Unpack(Box<Entry<Archive<R>>>),
+    /// The entry is a directory which is unpacked at the end.
+    Delay,
+    /// The entry repeats an earlier path and is skipped.
+    Skip,
+    /// The entry failed and was recorded in the report.
+    Fail,
+}
+
+/// How unpacking an entry turned out, see `Unpacker::finish`.
+pub(crate) enum Outcome {
+    Unpacked,
+    Skipped,
+    Failed,
+}
+
+impl<R: AsyncRead + Unpin> Unpacker<R> {
+    /// Prepares unpacking `archive` into `dst` with the options of the
+    /// archive, checking for free space first if requested.
+    pub(crate) async fn new(archive: &Archive<R>, dst: &Path) -> io::Result<Unpacker<R>> {
+        let (fixed_buffers, duplicate_entries, delay_directories, keep_going, check_free_space) = {
+            let inner = archive.inner.lock().unwrap();
+            (
+                inner.fixed_buffers,
+                inner.duplicate_entries,
+                inner.delay_directory_restore,
+                inner.continue_on_error,
+                inner.check_free_space,
+            )
+        };
+        let buffers = fixed_buffers.and_then(|(count, size)| FixedBuffers::register(count, size));
+        let dst = prepare_dst(dst).await?;
+        if let Some(rewind) = check_free_space {
+            archive.check_free_space(rewind, &dst).await?;
+        }
+        Ok(Unpacker {
+            sink: Rc::new(FsSink::new(dst)),
+            buffers: Rc::new(buffers),
+            duplicate_entries,
+            delay_directories,
+            keep_going,
+            unpacked: HashSet::new(),
+            directories: Vec::new(),
+            report: UnpackReport::default(),
+        })
+    }
+
+    /// Decides whether the next entry of the archive is unpacked now, later,
+    /// or not at all.
+    pub(crate) fn admit(&mut self, file: Entry<Archive<R>>) -> io::Result<Admission<R>> {
+        // Unless disabled, delay any directory entries until the end (they will be created if
+        // needed by descendants), to ensure that directory permissions do not interfer with
+        // descendant extraction, and that writing descendants does not change their mtime.
+        let kind = file.header().entry_type();
+        if self.delay_directories && (kind.is_dir() || kind.is_gnu_dumpdir()) {
+            self.directories.push(file);
+            return Ok(Admission::Delay);
+        }
+        let path = match file.path() {
+            Ok(path) => path
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+                .collect::<PathBuf>(),
+            Err(e) => {
+                self.report.fail(&file, e, self.keep_going)?;
+                return Ok(Admission::Fail);
+            }
+        };
+        if !self.unpacked.insert(path.clone()) {
+            match self.duplicate_entries {
+                DuplicateEntries::LastWins => {}
+                DuplicateEntries::FirstWins => {
+                    self.report.duplicates.push(path);
+                    return Ok(Admission::Skip);
+                }
+                DuplicateEntries::Error => {
+                    let e = other(&format!(
+                        "archive contains `{}` more than once",
+                        path.display()
+                    ));
+                    self.report.fail(&file, e, self.keep_going)?;
+                    return Ok(Admission::Fail);
+                }
+            }
+            self.report.duplicates.push(path);
+        }
+        Ok(Admission::Unpack(Box::new(file)))
+    }
+
+    /// Returns the delayed directories, subdirectories before their parents.
+    pub(crate) fn delayed_directories(&mut self) -> Vec<Entry<Archive<R>>> {
+        let mut directories = std::mem::take(&mut self.directories);
+        sort_directories(&mut directories);
+        directories
+    }
+
+    /// Unpacks `entry`, handing it back together with the result for
+    /// `finish`.
+    ///
+    /// The returned future does not borrow the unpacker, so that it can be
+    /// polled while the unpacker is used elsewhere.
+    pub(crate) fn unpack(
+        &self,
+        mut entry: Entry<Archive<R>>,
+    ) -> impl Future<Output = (Entry<Archive<R>>, io::Result<Option<PathBuf>>)> {
+        let sink = self.sink.clone();
+        let buffers = self.buffers.clone();
+        async move {
+            let res = sink.unpack_entry(&mut entry, (*buffers).as_ref()).await;
+            (entry, res)
+        }
+    }
 
-impl<R: AsyncRead + Unpin> Archive<R> {
-    /// Create a new archive with the underlying object as the reader.
-    pub fn new(obj: R) -> Archive<R> {
-        Archive {
-            inner: Arc::new(Mutex::new(ArchiveInner {
-                unpack_xattrs: false,
-                preserve_permissions: false,
-                preserve_mtime: true,
-                ignore_zeros: false,
-                obj,
-                pos: 0,
-            })),
+    /// Records the result of unpacking `entry` in the report, returning the
+    /// error unless continuing on errors.
+    pub(crate) fn finish(
+        &mut self,
+        entry: &mut Entry<Archive<R>>,
+        res: io::Result<Option<PathBuf>>,
+    ) -> io::Result<Outcome> {
+        match res {
+            Ok(Some(_)) => {
+                self.report.record(entry)?;
+                Ok(Outcome::Unpacked)
+            }
+            Ok(None) => {
+                self.report.skipped.push(entry.path()?.into_owned());
+                Ok(Outcome::Skipped)
+            }
+            Err(e) => {
+                self.report.fail(entry, e, self.keep_going)?;
+                Ok(Outcome::Failed)
+            }
         }
     }
+}
 
-    /// Unwrap this archive, returning the underlying object.
-    pub fn into_inner(self) -> Result<R, Self> {
-        match Arc::try_unwrap(self.inner) {
-            Ok(inner) => Ok(inner.into_inner().unwrap().obj),
-            Err(inner) => Err(Self { inner }),
-        }
+/// Orders the directories deferred to the end of an unpack so that
+/// subdirectories come before their parents.
+///
+/// Unpacking a directory creates its missing ancestors, which would change
+/// the mtime of a parent restored before.
+pub(crate) fn sort_directories<R: AsyncRead + Unpin>(directories: &mut [Entry<Archive<R>>]) {
+    directories.sort_by_cached_key(|dir| {
+        cmp::Reverse(dir.path().map_or(0, |path| path.components().count()))
+    });
+}
+
+/// Creates the destination directory of an unpack if needed, returning its
+/// canonical path.
+pub(crate) async fn prepare_dst(dst: &Path) -> io::Result<PathBuf> {
+    if fs::StatxBuilder::new()
+        .flags(libc::AT_SYMLINK_NOFOLLOW)
+        .pathname(dst)?
+        .statx()
+        .await
+        .is_err()
+    {
+        tokio_uring::fs::create_dir_all(&dst)
+            .await
+            .map_err(|e| TarError::new(&format!("failed to create `{}`", dst.display()), e))?;
     }
 
-    /// Construct an stream over the entries in this archive.
-    ///
-    /// Note that care must be taken to consider each entry within an archive in
-    /// sequence. If entries are processed out of sequence (from what the
-    /// stream returns), then the contents read for each entry may be
-    /// corrupted.
-    pub fn entries(self) -> io::Result<Entries<R>> {
-        if self.inner.lock().unwrap().pos != 0 {
-            return Err(other(
-                "cannot call entries unless archive is at \
-                 position 0",
-            ));
-        }
+    // Canonicalizing the dst directory will prepend the path with '\\?\'
+    // on windows which will allow windows APIs to treat the path as an
+    // extended-length path with a 32,767 character limit. Otherwise all
+    // unpacked paths over 260 characters will fail on creation with a
+    // NotFound exception.
+    Ok(dst.canonicalize().unwrap_or_else(|_| dst.to_path_buf()))
+}
 
-        Ok(Entries {
-            archive: self,
-            current: (0, None, 0, None),
-            fields: None,
-            gnu_longlink: None,
-            gnu_longname: None,
-            pax_extensions: None,
-        })
+/// Returns the number of bytes available to unprivileged users on the
+/// filesystem containing `path`.
+fn free_space(path: &Path) -> io::Result<u64> {
+    let path =
+        CString::new(path.as_os_str().as_bytes()).map_err(|_| other("path contains a nul byte"))?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return Err(io::Error::last_os_error());
     }
+    let stat = unsafe { stat.assume_init() };
+    // The types of these fields differ between platforms.
+    #[allow(clippy::unnecessary_cast)]
+    let available = (stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64);
+    Ok(available)
+}
 
-    /// Construct an stream over the raw entries in this archive.
+/// Describes the outcome of `Archive::unpack_with_report`.
+#[derive(Debug, Default)]
+pub struct UnpackReport {
+    mangled_names: Vec<(PathBuf, PathBuf)>,
+    warnings: Vec<(PathBuf, io::Error)>,
+    duplicates: Vec<PathBuf>,
+    failures: Vec<(PathBuf, io::Error)>,
+    skipped: Vec<PathBuf>,
+    sanitized: Vec<(PathBuf, PathBuf)>,
+}
+
+impl UnpackReport {
+    /// Returns the entries whose names were escaped because of
+    /// `ArchiveBuilder::set_mangle_names`, in the order they were unpacked.
     ///
-    /// Note that care must be taken to consider each entry within an archive in
-    /// sequence. If entries are processed out of sequence (from what the
-    /// stream returns), then the contents read for each entry may be
-    /// corrupted.
-    pub fn entries_raw(self) -> io::Result<RawEntries<R>> {
-        if self.inner.lock().unwrap().pos != 0 {
-            return Err(other(
-                "cannot call entries_raw unless archive is at \
-                 position 0",
-            ));
-        }
+    /// Each pair holds the path recorded in the archive and the path, relative
+    /// to the destination, which the entry was actually written to.
+    pub fn mangled_names(&self) -> &[(PathBuf, PathBuf)] {
+        &self.mangled_names
+    }
 
-        Ok(RawEntries {
-            archive: self,
-            current: (0, None, 0),
-        })
+    /// Returns the metadata errors which were ignored because of
+    /// `UnsupportedMetadata::Warn`, together with the path of the entry in
+    /// the archive.
+    pub fn warnings(&self) -> &[(PathBuf, io::Error)] {
+        &self.warnings
     }
 
-    /// Unpacks the contents tarball into the specified `dst`.
-    ///
-    /// This function will iterate over the entire contents of this tarball,
-    /// extracting each file in turn to the location specified by the entry's
-    /// path name.
-    ///
-    /// This operation is relatively sensitive in that it will not write files
-    /// outside of the path specified by `dst`. Files in the archive which have
-    /// a '..' in their path are skipped during the unpacking process.
+    /// Returns the paths of entries which repeated the path of an earlier
+    /// entry, once for every repetition, in archive order.
     ///
-    /// # Examples
-    ///
-    /// ```no_run
-    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { async_std::task::block_on(async {
-    /// #
-    /// use async_std::fs::File;
-    /// use async_tar::Archive;
-    ///
-    /// let mut ar = Archive::new(File::open("foo.tar").await?);
-    /// ar.unpack("foo").await?;
-    /// #
-    /// # Ok(()) }) }
-    /// ```
-    pub async fn unpack<P: AsRef<Path>>(self, dst: P) -> io::Result<()> {
-        let mut entries = self.entries()?;
-        let mut pinned = Pin::new(&mut entries);
-        let dst = dst.as_ref();
+    /// Depending on `ArchiveBuilder::set_duplicate_entries` these entries
+    /// either replaced the earlier ones or were skipped.
+    pub fn duplicates(&self) -> &[PathBuf] {
+        &self.duplicates
+    }
 
-        if fs::StatxBuilder::new()
-            .flags(libc::AT_SYMLINK_NOFOLLOW)
-            .pathname(dst)?
-            .statx()
-            .await
-            .is_err()
-        {
-            tokio_uring::fs::create_dir_all(&dst)
-                .await
-                .map_err(|e| TarError::new(&format!("failed to create `{}`", dst.display()), e))?;
-        }
+    /// Returns the entries which could not be unpacked because of
+    /// `ArchiveBuilder::set_continue_on_error`, together with the error, in
+    /// the order they were unpacked.
+    pub fn failures(&self) -> &[(PathBuf, io::Error)] {
+        &self.failures
+    }
 
-        // Canonicalizing the dst directory will prepend the path with '\\?\'
-        // on windows which will allow windows APIs to treat the path as an
-        // extended-length path with a 32,767 character limit. Otherwise all
-        // unpacked paths over 260 characters will fail on creation with a
-        // NotFound exception.
-        let dst = &dst.canonicalize().unwrap_or_else(|_| dst.to_path_buf());
+    /// Returns the paths of entries which were not unpacked, in archive
+    /// order: those which would have ended up outside of the destination,
+    /// such as paths containing `..`, and those of unknown types skipped
+    /// because of `UnknownEntryTypes::Skip`.
+    pub fn skipped(&self) -> &[PathBuf] {
+        &self.skipped
+    }
 
-        // Delay any directory entries until the end (they will be created if needed by
-        // descendants), to ensure that directory permissions do not interfer with descendant
-        // extraction.
-        let mut directories = Vec::new();
-        while let Some(entry) = pinned.next().await {
-            let mut file = entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
-            if file.header().entry_type() == crate::EntryType::Directory {
-                directories.push(file);
-            } else {
-                file.unpack_in(dst).await?;
-            }
+    /// Returns the entries with an absolute path, which were unpacked below
+    /// the destination with their leading `/`s removed.
+    ///
+    /// Each pair holds the path recorded in the archive and the path, relative
+    /// to the destination, which the entry was written to before any
+    /// mangling, see `mangled_names`.
+    pub fn sanitized(&self) -> &[(PathBuf, PathBuf)] {
+        &self.sanitized
+    }
+
+    /// Returns `true` if every entry was unpacked successfully.
+    pub fn is_complete(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    fn record<R: AsyncRead + Unpin>(&mut self, entry: &mut Entry<Archive<R>>) -> io::Result<()> {
+        let path = entry.path()?.into_owned();
+        if path.has_root() {
+            let relative = path
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect::<PathBuf>();
+            self.sanitized.push((path.clone(), relative));
         }
-        for mut dir in directories {
-            dir.unpack_in(dst).await?;
+        if let Some(mangled) = entry.mangled_path() {
+            self.mangled_names
+                .push((path.clone(), mangled.to_path_buf()));
         }
+        for warning in entry.take_metadata_warnings() {
+            self.warnings.push((path.clone(), warning));
+        }
+        Ok(())
+    }
 
+    /// Records that unpacking `entry` failed with `err`, or returns the error
+    /// unless `keep_going`.
+    fn fail<R: AsyncRead + Unpin>(
+        &mut self,
+        entry: &Entry<Archive<R>>,
+        err: io::Error,
+        keep_going: bool,
+    ) -> io::Result<()> {
+        if !keep_going {
+            return Err(err);
+        }
+        let path = PathBuf::from(OsStr::from_bytes(&entry.path_bytes()));
+        self.failures.push((path, err));
         Ok(())
     }
 }
 
+/// What `Archive::unpack` does when an archive contains the same path more
+/// than once.
+///
+/// Directory entries are not considered, repeating them is harmless.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DuplicateEntries {
+    /// Unpack every entry, so the last one with a path ends up on disk, as
+    /// GNU tar does.
+    ///
+    /// This is the default.
+    #[default]
+    LastWins,
+    /// Keep the first entry with a path and skip later ones.
+    FirstWins,
+    /// Fail the extraction at the first repeated path.
+    Error,
+}
+
+/// How iteration over the entries of an archive came to an end.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum Termination {
+    /// The end of the archive was marked by blocks of zeros.
+    ///
+    /// Iteration stops at the first zero block unless zeros are ignored, in
+    /// which case this counts all zero blocks read before the end of the
+    /// input.
+    CleanEof {
+        /// Number of consecutive zero blocks at the end of the archive.
+        zero_blocks: u64,
+    },
+
+    /// The input ended at a block boundary without an end-of-archive marker,
+    /// which usually means the archive was truncated.
+    TruncatedAfter {
+        /// Position in the archive at which the input ended.
+        offset: u64,
+    },
+
+    /// Reading was aborted because the input exceeded the limit set with
+    /// `ArchiveBuilder::set_max_input_bytes`.
+    StoppedByLimit,
+
+    /// The input ended while looking for a valid header after a damaged one,
+    /// see `ArchiveBuilder::set_resync_headers`.
+    ///
+    /// Zero blocks in the damaged region do not mark the end of the archive,
+    /// so this is reported even if the input ends with them.
+    DamagedAfter {
+        /// Position in the archive of the first damaged block.
+        offset: u64,
+        /// Number of bytes skipped from there up to the end of the input.
+        skipped: u64,
+    },
+}
+
 /// Stream of `Entry`s.
+///
+/// Once the stream has returned `None` it keeps doing so, see `FusedStream`.
 #[pin_project]
 #[derive(Debug)]
 pub struct Entries<R: AsyncRead + Unpin> {
@@ -274,6 +1767,20 @@ pub struct Entries<R: AsyncRead + Unpin> {
     gnu_longname: Option<Vec<u8>>,
     gnu_longlink: Option<Vec<u8>>,
     pax_extensions: Option<Vec<u8>>,
+    /// Bytes skipped before extension headers, carried over to their entry.
+    skipped: u64,
+    /// Position of the first extension header describing the next entry.
+    extension_pos: Option<u64>,
+    volume_label: Option<Vec<u8>>,
+    global_extensions: Vec<u8>,
+    /// Sparse map of a GNU sparse 1.0 entry read so far.
+    sparse_map: Vec<u8>,
+    /// Records of the pax extension entry being read.
+    pax_parser: Option<PaxParser>,
+    /// Item returned by `peek` but not yet by `poll_next`.
+    peeked: Option<Option<io::Result<Entry<Archive<R>>>>>,
+    /// Whether `None` has been returned.
+    done: bool,
 }
 
 macro_rules! ready_opt_err {
@@ -295,11 +1802,85 @@ macro_rules! ready_err {
     };
 }
 
+impl<R: AsyncRead + Unpin> Entries<R> {
+    /// Returns how iteration came to an end.
+    ///
+    /// This is `None` while entries remain, or if iteration was stopped by an
+    /// error other than exceeding the input limit.
+    pub fn termination(&self) -> Option<Termination> {
+        self.archive.inner.lock().unwrap().termination
+    }
+
+    /// Returns the position of the next header to be read, relative to the
+    /// start of the archive.
+    ///
+    /// Once an entry has been processed, this is where iteration can be
+    /// resumed with `Archive::entries_from`. Peeking moves it past the peeked
+    /// entry.
+    pub fn position(&self) -> u64 {
+        self.current.0
+    }
+
+    /// Returns the name of the most recent GNU volume label seen so far.
+    ///
+    /// Volume label headers describe the archive rather than a file, so they
+    /// are not yielded as entries.
+    pub fn volume_label(&self) -> Option<&[u8]> {
+        self.volume_label.as_deref()
+    }
+
+    /// Returns the records of the PAX global extended headers seen so far.
+    ///
+    /// Global records apply to every following entry unless the entry's own
+    /// extended header overrides them, and are included in
+    /// `Entry::pax_extensions`. Global headers are not yielded as entries.
+    pub fn global_extensions(&self) -> PaxExtensions<'_> {
+        pax_extensions(&self.global_extensions)
+    }
+
+    /// Returns the next entry without advancing past it.
+    ///
+    /// The entry, or error, is returned again by the next call to `next`, so
+    /// its header and pax metadata can be inspected before deciding whether
+    /// to read it. Like `next`, this skips the unread data of the entry
+    /// returned before.
+    pub async fn peek(&mut self) -> Option<&io::Result<Entry<Archive<R>>>> {
+        if self.peeked.is_none() {
+            let item = self.next().await;
+            self.peeked = Some(item);
+        }
+        self.peeked.as_ref().and_then(Option::as_ref)
+    }
+}
+
 impl<R: AsyncRead + Unpin> Stream for Entries<R> {
     type Item = io::Result<Entry<Archive<R>>>;
 
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let item = std::task::ready!(self.as_mut().poll_entry(cx));
+        *self.project().done = item.is_none();
+        Poll::Ready(item)
+    }
+}
+
+impl<R: AsyncRead + Unpin> FusedStream for Entries<R> {
+    fn is_terminated(&self) -> bool {
+        self.done
+    }
+}
+
+impl<R: AsyncRead + Unpin> Entries<R> {
+    fn poll_entry(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<Entry<Archive<R>>>>> {
         let mut this = self.project();
+        if let Some(item) = this.peeked.take() {
+            return Poll::Ready(item);
+        }
         loop {
             let (next, current_header, current_header_pos, _) = &mut this.current;
 
@@ -326,7 +1907,10 @@ impl<R: AsyncRead + Unpin> Stream for Entries<R> {
                     ))));
                 }
 
-                *this.gnu_longname = Some(ready_err!(Pin::new(fields).poll_read_all(cx)));
+                check_metadata_size(this.archive, fields)?;
+                *this.gnu_longname = Some(ready_err!(Pin::new(&mut *fields).poll_read_all(cx)));
+                *this.skipped += fields.skipped;
+                this.extension_pos.get_or_insert(fields.header_pos);
                 *this.fields = None;
                 continue;
             }
@@ -338,7 +1922,28 @@ impl<R: AsyncRead + Unpin> Stream for Entries<R> {
                          the same member",
                     ))));
                 }
-                *this.gnu_longlink = Some(ready_err!(Pin::new(fields).poll_read_all(cx)));
+                check_metadata_size(this.archive, fields)?;
+                *this.gnu_longlink = Some(ready_err!(Pin::new(&mut *fields).poll_read_all(cx)));
+                *this.skipped += fields.skipped;
+                this.extension_pos.get_or_insert(fields.header_pos);
+                *this.fields = None;
+                continue;
+            }
+
+            if fields.header.as_gnu().is_some() && fields.header.entry_type().is_gnu_volume_label()
+            {
+                ready_err!(Pin::new(&mut *fields).poll_read_all(cx));
+                *this.volume_label = Some(fields.header.path_bytes().into_owned());
+                *this.skipped += fields.skipped;
+                *this.fields = None;
+                continue;
+            }
+
+            if is_recognized_header && fields.header.entry_type().is_pax_global_extensions() {
+                check_metadata_size(this.archive, fields)?;
+                let update = ready_err!(poll_parse_pax(this.archive, this.pax_parser, fields, cx));
+                *this.global_extensions = merge_global_extensions(this.global_extensions, &update)?;
+                *this.skipped += fields.skipped;
                 *this.fields = None;
                 continue;
             }
@@ -350,14 +1955,39 @@ impl<R: AsyncRead + Unpin> Stream for Entries<R> {
                          the same member",
                     ))));
                 }
-                *this.pax_extensions = Some(ready_err!(Pin::new(fields).poll_read_all(cx)));
+                check_metadata_size(this.archive, fields)?;
+                *this.pax_extensions = Some(ready_err!(poll_parse_pax(
+                    this.archive,
+                    this.pax_parser,
+                    fields,
+                    cx
+                )));
+                *this.skipped += fields.skipped;
+                this.extension_pos.get_or_insert(fields.header_pos);
                 *this.fields = None;
                 continue;
             }
 
-            fields.long_pathname = this.gnu_longname.take();
-            fields.long_linkname = this.gnu_longlink.take();
-            fields.pax_extensions = this.pax_extensions.take();
+            // Reading sparse maps may return `Pending`, only take the
+            // extensions the first time around.
+            if this.gnu_longname.is_some() {
+                fields.long_pathname = this.gnu_longname.take();
+            }
+            if this.gnu_longlink.is_some() {
+                fields.long_linkname = this.gnu_longlink.take();
+            }
+            if this.pax_extensions.is_some() || fields.pax_extensions.is_none() {
+                fields.pax_extensions =
+                    apply_global_extensions(this.pax_extensions.take(), this.global_extensions);
+            }
+            fields.skipped += std::mem::take(this.skipped);
+
+            ready_err!(poll_parse_pax_sparse(
+                this.archive,
+                this.sparse_map,
+                fields,
+                cx
+            ));
 
             let (next, _, current_pos, current_ext) = &mut this.current;
             ready_err!(poll_parse_sparse_header(
@@ -369,24 +1999,65 @@ impl<R: AsyncRead + Unpin> Stream for Entries<R> {
                 cx
             ));
 
-            return Poll::Ready(Some(Ok(this.fields.take().unwrap().into_entry())));
+            let mut fields = this.fields.take().unwrap();
+            if let Some(start) = this.extension_pos.take() {
+                fields.raw_span.start = start;
+            }
+            // Extended sparse headers moved the next header further.
+            fields.raw_span.end = this.current.0;
+            let pos = this.archive.inner.lock().unwrap().pos;
+            fields.record_layout(this.archive.clone(), pos);
+            return Poll::Ready(Some(Ok(fields.into_entry())));
         }
     }
 }
 
 /// Stream of raw `Entry`s.
+///
+/// Like `Entries`, this keeps returning `None` once it has ended.
 pub struct RawEntries<R: AsyncRead + Unpin> {
     archive: Archive<R>,
     current: (u64, Option<Header>, usize),
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> RawEntries<R> {
+    /// Returns how iteration came to an end.
+    ///
+    /// See `Entries::termination` for details.
+    pub fn termination(&self) -> Option<Termination> {
+        self.archive.inner.lock().unwrap().termination
+    }
 }
 
 impl<R: AsyncRead + Unpin> Stream for RawEntries<R> {
     type Item = io::Result<Entry<Archive<R>>>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.done {
+            return Poll::Ready(None);
+        }
         let archive = self.archive.clone();
         let (next, current_header, current_header_pos) = &mut self.current;
-        poll_next_raw(&archive, next, current_header, current_header_pos, cx)
+        let mut entry = std::task::ready!(poll_next_raw(
+            &archive,
+            next,
+            current_header,
+            current_header_pos,
+            cx
+        ));
+        if let Some(Ok(entry)) = entry.as_mut() {
+            let pos = archive.inner.lock().unwrap().pos;
+            entry.record_layout(archive, pos);
+        }
+        self.done = entry.is_none();
+        Poll::Ready(entry)
+    }
+}
+
+impl<R: AsyncRead + Unpin> FusedStream for RawEntries<R> {
+    fn is_terminated(&self) -> bool {
+        self.done
     }
 }
 
@@ -404,7 +2075,7 @@ fn poll_next_raw<R: AsyncRead + Unpin>(
         // Seek to the start of the next header in the archive
         if current_header.is_none() {
             let delta = *next - archive.inner.lock().unwrap().pos;
-            match std::task::ready!(poll_skip(archive.clone(), cx, delta)) {
+            match std::task::ready!(poll_skip_archive(&archive, cx, delta)) {
                 Ok(_) => {}
                 Err(err) => return Poll::Ready(Some(Err(err))),
             }
@@ -423,19 +2094,57 @@ fn poll_next_raw<R: AsyncRead + Unpin>(
             current_header_pos,
         )) {
             Ok(true) => {}
-            Ok(false) => return Poll::Ready(None),
+            Ok(false) => {
+                let mut inner = archive.inner.lock().unwrap();
+                let skipped = std::mem::replace(&mut inner.skipped, 0);
+                inner.termination = Some(match inner.zero_blocks {
+                    _ if skipped > 0 => Termination::DamagedAfter {
+                        offset: *next - skipped,
+                        skipped,
+                    },
+                    0 => Termination::TruncatedAfter { offset: *next },
+                    zero_blocks => Termination::CleanEof { zero_blocks },
+                });
+                return Poll::Ready(None);
+            }
             Err(err) => return Poll::Ready(Some(Err(err))),
         }
 
         // If a header is not all zeros, we have another valid header.
         // Otherwise, check if we are ignoring zeros and continue, or break as if this is the
         // end of the archive.
+        let mut inner = archive.inner.lock().unwrap();
         if !header.as_bytes().iter().all(|i| *i == 0) {
+            inner.zero_blocks = 0;
             *next += 512;
-            break;
+            if !inner.resync_headers
+                || (checksum_matches(header)
+                    && (inner.skipped == 0
+                        || header.as_ustar().is_some()
+                        || header.as_gnu().is_some()))
+            {
+                break;
+            }
+            // Damaged, skip this block and look at the next one.
+            inner.skipped += 512;
+            *current_header = None;
+            header_pos = *next;
+            continue;
         }
 
-        if !archive.inner.lock().unwrap().ignore_zeros {
+        inner.zero_blocks += 1;
+        if inner.skipped > 0 {
+            // Still inside of the damaged region.
+            inner.skipped += 512;
+            *current_header = None;
+            *next += 512;
+            header_pos = *next;
+            continue;
+        }
+        if !inner.ignore_zeros {
+            inner.termination = Some(Termination::CleanEof {
+                zero_blocks: inner.zero_blocks,
+            });
             return Poll::Ready(None);
         }
 
@@ -445,54 +2154,119 @@ fn poll_next_raw<R: AsyncRead + Unpin>(
 
     let header = current_header.as_mut().unwrap();
 
-    // Make sure the checksum is ok
-    let sum = header.as_bytes()[..148]
-        .iter()
-        .chain(&header.as_bytes()[156..])
-        .fold(0, |a, b| a + (*b as u32))
-        + 8 * 32;
-    let cksum = header.cksum()?;
-    if sum != cksum {
+    // Make sure the checksum is ok.
+    header.cksum()?;
+    if !checksum_matches(header) {
         return Poll::Ready(Some(Err(other("archive header checksum mismatch"))));
     }
 
     let file_pos = *next;
     let size = header.entry_size()?;
 
-    let data = EntryIo::Data(archive.clone().take(size));
+    // Directories, links and empty files have no data to poll at all.
+    let data = if size == 0 {
+        Vec::new()
+    } else {
+        vec![EntryIo::Data(archive.clone().take(size))]
+    };
 
     let header = current_header.take().unwrap();
 
+    let skipped = std::mem::replace(&mut archive.inner.lock().unwrap().skipped, 0);
+
     let ArchiveInner {
         unpack_xattrs,
+        unpack_file_flags,
         preserve_mtime,
+        preserve_atime,
         preserve_permissions,
+        mask,
+        preallocate,
+        direct_io,
+        max_symlink_depth,
+        mangle_names,
+        metadata_order,
+        unsupported_metadata,
+        clamp_future_mtime,
+        star_compat,
+        skip_unchanged,
+        unknown_entry_types,
+        unknown_entry_callback,
+        #[cfg(feature = "encoding_rs")]
+        name_encoding,
+        clock,
+        digest,
         ..
     } = &*archive.inner.lock().unwrap();
 
+    // Store where the next entry is, rounding up by 512 bytes (the size of
+    // a header);
+    *next += (size + 511) & !(512 - 1);
+
     let ret = EntryFields {
         size,
         header_pos,
         file_pos,
-        data: vec![data],
+        raw_span: header_pos..*next,
+        data,
         header,
         long_pathname: None,
         long_linkname: None,
         pax_extensions: None,
         unpack_xattrs: *unpack_xattrs,
+        unpack_file_flags: *unpack_file_flags,
         preserve_permissions: *preserve_permissions,
+        mask: *mask,
         preserve_mtime: *preserve_mtime,
+        preserve_atime: *preserve_atime,
+        preallocate: *preallocate,
+        direct_io: *direct_io,
+        max_symlink_depth: *max_symlink_depth,
+        mangle_names: *mangle_names,
+        metadata_order: *metadata_order,
+        unsupported_metadata: *unsupported_metadata,
+        clamp_future_mtime: *clamp_future_mtime,
+        star_compat: *star_compat,
+        skip_unchanged: *skip_unchanged,
+        unknown_entry_types: *unknown_entry_types,
+        unknown_entry_callback: unknown_entry_callback.clone(),
+        #[cfg(feature = "encoding_rs")]
+        name_encoding: *name_encoding,
+        clock: clock.clone(),
+        metadata_warnings: Vec::new(),
+        mangled_path: None,
+        skipped,
+        digest: DigestState::new(*digest),
+        progress: None,
         read_state: None,
+        buffered: Vec::new(),
+        buffered_pos: 0,
+        buffered_len: 0,
+        position: 0,
+        layout: None,
     };
 
-    // Store where the next entry is, rounding up by 512 bytes (the size of
-    // a header);
-    let size = (size + 511) & !(512 - 1);
-    *next += size;
-
     Poll::Ready(Some(Ok(ret.into_entry())))
 }
 
+/// Returns whether the checksum recorded in `header` matches its contents.
+///
+/// Some historic implementations summed the header as signed bytes, so
+/// accept that too.
+pub(crate) fn checksum_matches(header: &Header) -> bool {
+    let bytes = || {
+        header.as_bytes()[..148]
+            .iter()
+            .chain(&header.as_bytes()[156..])
+    };
+    let sum = bytes().fold(0, |a, b| a + (*b as u32)) + 8 * 32;
+    let signed_sum = bytes().fold(0, |a, b| a + (*b as i8 as i32)) + 8 * 32;
+    match header.cksum() {
+        Ok(cksum) => sum == cksum || signed_sum == cksum as i32,
+        Err(_) => false,
+    }
+}
+
 fn poll_parse_sparse_header<R: AsyncRead + Unpin>(
     archive: &Archive<R>,
     next: &mut u64,
@@ -619,6 +2393,183 @@ fn poll_parse_sparse_header<R: AsyncRead + Unpin>(
     Poll::Ready(Ok(()))
 }
 
+/// Upper bound for the sparse map of a GNU sparse 1.0 entry.
+const MAX_SPARSE_MAP: usize = 1024 * 1024;
+
+/// Default for `ArchiveBuilder::set_max_metadata_size`.
+const DEFAULT_MAX_METADATA_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Reads the records of the pax extension entry `fields`, see `PaxParser`.
+fn poll_parse_pax<R: AsyncRead + Unpin>(
+    archive: &Archive<R>,
+    state: &mut Option<PaxParser>,
+    fields: &mut EntryFields<Archive<R>>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<Vec<u8>>> {
+    let parser = state.get_or_insert_with(|| {
+        let inner = archive.inner.lock().unwrap();
+        PaxParser::new(inner.max_pax_record_size, inner.max_metadata_size)
+    });
+    let mut buf = [0; 4096];
+    loop {
+        let mut read_buf = ReadBuf::new(&mut buf);
+        let res = std::task::ready!(Pin::new(&mut *fields).poll_read(cx, &mut read_buf))
+            .and_then(|()| parser.feed(read_buf.filled()));
+        if let Err(e) = res {
+            *state = None;
+            return Poll::Ready(Err(e));
+        }
+        if read_buf.filled().is_empty() {
+            break;
+        }
+    }
+    Poll::Ready(Ok(state.take().unwrap().finish()))
+}
+
+/// Fails if the metadata entry `fields` is too large to be read into memory,
+/// see `ArchiveBuilder::set_max_metadata_size`.
+fn check_metadata_size<R: AsyncRead + Unpin>(
+    archive: &Archive<R>,
+    fields: &EntryFields<Archive<R>>,
+) -> io::Result<()> {
+    let max = archive.inner.lock().unwrap().max_metadata_size;
+    if fields.size > max {
+        return Err(other(&format!(
+            "{:?} entry at offset {} is {} bytes large, more than the limit of {} bytes",
+            fields.header.entry_type(),
+            fields.header_pos,
+            fields.size,
+            max
+        )));
+    }
+    Ok(())
+}
+
+/// Sets up the data of a GNU sparse 1.0 entry, which is described by pax
+/// extensions and stores its sparse map at the start of the entry data.
+///
+/// The map is a list of decimal numbers, each terminated by a newline: the
+/// number of chunks followed by the offset and length of each chunk. It is
+/// padded to a 512-byte boundary, after which the chunks follow back to back.
+fn poll_parse_pax_sparse<R: AsyncRead + Unpin>(
+    archive: &Archive<R>,
+    map: &mut Vec<u8>,
+    entry: &mut EntryFields<Archive<R>>,
+    cx: &mut Context<'_>,
+) -> Poll<io::Result<()>> {
+    let real_size = match entry.pax_extensions {
+        Some(ref pax) => {
+            let pax = pax_extensions(pax);
+            if pax.get("GNU.sparse.major") != Some(b"1")
+                || pax.get("GNU.sparse.minor") != Some(b"0")
+            {
+                return Poll::Ready(Ok(()));
+            }
+            match pax.number("GNU.sparse.realsize") {
+                Some(size) => size,
+                None => return Poll::Ready(Err(other("sparse file without a valid real size"))),
+            }
+        }
+        None => return Poll::Ready(Ok(())),
+    };
+
+    let chunks = loop {
+        // Only whole blocks hold a complete map.
+        let partial = map.len() % 512;
+        if partial == 0 && !map.is_empty() {
+            if let Some(chunks) = parse_sparse_map(map)? {
+                break chunks;
+            }
+        }
+        if map.len() >= MAX_SPARSE_MAP {
+            return Poll::Ready(Err(other("sparse map is too long")));
+        }
+        let mut block = [0; 512];
+        let mut buf = ReadBuf::new(&mut block[partial..]);
+        let data = match entry.data.first_mut() {
+            Some(data) => data,
+            None => return Poll::Ready(Err(other("unexpected EOF in sparse map"))),
+        };
+        std::task::ready!(Pin::new(data).poll_read(cx, &mut buf))?;
+        if buf.filled().is_empty() {
+            return Poll::Ready(Err(other("unexpected EOF in sparse map")));
+        }
+        map.extend_from_slice(buf.filled());
+    };
+
+    let mut remaining = entry
+        .size
+        .checked_sub(map.len() as u64)
+        .ok_or_else(|| other("sparse map is larger than the entry"))?;
+    map.clear();
+    entry.data.truncate(0);
+    let mut cur = 0;
+    for (off, len) in chunks {
+        if off < cur {
+            return Poll::Ready(Err(other("out of order or overlapping sparse blocks")));
+        } else if cur < off {
+            entry
+                .data
+                .push(EntryIo::Pad(tokio::io::repeat(0).take(off - cur)));
+        }
+        cur = off
+            .checked_add(len)
+            .ok_or_else(|| other("more bytes listed in sparse file than u64 can hold"))?;
+        remaining = remaining
+            .checked_sub(len)
+            .ok_or_else(|| other("sparse file consumed more data than the header listed"))?;
+        entry.data.push(EntryIo::Data(archive.clone().take(len)));
+    }
+    if cur > real_size {
+        return Poll::Ready(Err(other("mismatch in sparse file chunks and real size")));
+    } else if cur < real_size {
+        entry
+            .data
+            .push(EntryIo::Pad(tokio::io::repeat(0).take(real_size - cur)));
+    }
+    if remaining > 0 {
+        return Poll::Ready(Err(other(
+            "mismatch in sparse file chunks and entry size in header",
+        )));
+    }
+    entry.size = real_size;
+    Poll::Ready(Ok(()))
+}
+
+/// Parses a complete sparse map, returning `None` if more blocks are needed.
+fn parse_sparse_map(map: &[u8]) -> io::Result<Option<Vec<(u64, u64)>>> {
+    let mut rest = map;
+    let mut next_number = || -> io::Result<Option<u64>> {
+        let end = match rest.iter().position(|b| *b == b'\n') {
+            Some(end) => end,
+            None => return Ok(None),
+        };
+        let number = str::from_utf8(&rest[..end])
+            .ok()
+            .and_then(|n| n.parse().ok())
+            .ok_or_else(|| other("invalid number in sparse map"))?;
+        rest = &rest[end + 1..];
+        Ok(Some(number))
+    };
+    let count = match next_number()? {
+        Some(count) => count,
+        None => return Ok(None),
+    };
+    let mut chunks = Vec::new();
+    for _ in 0..count {
+        let off = match next_number()? {
+            Some(off) => off,
+            None => return Ok(None),
+        };
+        let len = match next_number()? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        chunks.push((off, len));
+    }
+    Ok(Some(chunks))
+}
+
 impl<R: AsyncRead + Unpin> AsyncRead for Archive<R> {
     fn poll_read(
         self: Pin<&mut Self>,
@@ -626,17 +2577,35 @@ impl<R: AsyncRead + Unpin> AsyncRead for Archive<R> {
         into: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
         let mut lock = self.inner.lock().unwrap();
-        let mut inner = Pin::new(&mut *lock);
-        let r = Pin::new(&mut inner.obj);
-
-        let filled_before = into.filled().len();
-        let res = std::task::ready!(r.poll_read(cx, into));
+        let inner = &mut *lock;
+
+        let obj = &mut inner.obj;
+        let pos = &mut inner.pos;
+        let max = inner.max_input_bytes;
+        let timeout = &mut inner.read_timeout;
+        let mut read = |cx: &mut Context<'_>, buf: &mut ReadBuf<'_>| match timeout {
+            Some(timeout) => {
+                timeout.poll_read(cx, |cx| poll_read_limited(Pin::new(obj), cx, buf, pos, max))
+            }
+            None => poll_read_limited(Pin::new(obj), cx, buf, pos, max),
+        };
+        let res = match inner.throttle.as_mut() {
+            Some(throttle) => std::task::ready!(throttle.poll_read(cx, into, read)),
+            None => std::task::ready!(read(cx, into)),
+        };
         match res {
             Ok(i) => {
-                inner.pos += (into.filled().len() - filled_before) as u64;
+                if let Some(fadvise) = inner.fadvise.as_mut() {
+                    fadvise.advance(inner.pos);
+                }
                 Poll::Ready(Ok(i))
             }
-            Err(err) => Poll::Ready(Err(err)),
+            Err(err) => {
+                if matches!(err.get_ref(), Some(e) if e.is::<InputLimitExceeded>()) {
+                    inner.termination = Some(Termination::StoppedByLimit);
+                }
+                Poll::Ready(Err(err))
+            }
         }
     }
 }
@@ -670,12 +2639,44 @@ fn poll_try_read_all<R: AsyncRead + Unpin>(
     Poll::Ready(Ok(true))
 }
 
+/// Skips `amt` bytes of the archive, seeking over them if enabled with
+/// `ArchiveBuilder::set_seek_skips`.
+fn poll_skip_archive<R: AsyncRead + Unpin>(
+    archive: &Archive<R>,
+    cx: &mut Context<'_>,
+    amt: u64,
+) -> Poll<io::Result<()>> {
+    {
+        let mut guard = archive.inner.lock().unwrap();
+        let inner = &mut *guard;
+        let within_limit = match inner.max_input_bytes {
+            Some(max) => inner.pos.saturating_add(amt) <= max,
+            None => true,
+        };
+        match inner.seek {
+            Some(seek) if amt > 0 && within_limit => {
+                std::task::ready!(seek(&mut inner.obj, cx, amt, &mut inner.seeking))?;
+                inner.pos += amt;
+                if let Some(fadvise) = inner.fadvise.as_mut() {
+                    fadvise.advance(inner.pos);
+                }
+                return Poll::Ready(Ok(()));
+            }
+            _ => {}
+        }
+    }
+    poll_skip(archive.clone(), cx, amt)
+}
+
 /// Skip n bytes on the given source.
 fn poll_skip<R: AsyncRead + Unpin>(
     mut source: R,
     cx: &mut Context<'_>,
     mut amt: u64,
 ) -> Poll<io::Result<()>> {
+    if amt == 0 {
+        return Poll::Ready(Ok(()));
+    }
     let mut buf = [0u8; 4096 * 8];
     while amt > 0 {
         let n = cmp::min(amt, buf.len() as u64);
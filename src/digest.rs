@@ -0,0 +1,122 @@
+use std::fmt;
+
+/// A hash function used to compute digests of entry contents while they are
+/// read, see `ArchiveBuilder::set_digest`.
+///
+/// Each algorithm is only available when the crate feature of the same name
+/// is enabled.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum DigestAlgorithm {
+    /// SHA-256, available with the `sha2` feature.
+    #[cfg(feature = "sha2")]
+    Sha256,
+
+    /// BLAKE3, available with the `blake3` feature.
+    #[cfg(feature = "blake3")]
+    Blake3,
+}
+
+pub(crate) enum Hasher {
+    #[cfg(feature = "sha2")]
+    Sha256(sha2::Sha256),
+    #[cfg(feature = "blake3")]
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    fn new(algorithm: DigestAlgorithm) -> Hasher {
+        match algorithm {
+            #[cfg(feature = "sha2")]
+            DigestAlgorithm::Sha256 => Hasher::Sha256(sha2::Digest::new()),
+            #[cfg(feature = "blake3")]
+            DigestAlgorithm::Blake3 => Hasher::Blake3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        let _ = data;
+        match *self {
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(ref mut h) => sha2::Digest::update(h, data),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(ref mut h) => {
+                h.update(data);
+            }
+        }
+    }
+
+    fn finalize(self) -> [u8; 32] {
+        match self {
+            #[cfg(feature = "sha2")]
+            Hasher::Sha256(h) => sha2::Digest::finalize(h).into(),
+            #[cfg(feature = "blake3")]
+            Hasher::Blake3(h) => h.finalize().into(),
+        }
+    }
+}
+
+/// Digest of the contents of an entry, computed as they are read.
+pub(crate) enum DigestState {
+    Off,
+    Running(Hasher),
+    Done([u8; 32]),
+}
+
+impl DigestState {
+    // Without any digest feature `DigestAlgorithm` has no values.
+    #[cfg_attr(
+        not(any(feature = "sha2", feature = "blake3")),
+        allow(unreachable_code)
+    )]
+    pub(crate) fn new(algorithm: Option<DigestAlgorithm>) -> DigestState {
+        match algorithm {
+            Some(algorithm) => DigestState::Running(Hasher::new(algorithm)),
+            None => DigestState::Off,
+        }
+    }
+
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        if let DigestState::Running(hasher) = self {
+            hasher.update(data);
+        }
+    }
+
+    /// Feeds `len` zero bytes, for the holes of sparse files.
+    pub(crate) fn update_zeros(&mut self, mut len: u64) {
+        if let DigestState::Running(hasher) = self {
+            let zeros = [0; 4096];
+            while len > 0 {
+                let n = len.min(zeros.len() as u64) as usize;
+                hasher.update(&zeros[..n]);
+                len -= n as u64;
+            }
+        }
+    }
+
+    /// Completes the digest once all of the contents have been fed.
+    pub(crate) fn finish(&mut self) {
+        if let DigestState::Running(_) = self {
+            if let DigestState::Running(hasher) = std::mem::replace(self, DigestState::Off) {
+                *self = DigestState::Done(hasher.finalize());
+            }
+        }
+    }
+
+    pub(crate) fn digest(&self) -> Option<[u8; 32]> {
+        match self {
+            DigestState::Done(digest) => Some(*digest),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Debug for DigestState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DigestState::Off => write!(f, "Off"),
+            DigestState::Running(_) => write!(f, "Running"),
+            DigestState::Done(digest) => f.debug_tuple("Done").field(digest).finish(),
+        }
+    }
+}
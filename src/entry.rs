@@ -1,26 +1,47 @@
 use std::{
     borrow::Cow,
-    cmp, fmt,
+    cmp,
+    ffi::{OsStr, OsString},
+    fmt,
     fs::Permissions,
     io::{Error, ErrorKind},
     marker,
+    ops::Range,
     os::{
         fd::{AsRawFd, FromRawFd},
-        unix::fs::PermissionsExt,
+        unix::{
+            ffi::{OsStrExt, OsStringExt},
+            fs::{OpenOptionsExt, PermissionsExt},
+        },
     },
     path::{Component, Path, PathBuf},
     pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
     task::{Context, Poll},
+    time::UNIX_EPOCH,
 };
 
-use bytes::BytesMut;
+use bytes::{BufMut, Bytes, BytesMut};
+#[cfg(feature = "encoding_rs")]
+use encoding_rs::Encoding;
 use filetime::{self, FileTime};
+use futures_core::Stream;
 use pin_project::pin_project;
-use tokio::io::{self, AsyncRead, AsyncReadExt, ReadBuf};
-use tokio_uring::fs;
+use tokio::io::{
+    self, AsyncBufRead, AsyncRead, AsyncReadExt, AsyncSeek, AsyncWrite, ReadBuf, SeekFrom,
+};
+use tokio_uring::{
+    buf::{fixed::FixedBufPool, BoundedBuf},
+    fs,
+};
 
 use crate::{
-    error::TarError, header::bytes2path, other, pax::pax_extensions, Archive, Header, PaxExtensions,
+    clock::SharedClock, digest::DigestState, error::TarError, header::bytes2path,
+    incremental::parse_dumpdir, link::link_stays_within, other, pax::pax_extensions, Archive,
+    DumpDirEntry, EntryMetadata, EntryType, Header, IncrementalDir, PaxExtensions,
 };
 
 /// A read-only view into an entry of an archive.
@@ -54,30 +75,110 @@ pub struct EntryFields<R: AsyncRead + Unpin> {
     pub size: u64,
     pub header_pos: u64,
     pub file_pos: u64,
+    /// Positions in the archive from the first extension header describing
+    /// this entry up to the end of its padded data.
+    pub(crate) raw_span: Range<u64>,
     #[pin]
     pub data: Vec<EntryIo<R>>,
     pub unpack_xattrs: bool,
+    pub unpack_file_flags: bool,
     pub preserve_permissions: bool,
+    pub mask: u32,
     pub preserve_mtime: bool,
+    pub preserve_atime: bool,
+    pub preallocate: bool,
+    pub direct_io: bool,
+    pub max_symlink_depth: Option<u32>,
+    pub mangle_names: bool,
+    pub metadata_order: MetadataOrder,
+    pub unsupported_metadata: UnsupportedMetadata,
+    pub(crate) metadata_warnings: Vec<io::Error>,
+    pub clamp_future_mtime: bool,
+    pub star_compat: bool,
+    pub skip_unchanged: bool,
+    pub unknown_entry_types: UnknownEntryTypes,
+    pub unknown_entry_callback: Option<UnknownEntryCallback>,
+    #[cfg(feature = "encoding_rs")]
+    pub name_encoding: Option<&'static Encoding>,
+    pub(crate) clock: SharedClock,
+    pub(crate) mangled_path: Option<PathBuf>,
+    pub(crate) skipped: u64,
+    pub(crate) digest: DigestState,
+    /// Number of bytes written so far while this entry is unpacked.
+    pub(crate) progress: Option<Arc<AtomicU64>>,
     #[pin]
     pub(crate) read_state: Option<EntryIo<R>>,
+    /// Data read ahead for `AsyncBufRead`, of which the bytes from
+    /// `buffered_pos` to `buffered_len` have not been consumed yet.
+    pub(crate) buffered: Vec<u8>,
+    pub(crate) buffered_pos: usize,
+    pub(crate) buffered_len: usize,
+    /// Number of bytes of the contents read so far, including those which
+    /// are still buffered.
+    pub(crate) position: u64,
+    /// Where the contents are stored in the archive, for seeking.
+    pub(crate) layout: Option<Layout<R>>,
+}
+
+/// Where the contents of an entry are stored in the archive, see the
+/// `AsyncSeek` implementation of `Entry`.
+pub(crate) struct Layout<R> {
+    reader: R,
+    segments: Vec<Segment>,
+    /// Whether the reader is being seeked.
+    seeking: bool,
+}
+
+/// A stretch of the contents of an entry.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Segment {
+    pub(crate) len: u64,
+    /// Position of the data in the archive, or `None` for a hole of a sparse
+    /// file.
+    pub(crate) pos: Option<u64>,
 }
 
 impl<R: AsyncRead + Unpin> fmt::Debug for EntryFields<R> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("EntryFields")
-            .field("long_pathname", &self.long_pathname)
+        let mut d = f.debug_struct("EntryFields");
+        d.field("long_pathname", &self.long_pathname)
             .field("long_linkname", &self.long_linkname)
             .field("pax_extensions", &self.pax_extensions)
             .field("header", &self.header)
             .field("size", &self.size)
             .field("header_pos", &self.header_pos)
+            .field("raw_span", &self.raw_span)
             .field("file_pos", &self.file_pos)
             .field("data", &self.data)
             .field("unpack_xattrs", &self.unpack_xattrs)
+            .field("unpack_file_flags", &self.unpack_file_flags)
             .field("preserve_permissions", &self.preserve_permissions)
+            .field("mask", &self.mask)
             .field("preserve_mtime", &self.preserve_mtime)
+            .field("preserve_atime", &self.preserve_atime)
+            .field("preallocate", &self.preallocate)
+            .field("direct_io", &self.direct_io)
+            .field("max_symlink_depth", &self.max_symlink_depth)
+            .field("mangle_names", &self.mangle_names)
+            .field("metadata_order", &self.metadata_order)
+            .field("unsupported_metadata", &self.unsupported_metadata)
+            .field("metadata_warnings", &self.metadata_warnings)
+            .field("clamp_future_mtime", &self.clamp_future_mtime)
+            .field("star_compat", &self.star_compat)
+            .field("skip_unchanged", &self.skip_unchanged)
+            .field("unknown_entry_types", &self.unknown_entry_types)
+            .field("unknown_entry_callback", &self.unknown_entry_callback);
+        #[cfg(feature = "encoding_rs")]
+        d.field("name_encoding", &self.name_encoding);
+        d.field("clock", &self.clock)
+            .field("mangled_path", &self.mangled_path)
+            .field("skipped", &self.skipped)
+            .field("digest", &self.digest)
+            .field("progress", &self.progress)
             .field("read_state", &self.read_state)
+            .field("buffered", &(self.buffered_len - self.buffered_pos))
+            .field("position", &self.position)
+            .field("layout", &self.layout.as_ref().map(|l| &l.segments))
             .finish()
     }
 }
@@ -97,6 +198,324 @@ impl<R: AsyncRead + Unpin> fmt::Debug for EntryIo<R> {
     }
 }
 
+/// A pool of buffers registered with the kernel, used on the write path of
+/// `Archive::unpack` to avoid mapping a fresh buffer for every write.
+pub(crate) struct FixedBuffers {
+    pool: FixedBufPool<Vec<u8>>,
+    size: usize,
+}
+
+impl FixedBuffers {
+    /// Registers `count` buffers of `size` bytes each.
+    ///
+    /// Returns `None` if the kernel refuses the registration, for example
+    /// because it would exceed `RLIMIT_MEMLOCK`, in which case callers should
+    /// fall back to regular buffers.
+    pub(crate) fn register(count: usize, size: usize) -> Option<FixedBuffers> {
+        if count == 0 || size == 0 {
+            return None;
+        }
+        let pool = FixedBufPool::new((0..count).map(|_| vec![0; size]));
+        pool.register().ok()?;
+        Some(FixedBuffers { pool, size })
+    }
+}
+
+/// Alignment of buffers, file offsets and lengths for `O_DIRECT` writes.
+///
+/// This covers the logical block size of virtually all block devices.
+const DIRECT_ALIGN: usize = 4096;
+
+/// Size of the buffer used for `O_DIRECT` writes, a multiple of
+/// `DIRECT_ALIGN`.
+const DIRECT_BUF_SIZE: usize = 1024 * 1024;
+
+/// An entry whose contents are held in memory, see `Entry::into_owned`.
+pub type OwnedEntry = Entry<std::io::Cursor<Bytes>>;
+
+/// Largest chunk yielded by `EntryBytesStream`.
+const BYTES_STREAM_CHUNK: usize = 64 * 1024;
+
+/// Size of the buffer filled by `AsyncBufRead::poll_fill_buf` on entries.
+const BUF_READ_CAPACITY: usize = 8 * 1024;
+
+/// Buffer used to copy entry data into a destination file.
+enum WriteBuf<'a> {
+    Heap(Option<BytesMut>),
+    Fixed(&'a FixedBuffers),
+    /// A second handle to the destination opened with `O_DIRECT`, used for
+    /// all aligned blocks. Cleared if the kernel rejects a direct write.
+    Direct(Option<fs::File>, Option<Vec<u8>>),
+}
+
+impl<'a> WriteBuf<'a> {
+    fn new(buffers: Option<&'a FixedBuffers>) -> WriteBuf<'a> {
+        match buffers {
+            Some(buffers) => WriteBuf::Fixed(buffers),
+            None => WriteBuf::Heap(None),
+        }
+    }
+
+    /// Copies all of `data` into `f` starting at `offset`, returning the
+    /// number of bytes written.
+    async fn write_all_from<R: AsyncRead + Unpin>(
+        &mut self,
+        f: &fs::File,
+        data: &mut io::Take<R>,
+        offset: u64,
+        digest: &mut DigestState,
+        progress: Option<&AtomicU64>,
+    ) -> io::Result<u64> {
+        if let WriteBuf::Direct(direct, slot) = self {
+            if direct.is_some() && offset & (DIRECT_ALIGN as u64 - 1) == 0 {
+                return write_direct(f, direct, slot, data, offset, digest, progress).await;
+            }
+        }
+        let expected = data.limit();
+        let mut bytes_written = 0;
+        while bytes_written < expected {
+            let pos = offset + bytes_written;
+            let bytes_read = match self {
+                WriteBuf::Heap(slot) => {
+                    let mut read_buf = slot
+                        .take()
+                        .unwrap_or_else(|| BytesMut::zeroed(1 * 1024 * 1024));
+                    let bytes_read = data.read(&mut read_buf).await?;
+                    if bytes_read == 0 {
+                        return Err(other("expected more bytes from stream"));
+                    }
+                    digest.update(&read_buf[..bytes_read]);
+                    let remaining = read_buf.split_off(bytes_read);
+                    let (res, mut buf) = f.write_all_at(read_buf, pos).await;
+                    res?;
+                    buf.unsplit(remaining);
+                    *slot = Some(buf);
+                    bytes_read
+                }
+                WriteBuf::Fixed(buffers) => {
+                    let mut buf = buffers.pool.next(buffers.size).await;
+                    let bytes_read = data.read(&mut buf[..]).await?;
+                    if bytes_read == 0 {
+                        return Err(other("expected more bytes from stream"));
+                    }
+                    digest.update(&buf[..bytes_read]);
+                    let (res, _) = f.write_fixed_all_at(buf.slice(..bytes_read), pos).await;
+                    res?;
+                    bytes_read
+                }
+                // The data does not start on a block boundary, e.g. after a
+                // hole in a sparse file, so go through the page cache instead.
+                WriteBuf::Direct(_, slot) => {
+                    let mut read_buf = slot
+                        .take()
+                        .unwrap_or_else(|| vec![0; DIRECT_BUF_SIZE + DIRECT_ALIGN]);
+                    let bytes_read = data.read(&mut read_buf).await?;
+                    if bytes_read == 0 {
+                        return Err(other("expected more bytes from stream"));
+                    }
+                    digest.update(&read_buf[..bytes_read]);
+                    let (res, buf) = f.write_all_at(read_buf.slice(..bytes_read), pos).await;
+                    res?;
+                    *slot = Some(buf.into_inner());
+                    bytes_read
+                }
+            };
+            bytes_written += bytes_read as u64;
+            if let Some(progress) = progress {
+                progress.fetch_add(bytes_read as u64, Ordering::Relaxed);
+            }
+        }
+        Ok(bytes_written)
+    }
+}
+
+/// Copies all of `data` into the file at `offset`, which must be aligned to
+/// `DIRECT_ALIGN`, writing whole blocks through the `O_DIRECT` handle.
+///
+/// A trailing partial block is written through the regular handle `f`. If the
+/// kernel rejects a direct write, for example because the device needs a
+/// larger alignment, the direct handle is dropped and the rest of the data is
+/// written through `f` as well.
+async fn write_direct<R: AsyncRead + Unpin>(
+    f: &fs::File,
+    direct: &mut Option<fs::File>,
+    slot: &mut Option<Vec<u8>>,
+    data: &mut io::Take<R>,
+    offset: u64,
+    digest: &mut DigestState,
+    progress: Option<&AtomicU64>,
+) -> io::Result<u64> {
+    let expected = data.limit();
+    let mut buf = slot
+        .take()
+        .unwrap_or_else(|| vec![0; DIRECT_BUF_SIZE + DIRECT_ALIGN]);
+    // The heap allocation of a `Vec` does not move with it, so an aligned
+    // window into it stays aligned while the buffer is handed to the kernel.
+    let start = buf.as_ptr().align_offset(DIRECT_ALIGN);
+    let mut bytes_written = 0;
+    while bytes_written < expected {
+        let want = (expected - bytes_written).min(DIRECT_BUF_SIZE as u64) as usize;
+        let mut filled = 0;
+        while filled < want {
+            let bytes_read = data.read(&mut buf[start + filled..start + want]).await?;
+            if bytes_read == 0 {
+                return Err(other("expected more bytes from stream"));
+            }
+            filled += bytes_read;
+        }
+        digest.update(&buf[start..start + filled]);
+
+        let pos = offset + bytes_written;
+        let mut aligned = filled - filled % DIRECT_ALIGN;
+        if let (Some(file), true) = (direct.as_ref(), aligned > 0) {
+            let (res, slice) = file
+                .write_all_at(buf.slice(start..start + aligned), pos)
+                .await;
+            buf = slice.into_inner();
+            match res {
+                Ok(()) => {}
+                Err(e) if e.raw_os_error() == Some(libc::EINVAL) => {
+                    *direct = None;
+                    aligned = 0;
+                }
+                Err(e) => return Err(e),
+            }
+        } else {
+            aligned = 0;
+        }
+        if aligned < filled {
+            let (res, slice) = f
+                .write_all_at(
+                    buf.slice(start + aligned..start + filled),
+                    pos + aligned as u64,
+                )
+                .await;
+            buf = slice.into_inner();
+            res?;
+        }
+        bytes_written += filled as u64;
+        if let Some(progress) = progress {
+            progress.fetch_add(filled as u64, Ordering::Relaxed);
+        }
+    }
+    *slot = Some(buf);
+    Ok(bytes_written)
+}
+
+/// Returns whether `err`, or an error it wraps, indicates that the
+/// filesystem does not support an operation or does not allow it for the
+/// current user.
+fn is_unsupported(err: &io::Error) -> bool {
+    let mut err: &(dyn std::error::Error + 'static) = err;
+    loop {
+        if let Some(io) = err.downcast_ref::<io::Error>() {
+            if let Some(libc::EOPNOTSUPP) | Some(libc::EPERM) = io.raw_os_error() {
+                return true;
+            }
+        }
+        match err.source() {
+            Some(source) => err = source,
+            None => return false,
+        }
+    }
+}
+
+/// Escapes a single path component for `ArchiveBuilder::set_mangle_names`,
+/// returning it unchanged if nothing needed escaping.
+fn mangle_name(name: &OsStr) -> Cow<'_, OsStr> {
+    let bytes = name.as_bytes();
+    if !bytes.iter().any(|b| *b == 0 || *b == b'%') {
+        return Cow::Borrowed(name);
+    }
+    let mut mangled = Vec::with_capacity(bytes.len() + 4);
+    for &b in bytes {
+        match b {
+            0 => mangled.extend_from_slice(b"%00"),
+            b'%' => mangled.extend_from_slice(b"%25"),
+            b => mangled.push(b),
+        }
+    }
+    Cow::Owned(OsString::from_vec(mangled))
+}
+
+/// Escapes every normal component of a link target with `mangle_name`.
+fn mangle_path(path: &Path) -> PathBuf {
+    path.components()
+        .map(|part| match part {
+            Component::Normal(part) => mangle_name(part),
+            part => Cow::Borrowed(part.as_os_str()),
+        })
+        .collect()
+}
+
+/// What to do when the destination filesystem rejects metadata while
+/// unpacking.
+///
+/// Filesystems like FAT, exFAT or many network mounts cannot store
+/// permissions, timestamps or extended attributes, and fail with `EOPNOTSUPP`
+/// or `EPERM` when asked to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnsupportedMetadata {
+    /// Fail the extraction.
+    ///
+    /// This is the default.
+    #[default]
+    Fail,
+    /// Keep going and record the error as a warning, available from
+    /// `Entry::metadata_warnings` and `UnpackReport::warnings`. Other
+    /// errors still fail the extraction.
+    Warn,
+}
+
+/// What happens when unpacking an entry whose type is not known to this
+/// crate, such as the ACL entries of Solaris tar.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnknownEntryTypes {
+    /// Unpack the entry as a regular file, as POSIX asks for.
+    ///
+    /// This is the default.
+    #[default]
+    AsRegular,
+    /// Leave the entry out.
+    Skip,
+    /// Fail the extraction.
+    Error,
+}
+
+/// Function called for entries of unknown types, see
+/// `ArchiveBuilder::set_unknown_entry_callback`.
+#[derive(Clone)]
+pub(crate) struct UnknownEntryCallback(pub(crate) Arc<UnknownEntryFn>);
+
+type UnknownEntryFn = dyn Fn(&Path, EntryType) + Send + Sync;
+
+impl fmt::Debug for UnknownEntryCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("UnknownEntryCallback")
+    }
+}
+
+/// The order in which the metadata of an unpacked file is applied.
+///
+/// Applying a restrictive mode such as `0400` too early can make later steps
+/// fail with `EPERM` or `EACCES`, since setting extended attributes requires
+/// write access to the file. Timestamps come last so that nothing applied
+/// after them can disturb them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MetadataOrder {
+    /// Extended attributes, then the mode, then timestamps.
+    ///
+    /// This is the default.
+    #[default]
+    XattrsFirst,
+    /// Timestamps, then the mode, then extended attributes, which is the
+    /// order used by earlier releases.
+    ModeFirst,
+}
+
 /// When unpacking items the unpacked thing is returned to allow custom
 /// additional handling by users. Today the File is returned, in future
 /// the enum may be extended with kinds for links, directories etc.
@@ -107,6 +526,12 @@ pub enum Unpacked {
     File(fs::File),
     /// A directory, hardlink, symlink, or other node was unpacked.
     Other,
+    /// The file already existed unchanged and was not written, see
+    /// `ArchiveBuilder::set_skip_unchanged`.
+    Unchanged,
+    /// The entry has an unknown type and was not unpacked, see
+    /// `UnknownEntryTypes::Skip`.
+    Skipped,
 }
 
 impl<R: AsyncRead + Unpin> Entry<R> {
@@ -122,6 +547,12 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     ///
     /// It is recommended to use this method instead of inspecting the `header`
     /// directly to ensure that various archive formats are handled correctly.
+    ///
+    /// Names taken from the header or a GNU long name entry are decoded with
+    /// the encoding set by `ArchiveBuilder::set_name_encoding`, if any. Names
+    /// taken from pax records have to be UTF-8, unless the archive marks them
+    /// as raw bytes with `hdrcharset=BINARY`, in which case they are decoded
+    /// like names from the header.
     pub fn path(&self) -> io::Result<Cow<Path>> {
         self.fields.path()
     }
@@ -132,6 +563,11 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     /// separators, and it will not always return the same value as
     /// `self.header().path_bytes()` as some archive formats have support for
     /// longer path names described in separate entries.
+    ///
+    /// The same `ArchiveBuilder::set_name_encoding` decoding as for `path`
+    /// applies to names from the header or a GNU long name entry, while pax
+    /// names are returned as stored whatever their `hdrcharset`. Use
+    /// `self.header().path_bytes()` for the undecoded header field.
     pub fn path_bytes(&self) -> Cow<[u8]> {
         self.fields.path_bytes()
     }
@@ -149,6 +585,8 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     ///
     /// It is recommended to use this method instead of inspecting the `header`
     /// directly to ensure that various archive formats are handled correctly.
+    ///
+    /// Link names are decoded and validated like names in `path`.
     pub fn link_name(&self) -> io::Result<Option<Cow<Path>>> {
         self.fields.link_name()
     }
@@ -158,6 +596,10 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     /// Note that this will not always return the same value as
     /// `self.header().link_name_bytes()` as some archive formats have support for
     /// longer path names described in separate entries.
+    ///
+    /// Like `path_bytes`, link names from the header or a GNU long link entry
+    /// are decoded with `ArchiveBuilder::set_name_encoding`, and pax link
+    /// names are returned as stored.
     pub fn link_name_bytes(&self) -> Option<Cow<[u8]>> {
         self.fields.link_name_bytes()
     }
@@ -184,6 +626,45 @@ impl<R: AsyncRead + Unpin> Entry<R> {
         self.fields.pax_extensions().await
     }
 
+    /// Returns the directory listing of a GNU dumpdir entry, written by
+    /// incremental backups, or `None` for other entries.
+    ///
+    /// The listing is the data of the entry, which is read by this function.
+    pub async fn incremental_dir(&mut self) -> io::Result<Option<IncrementalDir>> {
+        if !self.header().entry_type().is_gnu_dumpdir() {
+            return Ok(None);
+        }
+        match self.dumpdir_entries().await? {
+            Some(records) => IncrementalDir::from_records(&records).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the records listing the contents of a GNU dumpdir entry, or
+    /// `None` for other entries.
+    ///
+    /// The listing is the data of the entry, which is read by this function.
+    /// See `incremental_dir` for a summary of the records.
+    pub async fn dumpdir_entries(&mut self) -> io::Result<Option<Vec<DumpDirEntry>>> {
+        if !self.header().entry_type().is_gnu_dumpdir() {
+            return Ok(None);
+        }
+        let data = self.fields.read_all().await?;
+        parse_dumpdir(&data).map(Some)
+    }
+
+    /// Returns an iterator over the pax records describing this entry, such
+    /// as `path`, `linkpath` or vendor specific keys, including the global
+    /// records in effect.
+    ///
+    /// Unlike `pax_extensions` this never reads from the archive, so the
+    /// contents of the entry are left untouched and can still be read or
+    /// unpacked afterwards. For raw entries which are themselves a list of
+    /// extensions, `None` is returned until `pax_extensions` has read them.
+    pub fn pax_headers(&self) -> Option<PaxExtensions<'_>> {
+        self.fields.pax_extensions.as_deref().map(pax_extensions)
+    }
+
     /// Returns access to the header of this entry in the archive.
     ///
     /// This provides access to the metadata for this entry in the archive.
@@ -191,6 +672,81 @@ impl<R: AsyncRead + Unpin> Entry<R> {
         &self.fields.header
     }
 
+    /// Returns mutable access to the header of this entry, for rewriting
+    /// metadata such as the owner, mode or modification time before the
+    /// entry is unpacked or appended to another archive.
+    ///
+    /// The contents of the entry were located when its header was read, so
+    /// changing the size or type here does not change what is read. Paths
+    /// and link names recorded in GNU or pax records take precedence over
+    /// those in the header, see `path` and `link_name`.
+    pub fn header_mut(&mut self) -> &mut Header {
+        &mut self.fields.header
+    }
+
+    /// Returns the metadata of this entry, combining the header with the GNU
+    /// and pax records which describe the entry.
+    ///
+    /// Like `pax_headers`, this never reads from the archive. Fields which
+    /// are corrupted in the header and not overridden by a pax record result
+    /// in an error.
+    pub fn metadata(&self) -> io::Result<EntryMetadata> {
+        EntryMetadata::new(self)
+    }
+
+    /// Returns the size of the file this entry describes.
+    ///
+    /// For sparse files this is the size after the holes have been filled in,
+    /// taken from the PAX `GNU.sparse.realsize` record or the GNU header,
+    /// and is what unpacking the entry produces on disk. For all other entries
+    /// it is the same as `stored_data_size`.
+    pub fn logical_size(&self) -> io::Result<u64> {
+        self.fields.logical_size()
+    }
+
+    /// Returns the last access time of the file in Unix time format, if the
+    /// header records one.
+    ///
+    /// This is taken from the `atime` field of GNU headers, or from headers
+    /// written by `star` if `ArchiveBuilder::set_star_compat` is enabled. A
+    /// zero field, which GNU tar writes unless asked to keep access times,
+    /// counts as not recorded.
+    pub fn atime(&self) -> io::Result<Option<u64>> {
+        self.fields.atime()
+    }
+
+    /// Returns the last status change time of the file in Unix time format,
+    /// if the header records one.
+    ///
+    /// This comes from the same headers as `atime`. It is only informational,
+    /// as the change time of a file cannot be set.
+    pub fn ctime(&self) -> io::Result<Option<u64>> {
+        self.fields.ctime()
+    }
+
+    /// Returns the number of data bytes this entry occupies in the archive,
+    /// excluding padding.
+    ///
+    /// For sparse files this only counts the stored data regions.
+    pub fn stored_data_size(&self) -> io::Result<u64> {
+        self.fields.header.entry_size()
+    }
+
+    /// Returns the digest of the contents of this entry, computed with the
+    /// algorithm configured by `ArchiveBuilder::set_digest`.
+    ///
+    /// This is only available once all of the contents have been read, or
+    /// the entry has been unpacked.
+    pub fn digest(&self) -> Option<[u8; 32]> {
+        self.fields.digest.digest()
+    }
+
+    /// Returns the number of damaged bytes which were skipped right before
+    /// the header of this entry, see `ArchiveBuilder::set_resync_headers`.
+    pub fn skipped_bytes(&self) -> u64 {
+        self.fields.skipped
+    }
+
     /// Returns the starting position, in bytes, of the header of this entry in
     /// the archive.
     ///
@@ -211,6 +767,27 @@ impl<R: AsyncRead + Unpin> Entry<R> {
         self.fields.file_pos
     }
 
+    /// Returns the range of bytes this entry occupies in the archive.
+    ///
+    /// The range starts at the first GNU long name, long link or PAX header
+    /// describing this entry, or at its own header if there are none, and
+    /// ends after the padding of its data, where the next header starts.
+    /// Copying this range verbatim into another archive reproduces the entry
+    /// exactly, except for global PAX extensions applying to it.
+    pub fn raw_span(&self) -> Range<u64> {
+        self.fields.raw_span.clone()
+    }
+
+    /// Returns the number of bytes the data of this entry occupies in the
+    /// archive, including the padding to the next 512-byte block.
+    ///
+    /// Together with `raw_header_position` and `raw_file_position` this is
+    /// enough to locate the entry in an external index, for example to
+    /// serve it with HTTP range requests.
+    pub fn raw_padded_size(&self) -> u64 {
+        self.fields.raw_span.end - self.fields.file_pos
+    }
+
     /// Writes this file to the specified location.
     ///
     /// This function will write the entire contents of this file into the
@@ -230,13 +807,12 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     /// # Examples
     ///
     /// ```no_run
-    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { async_std::task::block_on(async {
+    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { tokio_uring::start(async {
     /// #
-    /// use async_std::fs::File;
-    /// use async_std::prelude::*;
     /// use async_tar::Archive;
+    /// use futures_util::StreamExt;
     ///
-    /// let mut ar = Archive::new(File::open("foo.tar").await?);
+    /// let ar = Archive::new(tokio::fs::File::open("foo.tar").await?);
     /// let mut entries = ar.entries()?;
     /// let mut i = 0;
     /// while let Some(file) = entries.next().await {
@@ -248,7 +824,7 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     /// # Ok(()) }) }
     /// ```
     pub async fn unpack<P: AsRef<Path>>(&mut self, dst: P) -> io::Result<Unpacked> {
-        self.fields.unpack(None, dst.as_ref()).await
+        self.fields.unpack(None, dst.as_ref(), None).await
     }
 
     /// Extracts this file under the specified path, avoiding security issues.
@@ -260,18 +836,21 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     /// `dst` will be overwritten.
     ///
     /// This function carefully avoids writing outside of `dst`. If the file has
-    /// a '..' in its path, this function will skip it and return false.
+    /// a '..' in its path, this function will skip it and return `None`, as
+    /// it does for entries of unknown types with `UnknownEntryTypes::Skip`.
+    /// Otherwise the path the file was written to is returned, after leading
+    /// `/`s were removed and names were escaped, see
+    /// `ArchiveBuilder::set_mangle_names`.
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { async_std::task::block_on(async {
+    /// # fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> { tokio_uring::start(async {
     /// #
-    /// use async_std::fs::File;
     /// use async_tar::Archive;
-    /// use async_std::prelude::*;
+    /// use futures_util::StreamExt;
     ///
-    /// let mut ar = Archive::new(File::open("foo.tar").await?);
+    /// let ar = Archive::new(tokio::fs::File::open("foo.tar").await?);
     /// let mut entries = ar.entries()?;
     /// let mut i = 0;
     /// while let Some(file) = entries.next().await {
@@ -282,8 +861,27 @@ impl<R: AsyncRead + Unpin> Entry<R> {
     /// #
     /// # Ok(()) }) }
     /// ```
-    pub async fn unpack_in<P: AsRef<Path>>(&mut self, dst: P) -> io::Result<bool> {
-        self.fields.unpack_in(dst.as_ref()).await
+    pub async fn unpack_in<P: AsRef<Path>>(&mut self, dst: P) -> io::Result<Option<PathBuf>> {
+        self.fields.unpack_in(dst.as_ref(), None).await
+    }
+
+    /// Counts the bytes written by the following unpack of this entry in the
+    /// returned counter.
+    pub(crate) fn track_progress(&mut self) -> Arc<AtomicU64> {
+        self.fields
+            .progress
+            .get_or_insert_with(|| Arc::new(AtomicU64::new(0)))
+            .clone()
+    }
+
+    /// Same as `unpack_in`, but writes file data through the given pool of
+    /// registered buffers.
+    pub(crate) async fn unpack_in_with(
+        &mut self,
+        dst: &Path,
+        buffers: Option<&FixedBuffers>,
+    ) -> io::Result<Option<PathBuf>> {
+        self.fields.unpack_in(dst, buffers).await
     }
 
     /// Indicate whether extended file attributes (xattrs on Unix) are preserved
@@ -297,6 +895,23 @@ impl<R: AsyncRead + Unpin> Entry<R> {
         self.fields.unpack_xattrs = unpack_xattrs;
     }
 
+    /// Indicate whether BSD file flags, such as `uchg` or `nodump`, are
+    /// restored from the `SCHILY.fflags` pax record when unpacking this entry.
+    ///
+    /// The flags are mapped to inode flags, which are set with the
+    /// `FS_IOC_SETFLAGS` ioctl once everything else about a regular file is in
+    /// place: `uchg` and `schg` to `FS_IMMUTABLE_FL`, `uappnd` and `sappnd` to
+    /// `FS_APPEND_FL` and `nodump` to `FS_NODUMP_FL`. Their `no` forms, and
+    /// `dump`, clear the flag again, and other names are ignored. The first
+    /// two need `CAP_LINUX_IMMUTABLE`, so without it unpacking fails with
+    /// `EPERM`. Filesystems without inode flags fail with `EOPNOTSUPP`.
+    /// `UnsupportedMetadata::Warn` turns either into a warning.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_unpack_file_flags(&mut self, unpack: bool) {
+        self.fields.unpack_file_flags = unpack;
+    }
+
     /// Indicate whether extended permissions (like suid on Unix) are preserved
     /// when unpacking this entry.
     ///
@@ -306,13 +921,299 @@ impl<R: AsyncRead + Unpin> Entry<R> {
         self.fields.preserve_permissions = preserve;
     }
 
-    /// Indicate whether access time information is preserved when unpacking
+    /// Set the mask of the permission bits when unpacking this entry.
+    ///
+    /// Like a umask, the bits set in the mask are cleared from the mode of
+    /// the unpacked file or directory, so a mask of `0o022` removes write
+    /// access for the group and others. The mask applies after
+    /// `set_preserve_permissions` and is 0 by default.
+    pub fn set_mask(&mut self, mask: u32) {
+        self.fields.mask = mask;
+    }
+
+    /// Indicate whether modification time information is preserved when unpacking
     /// this entry.
     ///
     /// This flag is enabled by default.
     pub fn set_preserve_mtime(&mut self, preserve: bool) {
         self.fields.preserve_mtime = preserve;
     }
+
+    /// Indicate whether the access time recorded in the header is restored
+    /// along with the modification time when unpacking this entry, see
+    /// `atime`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_preserve_atime(&mut self, preserve: bool) {
+        self.fields.preserve_atime = preserve;
+    }
+
+    /// Indicate whether space for this file is allocated up front, using the
+    /// size recorded in the archive, before its data is written.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_preallocate(&mut self, preallocate: bool) {
+        self.fields.preallocate = preallocate;
+    }
+
+    /// Indicate whether the data of this file is written with `O_DIRECT`,
+    /// bypassing the page cache.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_direct_io(&mut self, direct_io: bool) {
+        self.fields.direct_io = direct_io;
+    }
+
+    /// Limit the number of symbolic links followed while checking that this
+    /// entry is unpacked inside the destination directory.
+    ///
+    /// By default only the operating system's limit applies.
+    pub fn set_max_symlink_depth(&mut self, depth: u32) {
+        self.fields.max_symlink_depth = Some(depth);
+    }
+
+    /// Indicate whether names which cannot be created on the destination
+    /// filesystem are escaped when unpacking this entry, see
+    /// `ArchiveBuilder::set_mangle_names`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_mangle_names(&mut self, mangle: bool) {
+        self.fields.mangle_names = mangle;
+    }
+
+    /// Set the order in which metadata is applied to this entry when it is
+    /// unpacked, see `MetadataOrder`.
+    pub fn set_metadata_order(&mut self, order: MetadataOrder) {
+        self.fields.metadata_order = order;
+    }
+
+    /// Indicate whether a modification time lying in the future is clamped to
+    /// the current time when unpacking this entry, see
+    /// `ArchiveBuilder::set_clamp_future_mtime`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_clamp_future_mtime(&mut self, clamp: bool) {
+        self.fields.clamp_future_mtime = clamp;
+    }
+
+    /// Indicate whether this entry's header is checked for the `star`
+    /// dialects, see `ArchiveBuilder::set_star_compat`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_star_compat(&mut self, star: bool) {
+        self.fields.star_compat = star;
+    }
+
+    /// Indicate whether this entry is left alone when unpacking if the
+    /// destination already holds a file with the same size and modification
+    /// time, see `ArchiveBuilder::set_skip_unchanged`.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_skip_unchanged(&mut self, skip: bool) {
+        self.fields.skip_unchanged = skip;
+    }
+
+    /// Set what happens when unpacking this entry if its type is not known
+    /// to this crate, see `UnknownEntryTypes`.
+    pub fn set_unknown_entry_types(&mut self, policy: UnknownEntryTypes) {
+        self.fields.unknown_entry_types = policy;
+    }
+
+    /// Set what happens when the filesystem rejects metadata while this entry
+    /// is unpacked, see `UnsupportedMetadata`.
+    pub fn set_unsupported_metadata(&mut self, policy: UnsupportedMetadata) {
+        self.fields.unsupported_metadata = policy;
+    }
+
+    /// Returns the metadata errors which were ignored while unpacking this
+    /// entry because of `UnsupportedMetadata::Warn`.
+    pub fn metadata_warnings(&self) -> &[io::Error] {
+        &self.fields.metadata_warnings
+    }
+
+    pub(crate) fn take_metadata_warnings(&mut self) -> Vec<io::Error> {
+        std::mem::take(&mut self.fields.metadata_warnings)
+    }
+
+    /// Records where the contents of this entry are stored in the archive,
+    /// see `EntryFields::record_layout`.
+    pub(crate) fn record_layout(&mut self, reader: R, start: u64) {
+        self.fields.record_layout(reader, start)
+    }
+
+    /// Returns the stretches of data and holes making up the contents of this
+    /// entry, or nothing if its layout was not recorded.
+    pub(crate) fn segments(&self) -> &[Segment] {
+        match self.fields.layout {
+            Some(ref layout) => &layout.segments,
+            None => &[],
+        }
+    }
+
+    /// Returns the path, relative to the destination, which this entry was
+    /// unpacked to if escaping changed it from the path in the archive.
+    pub fn mangled_path(&self) -> Option<&Path> {
+        self.fields.mangled_path.as_deref()
+    }
+
+    /// Returns whether this entry is an OCI whiteout, which marks a file of a
+    /// lower layer of a container image as deleted.
+    ///
+    /// Whiteouts are named `.wh.` followed by the name of the deleted file,
+    /// or `.wh..wh..opq` for opaque whiteouts, see `is_opaque_whiteout`.
+    /// Other names starting with `.wh..wh.` are reserved and not treated as
+    /// whiteouts.
+    pub fn is_whiteout(&self) -> bool {
+        self.whiteout().is_some()
+    }
+
+    /// Returns whether this entry is an OCI opaque whiteout, which hides all
+    /// contents of its directory in lower layers.
+    pub fn is_opaque_whiteout(&self) -> bool {
+        matches!(self.whiteout(), Some((_, None)))
+    }
+
+    /// Returns the path hidden by this whiteout, or `None` if this entry is
+    /// not a whiteout.
+    ///
+    /// For opaque whiteouts this is the directory containing the entry.
+    pub fn whiteout_target(&self) -> Option<PathBuf> {
+        self.whiteout().map(|(parent, name)| match name {
+            Some(name) => parent.join(name),
+            None => parent,
+        })
+    }
+
+    /// Splits a whiteout into its directory and the name of the hidden file,
+    /// which is `None` for opaque whiteouts.
+    fn whiteout(&self) -> Option<(PathBuf, Option<OsString>)> {
+        let path = self.path().ok()?;
+        let name = path.file_name()?.as_bytes().strip_prefix(b".wh.")?;
+        let hidden = match name.strip_prefix(b".wh.") {
+            Some(b".opq") => None,
+            Some(_) => return None,
+            None => Some(OsStr::from_bytes(name).to_os_string()),
+        };
+        let parent = path.parent().unwrap_or_else(|| Path::new(""));
+        Some((parent.to_path_buf(), hidden))
+    }
+
+    /// Reads all remaining contents of this entry into a vector, failing if
+    /// they are larger than `limit` bytes.
+    ///
+    /// The size recorded in the archive is checked before anything is read,
+    /// so a huge entry is rejected without buffering any of it.
+    pub async fn read_to_vec(&mut self, limit: u64) -> io::Result<Vec<u8>> {
+        let size = self.logical_size()?;
+        let too_large = |size: u64| {
+            other(&format!(
+                "entry of {} bytes is larger than the limit of {} bytes",
+                size, limit
+            ))
+        };
+        if size > limit {
+            return Err(too_large(size));
+        }
+        let mut buf = Vec::with_capacity(size as usize);
+        AsyncReadExt::take(&mut *self, limit.saturating_add(1))
+            .read_to_end(&mut buf)
+            .await?;
+        if buf.len() as u64 > limit {
+            return Err(too_large(buf.len() as u64));
+        }
+        Ok(buf)
+    }
+
+    /// Reads all remaining contents of this entry into a string, failing if
+    /// they are larger than `limit` bytes or not valid UTF-8.
+    ///
+    /// This is named so as not to clash with `AsyncReadExt::read_to_string`,
+    /// which has no limit.
+    pub async fn read_to_string_limited(&mut self, limit: u64) -> io::Result<String> {
+        let buf = self.read_to_vec(limit).await?;
+        String::from_utf8(buf).map_err(|_| other("entry contents were not valid utf-8"))
+    }
+
+    /// Writes the contents of this entry to `writer` and flushes it.
+    ///
+    /// This streams the entry into a socket, compressor or hashing writer
+    /// without extracting it to disk first. Sparse files are written with
+    /// their holes filled with zeros, just like when reading the entry.
+    pub async fn copy_to<W>(&mut self, writer: &mut W) -> io::Result<()>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        self.copy_to_counted(writer).await.map(|_| ())
+    }
+
+    /// Same as `copy_to`, but returns the number of bytes written.
+    pub async fn copy_to_counted<W>(&mut self, writer: &mut W) -> io::Result<u64>
+    where
+        W: AsyncWrite + Unpin + ?Sized,
+    {
+        io::copy(self, writer).await
+    }
+
+    /// Writes the contents of this entry to a new file at `path`, returning
+    /// the number of bytes written. Any existing file at `path` is truncated.
+    ///
+    /// Unlike `unpack_in`, `path` is used as given rather than joined with
+    /// the path of the entry and checked against a destination, so this is
+    /// meant for extracting known members of trusted archives. Missing parent
+    /// directories are created if `create_parents` is set. Only the contents
+    /// are written, whatever the type of the entry, and no metadata is
+    /// restored.
+    pub async fn save_to<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        create_parents: bool,
+    ) -> io::Result<u64> {
+        let path = path.as_ref();
+        if create_parents {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+        }
+        let mut file = tokio::fs::File::create(path)
+            .await
+            .map_err(|e| TarError::new(&format!("failed to create `{}`", path.display()), e))?;
+        self.copy_to_counted(&mut file).await
+    }
+
+    /// Consumes this entry, returning all of its remaining contents as
+    /// `Bytes`.
+    ///
+    /// The result can be cloned and shared cheaply, which suits small
+    /// payloads like configuration files. See `read_to_vec` to guard against
+    /// huge entries.
+    pub async fn into_bytes(mut self) -> io::Result<Bytes> {
+        self.fields.read_all().await.map(Bytes::from)
+    }
+
+    /// Reads the remaining contents of this entry into memory, returning an
+    /// entry which no longer borrows from the archive.
+    ///
+    /// Entries read from an `Entries` stream share the reader of the archive,
+    /// so their contents are corrupted once the stream has moved on. The
+    /// returned entry keeps its header, PAX records and long names, and can
+    /// be read, unpacked or sent to another task at any time. Sparse files
+    /// have their holes filled with zeros.
+    pub async fn into_owned(mut self) -> io::Result<OwnedEntry> {
+        let data = self.fields.read_all().await?;
+        Ok(self.fields.with_data(Bytes::from(data)).into_entry())
+    }
+
+    /// Converts this entry into a stream of its data in `Bytes` chunks.
+    ///
+    /// This is handy for forwarding the contents of an entry into HTTP
+    /// bodies, channels or uploads. Each chunk is at most 64KiB large.
+    pub fn into_bytes_stream(self) -> EntryBytesStream<R> {
+        EntryBytesStream {
+            entry: self,
+            buf: BytesMut::new(),
+            done: false,
+        }
+    }
 }
 
 impl<R: AsyncRead + Unpin> AsyncRead for Entry<R> {
@@ -326,11 +1227,279 @@ impl<R: AsyncRead + Unpin> AsyncRead for Entry<R> {
     }
 }
 
+impl<R: AsyncRead + Unpin> AsyncBufRead for Entry<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        self.project().fields.poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.project().fields.consume(amt)
+    }
+}
+
+/// Seeking is supported when the archive is read from a seekable reader. The
+/// position is relative to the start of the contents of the entry, and reads
+/// stop at its end. Holes of sparse files read as zeros wherever the seek
+/// lands. Seeking turns off the digest of the entry, see
+/// `ArchiveBuilder::set_digest`.
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncSeek for Entry<Archive<R>> {
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+        self.get_mut().fields.start_seek(position)
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        self.get_mut().fields.poll_seek_complete(cx)
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> EntryFields<Archive<R>> {
+    fn start_seek(&mut self, position: SeekFrom) -> io::Result<()> {
+        let layout = match self.layout.as_mut() {
+            Some(layout) => layout,
+            None => return Err(other("entry does not support seeking")),
+        };
+        if layout.seeking {
+            return Err(other("other seek in progress"));
+        }
+        let current = self.position - (self.buffered_len - self.buffered_pos) as u64;
+        let (base, offset) = match position {
+            SeekFrom::Start(offset) => (0, offset as i64),
+            SeekFrom::End(offset) => (self.size, offset),
+            SeekFrom::Current(offset) => (current, offset),
+        };
+        let target = if offset < 0 {
+            base.checked_sub(offset.unsigned_abs())
+        } else {
+            base.checked_add(offset as u64)
+        };
+        let target = match target {
+            Some(target) => target.min(self.size),
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "invalid seek to a negative or overflowing position",
+                ))
+            }
+        };
+
+        // Rebuild the remaining contents from the segment `target` is in.
+        let mut data = Vec::new();
+        let mut archive_pos = None;
+        let mut start = 0;
+        for segment in &layout.segments {
+            let end = start + segment.len;
+            if end > target {
+                let skip = target.saturating_sub(start);
+                let len = segment.len - skip;
+                match segment.pos {
+                    Some(pos) => {
+                        archive_pos.get_or_insert(pos + skip);
+                        data.push(EntryIo::Data(layout.reader.clone().take(len)));
+                    }
+                    None => data.push(EntryIo::Pad(io::repeat(0).take(len))),
+                }
+            }
+            start = end;
+        }
+        if let Some(pos) = archive_pos {
+            layout.reader.start_seek_to(pos)?;
+            layout.seeking = true;
+        }
+        self.data = data;
+        self.read_state = None;
+        self.buffered_pos = 0;
+        self.buffered_len = 0;
+        self.position = target;
+        self.digest = DigestState::new(None);
+        Ok(())
+    }
+
+    fn poll_seek_complete(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+        if let Some(layout) = self.layout.as_mut() {
+            if layout.seeking {
+                std::task::ready!(layout.reader.poll_seek_complete(cx))?;
+                layout.seeking = false;
+            }
+        }
+        let buffered = (self.buffered_len - self.buffered_pos) as u64;
+        Poll::Ready(Ok(self.position - buffered))
+    }
+}
+
+/// A stream of the data of an entry, created by `Entry::into_bytes_stream`.
+#[derive(Debug)]
+pub struct EntryBytesStream<R: AsyncRead + Unpin> {
+    entry: Entry<R>,
+    buf: BytesMut,
+    done: bool,
+}
+
+impl<R: AsyncRead + Unpin> EntryBytesStream<R> {
+    /// Returns the entry whose data is streamed.
+    pub fn entry(&self) -> &Entry<R> {
+        &self.entry
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for EntryBytesStream<R> {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<io::Result<Bytes>>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        if this.buf.capacity() < BYTES_STREAM_CHUNK {
+            this.buf.reserve(BYTES_STREAM_CHUNK);
+        }
+        let spare = unsafe { this.buf.chunk_mut().as_uninit_slice_mut() };
+        let len = spare.len().min(BYTES_STREAM_CHUNK);
+        let mut read_buf = ReadBuf::uninit(&mut spare[..len]);
+        match Pin::new(&mut this.entry).poll_read(cx, &mut read_buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(e)) => {
+                this.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(Ok(())) => {
+                let n = read_buf.filled().len();
+                if n == 0 {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                // The read initialized the first `n` bytes of the spare
+                // capacity.
+                unsafe { this.buf.advance_mut(n) };
+                Poll::Ready(Some(Ok(this.buf.split().freeze())))
+            }
+        }
+    }
+}
+
 impl<R: AsyncRead + Unpin> EntryFields<R> {
     pub fn from(entry: Entry<R>) -> Self {
         entry.fields
     }
 
+    /// Replaces the data of this entry with `data`, which is already
+    /// consumed from the archive.
+    fn with_data(self, data: Bytes) -> EntryFields<std::io::Cursor<Bytes>> {
+        let EntryFields {
+            long_pathname,
+            long_linkname,
+            pax_extensions,
+            header,
+            size: _,
+            header_pos,
+            file_pos,
+            raw_span,
+            data: _,
+            unpack_xattrs,
+            unpack_file_flags,
+            preserve_permissions,
+            mask,
+            preserve_mtime,
+            preserve_atime,
+            preallocate,
+            direct_io,
+            max_symlink_depth,
+            mangle_names,
+            metadata_order,
+            unsupported_metadata,
+            metadata_warnings,
+            clamp_future_mtime,
+            star_compat,
+            skip_unchanged,
+            unknown_entry_types,
+            unknown_entry_callback,
+            #[cfg(feature = "encoding_rs")]
+            name_encoding,
+            clock,
+            mangled_path,
+            skipped,
+            digest,
+            progress,
+            read_state: _,
+            buffered: _,
+            buffered_pos: _,
+            buffered_len: _,
+            position: _,
+            layout: _,
+        } = self;
+        let size = data.len() as u64;
+        EntryFields {
+            long_pathname,
+            long_linkname,
+            pax_extensions,
+            header,
+            size,
+            header_pos,
+            file_pos,
+            raw_span,
+            data: vec![EntryIo::Data(std::io::Cursor::new(data).take(size))],
+            unpack_xattrs,
+            unpack_file_flags,
+            preserve_permissions,
+            mask,
+            preserve_mtime,
+            preserve_atime,
+            preallocate,
+            direct_io,
+            max_symlink_depth,
+            mangle_names,
+            metadata_order,
+            unsupported_metadata,
+            metadata_warnings,
+            clamp_future_mtime,
+            star_compat,
+            skip_unchanged,
+            unknown_entry_types,
+            unknown_entry_callback,
+            #[cfg(feature = "encoding_rs")]
+            name_encoding,
+            clock,
+            mangled_path,
+            skipped,
+            digest,
+            progress,
+            read_state: None,
+            buffered: Vec::new(),
+            buffered_pos: 0,
+            buffered_len: 0,
+            position: 0,
+            layout: None,
+        }
+    }
+
+    /// Records where the contents of this entry, which are about to be read
+    /// from `reader` at position `start`, are stored.
+    pub(crate) fn record_layout(&mut self, reader: R, start: u64) {
+        let mut pos = start;
+        let segments = self
+            .data
+            .iter()
+            .map(|io| match io {
+                EntryIo::Data(data) => {
+                    let segment = Segment {
+                        len: data.limit(),
+                        pos: Some(pos),
+                    };
+                    pos += data.limit();
+                    segment
+                }
+                EntryIo::Pad(pad) => Segment {
+                    len: pad.limit(),
+                    pos: None,
+                },
+            })
+            .collect();
+        self.layout = Some(Layout {
+            reader,
+            segments,
+            seeking: false,
+        });
+    }
+
     pub fn into_entry(self) -> Entry<R> {
         Entry {
             fields: self,
@@ -361,27 +1530,91 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
     }
 
     fn path(&self) -> io::Result<Cow<'_, Path>> {
-        bytes2path(self.path_bytes())
+        match self.pax_name("path") {
+            Some(name) if self.long_pathname.is_none() => self.pax_name_to_path(name),
+            _ => bytes2path(self.path_bytes()),
+        }
     }
 
     fn path_bytes(&self) -> Cow<[u8]> {
         if let Some(ref bytes) = self.long_pathname {
             if let Some(&0) = bytes.last() {
-                Cow::Borrowed(&bytes[..bytes.len() - 1])
+                self.decode_name(Cow::Borrowed(&bytes[..bytes.len() - 1]))
             } else {
-                Cow::Borrowed(bytes)
+                self.decode_name(Cow::Borrowed(bytes))
             }
         } else {
-            if let Some(ref pax) = self.pax_extensions {
-                let pax = pax_extensions(pax)
-                    .filter_map(Result::ok)
-                    .find(|f| f.key_bytes() == b"path")
-                    .map(|f| f.value_bytes());
-                if let Some(field) = pax {
-                    return Cow::Borrowed(field);
+            if let Some(field) = self.pax_name("path") {
+                return Cow::Borrowed(field);
+            }
+            if self.star_compat {
+                if let Some(ustar) = self.header.as_ustar().filter(|h| h.is_star()) {
+                    return self.decode_name(ustar.star_path_bytes());
                 }
             }
-            self.header.path_bytes()
+            self.decode_name(self.header.path_bytes())
+        }
+    }
+
+    /// Decodes a name from the header or a GNU long name entry to UTF-8, see
+    /// `ArchiveBuilder::set_name_encoding`.
+    #[cfg(feature = "encoding_rs")]
+    fn decode_name<'a>(&self, name: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        let encoding = match self.name_encoding {
+            Some(encoding) => encoding,
+            None => return name,
+        };
+        match name {
+            Cow::Borrowed(name) => match encoding.decode_without_bom_handling(name).0 {
+                Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
+                Cow::Owned(name) => Cow::Owned(name.into_bytes()),
+            },
+            Cow::Owned(name) => Cow::Owned(
+                encoding
+                    .decode_without_bom_handling(&name)
+                    .0
+                    .into_owned()
+                    .into_bytes(),
+            ),
+        }
+    }
+
+    #[cfg(not(feature = "encoding_rs"))]
+    fn decode_name<'a>(&self, name: Cow<'a, [u8]>) -> Cow<'a, [u8]> {
+        name
+    }
+
+    /// Returns the pax record overriding the name stored under `key` in the
+    /// header.
+    fn pax_name(&self, key: &str) -> Option<&[u8]> {
+        let pax = pax_extensions(self.pax_extensions.as_ref()?);
+        // GNU sparse 1.0 entries store a placeholder in `path`.
+        if key == "path" {
+            if let Some(name) = pax.get("GNU.sparse.name") {
+                return Some(name);
+            }
+        }
+        pax.get(key)
+    }
+
+    /// Interprets a name from a pax record according to `hdrcharset`.
+    ///
+    /// Names marked as raw bytes are decoded like names from the header,
+    /// while all others have to be UTF-8.
+    fn pax_name_to_path<'a>(&self, name: &'a [u8]) -> io::Result<Cow<'a, Path>> {
+        let binary = match self.pax_extensions {
+            Some(ref pax) => pax_extensions(pax).binary_names(),
+            None => false,
+        };
+        if binary {
+            return bytes2path(self.decode_name(Cow::Borrowed(name)));
+        }
+        match std::str::from_utf8(name) {
+            Ok(name) => Ok(Cow::Borrowed(Path::new(name))),
+            Err(_) => Err(other(&format!(
+                "pax name `{}` is not valid UTF-8",
+                String::from_utf8_lossy(name)
+            ))),
         }
     }
 
@@ -391,9 +1624,12 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
     }
 
     fn link_name(&self) -> io::Result<Option<Cow<Path>>> {
-        match self.link_name_bytes() {
-            Some(bytes) => bytes2path(bytes).map(Some),
-            None => Ok(None),
+        match self.pax_name("linkpath") {
+            Some(name) if self.long_linkname.is_none() => self.pax_name_to_path(name).map(Some),
+            _ => match self.link_name_bytes() {
+                Some(bytes) => bytes2path(bytes).map(Some),
+                None => Ok(None),
+            },
         }
     }
 
@@ -401,13 +1637,63 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
         match self.long_linkname {
             Some(ref bytes) => {
                 if let Some(&0) = bytes.last() {
-                    Some(Cow::Borrowed(&bytes[..bytes.len() - 1]))
+                    Some(self.decode_name(Cow::Borrowed(&bytes[..bytes.len() - 1])))
                 } else {
-                    Some(Cow::Borrowed(bytes))
+                    Some(self.decode_name(Cow::Borrowed(bytes)))
+                }
+            }
+            None => match self.pax_name("linkpath") {
+                Some(field) => Some(Cow::Borrowed(field)),
+                None => self
+                    .header
+                    .link_name_bytes()
+                    .map(|name| self.decode_name(name)),
+            },
+        }
+    }
+
+    fn atime(&self) -> io::Result<Option<u64>> {
+        let atime = if let Some(gnu) = self.header.as_gnu() {
+            gnu.atime()?
+        } else {
+            match self.header.as_ustar() {
+                Some(ustar) if self.star_compat && ustar.is_star() => ustar.star_atime()?,
+                _ => 0,
+            }
+        };
+        Ok(Some(atime).filter(|t| *t != 0))
+    }
+
+    fn ctime(&self) -> io::Result<Option<u64>> {
+        let ctime = if let Some(gnu) = self.header.as_gnu() {
+            gnu.ctime()?
+        } else {
+            match self.header.as_ustar() {
+                Some(ustar) if self.star_compat && ustar.is_star() => ustar.star_ctime()?,
+                _ => 0,
+            }
+        };
+        Ok(Some(ctime).filter(|t| *t != 0))
+    }
+
+    fn logical_size(&self) -> io::Result<u64> {
+        if let (Some(pax), false) = (
+            &self.pax_extensions,
+            self.header.entry_type().is_pax_local_extensions(),
+        ) {
+            for ext in pax_extensions(pax) {
+                let ext = ext?;
+                if let b"GNU.sparse.realsize" | b"GNU.sparse.size" = ext.key_bytes() {
+                    let value = ext
+                        .value()
+                        .map_err(|_| other("pax sparse size was not valid utf-8"))?;
+                    return value
+                        .parse()
+                        .map_err(|_| other(&format!("invalid pax sparse size: {}", value)));
                 }
             }
-            None => self.header.link_name_bytes(),
         }
+        Ok(self.size)
     }
 
     async fn pax_extensions(&mut self) -> io::Result<Option<PaxExtensions<'_>>> {
@@ -422,7 +1708,11 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
         Ok(Some(pax_extensions(self.pax_extensions.as_ref().unwrap())))
     }
 
-    async fn unpack_in(&mut self, dst: &Path) -> io::Result<bool> {
+    async fn unpack_in(
+        &mut self,
+        dst: &Path,
+        buffers: Option<&FixedBuffers>,
+    ) -> io::Result<Option<PathBuf>> {
         // Notes regarding bsdtar 2.8.3 / libarchive 2.8.3:
         // * Leading '/'s are trimmed. For example, `///test` is treated as
         //   `test`.
@@ -437,6 +1727,7 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
         // library, but we specially handle a few cases here as well.
 
         let mut file_dst = dst.to_path_buf();
+        let mut mangled = false;
         {
             let path = self.path().map_err(|e| {
                 TarError::new(
@@ -455,23 +1746,33 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
                     // unpacking the file to prevent directory traversal
                     // security issues.  See, e.g.: CVE-2001-1267,
                     // CVE-2002-0399, CVE-2005-1918, CVE-2007-4131
-                    Component::ParentDir => return Ok(false),
+                    Component::ParentDir => return Ok(None),
 
+                    Component::Normal(part) if self.mangle_names => {
+                        let name = mangle_name(part);
+                        mangled |= matches!(name, Cow::Owned(_));
+                        file_dst.push(name);
+                    }
                     Component::Normal(part) => file_dst.push(part),
                 }
             }
         }
+        self.mangled_path = if mangled {
+            file_dst.strip_prefix(dst).ok().map(Path::to_path_buf)
+        } else {
+            None
+        };
 
         // Skip cases where only slashes or '.' parts were seen, because
         // this is effectively an empty filename.
         if *dst == *file_dst {
-            return Ok(true);
+            return Ok(Some(file_dst));
         }
 
         // Skip entries without a parent (i.e. outside of FS root)
         let parent = match file_dst.parent() {
             Some(p) => p,
-            None => return Ok(false),
+            None => return Ok(None),
         };
 
         self.ensure_dir_created(dst, parent)
@@ -480,11 +1781,15 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
 
         let canon_target = self.validate_inside_dst(dst, parent).await?;
 
-        self.unpack(Some(&canon_target), &file_dst)
+        let unpacked = self
+            .unpack(Some(&canon_target), &file_dst, buffers)
             .await
             .map_err(|e| TarError::new(&format!("failed to unpack `{}`", file_dst.display()), e))?;
 
-        Ok(true)
+        match unpacked {
+            Unpacked::Skipped => Ok(None),
+            _ => Ok(Some(file_dst)),
+        }
     }
 
     /// Unpack as destination directory `dst`.
@@ -508,14 +1813,24 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
     }
 
     /// Returns access to the header of this entry in the archive.
-    async fn unpack(&mut self, target_base: Option<&Path>, dst: &Path) -> io::Result<Unpacked> {
+    async fn unpack(
+        &mut self,
+        target_base: Option<&Path>,
+        dst: &Path,
+        buffers: Option<&FixedBuffers>,
+    ) -> io::Result<Unpacked> {
         let kind = self.header.entry_type();
 
-        if kind.is_dir() {
+        if kind.is_dir() || kind.is_gnu_dumpdir() {
             self.unpack_dir(dst).await?;
             if let Ok(mode) = self.header.mode() {
-                set_perms(dst, None, mode, self.preserve_permissions).await?;
+                let res = set_perms(dst, None, mode & !self.mask, self.preserve_permissions).await;
+                self.tolerate_metadata_error(res.map_err(Error::from))?;
             }
+            // Only correct if nothing is written into the directory
+            // afterwards, which `Archive::unpack` takes care of.
+            let res = set_mtime(self, dst);
+            self.tolerate_metadata_error(res)?;
             return Ok(Unpacked::Other);
         } else if kind.is_hard_link() || kind.is_symlink() {
             let src = match self.link_name()? {
@@ -534,6 +1849,11 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
                     String::from_utf8_lossy(self.header.as_bytes())
                 )));
             }
+            let src = if self.mangle_names {
+                Cow::Owned(mangle_path(&src))
+            } else {
+                src
+            };
 
             if kind.is_hard_link() {
                 let link_src = match target_base {
@@ -549,6 +1869,12 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
                     // links though they're canonicalized to their existing path
                     // so we need to validate at this time.
                     Some(p) => {
+                        if !link_stays_within(p, &self.path()?, &src, kind) {
+                            return Err(other(&format!(
+                                "hard link target {} is outside of the destination",
+                                src.display()
+                            )));
+                        }
                         let link_src = p.join(src);
                         self.validate_inside_dst(p, &link_src).await?;
                         link_src
@@ -584,6 +1910,7 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
             || kind.is_pax_local_extensions()
             || kind.is_gnu_longname()
             || kind.is_gnu_longlink()
+            || kind.is_gnu_volume_label()
         {
             return Ok(Unpacked::Other);
         };
@@ -594,8 +1921,11 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
         if self.header.as_ustar().is_none() && self.path_bytes().ends_with(b"/") {
             self.unpack_dir(dst).await?;
             if let Ok(mode) = self.header.mode() {
-                set_perms(dst, None, mode, self.preserve_permissions).await?;
+                let res = set_perms(dst, None, mode & !self.mask, self.preserve_permissions).await;
+                self.tolerate_metadata_error(res.map_err(Error::from))?;
             }
+            let res = set_mtime(self, dst);
+            self.tolerate_metadata_error(res)?;
             return Ok(Unpacked::Other);
         }
 
@@ -606,7 +1936,26 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
         // > typeflag value as a regular file.
         //
         // As a result if we don't recognize the kind we just write out the file
-        // as we would normally.
+        // as we would normally, unless asked to do otherwise.
+        if let EntryType::Other(_) = kind {
+            if let Some(callback) = &self.unknown_entry_callback {
+                (callback.0)(&self.path()?, kind);
+            }
+            match self.unknown_entry_types {
+                UnknownEntryTypes::AsRegular => {}
+                UnknownEntryTypes::Skip => return Ok(Unpacked::Skipped),
+                UnknownEntryTypes::Error => {
+                    return Err(other(&format!(
+                        "entry type `{}` is not supported",
+                        kind.as_byte().escape_ascii()
+                    )));
+                }
+            }
+        }
+
+        if self.skip_unchanged && self.is_unchanged(dst).await {
+            return Ok(Unpacked::Unchanged);
+        }
 
         // Ensure we write a new file rather than overwriting in-place which
         // is attackable; if an existing file is found unlink it.
@@ -617,6 +1966,34 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
                 .open(dst)
                 .await
         }
+        // Reserve the space for the data of the file up front, leaving the
+        // holes of sparse files alone. Filesystems without `fallocate`
+        // support are not an error, the data is simply written without a
+        // reservation.
+        async fn preallocate<R: AsyncRead + Unpin>(
+            f: &fs::File,
+            data: &[EntryIo<R>],
+        ) -> io::Result<()> {
+            let mut offset = 0;
+            for io in data {
+                let len = match io {
+                    EntryIo::Data(d) => d.limit(),
+                    EntryIo::Pad(d) => {
+                        offset += d.limit();
+                        continue;
+                    }
+                };
+                if len == 0 {
+                    continue;
+                }
+                match f.fallocate(offset, len, 0).await {
+                    Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return Ok(()),
+                    res => res?,
+                }
+                offset += len;
+            }
+            Ok(())
+        }
         let mut f = async {
             let f = match open(dst).await {
                 Ok(f) => Ok(f),
@@ -632,34 +2009,60 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
                     }
                 }
             }?;
+            if self.preallocate && self.size > 0 {
+                preallocate(&f, &self.data).await?;
+            }
             let mut offset = 0;
-            let mut read_buf = BytesMut::zeroed(1 * 1024 * 1024);
+            let mut buf = WriteBuf::new(buffers);
+            if self.direct_io && self.size > 0 {
+                // Not every filesystem supports `O_DIRECT`, in which case the
+                // file is written normally.
+                let direct = fs::OpenOptions::new()
+                    .write(true)
+                    .custom_flags(libc::O_DIRECT)
+                    .open(dst)
+                    .await
+                    .ok();
+                if direct.is_some() {
+                    buf = WriteBuf::Direct(direct, None);
+                }
+            }
+            let progress = self.progress.clone();
+            // End of the data written so far, anything after it is a hole.
+            let mut end = 0;
             for io in self.data.drain(..) {
                 match io {
                     EntryIo::Data(mut d) => {
-                        let expected = d.limit();
-                        let mut bytes_written = 0;
-                        while bytes_written < expected {
-                            let bytes_read = d.read(&mut read_buf).await?;
-                            if bytes_read == 0 {
-                                return Err(other("expected more bytes from stream"));
-                            }
-                            let remaining = read_buf.split_off(bytes_read);
-                            let (res, mut buf) = f.write_all_at(read_buf, offset).await;
-                            res?;
-                            offset += bytes_read as u64;
-                            bytes_written += bytes_read as u64;
-                            buf.unsplit(remaining);
-                            read_buf = buf;
+                        let written = buf
+                            .write_all_from(
+                                &f,
+                                &mut d,
+                                offset,
+                                &mut self.digest,
+                                progress.as_deref(),
+                            )
+                            .await?;
+                        offset += written;
+                        if written > 0 {
+                            end = offset;
                         }
                     }
+                    // The file has just been created, so holes read as zeros
+                    // without allocating anything for them.
                     EntryIo::Pad(d) => {
-                        f.fallocate(offset, d.limit(), libc::FALLOC_FL_ZERO_RANGE)
-                            .await?;
+                        self.digest.update_zeros(d.limit());
                         offset += d.limit();
+                        if let Some(progress) = progress.as_deref() {
+                            progress.fetch_add(d.limit(), Ordering::Relaxed);
+                        }
                     }
                 }
             }
+            if end < offset && unsafe { libc::ftruncate(f.as_raw_fd(), offset as libc::off_t) } != 0
+            {
+                return Err(io::Error::last_os_error());
+            }
+            self.digest.finish();
             Ok::<fs::File, io::Error>(f)
         }
         .await
@@ -675,22 +2078,72 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
             )
         })?;
 
-        if self.preserve_mtime {
-            if let Ok(mtime) = self.header.mtime() {
-                let mtime = FileTime::from_unix_time(mtime as i64, 0);
-                filetime::set_file_times(dst, mtime, mtime).map_err(|e| {
-                    TarError::new(&format!("failed to set mtime for `{}`", dst.display()), e)
-                })?;
+        match self.metadata_order {
+            MetadataOrder::XattrsFirst => {
+                if self.unpack_xattrs {
+                    let res = set_xattrs(self, dst).await;
+                    self.tolerate_metadata_error(res)?;
+                }
+                if let Ok(mode) = self.header.mode() {
+                    let res = set_perms(
+                        dst,
+                        Some(&mut f),
+                        mode & !self.mask,
+                        self.preserve_permissions,
+                    )
+                    .await;
+                    self.tolerate_metadata_error(res.map_err(Error::from))?;
+                }
+                let res = set_mtime(self, dst);
+                self.tolerate_metadata_error(res)?;
+            }
+            MetadataOrder::ModeFirst => {
+                let res = set_mtime(self, dst);
+                self.tolerate_metadata_error(res)?;
+                if let Ok(mode) = self.header.mode() {
+                    let res = set_perms(
+                        dst,
+                        Some(&mut f),
+                        mode & !self.mask,
+                        self.preserve_permissions,
+                    )
+                    .await;
+                    self.tolerate_metadata_error(res.map_err(Error::from))?;
+                }
+                if self.unpack_xattrs {
+                    let res = set_xattrs(self, dst).await;
+                    self.tolerate_metadata_error(res)?;
+                }
             }
         }
-        if let Ok(mode) = self.header.mode() {
-            set_perms(dst, Some(&mut f), mode, self.preserve_permissions).await?;
-        }
-        if self.unpack_xattrs {
-            set_xattrs(self, dst).await?;
+        if self.unpack_file_flags {
+            let res = set_file_flags(self, &f, dst).await;
+            self.tolerate_metadata_error(res)?;
         }
         return Ok(Unpacked::File(f));
 
+        fn set_mtime<R: AsyncRead + Unpin>(me: &EntryFields<R>, dst: &Path) -> io::Result<()> {
+            if me.preserve_mtime {
+                if let Ok(mut mtime) = me.header.mtime() {
+                    if me.clamp_future_mtime {
+                        let now = me.clock.0.now().duration_since(UNIX_EPOCH);
+                        mtime = mtime.min(now.map_or(0, |now| now.as_secs()));
+                    }
+                    let mtime = FileTime::from_unix_time(mtime as i64, 0);
+                    let atime = match me.atime() {
+                        Ok(Some(atime)) if me.preserve_atime => {
+                            FileTime::from_unix_time(atime as i64, 0)
+                        }
+                        _ => mtime,
+                    };
+                    filetime::set_file_times(dst, atime, mtime).map_err(|e| {
+                        TarError::new(&format!("failed to set mtime for `{}`", dst.display()), e)
+                    })?;
+                }
+            }
+            Ok(())
+        }
+
         async fn set_perms(
             dst: &Path,
             f: Option<&mut fs::File>,
@@ -735,27 +2188,13 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
             me: &mut EntryFields<R>,
             dst: &Path,
         ) -> io::Result<()> {
-            use std::{ffi::OsStr, os::unix::prelude::*};
-
             let exts = match me.pax_extensions().await {
                 Ok(Some(e)) => e,
                 _ => return Ok(()),
             };
-            let exts = exts
-                .filter_map(Result::ok)
-                .filter_map(|e| {
-                    let key = e.key_bytes();
-                    let prefix = b"SCHILY.xattr.";
-                    if key.starts_with(prefix) {
-                        Some((&key[prefix.len()..], e))
-                    } else {
-                        None
-                    }
-                })
-                .map(|(key, e)| (OsStr::from_bytes(key), e.value_bytes()));
 
-            for (key, value) in exts {
-                xattr::set(dst, key, value).map_err(|e| {
+            for (key, value) in exts.xattrs() {
+                xattr::set(dst, &key, &value).map_err(|e| {
                     TarError::new(
                         &format!(
                             "failed to set extended \
@@ -763,7 +2202,7 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
                              Xattrs: key={:?}, value={:?}.",
                             dst.display(),
                             key,
-                            String::from_utf8_lossy(value)
+                            String::from_utf8_lossy(&value)
                         ),
                         e,
                     )
@@ -772,6 +2211,120 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
 
             Ok(())
         }
+
+        async fn set_file_flags<R: AsyncRead + Unpin>(
+            me: &mut EntryFields<R>,
+            f: &fs::File,
+            dst: &Path,
+        ) -> io::Result<()> {
+            let names = match me.pax_extensions().await {
+                Ok(Some(exts)) => exts.file_flags().collect::<Vec<_>>(),
+                _ => return Ok(()),
+            };
+            if names.is_empty() {
+                return Ok(());
+            }
+            set_inode_flags(f, &names).map_err(|e| {
+                TarError::new(
+                    &format!(
+                        "failed to set file flags {} on {}",
+                        names.join(","),
+                        dst.display()
+                    ),
+                    e,
+                )
+                .into()
+            })
+        }
+
+        /// Applies the BSD flags `names` which have a Linux inode flag
+        /// counterpart on top of the inode flags `f` already has.
+        fn set_inode_flags(f: &fs::File, names: &[&str]) -> io::Result<()> {
+            const FS_IMMUTABLE_FL: libc::c_int = 0x10;
+            const FS_APPEND_FL: libc::c_int = 0x20;
+            const FS_NODUMP_FL: libc::c_int = 0x40;
+
+            // Filesystems without inode flags reject the ioctl with ENOTTY.
+            let last_error = || match io::Error::last_os_error() {
+                e if e.raw_os_error() == Some(libc::ENOTTY) => {
+                    io::Error::from_raw_os_error(libc::EOPNOTSUPP)
+                }
+                e => e,
+            };
+            let mut current: libc::c_int = 0;
+            if unsafe { libc::ioctl(f.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut current) } != 0 {
+                return Err(last_error());
+            }
+            let mut flags = current;
+            for &name in names {
+                let (name, set) = match name {
+                    "nodump" => (name, true),
+                    "dump" => ("nodump", false),
+                    _ => match name.strip_prefix("no") {
+                        Some(rest) => (rest, false),
+                        None => (name, true),
+                    },
+                };
+                let flag = match name {
+                    "nodump" => FS_NODUMP_FL,
+                    "sappnd" | "sappend" | "uappnd" | "uappend" => FS_APPEND_FL,
+                    "schg" | "schange" | "simmutable" | "uchg" | "uchange" | "uimmutable" => {
+                        FS_IMMUTABLE_FL
+                    }
+                    _ => continue,
+                };
+                if set {
+                    flags |= flag;
+                } else {
+                    flags &= !flag;
+                }
+            }
+            if flags != current
+                && unsafe { libc::ioctl(f.as_raw_fd(), libc::FS_IOC_SETFLAGS, &flags) } != 0
+            {
+                return Err(last_error());
+            }
+            Ok(())
+        }
+    }
+
+    /// Applies the `UnsupportedMetadata` policy to the result of setting a
+    /// piece of metadata, recording the error as a warning if it is ignored.
+    fn tolerate_metadata_error(&mut self, res: io::Result<()>) -> io::Result<()> {
+        match res {
+            Err(e)
+                if self.unsupported_metadata == UnsupportedMetadata::Warn && is_unsupported(&e) =>
+            {
+                self.metadata_warnings.push(e);
+                Ok(())
+            }
+            res => res,
+        }
+    }
+
+    /// Returns whether `dst` is a regular file with the size and modification
+    /// time of this entry.
+    async fn is_unchanged(&self, dst: &Path) -> bool {
+        let mtime = match self.header.mtime() {
+            Ok(mtime) => mtime,
+            Err(_) => return false,
+        };
+        let mut builder = fs::StatxBuilder::new();
+        builder
+            .flags(libc::AT_SYMLINK_NOFOLLOW)
+            .mask(libc::STATX_TYPE | libc::STATX_SIZE | libc::STATX_MTIME);
+        let stat = match builder.pathname(dst) {
+            Ok(builder) => builder.statx().await,
+            Err(e) => Err(e),
+        };
+        match stat {
+            Ok(stat) => {
+                u32::from(stat.stx_mode) & libc::S_IFMT == libc::S_IFREG
+                    && stat.stx_size == self.size
+                    && stat.stx_mtime.tv_sec == mtime as i64
+            }
+            Err(_) => false,
+        }
     }
 
     async fn ensure_dir_created(&self, dst: &Path, dir: &Path) -> io::Result<()> {
@@ -801,6 +2354,9 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
     }
 
     async fn validate_inside_dst(&self, dst: &Path, file_dst: &Path) -> io::Result<PathBuf> {
+        if let Some(max) = self.max_symlink_depth {
+            check_symlink_depth(file_dst, max)?;
+        }
         // Abort if target (canonical) parent is outside of `dst`
         let canon_parent = file_dst.canonicalize().map_err(|err| {
             Error::new(
@@ -829,13 +2385,97 @@ impl<R: AsyncRead + Unpin> EntryFields<R> {
     }
 }
 
+/// Resolves `path` the way the kernel would, failing with an error once more
+/// than `max` symbolic links have to be followed.
+fn check_symlink_depth(path: &Path, max: u32) -> io::Result<()> {
+    fn push_components(pending: &mut Vec<PathBuf>, path: &Path) {
+        let start = pending.len();
+        pending.extend(path.components().map(|c| PathBuf::from(c.as_os_str())));
+        pending[start..].reverse();
+    }
+
+    let mut pending = Vec::new();
+    push_components(&mut pending, path);
+    let mut resolved = PathBuf::new();
+    let mut followed = 0;
+    while let Some(part) = pending.pop() {
+        match part.components().next() {
+            Some(Component::Normal(name)) => {
+                let next = resolved.join(name);
+                match std::fs::read_link(&next) {
+                    Ok(target) => {
+                        followed += 1;
+                        if followed > max {
+                            return Err(other(&format!(
+                                "more than {} levels of symbolic links while resolving {}",
+                                max,
+                                path.display()
+                            )));
+                        }
+                        push_components(&mut pending, &target);
+                    }
+                    // Not a symlink, or does not exist yet.
+                    Err(_) => resolved = next,
+                }
+            }
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            Some(Component::RootDir) | Some(Component::Prefix(..)) => resolved = part,
+            Some(Component::CurDir) | None => {}
+        }
+    }
+    Ok(())
+}
+
 impl<R: AsyncRead + Unpin> AsyncRead for EntryFields<R> {
     fn poll_read(
         self: Pin<&mut Self>,
         cx: &mut Context<'_>,
         into: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if this.buffered_pos < this.buffered_len {
+            let n = into.remaining().min(this.buffered_len - this.buffered_pos);
+            into.put_slice(&this.buffered[this.buffered_pos..this.buffered_pos + n]);
+            this.buffered_pos += n;
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(this).poll_read_unbuffered(cx, into)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncBufRead for EntryFields<R> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        if this.buffered_pos == this.buffered_len {
+            let mut buffered = std::mem::take(&mut this.buffered);
+            buffered.resize(BUF_READ_CAPACITY, 0);
+            let mut read_buf = ReadBuf::new(&mut buffered);
+            let res = Pin::new(&mut *this).poll_read_unbuffered(cx, &mut read_buf);
+            let n = read_buf.filled().len();
+            this.buffered = buffered;
+            std::task::ready!(res)?;
+            this.buffered_pos = 0;
+            this.buffered_len = n;
+        }
+        Poll::Ready(Ok(&this.buffered[this.buffered_pos..this.buffered_len]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.buffered_pos = (this.buffered_pos + amt).min(this.buffered_len);
+    }
+}
+
+impl<R: AsyncRead + Unpin> EntryFields<R> {
+    fn poll_read_unbuffered(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
     ) -> Poll<io::Result<()>> {
         let mut this = self.project();
+        let filled_before = into.filled().len();
         loop {
             if this.read_state.is_none() {
                 if this.data.as_ref().is_empty() {
@@ -849,14 +2489,17 @@ impl<R: AsyncRead + Unpin> AsyncRead for EntryFields<R> {
             if let Some(ref mut io) = &mut *this.read_state {
                 let ret = Pin::new(io).poll_read(cx, into);
                 match ret {
-                    Poll::Ready(Ok(())) if into.filled().len() == 0 => {
+                    Poll::Ready(Ok(())) if into.filled().len() == filled_before => {
                         *this.read_state = None;
                         if this.data.as_ref().is_empty() {
+                            this.digest.finish();
                             return Poll::Ready(Ok(()));
                         }
                         continue;
                     }
                     Poll::Ready(Ok(())) => {
+                        this.digest.update(&into.filled()[filled_before..]);
+                        *this.position += (into.filled().len() - filled_before) as u64;
                         return Poll::Ready(Ok(()));
                     }
                     Poll::Ready(Err(err)) => {
@@ -868,6 +2511,7 @@ impl<R: AsyncRead + Unpin> AsyncRead for EntryFields<R> {
                 }
             }
             // Unable to pull another value from `data`, so we are done.
+            this.digest.finish();
             return Poll::Ready(Ok(()));
         }
     }
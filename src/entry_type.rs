@@ -1,4 +1,6 @@
 // See https://en.wikipedia.org/wiki/Tar_%28computing%29#UStar_format
+use std::{error, fmt, str::FromStr};
+
 /// Indicate for the type of file described by a header.
 ///
 /// Each `Header` has an `entry_type` method returning an instance of this type
@@ -30,6 +32,13 @@ pub enum EntryType {
     GNULongLink,
     /// GNU extension - sparse file
     GNUSparse,
+    /// GNU extension - continuation of a file from the previous volume
+    GNUMultiVolume,
+    /// GNU extension - volume label
+    GNUVolumeLabel,
+    /// GNU extension - directory whose data lists its contents, written by
+    /// incremental backups
+    GNUDumpDir,
     /// Global extended header
     XGlobalHeader,
     /// Extended Header
@@ -58,6 +67,9 @@ impl EntryType {
             b'L' => EntryType::GNULongName,
             b'K' => EntryType::GNULongLink,
             b'S' => EntryType::GNUSparse,
+            b'M' => EntryType::GNUMultiVolume,
+            b'V' => EntryType::GNUVolumeLabel,
+            b'D' => EntryType::GNUDumpDir,
             other => EntryType::Other(other),
         }
     }
@@ -78,10 +90,40 @@ impl EntryType {
             EntryType::GNULongName => b'L',
             EntryType::GNULongLink => b'K',
             EntryType::GNUSparse => b'S',
+            EntryType::GNUMultiVolume => b'M',
+            EntryType::GNUVolumeLabel => b'V',
+            EntryType::GNUDumpDir => b'D',
             EntryType::Other(other) => other,
         }
     }
 
+    /// Returns a short name for this entry type, such as `"regular"`,
+    /// `"symlink"` or `"character-device"`.
+    ///
+    /// Unknown types are all named `"other"`, their `Display` output includes
+    /// the raw byte as well.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EntryType::Regular => "regular",
+            EntryType::Link => "hard-link",
+            EntryType::Symlink => "symlink",
+            EntryType::Char => "character-device",
+            EntryType::Block => "block-device",
+            EntryType::Directory => "directory",
+            EntryType::Fifo => "fifo",
+            EntryType::Continuous => "contiguous",
+            EntryType::GNULongName => "gnu-long-name",
+            EntryType::GNULongLink => "gnu-long-link",
+            EntryType::GNUSparse => "gnu-sparse",
+            EntryType::GNUMultiVolume => "gnu-multi-volume",
+            EntryType::GNUVolumeLabel => "gnu-volume-label",
+            EntryType::GNUDumpDir => "gnu-dump-dir",
+            EntryType::XGlobalHeader => "pax-global-header",
+            EntryType::XHeader => "pax-header",
+            EntryType::Other(_) => "other",
+        }
+    }
+
     /// Creates a new entry type representing a regular file.
     pub fn file() -> EntryType {
         EntryType::Regular
@@ -177,6 +219,23 @@ impl EntryType {
         self == EntryType::GNULongLink
     }
 
+    /// Returns whether this type represents a GNU multi-volume continuation
+    /// header.
+    pub fn is_gnu_multivolume(self) -> bool {
+        self == EntryType::GNUMultiVolume
+    }
+
+    /// Returns whether this type represents a GNU volume label.
+    pub fn is_gnu_volume_label(self) -> bool {
+        self == EntryType::GNUVolumeLabel
+    }
+
+    /// Returns whether this type represents a GNU dumpdir, a directory
+    /// written by an incremental backup.
+    pub fn is_gnu_dumpdir(self) -> bool {
+        self == EntryType::GNUDumpDir
+    }
+
     /// Returns whether this type represents a GNU long name header.
     pub fn is_pax_global_extensions(self) -> bool {
         self == EntryType::XGlobalHeader
@@ -187,3 +246,63 @@ impl EntryType {
         self == EntryType::XHeader
     }
 }
+
+impl fmt::Display for EntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EntryType::Other(byte) => write!(f, "other({})", byte),
+            kind => f.write_str(kind.as_str()),
+        }
+    }
+}
+
+impl FromStr for EntryType {
+    type Err = ParseEntryTypeError;
+
+    /// Parses the names returned by `as_str`, and `other(N)` with the raw
+    /// byte `N` in decimal as written by `Display`.
+    fn from_str(s: &str) -> Result<EntryType, ParseEntryTypeError> {
+        let kind = match s {
+            "regular" => EntryType::Regular,
+            "hard-link" => EntryType::Link,
+            "symlink" => EntryType::Symlink,
+            "character-device" => EntryType::Char,
+            "block-device" => EntryType::Block,
+            "directory" => EntryType::Directory,
+            "fifo" => EntryType::Fifo,
+            "contiguous" => EntryType::Continuous,
+            "gnu-long-name" => EntryType::GNULongName,
+            "gnu-long-link" => EntryType::GNULongLink,
+            "gnu-sparse" => EntryType::GNUSparse,
+            "gnu-multi-volume" => EntryType::GNUMultiVolume,
+            "gnu-volume-label" => EntryType::GNUVolumeLabel,
+            "gnu-dump-dir" => EntryType::GNUDumpDir,
+            "pax-global-header" => EntryType::XGlobalHeader,
+            "pax-header" => EntryType::XHeader,
+            _ => {
+                let byte = s
+                    .strip_prefix("other(")
+                    .and_then(|s| s.strip_suffix(')'))
+                    .and_then(|byte| byte.parse().ok());
+                // Known bytes have a name of their own.
+                match byte.map(EntryType::new) {
+                    Some(kind @ EntryType::Other(_)) => kind,
+                    _ => return Err(ParseEntryTypeError(())),
+                }
+            }
+        };
+        Ok(kind)
+    }
+}
+
+/// Error returned when parsing an unknown name into an `EntryType`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEntryTypeError(());
+
+impl fmt::Display for ParseEntryTypeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("unknown entry type name")
+    }
+}
+
+impl error::Error for ParseEntryTypeError {}
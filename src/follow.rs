@@ -0,0 +1,99 @@
+use std::{
+    fmt,
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// A boxed future returned by the notifier of a `FollowReader`.
+///
+/// It resolves to `true` once more data may be available, or to `false` to
+/// stop following and pass the end of file on.
+pub type FollowFuture = Pin<Box<dyn Future<Output = bool>>>;
+
+/// A reader which waits for more data instead of reporting the end of file,
+/// for consuming an archive while another process is still writing it.
+///
+/// Whenever the inner reader runs dry, the notifier is called and the read is
+/// retried once the returned future resolves. Wrapped in an `Archive`, this
+/// lets `Entries` yield members as they are appended, and iteration ends
+/// normally when the end-of-archive marker arrives.
+pub struct FollowReader<R> {
+    inner: R,
+    notify: Box<dyn FnMut() -> FollowFuture>,
+    waiting: Option<FollowFuture>,
+    stopped: bool,
+}
+
+impl<R: AsyncRead + Unpin> FollowReader<R> {
+    /// Follows `inner`, calling `notify` each time it reaches the end of
+    /// file to wait for more data, for example from a file system watcher.
+    pub fn new<F>(inner: R, notify: F) -> FollowReader<R>
+    where
+        F: FnMut() -> FollowFuture + 'static,
+    {
+        FollowReader {
+            inner,
+            notify: Box::new(notify),
+            waiting: None,
+            stopped: false,
+        }
+    }
+
+    /// Follows `inner`, retrying every `interval` after it reaches the end of
+    /// file.
+    pub fn with_interval(inner: R, interval: Duration) -> FollowReader<R> {
+        FollowReader::new(inner, move || {
+            Box::pin(async move {
+                tokio::time::sleep(interval).await;
+                true
+            })
+        })
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> fmt::Debug for FollowReader<R>
+where
+    R: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FollowReader")
+            .field("inner", &self.inner)
+            .field("waiting", &self.waiting.is_some())
+            .field("stopped", &self.stopped)
+            .finish()
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for FollowReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(waiting) = this.waiting.as_mut() {
+                let retry = std::task::ready!(waiting.as_mut().poll(cx));
+                this.waiting = None;
+                this.stopped = !retry;
+            }
+
+            let filled = into.filled().len();
+            std::task::ready!(Pin::new(&mut this.inner).poll_read(cx, into))?;
+            if into.filled().len() > filled || into.remaining() == 0 || this.stopped {
+                return Poll::Ready(Ok(()));
+            }
+            this.waiting = Some((this.notify)());
+        }
+    }
+}
@@ -0,0 +1,364 @@
+//! Read-only filesystem view of an archive.
+//!
+//! This module provides the primitives needed to expose an archive through a
+//! userspace filesystem such as FUSE: a stable inode table, directory
+//! listings, attributes, link targets and random-access reads of file
+//! contents. It is independent of any particular FUSE binding; an
+//! implementation of e.g. `fuser::Filesystem` only has to forward its
+//! `lookup`, `getattr`, `readdir`, `readlink` and `read` callbacks to the
+//! methods of the same name on `ArchiveFs`.
+//!
+//! Directories which are only implied by the paths of other members are
+//! synthesized, hard links share the inode of their target, and when a path
+//! is listed several times the last occurrence wins.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    io,
+    path::{Component, Path},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::io::{AsyncRead, AsyncSeek};
+
+use crate::{other, ArchiveIndex, EntryType};
+
+/// Inode number of the root directory.
+pub const ROOT_INODE: u64 = 1;
+
+/// The kind of a node in an `ArchiveFs`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum NodeKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// A symbolic link.
+    Symlink,
+    /// A character device.
+    CharDevice,
+    /// A block device.
+    BlockDevice,
+    /// A named pipe.
+    NamedPipe,
+}
+
+/// Attributes of a node, as reported by `getattr`.
+#[derive(Clone, Debug)]
+pub struct NodeAttr {
+    /// Inode number.
+    pub ino: u64,
+    /// Kind of node.
+    pub kind: NodeKind,
+    /// Size of the contents in bytes.
+    pub size: u64,
+    /// Number of 512-byte blocks occupied by the contents.
+    pub blocks: u64,
+    /// Permission bits, including setuid/setgid/sticky.
+    pub perm: u16,
+    /// Number of hard links to this node.
+    pub nlink: u32,
+    /// Owning user id.
+    pub uid: u32,
+    /// Owning group id.
+    pub gid: u32,
+    /// Modification time.
+    pub mtime: SystemTime,
+    /// Device major/minor numbers, for device nodes.
+    pub device: Option<(u32, u32)>,
+}
+
+/// An entry of a directory listing produced by `readdir`.
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    /// Inode number of the entry.
+    pub ino: u64,
+    /// Kind of the entry.
+    pub kind: NodeKind,
+    /// Name of the entry within its directory.
+    pub name: OsString,
+}
+
+#[derive(Debug)]
+struct Node {
+    parent: u64,
+    kind: NodeKind,
+    /// Position of the describing member in the index, if any.
+    entry: Option<usize>,
+    children: BTreeMap<OsString, u64>,
+    nlink: u32,
+}
+
+/// A read-only filesystem over the members of a seekable archive.
+#[derive(Debug)]
+pub struct ArchiveFs<R> {
+    reader: R,
+    index: ArchiveIndex,
+    nodes: Vec<Node>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> ArchiveFs<R> {
+    /// Indexes the archive in `reader` and builds its inode table.
+    ///
+    /// The reader is rewound to position 0, which must be the start of the
+    /// archive.
+    pub async fn new(reader: R) -> io::Result<ArchiveFs<R>> {
+        let (reader, index) = ArchiveIndex::build_seekable(reader).await?;
+        Ok(ArchiveFs::from_index(reader, index))
+    }
+
+    /// Builds the inode table from an existing index of the archive in
+    /// `reader`.
+    pub fn from_index(reader: R, index: ArchiveIndex) -> ArchiveFs<R> {
+        let mut fs = ArchiveFs {
+            reader,
+            index,
+            nodes: vec![Node {
+                parent: ROOT_INODE,
+                kind: NodeKind::Directory,
+                entry: None,
+                children: BTreeMap::new(),
+                nlink: 2,
+            }],
+        };
+        for i in 0..fs.index.len() {
+            fs.insert(i);
+        }
+        fs
+    }
+
+    /// Returns the index of the archive.
+    pub fn index(&self) -> &ArchiveIndex {
+        &self.index
+    }
+
+    /// Unwraps this filesystem, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Looks up `name` in the directory `parent`.
+    pub fn lookup(&self, parent: u64, name: &OsStr) -> Option<NodeAttr> {
+        let node = self.node(parent)?;
+        let ino = match name.to_str() {
+            Some(".") => parent,
+            Some("..") => node.parent,
+            _ => *node.children.get(name)?,
+        };
+        self.getattr(ino)
+    }
+
+    /// Returns the attributes of the node `ino`.
+    pub fn getattr(&self, ino: u64) -> Option<NodeAttr> {
+        let node = self.node(ino)?;
+        let mut attr = NodeAttr {
+            ino,
+            kind: node.kind,
+            size: 0,
+            blocks: 0,
+            perm: if node.kind == NodeKind::Directory {
+                0o755
+            } else {
+                0o644
+            },
+            nlink: node.nlink,
+            uid: 0,
+            gid: 0,
+            mtime: UNIX_EPOCH,
+            device: None,
+        };
+        if let Some(entry) = node.entry.map(|i| self.index.entry(i)) {
+            let header = entry.header();
+            if node.kind == NodeKind::File {
+                attr.size = entry.size();
+            } else if node.kind == NodeKind::Symlink {
+                attr.size = entry.link_name().map_or(0, |l| l.as_os_str().len() as u64);
+            }
+            attr.blocks = attr.size.saturating_add(511) / 512;
+            if let Ok(mode) = header.mode() {
+                attr.perm = (mode & 0o7777) as u16;
+            }
+            attr.uid = header.uid().map_or(0, |u| u as u32);
+            attr.gid = header.gid().map_or(0, |g| g as u32);
+            if let Ok(mtime) = header.mtime() {
+                attr.mtime = UNIX_EPOCH + Duration::from_secs(mtime);
+            }
+            if let (Ok(Some(major)), Ok(Some(minor))) =
+                (header.device_major(), header.device_minor())
+            {
+                if matches!(node.kind, NodeKind::CharDevice | NodeKind::BlockDevice) {
+                    attr.device = Some((major, minor));
+                }
+            }
+        }
+        Some(attr)
+    }
+
+    /// Lists the directory `ino`, including the `.` and `..` entries.
+    ///
+    /// Returns `None` if `ino` does not exist or is not a directory.
+    pub fn readdir(&self, ino: u64) -> Option<Vec<DirEntry>> {
+        let node = self.node(ino)?;
+        if node.kind != NodeKind::Directory {
+            return None;
+        }
+        let mut list = vec![
+            DirEntry {
+                ino,
+                kind: NodeKind::Directory,
+                name: ".".into(),
+            },
+            DirEntry {
+                ino: node.parent,
+                kind: NodeKind::Directory,
+                name: "..".into(),
+            },
+        ];
+        list.extend(node.children.iter().map(|(name, child)| DirEntry {
+            ino: *child,
+            kind: self.nodes[(*child - 1) as usize].kind,
+            name: name.clone(),
+        }));
+        Some(list)
+    }
+
+    /// Returns the target of the symlink `ino`.
+    pub fn readlink(&self, ino: u64) -> Option<&Path> {
+        let node = self.node(ino)?;
+        if node.kind != NodeKind::Symlink {
+            return None;
+        }
+        self.index.entry(node.entry?).link_name()
+    }
+
+    /// Reads up to `size` bytes of the file `ino`, starting at `offset`.
+    ///
+    /// Reads past the end of the file return fewer bytes, or none at all.
+    /// Holes of sparse files read as zeros.
+    pub async fn read(&mut self, ino: u64, offset: u64, size: u32) -> io::Result<Vec<u8>> {
+        let entry = match self.node(ino) {
+            Some(node) if node.kind == NodeKind::File => node.entry,
+            Some(_) => return Err(other("not a regular file")),
+            None => return Err(io::Error::new(io::ErrorKind::NotFound, "no such inode")),
+        };
+        let entry = match entry {
+            Some(i) => self.index.entry(i),
+            None => return Ok(Vec::new()),
+        };
+        if offset >= entry.size() {
+            return Ok(Vec::new());
+        }
+        let mut buf = Vec::with_capacity(size as usize);
+        entry
+            .read_range_into(&mut self.reader, offset, u64::from(size), &mut buf)
+            .await?;
+        Ok(buf)
+    }
+
+    fn node(&self, ino: u64) -> Option<&Node> {
+        ino.checked_sub(1).and_then(|i| self.nodes.get(i as usize))
+    }
+
+    fn insert(&mut self, i: usize) {
+        let entry = self.index.entry(i);
+        let kind = match entry.header().entry_type() {
+            EntryType::Directory | EntryType::GNUDumpDir => NodeKind::Directory,
+            EntryType::Symlink => NodeKind::Symlink,
+            EntryType::Char => NodeKind::CharDevice,
+            EntryType::Block => NodeKind::BlockDevice,
+            EntryType::Fifo => NodeKind::NamedPipe,
+            EntryType::Link => {
+                let target = entry.link_name().and_then(|l| self.resolve(l));
+                let path = entry.path().to_path_buf();
+                if let (Some(target), Some(name)) = (target, path.file_name()) {
+                    if self.nodes[(target - 1) as usize].kind != NodeKind::Directory {
+                        let parent = self.make_dirs(path.parent().unwrap_or(Path::new("")));
+                        self.nodes[(target - 1) as usize].nlink += 1;
+                        self.nodes[(parent - 1) as usize]
+                            .children
+                            .insert(name.to_os_string(), target);
+                    }
+                }
+                return;
+            }
+            kind if kind.is_pax_global_extensions() => return,
+            _ => NodeKind::File,
+        };
+
+        let path = entry.path().to_path_buf();
+        let name = match path.file_name() {
+            Some(name) => name.to_os_string(),
+            None => {
+                if kind == NodeKind::Directory {
+                    self.nodes[0].entry = Some(i);
+                }
+                return;
+            }
+        };
+        let parent = self.make_dirs(path.parent().unwrap_or(Path::new("")));
+        let existing = self.nodes[(parent - 1) as usize]
+            .children
+            .get(&name)
+            .copied();
+        match existing {
+            Some(ino) if kind == NodeKind::Directory && self.is_dir(ino) => {
+                self.nodes[(ino - 1) as usize].entry = Some(i);
+            }
+            _ => {
+                let ino = self.push(parent, kind, Some(i));
+                self.nodes[(parent - 1) as usize].children.insert(name, ino);
+            }
+        }
+    }
+
+    fn push(&mut self, parent: u64, kind: NodeKind, entry: Option<usize>) -> u64 {
+        self.nodes.push(Node {
+            parent,
+            kind,
+            entry,
+            children: BTreeMap::new(),
+            nlink: if kind == NodeKind::Directory { 2 } else { 1 },
+        });
+        self.nodes.len() as u64
+    }
+
+    fn is_dir(&self, ino: u64) -> bool {
+        self.nodes[(ino - 1) as usize].kind == NodeKind::Directory
+    }
+
+    /// Returns the directory at `path`, creating any missing directories.
+    fn make_dirs(&mut self, path: &Path) -> u64 {
+        let mut ino = ROOT_INODE;
+        for part in path.components() {
+            let name = match part {
+                Component::Normal(name) => name.to_os_string(),
+                _ => continue,
+            };
+            ino = match self.nodes[(ino - 1) as usize].children.get(&name) {
+                Some(child) if self.is_dir(*child) => *child,
+                _ => {
+                    let child = self.push(ino, NodeKind::Directory, None);
+                    self.nodes[(ino - 1) as usize].nlink += 1;
+                    self.nodes[(ino - 1) as usize].children.insert(name, child);
+                    child
+                }
+            };
+        }
+        ino
+    }
+
+    /// Resolves a path relative to the archive root to an inode, without
+    /// following symlinks.
+    fn resolve(&self, path: &Path) -> Option<u64> {
+        let mut ino = ROOT_INODE;
+        for part in path.components() {
+            match part {
+                Component::Normal(name) => ino = *self.node(ino)?.children.get(name)?,
+                Component::ParentDir => ino = self.node(ino)?.parent,
+                _ => {}
+            }
+        }
+        Some(ino)
+    }
+}
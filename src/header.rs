@@ -5,6 +5,8 @@ use std::os::windows::prelude::*;
 
 use std::{
     borrow::Cow,
+    cmp,
+    ffi::{OsStr, OsString},
     fmt, fs, io, iter,
     iter::repeat,
     mem,
@@ -12,7 +14,11 @@ use std::{
     str,
 };
 
-use crate::{other, EntryType};
+use crate::{
+    other,
+    pax::{pax_record, xattr_records},
+    EntryType,
+};
 
 /// Representation of the header of an entry in an archive
 #[repr(C)]
@@ -35,6 +41,37 @@ pub enum HeaderMode {
     Deterministic,
 }
 
+/// Layout of the checksum field written by `Header::set_cksum_with`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum ChecksumFormat {
+    /// Six octal digits followed by a NUL and a space, as specified by POSIX
+    /// and written by GNU tar. Some older readers only accept this form.
+    Strict,
+
+    /// Seven octal digits followed by a NUL, as written by earlier versions of
+    /// this crate and several other implementations.
+    Compat,
+}
+
+/// Form of the pax records holding extended attributes, see
+/// `HeaderBuilder::xattr_format`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum XattrFormat {
+    /// `SCHILY.xattr.` records with the raw name and value, as written by GNU
+    /// tar and star.
+    Schily,
+
+    /// `LIBARCHIVE.xattr.` records with a percent-encoded name and a base64
+    /// value, as written by bsdtar. These can carry any name and value.
+    Libarchive,
+
+    /// Both forms, as bsdtar writes by default. Readers which understand both
+    /// use only one of them.
+    Both,
+}
+
 /// Representation of the header of an entry in an archive
 #[repr(C)]
 #[allow(missing_docs)]
@@ -272,6 +309,33 @@ impl Header {
         &mut self.bytes
     }
 
+    /// Returns the bytes of this header which are not assigned to any field by
+    /// its format.
+    ///
+    /// These are normally zero, but some producers stash data in them. They
+    /// are kept as-is when reading, so re-emitting `as_bytes` reproduces the
+    /// original header exactly.
+    pub fn padding(&self) -> &[u8] {
+        if let Some(gnu) = self.as_gnu() {
+            &gnu.pad
+        } else if let Some(ustar) = self.as_ustar() {
+            &ustar.pad
+        } else {
+            &self.as_old().pad
+        }
+    }
+
+    /// Same as `padding`, but the mutable version.
+    pub fn padding_mut(&mut self) -> &mut [u8] {
+        if self.is_gnu() {
+            &mut self.as_gnu_mut().unwrap().pad
+        } else if self.is_ustar() {
+            &mut self.as_ustar_mut().unwrap().pad
+        } else {
+            &mut self.as_old_mut().pad
+        }
+    }
+
     /// Blanket sets the metadata in this header from the metadata argument
     /// provided.
     ///
@@ -319,6 +383,10 @@ impl Header {
     }
 
     /// Encodes the `size` argument into the size field of this header.
+    ///
+    /// Sizes of 8 GiB and more do not fit the octal field and are stored in
+    /// the base-256 form understood by GNU tar and most other readers, see
+    /// `uses_base256`.
     pub fn set_size(&mut self, size: u64) {
         num_field_wrapper_into(&mut self.as_old_mut().size, size);
     }
@@ -457,6 +525,8 @@ impl Header {
     }
 
     /// Encodes the `uid` provided into this header.
+    ///
+    /// IDs of 2097152 and more are stored in base-256 form, like large sizes.
     pub fn set_uid(&mut self, uid: u64) {
         num_field_wrapper_into(&mut self.as_old_mut().uid, uid);
     }
@@ -472,6 +542,8 @@ impl Header {
     }
 
     /// Encodes the `gid` provided into this header.
+    ///
+    /// IDs of 2097152 and more are stored in base-256 form, like large sizes.
     pub fn set_gid(&mut self, gid: u64) {
         num_field_wrapper_into(&mut self.as_old_mut().gid, gid);
     }
@@ -489,11 +561,29 @@ impl Header {
     /// Encodes the `mtime` provided into this header.
     ///
     /// Note that this time is typically a number of seconds passed since
-    /// January 1, 1970.
+    /// January 1, 1970. Times beyond the octal range, after the year 2242,
+    /// are stored in base-256 form, like large sizes.
     pub fn set_mtime(&mut self, mtime: u64) {
         num_field_wrapper_into(&mut self.as_old_mut().mtime, mtime);
     }
 
+    /// Returns whether any numeric field of this header is stored in the
+    /// base-256 form, which strict ustar readers do not understand.
+    ///
+    /// The setters fall back to this form for values too large for octal.
+    /// Use `HeaderBuilder::strict_ustar` to reject such values instead.
+    pub fn uses_base256(&self) -> bool {
+        let old = self.as_old();
+        let mut fields = vec![&old.size[..], &old.uid[..], &old.gid[..], &old.mtime[..]];
+        if let Some(gnu) = self.as_gnu() {
+            fields.extend_from_slice(&[&gnu.atime[..], &gnu.ctime[..], &gnu.realsize[..]]);
+            for block in gnu.sparse.iter() {
+                fields.extend_from_slice(&[&block.offset[..], &block.numbytes[..]]);
+            }
+        }
+        fields.iter().any(|field| field[0] & 0x80 != 0)
+    }
+
     /// Return the user name of the owner of this file.
     ///
     /// A return value of `Ok(Some(..))` indicates that the user name was
@@ -670,9 +760,29 @@ impl Header {
 
     /// Sets the checksum field of this header based on the current fields in
     /// this header.
+    ///
+    /// The field is written in the canonical `ChecksumFormat::Strict` layout.
     pub fn set_cksum(&mut self) {
+        self.set_cksum_with(ChecksumFormat::Strict);
+    }
+
+    /// Sets the checksum field of this header based on the current fields in
+    /// this header, using the given layout for the field.
+    pub fn set_cksum_with(&mut self, format: ChecksumFormat) {
         let cksum = self.calculate_cksum();
-        octal_into(&mut self.as_old_mut().cksum, cksum);
+        let field = &mut self.as_old_mut().cksum;
+        match format {
+            ChecksumFormat::Strict => {
+                // The largest possible sum, 512 * 255, fits in six digits.
+                octal_into(&mut field[..7], cksum);
+                field[6] = 0;
+                field[7] = b' ';
+            }
+            ChecksumFormat::Compat => {
+                octal_into(field, cksum);
+                field[7] = 0;
+            }
+        }
     }
 
     fn calculate_cksum(&self) -> u32 {
@@ -901,6 +1011,398 @@ impl fmt::Debug for Header {
     }
 }
 
+/// A builder for a `Header`, setting its fields in one chain.
+///
+/// Fallible fields like the path are only encoded by `build`, which also
+/// validates the result and computes the checksum. Unless set otherwise the
+/// header describes a regular file of size 0 owned by root, with mode
+/// `0o644`, or `0o755` for directories, and modification time 0.
+///
+/// ```
+/// use async_tar::{EntryType, HeaderBuilder};
+///
+/// let header = HeaderBuilder::new_gnu()
+///     .path("bin/hello")
+///     .size(42)
+///     .mode(0o755)
+///     .mtime(1_600_000_000)
+///     .build()?;
+/// assert_eq!(header.entry_type(), EntryType::Regular);
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct HeaderBuilder {
+    header: Header,
+    path: Option<PathBuf>,
+    link_name: Option<PathBuf>,
+    mode: Option<u32>,
+    username: Option<String>,
+    groupname: Option<String>,
+    strict_ustar: bool,
+    pax_records: Vec<u8>,
+    xattrs: Vec<(OsString, Vec<u8>)>,
+    xattr_format: XattrFormat,
+    mtime_clamp: Option<u64>,
+}
+
+impl HeaderBuilder {
+    /// Starts building a GNU header, see `Header::new_gnu`.
+    pub fn new_gnu() -> HeaderBuilder {
+        HeaderBuilder::from_header(Header::new_gnu())
+    }
+
+    /// Starts building a UStar header, see `Header::new_ustar`.
+    pub fn new_ustar() -> HeaderBuilder {
+        HeaderBuilder::from_header(Header::new_ustar())
+    }
+
+    /// Starts building an old header, see `Header::new_old`.
+    ///
+    /// These are plain V7 headers without magic or prefix, as accepted by the
+    /// oldest extractors. That format only knows regular files, hard links
+    /// and symbolic links, so directories are stored as regular entries with
+    /// a trailing slash, and `build` fails for the other entry types as well
+    /// as for user and group names.
+    pub fn new_old() -> HeaderBuilder {
+        HeaderBuilder::from_header(Header::new_old())
+    }
+
+    fn from_header(mut header: Header) -> HeaderBuilder {
+        header.set_size(0);
+        header.set_uid(0);
+        header.set_gid(0);
+        HeaderBuilder {
+            mode: None,
+            ..HeaderBuilder::keeping(header)
+        }
+    }
+
+    /// Starts building from an existing `header`, keeping its type, owner,
+    /// mode and times unless they are set otherwise.
+    pub(crate) fn keeping(header: Header) -> HeaderBuilder {
+        HeaderBuilder {
+            mode: header.mode().ok(),
+            header,
+            path: None,
+            link_name: None,
+            username: None,
+            groupname: None,
+            strict_ustar: false,
+            pax_records: Vec::new(),
+            xattrs: Vec::new(),
+            xattr_format: XattrFormat::Schily,
+            mtime_clamp: None,
+        }
+    }
+
+    /// Sets the path of the entry, which is required.
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the target of a link entry, which is required for hard links and
+    /// symbolic links.
+    pub fn link_name<P: AsRef<Path>>(mut self, link_name: P) -> Self {
+        self.link_name = Some(link_name.as_ref().to_path_buf());
+        self
+    }
+
+    /// Sets the type of the entry.
+    pub fn entry_type(mut self, ty: EntryType) -> Self {
+        self.header.set_entry_type(ty);
+        self
+    }
+
+    /// Sets the size of the data of the entry.
+    pub fn size(mut self, size: u64) -> Self {
+        self.header.set_size(size);
+        self
+    }
+
+    /// Sets the mode bits of the entry.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Sets the modification time of the entry in Unix time format.
+    pub fn mtime(mut self, mtime: u64) -> Self {
+        let mtime = match self.mtime_clamp {
+            Some(max) => cmp::min(mtime, max),
+            None => mtime,
+        };
+        self.header.set_mtime(mtime);
+        self
+    }
+
+    /// Caps the modification time at `max`, in Unix time format, like the
+    /// `--clamp-mtime` option of GNU tar.
+    ///
+    /// Passing the value of `SOURCE_DATE_EPOCH` makes archives reproducible.
+    /// The limit applies to times set before and after this call, so it can
+    /// be set once on a builder which is cloned for every entry.
+    pub fn clamp_mtime(mut self, max: u64) -> Self {
+        self.mtime_clamp = Some(max);
+        if let Ok(mtime) = self.header.mtime() {
+            self.header.set_mtime(cmp::min(mtime, max));
+        }
+        self
+    }
+
+    /// Sets the user ID of the owner of the entry.
+    pub fn uid(mut self, uid: u64) -> Self {
+        self.header.set_uid(uid);
+        self
+    }
+
+    /// Sets the group ID of the owner of the entry.
+    pub fn gid(mut self, gid: u64) -> Self {
+        self.header.set_gid(gid);
+        self
+    }
+
+    /// Sets the user name of the owner of the entry.
+    pub fn username(mut self, name: &str) -> Self {
+        self.username = Some(name.to_string());
+        self
+    }
+
+    /// Sets the group name of the owner of the entry.
+    pub fn groupname(mut self, name: &str) -> Self {
+        self.groupname = Some(name.to_string());
+        self
+    }
+
+    /// Indicate whether numeric fields too large for their octal encoding
+    /// make `build` fail, rather than being stored in base-256 form.
+    ///
+    /// This keeps headers readable by strict ustar implementations and is
+    /// disabled by default, see `Header::uses_base256`.
+    pub fn strict_ustar(mut self, strict: bool) -> Self {
+        self.strict_ustar = strict;
+        self
+    }
+
+    /// Adds a custom pax record, such as `SCHILY.xattr.user.comment` or a
+    /// vendor specific `MYAPP.checksum`, to describe the entry.
+    ///
+    /// Records are written in a pax extended header by
+    /// `build_with_extensions`, for both GNU and UStar headers, and are
+    /// ignored by `build`.
+    pub fn pax_extension<K, V>(mut self, key: K, value: V) -> Self
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let record = pax_record(key.as_ref(), value.as_ref());
+        self.pax_records.extend_from_slice(&record);
+        self
+    }
+
+    /// Adds several custom pax records, see `pax_extension`.
+    pub fn pax_extensions<I, K, V>(self, records: I) -> Self
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        records.into_iter().fold(self, |builder, (key, value)| {
+            builder.pax_extension(key, value)
+        })
+    }
+
+    /// Adds an extended attribute of the entry.
+    ///
+    /// Attributes are stored in pax records by `build_with_extensions` just
+    /// like custom records, see `pax_extension`, and are ignored by `build`.
+    pub fn xattr<N: AsRef<OsStr>>(mut self, name: N, value: &[u8]) -> Self {
+        self.xattrs
+            .push((name.as_ref().to_os_string(), value.to_vec()));
+        self
+    }
+
+    /// Sets the form of the pax records holding extended attributes.
+    ///
+    /// This is `XattrFormat::Schily` by default, which GNU tar understands.
+    pub fn xattr_format(mut self, format: XattrFormat) -> Self {
+        self.xattr_format = format;
+        self
+    }
+
+    /// Encodes the fields into the header and computes its checksum.
+    ///
+    /// This fails if the path is missing, a link has no link name, or a
+    /// field cannot be encoded in the chosen header format, for example a
+    /// path which is too long or, with `strict_ustar`, a size of 8 GiB.
+    pub fn build(self) -> io::Result<Header> {
+        let HeaderBuilder {
+            mut header,
+            path,
+            link_name,
+            mode,
+            username,
+            groupname,
+            strict_ustar,
+            pax_records: _,
+            xattrs: _,
+            xattr_format: _,
+            mtime_clamp: _,
+        } = self;
+        if strict_ustar && header.uses_base256() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "numeric field too large for a strict ustar header",
+            ));
+        }
+        let kind = header.entry_type();
+        let mut path = match path {
+            Some(path) => path.into_os_string(),
+            None => return Err(other("header path is not set")),
+        };
+        if header.as_ustar().is_none() && header.as_gnu().is_none() {
+            if kind.is_dir() {
+                if !ends_with_slash(Path::new(&path)) {
+                    path.push("/");
+                }
+                header.set_entry_type(EntryType::Regular);
+            } else if !(kind.is_file() || kind.is_hard_link() || kind.is_symlink()) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{} entries cannot be stored in an old header", kind),
+                ));
+            }
+        }
+        header.set_path(path)?;
+        match link_name {
+            Some(link_name) => header.set_link_name(link_name)?,
+            None if kind.is_hard_link() || kind.is_symlink() => {
+                return Err(other("header link name is not set"))
+            }
+            None => {}
+        }
+        let default_mode = if kind.is_dir() { 0o755 } else { 0o644 };
+        header.set_mode(mode.unwrap_or(default_mode));
+        if let Some(name) = username {
+            header.set_username(&name)?;
+        }
+        if let Some(name) = groupname {
+            header.set_groupname(&name)?;
+        }
+        header.set_cksum();
+        Ok(header)
+    }
+
+    /// Like `build`, but stores a path or link name which does not fit the
+    /// header in an extension instead of failing, returning the blocks to
+    /// write before the data of the entry.
+    ///
+    /// GNU headers are preceded by GNU long name and long link entries, UStar
+    /// headers by a pax extended header with `path` and `linkpath` records.
+    /// The header itself keeps the truncated names for readers which do not
+    /// understand the extension. Custom records added with `pax_extension`
+    /// go into the same pax extended header, which GNU headers get as well.
+    /// Old headers have no extensions, so they fail just like with `build`,
+    /// and also when custom records were added. Without long names or custom
+    /// records, the result is the header alone.
+    ///
+    /// This makes the constructor pick the dialect of the archive: GNU
+    /// headers store numbers too large for octal in base-256 form, while
+    /// UStar headers store them in `size`, `uid`, `gid` and `mtime` pax
+    /// records. The header then holds 0 for the owner and time, and keeps the
+    /// base-256 size for readers without pax support, so a size of 8 GiB or
+    /// more still fails with `strict_ustar`. Use `build` for plain UStar
+    /// headers, which fail for names that do not fit.
+    pub fn build_with_extensions(mut self) -> io::Result<Vec<u8>> {
+        let gnu = self.header.as_gnu().is_some();
+        if !gnu && self.header.as_ustar().is_none() {
+            if !self.pax_records.is_empty() || !self.xattrs.is_empty() {
+                return Err(other("old headers cannot carry pax records"));
+            }
+            return self.build().map(|header| header.as_bytes().to_vec());
+        }
+        let mut custom = Vec::new();
+        for (name, value) in &self.xattrs {
+            custom.extend(xattr_records(name.as_bytes(), value, self.xattr_format));
+        }
+        custom.extend_from_slice(&self.pax_records);
+        let mut long_path = None;
+        if let Some(path) = &self.path {
+            if self.header.clone().set_path(path).is_err() {
+                long_path = Some(encode_path(path, false)?);
+                self.path = Some(PathBuf::from("x"));
+            }
+        }
+        let mut long_link = None;
+        if let Some(link_name) = &self.link_name {
+            if self.header.clone().set_link_name(link_name).is_err() {
+                long_link = Some(encode_path(link_name, true)?);
+                self.link_name = Some(PathBuf::from("x"));
+            }
+        }
+        let mut numbers = Vec::new();
+        if !gnu {
+            let old = self.header.as_old_mut();
+            let fields = [
+                ("size", &mut old.size[..]),
+                ("uid", &mut old.uid[..]),
+                ("gid", &mut old.gid[..]),
+                ("mtime", &mut old.mtime[..]),
+            ];
+            for (key, field) in fields {
+                if field[0] & 0x80 != 0 {
+                    numbers.push((key, num_field_wrapper_from(field)?));
+                    if key != "size" {
+                        octal_into(field, 0);
+                    }
+                }
+            }
+        }
+        let mut header = self.build()?;
+
+        let mut blocks = Vec::new();
+        let mut records = Vec::new();
+        if gnu {
+            let longs = [
+                (EntryType::GNULongName, &long_path),
+                (EntryType::GNULongLink, &long_link),
+            ];
+            for (kind, long) in longs.iter() {
+                if let Some(name) = long {
+                    let mut data = name.clone();
+                    data.push(0);
+                    push_extension(&mut blocks, Header::new_gnu(), *kind, &data);
+                }
+            }
+        } else {
+            if let Some(path) = &long_path {
+                records.extend(pax_record(b"path", path));
+            }
+            if let Some(link_name) = &long_link {
+                records.extend(pax_record(b"linkpath", link_name));
+            }
+            for (key, value) in numbers {
+                records.extend(pax_record(key.as_bytes(), value.to_string().as_bytes()));
+            }
+        }
+        records.extend_from_slice(&custom);
+        if !records.is_empty() {
+            let kind = EntryType::XHeader;
+            push_extension(&mut blocks, Header::new_ustar(), kind, &records);
+        }
+
+        if let Some(path) = &long_path {
+            copy_truncated(&mut header.as_old_mut().name, path);
+        }
+        if let Some(link_name) = &long_link {
+            copy_truncated(&mut header.as_old_mut().linkname, link_name);
+        }
+        header.set_cksum();
+        blocks.extend_from_slice(header.as_bytes());
+        Ok(blocks)
+    }
+}
+
 impl OldHeader {
     /// Views this as a normal `Header`
     pub fn as_header(&self) -> &Header {
@@ -948,6 +1450,58 @@ impl UstarHeader {
         self._set_path(p.as_ref())
     }
 
+    /// Returns whether this header was written in the xstar or xustar
+    /// dialect of `star`.
+    ///
+    /// These dialects shorten `prefix` to 131 bytes and store the access and
+    /// change times in the remainder of it. Like GNU tar, a header is taken
+    /// to be one of them if it carries the `tar\0` signature at its end, or
+    /// if the prefix is terminated and both times look like octal numbers.
+    pub fn is_star(&self) -> bool {
+        let octal =
+            |field: &[u8]| matches!(field[0], b'0'..=b'7') && field[field.len() - 1] == b' ';
+        self.pad[8..] == b"tar\0"[..]
+            || (self.prefix[130] == 0
+                && octal(&self.prefix[131..143])
+                && octal(&self.prefix[143..]))
+    }
+
+    /// Like `path_bytes`, but reads `prefix` the way `star` lays it out, see
+    /// `is_star`.
+    pub fn star_path_bytes(&self) -> Cow<[u8]> {
+        let prefix = truncate(&self.prefix[..131]);
+        if prefix.is_empty() {
+            Cow::Borrowed(truncate(&self.name))
+        } else {
+            let mut bytes = prefix.to_vec();
+            bytes.push(b'/');
+            bytes.extend_from_slice(truncate(&self.name));
+            Cow::Owned(bytes)
+        }
+    }
+
+    /// Returns the last access time stored by `star` in Unix time format, see
+    /// `is_star`.
+    pub fn star_atime(&self) -> io::Result<u64> {
+        octal_from(&self.prefix[131..143]).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("{} when getting atime for {}", err, self.path_lossy()),
+            )
+        })
+    }
+
+    /// Returns the last change time stored by `star` in Unix time format, see
+    /// `is_star`.
+    pub fn star_ctime(&self) -> io::Result<u64> {
+        octal_from(&self.prefix[143..]).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("{} when getting ctime for {}", err, self.path_lossy()),
+            )
+        })
+    }
+
     fn _set_path(&mut self, path: &Path) -> io::Result<()> {
         // This can probably be optimized quite a bit more, but for now just do
         // something that's relatively easy and readable.
@@ -1223,7 +1777,7 @@ impl GnuHeader {
     /// This is applicable for sparse files where the returned size here is the
     /// size of the entire file after the sparse regions have been filled in.
     pub fn real_size(&self) -> io::Result<u64> {
-        octal_from(&self.realsize).map_err(|err| {
+        num_field_wrapper_from(&self.realsize).map_err(|err| {
             io::Error::new(
                 err.kind(),
                 format!(
@@ -1235,6 +1789,26 @@ impl GnuHeader {
         })
     }
 
+    /// Returns the offset within the file at which the data following this
+    /// header starts.
+    ///
+    /// This is only set in `GNUMultiVolume` headers, which continue a file
+    /// split across volumes, see `MultiVolumeReader`.
+    pub fn offset(&self) -> io::Result<u64> {
+        num_field_wrapper_from(&self.offset).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("{} when getting offset for {}", err, self.fullname_lossy()),
+            )
+        })
+    }
+
+    /// Encodes the offset within the file at which the data following this
+    /// header starts, see `offset`.
+    pub fn set_offset(&mut self, offset: u64) {
+        num_field_wrapper_into(&mut self.offset, offset);
+    }
+
     /// Indicates whether this header will be followed by additional
     /// sparse-header records.
     ///
@@ -1244,6 +1818,67 @@ impl GnuHeader {
         self.isextended[0] == 1
     }
 
+    /// Encodes the size of the file after the sparse regions have been filled
+    /// in, see `real_size`.
+    ///
+    /// Like `Header::set_size`, sizes of 8 GiB and more are stored in base-256
+    /// form.
+    pub fn set_real_size(&mut self, size: u64) {
+        num_field_wrapper_into(&mut self.realsize, size);
+    }
+
+    /// Sets whether extended sparse headers follow this header.
+    pub fn set_is_extended(&mut self, extended: bool) {
+        self.isextended[0] = extended as u8;
+    }
+
+    /// Records a block of `length` bytes of data at `offset` in the first free
+    /// slot of the four sparse records of this header.
+    ///
+    /// Returns an error if all slots are taken, see `set_sparse_blocks` for
+    /// maps which need extended sparse headers.
+    pub fn add_sparse_block(&mut self, offset: u64, length: u64) -> io::Result<()> {
+        add_sparse_block(&mut self.sparse, offset, length)
+    }
+
+    /// Records the data blocks of a sparse file as `(offset, length)` pairs,
+    /// replacing any recorded before.
+    ///
+    /// Readers expect every block but the last to end on a 512 byte boundary,
+    /// and the last one to end at the `real_size` of the file.
+    ///
+    /// The first four blocks are stored in this header. The returned extended
+    /// sparse headers hold the rest, with the extension flags of this header
+    /// and of each extended header set as needed, and are to be written
+    /// directly after this header.
+    pub fn set_sparse_blocks(&mut self, blocks: &[(u64, u64)]) -> Vec<GnuExtSparseHeader> {
+        let (first, rest) = blocks.split_at(cmp::min(blocks.len(), self.sparse.len()));
+        for slot in self.sparse.iter_mut() {
+            slot.offset = [0; 12];
+            slot.numbytes = [0; 12];
+        }
+        for &(offset, length) in first {
+            add_sparse_block(&mut self.sparse, offset, length).expect("room for block");
+        }
+        let mut extensions: Vec<GnuExtSparseHeader> = rest
+            .chunks(21)
+            .map(|chunk| {
+                let mut ext = GnuExtSparseHeader::new();
+                for &(offset, length) in chunk {
+                    ext.add_sparse_block(offset, length)
+                        .expect("room for block");
+                }
+                ext.set_is_extended(true);
+                ext
+            })
+            .collect();
+        if let Some(last) = extensions.last_mut() {
+            last.set_is_extended(false);
+        }
+        self.set_is_extended(!extensions.is_empty());
+        extensions
+    }
+
     /// Views this as a normal `Header`
     pub fn as_header(&self) -> &Header {
         unsafe { cast(self) }
@@ -1295,7 +1930,7 @@ impl GnuSparseHeader {
     ///
     /// Returns `Err` for a malformed `offset` field.
     pub fn offset(&self) -> io::Result<u64> {
-        octal_from(&self.offset).map_err(|err| {
+        num_field_wrapper_from(&self.offset).map_err(|err| {
             io::Error::new(
                 err.kind(),
                 format!("{} when getting offset from sparse header", err),
@@ -1307,13 +1942,27 @@ impl GnuSparseHeader {
     ///
     /// Returns `Err` for a malformed `numbytes` field.
     pub fn length(&self) -> io::Result<u64> {
-        octal_from(&self.numbytes).map_err(|err| {
+        num_field_wrapper_from(&self.numbytes).map_err(|err| {
             io::Error::new(
                 err.kind(),
                 format!("{} when getting length from sparse header", err),
             )
         })
     }
+
+    /// Encodes the offset of the block from the start of the file.
+    ///
+    /// Offsets of 8 GiB and more are stored in base-256 form.
+    pub fn set_offset(&mut self, offset: u64) {
+        num_field_wrapper_into(&mut self.offset, offset);
+    }
+
+    /// Encodes the length of the block.
+    ///
+    /// Lengths of 8 GiB and more are stored in base-256 form.
+    pub fn set_length(&mut self, length: u64) {
+        num_field_wrapper_into(&mut self.numbytes, length);
+    }
 }
 
 impl fmt::Debug for GnuSparseHeader {
@@ -1359,6 +2008,19 @@ impl GnuExtSparseHeader {
     pub fn is_extended(&self) -> bool {
         self.isextended[0] == 1
     }
+
+    /// Sets whether another sparse header follows this one.
+    pub fn set_is_extended(&mut self, extended: bool) {
+        self.isextended[0] = extended as u8;
+    }
+
+    /// Records a block of `length` bytes of data at `offset` in the first free
+    /// slot of the 21 sparse records of this header.
+    ///
+    /// Returns an error if all slots are taken.
+    pub fn add_sparse_block(&mut self, offset: u64, length: u64) -> io::Result<()> {
+        add_sparse_block(&mut self.sparse, offset, length)
+    }
 }
 
 impl Default for GnuExtSparseHeader {
@@ -1467,6 +2129,54 @@ fn copy_into(slot: &mut [u8], bytes: &[u8]) -> io::Result<()> {
     }
 }
 
+/// Stores a sparse block in the first of `slots` which is unused.
+fn add_sparse_block(slots: &mut [GnuSparseHeader], offset: u64, length: u64) -> io::Result<()> {
+    // Unused slots are all zeros, while set fields always start with a digit.
+    let slot = slots
+        .iter_mut()
+        .find(|slot| slot.offset[0] == 0 && slot.numbytes[0] == 0)
+        .ok_or_else(|| other("no room for another sparse block in the header"))?;
+    slot.set_offset(offset);
+    slot.set_length(length);
+    Ok(())
+}
+
+/// Returns `path` as `copy_path_into` would store it, without a length limit.
+fn encode_path(path: &Path, is_link_name: bool) -> io::Result<Vec<u8>> {
+    // Room for a trailing slash and the nul terminator.
+    let mut slot = vec![0; path2bytes(path)?.len() + 2];
+    copy_path_into(&mut slot, path, is_link_name)?;
+    let len = slot.iter().position(|b| *b == 0).unwrap_or(slot.len());
+    slot.truncate(len);
+    Ok(slot)
+}
+
+/// Copies as much of `bytes` as fits into `slot`, padding it with nuls.
+fn copy_truncated(slot: &mut [u8], bytes: &[u8]) {
+    let len = cmp::min(slot.len(), bytes.len());
+    slot[..len].copy_from_slice(&bytes[..len]);
+    slot[len..].iter_mut().for_each(|b| *b = 0);
+}
+
+/// Appends an extension entry of type `kind` holding `data` to `blocks`.
+fn push_extension(blocks: &mut Vec<u8>, mut header: Header, kind: EntryType, data: &[u8]) {
+    let name: &[u8] = if kind.is_pax_local_extensions() {
+        b"././@PaxHeader"
+    } else {
+        b"././@LongLink"
+    };
+    copy_truncated(&mut header.as_old_mut().name, name);
+    header.set_entry_type(kind);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_size(data.len() as u64);
+    header.set_cksum();
+    blocks.extend_from_slice(header.as_bytes());
+    blocks.extend_from_slice(data);
+    blocks.resize((blocks.len() + 511) & !(512 - 1), 0);
+}
+
 /// Copies `path` into the `slot` provided
 ///
 /// Returns an error if:
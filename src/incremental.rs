@@ -0,0 +1,152 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+};
+
+use crate::other;
+
+/// The kind of a record in the listing of a GNU dumpdir entry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DumpDirKind {
+    /// A file whose data is contained in the archive, stored as `Y`.
+    Dumped,
+    /// A file which is unchanged since an earlier backup and not contained
+    /// in the archive, stored as `N`.
+    Unchanged,
+    /// A subdirectory, stored as `D`.
+    Directory,
+    /// The old path of a renamed directory, stored as `R`. The new path
+    /// follows in a `RenameTo` record.
+    RenameFrom,
+    /// The new path of the directory named by the preceding `RenameFrom`
+    /// record, stored as `T`.
+    RenameTo,
+    /// A temporary name used to break cycles of renames, stored as `X`.
+    Temporary,
+}
+
+/// A record in the listing of a GNU dumpdir entry, see
+/// `Entry::dumpdir_entries`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DumpDirEntry {
+    kind: DumpDirKind,
+    name: OsString,
+}
+
+impl DumpDirEntry {
+    /// Returns what this record describes.
+    pub fn kind(&self) -> DumpDirKind {
+        self.kind
+    }
+
+    /// Returns the name of the directory member, or the path for renames.
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+}
+
+/// Parses the data of a GNU dumpdir entry into its records.
+pub(crate) fn parse_dumpdir(data: &[u8]) -> io::Result<Vec<DumpDirEntry>> {
+    let mut records = Vec::new();
+    for record in data.split(|b| *b == 0) {
+        let (&kind, name) = match record.split_first() {
+            Some(record) => record,
+            // The listing ends with an empty record.
+            None => break,
+        };
+        let kind = match kind {
+            b'Y' => DumpDirKind::Dumped,
+            b'N' => DumpDirKind::Unchanged,
+            b'D' => DumpDirKind::Directory,
+            b'R' => DumpDirKind::RenameFrom,
+            b'T' => DumpDirKind::RenameTo,
+            b'X' => DumpDirKind::Temporary,
+            _ => {
+                return Err(other(&format!(
+                    "unknown dumpdir record type `{}`",
+                    kind.escape_ascii()
+                )))
+            }
+        };
+        records.push(DumpDirEntry {
+            kind,
+            name: OsStr::from_bytes(name).to_os_string(),
+        });
+    }
+    Ok(records)
+}
+
+/// The contents of a directory as recorded by an incremental backup made with
+/// `tar --listed-incremental`, see `Entry::incremental_dir`.
+///
+/// Restoring such a backup like GNU tar does means removing the files in the
+/// directory which are not listed anymore, see `deleted_from`, and applying
+/// the recorded renames before unpacking the entries of the archive.
+#[derive(Clone, Debug, Default)]
+pub struct IncrementalDir {
+    contents: Vec<(OsString, bool)>,
+    renames: Vec<(PathBuf, PathBuf)>,
+}
+
+impl IncrementalDir {
+    /// Builds the listing from the records of a GNU dumpdir entry.
+    pub(crate) fn from_records(records: &[DumpDirEntry]) -> io::Result<IncrementalDir> {
+        let mut dir = IncrementalDir::default();
+        let mut renamed = None;
+        for record in records {
+            let name = record.name.clone();
+            match record.kind {
+                DumpDirKind::Dumped | DumpDirKind::Directory => dir.contents.push((name, true)),
+                DumpDirKind::Unchanged => dir.contents.push((name, false)),
+                DumpDirKind::RenameFrom => renamed = Some(PathBuf::from(name)),
+                DumpDirKind::RenameTo => match renamed.take() {
+                    Some(from) => dir.renames.push((from, PathBuf::from(name))),
+                    None => return Err(other("dumpdir rename target without a source")),
+                },
+                DumpDirKind::Temporary => {}
+            }
+        }
+        if renamed.is_some() {
+            return Err(other("dumpdir rename source without a target"));
+        }
+        Ok(dir)
+    }
+
+    /// Returns the names of the files and directories which were in the
+    /// directory at the time of the backup.
+    pub fn contents(&self) -> impl Iterator<Item = &OsStr> {
+        self.contents.iter().map(|(name, _)| name.as_os_str())
+    }
+
+    /// Returns whether the member `name` of the directory is contained in
+    /// this archive, rather than being unchanged since an earlier backup.
+    pub fn is_dumped(&self, name: &OsStr) -> bool {
+        self.contents
+            .iter()
+            .any(|(n, dumped)| *dumped && n.as_os_str() == name)
+    }
+
+    /// Returns the renames recorded since the earlier backup, as pairs of the
+    /// old and new path.
+    pub fn renames(&self) -> &[(PathBuf, PathBuf)] {
+        &self.renames
+    }
+
+    /// Returns the names of the files in `dir`, the restored directory, which
+    /// were deleted before the backup was made and should be removed.
+    pub async fn deleted_from(&self, dir: &Path) -> io::Result<Vec<OsString>> {
+        let mut deleted = Vec::new();
+        let mut read_dir = tokio::fs::read_dir(dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let name = entry.file_name();
+            if !self.contents.iter().any(|(n, _)| *n == name) {
+                deleted.push(name);
+            }
+        }
+        deleted.sort();
+        Ok(deleted)
+    }
+}
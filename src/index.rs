@@ -0,0 +1,296 @@
+use std::{
+    collections::HashMap,
+    io,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+};
+
+use futures_util::StreamExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, SeekFrom, Take};
+
+use crate::{entry::Segment, other, Archive, ArchiveBuilder, Header};
+
+/// An in-memory index of the members of an archive.
+///
+/// The index records, for every entry, where its header and data live in the
+/// underlying byte stream. Combined with a reader implementing `AsyncSeek`,
+/// this allows random access to the contents of individual members without
+/// walking the archive again.
+///
+/// When the same path is listed multiple times, lookups by path resolve to
+/// the last occurrence, matching the behavior of extraction.
+#[derive(Debug, Clone, Default)]
+pub struct ArchiveIndex {
+    entries: Vec<IndexEntry>,
+    by_path: HashMap<PathBuf, usize>,
+}
+
+/// A single member recorded in an `ArchiveIndex`.
+#[derive(Debug, Clone)]
+pub struct IndexEntry {
+    path: PathBuf,
+    link_name: Option<PathBuf>,
+    header: Header,
+    header_pos: u64,
+    file_pos: u64,
+    span: Range<u64>,
+    size: u64,
+    contiguous: bool,
+    /// Stretches of data and holes of a sparse entry, empty otherwise.
+    sparse: Vec<Segment>,
+}
+
+impl ArchiveIndex {
+    /// Builds an index by walking all entries of `archive`.
+    ///
+    /// Entry bodies are skipped rather than buffered, so this only needs a
+    /// sequential pass over the archive. Positions recorded in the index are
+    /// relative to the start of the archive.
+    pub async fn build<R: AsyncRead + Unpin>(archive: Archive<R>) -> io::Result<ArchiveIndex> {
+        let mut index = ArchiveIndex::default();
+        let mut entries = archive.entries()?;
+        while let Some(entry) = entries.next().await {
+            let entry = entry?;
+            let header = entry.header().clone();
+            let size = entry.logical_size()?;
+            let link_name = entry.link_name()?.map(|l| l.into_owned());
+            // Sparse files keep their data, and for pax sparse files their
+            // sparse map, in one run which does not match the contents.
+            let pax_sparse = match entry.pax_headers() {
+                Some(pax) => pax
+                    .filter_map(Result::ok)
+                    .any(|ext| is_sparse_key(ext.key_bytes())),
+                None => false,
+            };
+            let sparse = header.entry_type().is_gnu_sparse() || pax_sparse;
+            index.push(IndexEntry {
+                path: normalize(&entry.path()?),
+                link_name,
+                contiguous: !sparse && size <= entry.raw_padded_size(),
+                header_pos: entry.raw_header_position(),
+                file_pos: entry.raw_file_position(),
+                span: entry.raw_span(),
+                size,
+                sparse: if sparse {
+                    entry.segments().to_vec()
+                } else {
+                    Vec::new()
+                },
+                header,
+            });
+        }
+        Ok(index)
+    }
+
+    /// Indexes the archive in a seekable `reader`, handing the reader back
+    /// once done.
+    ///
+    /// The reader is rewound to position 0 first, which must be the start of
+    /// the archive. Entry data is seeked over rather than read.
+    pub async fn build_seekable<R>(mut reader: R) -> io::Result<(R, ArchiveIndex)>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+    {
+        reader.seek(SeekFrom::Start(0)).await?;
+        let archive = ArchiveBuilder::new(reader).set_seek_skips(true).build();
+        let index = ArchiveIndex::build(archive.clone()).await?;
+        let reader = archive
+            .into_inner()
+            .map_err(|_| other("archive still in use after indexing"))?;
+        Ok((reader, index))
+    }
+
+    fn push(&mut self, entry: IndexEntry) {
+        self.by_path.insert(entry.path.clone(), self.entries.len());
+        self.entries.push(entry);
+    }
+
+    /// Looks up the entry stored under `path`.
+    ///
+    /// Leading `/` and `./` components as well as trailing slashes are
+    /// ignored, so `"/a/b/"`, `"./a/b"` and `"a/b"` all name the same entry.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&IndexEntry> {
+        self.by_path
+            .get(&normalize(path.as_ref()))
+            .map(|i| &self.entries[*i])
+    }
+
+    #[cfg(feature = "fuse")]
+    pub(crate) fn entry(&self, i: usize) -> &IndexEntry {
+        &self.entries[i]
+    }
+
+    /// Returns an iterator over all indexed entries in archive order.
+    pub fn iter(&self) -> std::slice::Iter<'_, IndexEntry> {
+        self.entries.iter()
+    }
+
+    /// Returns the number of entries in the index.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the archive had no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl<'a> IntoIterator for &'a ArchiveIndex {
+    type Item = &'a IndexEntry;
+    type IntoIter = std::slice::Iter<'a, IndexEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl IndexEntry {
+    /// Returns the normalized path of this entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the link target of this entry, if it is a link.
+    pub fn link_name(&self) -> Option<&Path> {
+        self.link_name.as_deref()
+    }
+
+    /// Returns the header of this entry.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Returns the logical size of the entry's contents, see
+    /// `Entry::logical_size`.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the starting position, in bytes, of the header of this entry.
+    pub fn raw_header_position(&self) -> u64 {
+        self.header_pos
+    }
+
+    /// Returns the starting position, in bytes, of the data of this entry.
+    pub fn raw_file_position(&self) -> u64 {
+        self.file_pos
+    }
+
+    /// Returns the range of bytes this entry occupies in the archive,
+    /// including the headers describing it, see `Entry::raw_span`.
+    pub fn raw_span(&self) -> Range<u64> {
+        self.span.clone()
+    }
+
+    /// Returns the number of bytes the data of this entry occupies in the
+    /// archive, including padding, see `Entry::raw_padded_size`.
+    pub fn raw_padded_size(&self) -> u64 {
+        self.span.end - self.file_pos
+    }
+
+    /// Returns whether the data of this entry is stored as one contiguous
+    /// run of bytes, which is required for range reads.
+    ///
+    /// This is `false` for sparse files, whether described by GNU headers or
+    /// by pax records.
+    pub fn is_contiguous(&self) -> bool {
+        self.contiguous
+    }
+
+    /// Returns a reader over `len` bytes of this entry's contents, starting
+    /// at `offset` within the entry.
+    ///
+    /// `reader` must be positioned over the same archive this index was built
+    /// from, with position 0 corresponding to the start of the archive. The
+    /// range is clamped to the end of the entry.
+    pub async fn read_range<'r, R>(
+        &self,
+        reader: &'r mut R,
+        offset: u64,
+        len: u64,
+    ) -> io::Result<Take<&'r mut R>>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+    {
+        if !self.contiguous {
+            return Err(other(&format!(
+                "cannot read a range of non-contiguous entry {}",
+                self.path.display()
+            )));
+        }
+        if offset > self.size {
+            return Err(other(&format!(
+                "range start {} is beyond the end of {}",
+                offset,
+                self.path.display()
+            )));
+        }
+        let len = len.min(self.size - offset);
+        reader.seek(SeekFrom::Start(self.file_pos + offset)).await?;
+        Ok(reader.take(len))
+    }
+
+    /// Reads `len` bytes of this entry's contents, starting at `offset`
+    /// within the entry, into `buf`.
+    ///
+    /// Unlike `read_range` this also handles sparse entries, whose holes read
+    /// as zeros while their data is seeked to. The range is clamped to the
+    /// end of the entry.
+    pub(crate) async fn read_range_into<R>(
+        &self,
+        reader: &mut R,
+        offset: u64,
+        len: u64,
+        buf: &mut Vec<u8>,
+    ) -> io::Result<()>
+    where
+        R: AsyncRead + AsyncSeek + Unpin,
+    {
+        if self.sparse.is_empty() {
+            self.read_range(reader, offset, len)
+                .await?
+                .read_to_end(buf)
+                .await?;
+            return Ok(());
+        }
+        let end = offset.saturating_add(len).min(self.size);
+        let mut start = 0;
+        for segment in &self.sparse {
+            let segment_end = start + segment.len;
+            if segment_end > offset && start < end {
+                let from = offset.max(start);
+                let n = end.min(segment_end) - from;
+                match segment.pos {
+                    Some(pos) => {
+                        reader.seek(SeekFrom::Start(pos + from - start)).await?;
+                        let read = (&mut *reader).take(n).read_to_end(buf).await?;
+                        if read as u64 != n {
+                            return Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                format!("data of {} is truncated", self.path.display()),
+                            ));
+                        }
+                    }
+                    None => buf.resize(buf.len() + n as usize, 0),
+                }
+            }
+            start = segment_end;
+        }
+        Ok(())
+    }
+}
+
+/// Returns whether `key` is one of the pax records describing a sparse file,
+/// as opposed to `GNU.sparse.name` which only carries its name.
+fn is_sparse_key(key: &[u8]) -> bool {
+    key.starts_with(b"GNU.sparse.") && key != b"GNU.sparse.name"
+}
+
+/// Normalizes an archive path for lookups, dropping root, `.` and trailing
+/// slash components.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_) | Component::ParentDir))
+        .collect()
+}
@@ -23,21 +23,68 @@
 use std::io::{Error, ErrorKind};
 
 pub use crate::{
-    archive::{Archive, ArchiveBuilder, Entries},
-    entry::{Entry, Unpacked},
-    entry_type::EntryType,
+    archive::{
+        Archive, ArchiveBuilder, DuplicateEntries, Entries, InMemoryEntry, Termination,
+        UnpackReport,
+    },
+    clock::{Clock, FixedClock, SystemClock},
+    digest::DigestAlgorithm,
+    entry::{
+        Entry, EntryBytesStream, MetadataOrder, OwnedEntry, UnknownEntryTypes, Unpacked,
+        UnsupportedMetadata,
+    },
+    entry_type::{EntryType, ParseEntryTypeError},
+    follow::{FollowFuture, FollowReader},
     header::{
-        GnuExtSparseHeader, GnuHeader, GnuSparseHeader, Header, HeaderMode, OldHeader, UstarHeader,
+        ChecksumFormat, GnuExtSparseHeader, GnuHeader, GnuSparseHeader, Header, HeaderBuilder,
+        HeaderMode, OldHeader, UstarHeader, XattrFormat,
     },
+    incremental::{DumpDirEntry, DumpDirKind, IncrementalDir},
+    index::{ArchiveIndex, IndexEntry},
+    limit::{InputLimitExceeded, LimitedReader},
+    link::link_stays_within,
+    metadata::EntryMetadata,
     pax::{PaxExtension, PaxExtensions},
+    repack::repack,
+    repair::{repair, RepairFix, RepairOptions, RepairReport},
+    sink::{ExtractSink, FsSink, SinkFuture},
+    throttle::ThrottledReader,
+    timeout::TimeoutReader,
+    tree::{ArchiveTree, TreeNode},
+    unpack_stream::{UnpackEvent, UnpackStream},
+    volume::MultiVolumeReader,
 };
 
+#[cfg(feature = "arbitrary")]
+mod arbitrary_impls;
 mod archive;
+mod clock;
+mod digest;
 mod entry;
 mod entry_type;
 mod error;
+mod follow;
+#[cfg(feature = "fuse")]
+pub mod fuse;
 mod header;
+mod incremental;
+mod index;
+mod limit;
+mod link;
+mod metadata;
 mod pax;
+mod repack;
+mod repair;
+#[cfg(feature = "serde")]
+mod serde_impls;
+#[cfg(feature = "serve")]
+pub mod serve;
+mod sink;
+mod throttle;
+mod timeout;
+mod tree;
+mod unpack_stream;
+mod volume;
 
 fn other(msg: &str) -> Error {
     Error::new(ErrorKind::Other, msg)
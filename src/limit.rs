@@ -0,0 +1,124 @@
+use std::{
+    error, fmt,
+    io::{self, ErrorKind},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use pin_project::pin_project;
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Error raised when a reader produces more bytes than it is allowed to.
+///
+/// This is returned as the inner error of an `io::Error` of kind
+/// `InvalidData` by `LimitedReader` and by archives configured with
+/// `ArchiveBuilder::set_max_input_bytes`, and can be recovered with
+/// `io::Error::get_ref` and `downcast_ref`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputLimitExceeded {
+    limit: u64,
+}
+
+impl InputLimitExceeded {
+    /// Returns the number of bytes which were allowed.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+}
+
+impl fmt::Display for InputLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "input exceeded the limit of {} bytes", self.limit)
+    }
+}
+
+impl error::Error for InputLimitExceeded {}
+
+/// A reader which fails once its inner reader produces more than a given
+/// number of bytes.
+///
+/// Unlike `AsyncReadExt::take`, exceeding the limit is an error rather than
+/// an end of file, so an endless or oversized stream cannot be mistaken for a
+/// complete one. A stream ending exactly at the limit is read normally.
+#[pin_project]
+#[derive(Debug)]
+pub struct LimitedReader<R> {
+    #[pin]
+    inner: R,
+    read: u64,
+    limit: u64,
+}
+
+impl<R: AsyncRead> LimitedReader<R> {
+    /// Wraps `inner`, allowing at most `limit` bytes to be read from it.
+    pub fn new(inner: R, limit: u64) -> LimitedReader<R> {
+        LimitedReader {
+            inner,
+            read: 0,
+            limit,
+        }
+    }
+
+    /// Returns the number of bytes read so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.read
+    }
+
+    /// Returns the maximum number of bytes which may be read.
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead> AsyncRead for LimitedReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.project();
+        poll_read_limited(this.inner, cx, into, this.read, Some(*this.limit))
+    }
+}
+
+/// Reads from `reader` into `into`, adding the number of bytes read to
+/// `read` and failing with `InputLimitExceeded` once it goes past `limit`.
+pub(crate) fn poll_read_limited<R: AsyncRead>(
+    reader: Pin<&mut R>,
+    cx: &mut Context<'_>,
+    into: &mut ReadBuf<'_>,
+    read: &mut u64,
+    limit: Option<u64>,
+) -> Poll<io::Result<()>> {
+    let filled_before = into.filled().len();
+    match limit {
+        // Ask for one byte more than allowed, which tells a stream ending
+        // exactly at the limit apart from one that goes on.
+        Some(limit) if ((limit - (*read).min(limit)) as usize) < into.remaining() => {
+            let max = (limit - (*read).min(limit)) as usize + 1;
+            let mut buf = into.take(max);
+            let ptr = buf.filled().as_ptr();
+            std::task::ready!(reader.poll_read(cx, &mut buf))?;
+            assert_eq!(ptr, buf.filled().as_ptr());
+            let n = buf.filled().len();
+            // The bytes were initialized by the read into `buf`, which is a
+            // view of the unfilled part of `into`.
+            unsafe { into.assume_init(n) };
+            into.advance(n);
+        }
+        _ => std::task::ready!(reader.poll_read(cx, into))?,
+    }
+    *read += (into.filled().len() - filled_before) as u64;
+    match limit {
+        Some(limit) if *read > limit => Poll::Ready(Err(io::Error::new(
+            ErrorKind::InvalidData,
+            InputLimitExceeded { limit },
+        ))),
+        _ => Poll::Ready(Ok(())),
+    }
+}
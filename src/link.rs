@@ -0,0 +1,49 @@
+use std::path::{Component, Path};
+
+use crate::EntryType;
+
+/// Returns whether the link entry at `path`, pointing at `link_name`, stays
+/// inside of `root` when unpacked there.
+///
+/// Hard link names are relative to `root`, while symbolic link names are
+/// relative to the directory containing `path`. Absolute link names are only
+/// inside when they start with `root`. The check is purely lexical, so links
+/// which escape through symbolic links already on disk are not caught; when
+/// unpacking, hard links are additionally validated by canonicalizing their
+/// target.
+///
+/// Note that unpacking creates symbolic links pointing outside of the
+/// destination, as they are harmless until followed, but never writes
+/// through them.
+pub fn link_stays_within(root: &Path, path: &Path, link_name: &Path, kind: EntryType) -> bool {
+    let link_name = if link_name.has_root() {
+        match link_name.strip_prefix(root) {
+            Ok(rel) => rel,
+            Err(_) => return false,
+        }
+    } else {
+        link_name
+    };
+    let base = match path.parent() {
+        Some(parent) if !kind.is_hard_link() => parent,
+        _ => Path::new(""),
+    };
+
+    let mut depth = 0usize;
+    // Leading `/`s of entry paths are stripped when unpacking.
+    let base = base
+        .components()
+        .filter(|c| !matches!(c, Component::RootDir | Component::Prefix(_)));
+    for component in base.chain(link_name.components()) {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::ParentDir => match depth.checked_sub(1) {
+                Some(parent) => depth = parent,
+                None => return false,
+            },
+            Component::CurDir => {}
+            Component::RootDir | Component::Prefix(_) => return false,
+        }
+    }
+    true
+}
@@ -0,0 +1,146 @@
+use std::{
+    ffi::OsString,
+    io,
+    path::{Path, PathBuf},
+    str,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use tokio::io::AsyncRead;
+
+use crate::{Entry, EntryType};
+
+/// The metadata of an entry, with the GNU and pax records describing it
+/// applied on top of the header, see `Entry::metadata`.
+///
+/// Pax records take precedence over GNU long names and links, which in turn
+/// take precedence over the fields of the header, just like when the entry
+/// is unpacked.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EntryMetadata {
+    entry_type: EntryType,
+    path: PathBuf,
+    link_name: Option<PathBuf>,
+    size: u64,
+    mode: u32,
+    uid: u64,
+    gid: u64,
+    username: Option<Vec<u8>>,
+    groupname: Option<Vec<u8>>,
+    mtime: SystemTime,
+    xattrs: Vec<(OsString, Vec<u8>)>,
+}
+
+impl EntryMetadata {
+    pub(crate) fn new<R: AsyncRead + Unpin>(entry: &Entry<R>) -> io::Result<EntryMetadata> {
+        let header = entry.header();
+        let pax = entry.pax_headers();
+        let pax = pax.as_ref();
+        let number = |key| pax.and_then(|pax| pax.number(key));
+        let name = |key| pax.and_then(|pax| pax.get(key)).map(<[u8]>::to_vec);
+
+        let uid = match number("uid") {
+            Some(uid) => uid,
+            None => header.uid()?,
+        };
+        let gid = match number("gid") {
+            Some(gid) => gid,
+            None => header.gid()?,
+        };
+        let mtime = match pax.and_then(|pax| pax.mtime()) {
+            Some(mtime) => mtime,
+            None => UNIX_EPOCH + Duration::from_secs(header.mtime()?),
+        };
+        let nonempty = |name: &[u8]| Some(name.to_vec()).filter(|name| !name.is_empty());
+        Ok(EntryMetadata {
+            entry_type: header.entry_type(),
+            path: entry.path()?.into_owned(),
+            link_name: entry.link_name()?.map(|name| name.into_owned()),
+            size: entry.logical_size()?,
+            mode: header.mode()?,
+            uid,
+            gid,
+            username: name("uname").or_else(|| header.username_bytes().and_then(nonempty)),
+            groupname: name("gname").or_else(|| header.groupname_bytes().and_then(nonempty)),
+            mtime,
+            xattrs: pax.map_or_else(Vec::new, |pax| pax.xattrs().collect()),
+        })
+    }
+
+    /// Returns the type of the entry.
+    pub fn entry_type(&self) -> EntryType {
+        self.entry_type
+    }
+
+    /// Returns the path of the entry, as returned by `Entry::path`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the target of a link entry, as returned by `Entry::link_name`.
+    pub fn link_name(&self) -> Option<&Path> {
+        self.link_name.as_deref()
+    }
+
+    /// Returns the size of the file, which for sparse files includes the
+    /// holes, see `Entry::logical_size`.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the mode bits of the file.
+    pub fn mode(&self) -> u32 {
+        self.mode
+    }
+
+    /// Returns the user ID of the owner, from the `uid` pax record if there is
+    /// one, which is able to hold IDs too large for the header.
+    pub fn uid(&self) -> u64 {
+        self.uid
+    }
+
+    /// Returns the group ID of the owner, from the `gid` pax record if there
+    /// is one.
+    pub fn gid(&self) -> u64 {
+        self.gid
+    }
+
+    /// Returns the user name of the owner, or `None` if neither the pax
+    /// records nor the header name one.
+    ///
+    /// An `Err` indicates that the name is not valid utf-8.
+    pub fn username(&self) -> Result<Option<&str>, str::Utf8Error> {
+        self.username_bytes().map(str::from_utf8).transpose()
+    }
+
+    /// Returns the user name of the owner as bytes, if present.
+    pub fn username_bytes(&self) -> Option<&[u8]> {
+        self.username.as_deref()
+    }
+
+    /// Returns the group name of the owner, or `None` if neither the pax
+    /// records nor the header name one.
+    ///
+    /// An `Err` indicates that the name is not valid utf-8.
+    pub fn groupname(&self) -> Result<Option<&str>, str::Utf8Error> {
+        self.groupname_bytes().map(str::from_utf8).transpose()
+    }
+
+    /// Returns the group name of the owner as bytes, if present.
+    pub fn groupname_bytes(&self) -> Option<&[u8]> {
+        self.groupname.as_deref()
+    }
+
+    /// Returns the last modification time, including the fractional seconds
+    /// recorded by the `mtime` pax record.
+    pub fn mtime(&self) -> SystemTime {
+        self.mtime
+    }
+
+    /// Returns the extended attributes recorded in the pax records, see
+    /// `PaxExtensions::xattrs`.
+    pub fn xattrs(&self) -> &[(OsString, Vec<u8>)] {
+        &self.xattrs
+    }
+}
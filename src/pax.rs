@@ -1,11 +1,21 @@
-use std::{io, slice, str};
+use std::{
+    cmp,
+    ffi::{OsStr, OsString},
+    io,
+    os::unix::ffi::{OsStrExt, OsStringExt},
+    path::Path,
+    slice, str,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use crate::other;
+use crate::{other, XattrFormat};
 
 /// An iterator over the pax extensions in an archive entry.
 ///
 /// This iterator yields structures which can themselves be parsed into
-/// key/value pairs.
+/// key/value pairs. The well-known records can also be looked up with typed
+/// accessors like `path` or `mtime`, which take care of parsing their values.
+#[derive(Clone)]
 pub struct PaxExtensions<'entry> {
     data: slice::Split<'entry, u8, fn(&u8) -> bool>,
 }
@@ -22,6 +32,220 @@ pub fn pax_extensions(a: &[u8]) -> PaxExtensions {
     }
 }
 
+impl<'entry> PaxExtensions<'entry> {
+    /// Returns the raw value of the record named `key`.
+    ///
+    /// Malformed records are skipped. If a key is listed more than once the
+    /// first occurrence wins, which is the entry's own record when global
+    /// records are included.
+    pub fn get(&self, key: &str) -> Option<&'entry [u8]> {
+        self.clone()
+            .filter_map(Result::ok)
+            .find(|ext| ext.key_bytes() == key.as_bytes())
+            .map(|ext| ext.value_bytes())
+    }
+
+    /// Returns the `path` record, which overrides the path in the header.
+    pub fn path(&self) -> Option<&'entry Path> {
+        self.get("path").map(|v| Path::new(OsStr::from_bytes(v)))
+    }
+
+    /// Returns the `linkpath` record, which overrides the link name in the
+    /// header.
+    pub fn link_path(&self) -> Option<&'entry Path> {
+        self.get("linkpath")
+            .map(|v| Path::new(OsStr::from_bytes(v)))
+    }
+
+    /// Returns `true` if the `hdrcharset` record marks the `path`, `linkpath`,
+    /// `uname` and `gname` records as raw bytes rather than UTF-8.
+    pub fn binary_names(&self) -> bool {
+        self.get("hdrcharset") == Some(b"BINARY")
+    }
+
+    /// Returns the `size` record, which overrides the size in the header.
+    ///
+    /// This is `None` if the record is missing or not a valid number.
+    pub fn size(&self) -> Option<u64> {
+        self.number("size")
+    }
+
+    /// Returns the `mtime` record, which may carry a fractional part and be
+    /// negative.
+    ///
+    /// This is `None` if the record is missing or not a valid timestamp.
+    pub fn mtime(&self) -> Option<SystemTime> {
+        self.get("mtime").and_then(parse_time)
+    }
+
+    /// Returns the extended attributes recorded for the entry, as pairs of
+    /// attribute name and value.
+    ///
+    /// Both the `SCHILY.xattr.` records written by GNU tar and star, and the
+    /// `LIBARCHIVE.xattr.` records with encoded names and base64 values are
+    /// understood. Attributes listed in both forms are only returned once.
+    pub fn xattrs(&self) -> impl Iterator<Item = (OsString, Vec<u8>)> {
+        let mut xattrs: Vec<(OsString, Vec<u8>)> = Vec::new();
+        for ext in self.clone().filter_map(Result::ok) {
+            let key = ext.key_bytes();
+            let xattr = if let Some(name) = strip_prefix(key, b"SCHILY.xattr.") {
+                Some((
+                    OsStr::from_bytes(name).to_os_string(),
+                    ext.value_bytes().to_vec(),
+                ))
+            } else if let Some(name) = strip_prefix(key, b"LIBARCHIVE.xattr.") {
+                percent_decode(name)
+                    .zip(base64_decode(ext.value_bytes()))
+                    .map(|(name, value)| (OsString::from_vec(name), value))
+            } else {
+                None
+            };
+            if let Some((name, value)) = xattr {
+                if !xattrs.iter().any(|(n, _)| *n == name) {
+                    xattrs.push((name, value));
+                }
+            }
+        }
+        xattrs.into_iter()
+    }
+
+    /// Returns the BSD file flags listed in the `SCHILY.fflags` record, such
+    /// as `uchg` or `nodump`.
+    ///
+    /// Names starting with `no`, other than `nodump`, clear a flag instead.
+    /// Nothing is returned if the record is missing or not valid UTF-8.
+    pub fn file_flags(&self) -> impl Iterator<Item = &'entry str> {
+        self.get("SCHILY.fflags")
+            .and_then(|v| str::from_utf8(v).ok())
+            .unwrap_or("")
+            .split(',')
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Parses the record named `key` as a decimal number.
+    pub(crate) fn number(&self, key: &str) -> Option<u64> {
+        self.get(key)
+            .and_then(|v| str::from_utf8(v).ok())
+            .and_then(|v| v.parse().ok())
+    }
+}
+
+fn strip_prefix<'a>(key: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
+    if key.starts_with(prefix) {
+        Some(&key[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+/// Parses a pax timestamp such as `1234567890.5` or `-1.25`.
+fn parse_time(value: &[u8]) -> Option<SystemTime> {
+    let value = str::from_utf8(value).ok()?;
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let (secs, frac) = match value.find('.') {
+        Some(i) => (&value[..i], &value[i + 1..]),
+        None => (value, ""),
+    };
+    if secs.is_empty() || !secs.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    if !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    // Digits beyond nanosecond precision are dropped.
+    let nanos = frac
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |n, b| n * 10 + u32::from(b - b'0'));
+    let offset = Duration::new(secs.parse().ok()?, nanos);
+    if negative {
+        UNIX_EPOCH.checked_sub(offset)
+    } else {
+        UNIX_EPOCH.checked_add(offset)
+    }
+}
+
+/// Decodes the `%XX` escapes libarchive uses in attribute names.
+fn percent_decode(value: &[u8]) -> Option<Vec<u8>> {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.iter();
+    while let Some(&b) = bytes.next() {
+        if b == b'%' {
+            let hex = [*bytes.next()?, *bytes.next()?];
+            let hex = str::from_utf8(&hex).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+        } else {
+            decoded.push(b);
+        }
+    }
+    Some(decoded)
+}
+
+/// Encodes the bytes which cannot appear in a pax key as `%XX`.
+fn percent_encode(value: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(value.len());
+    for &b in value {
+        if b <= b' ' || b >= 0x7f || b == b'%' || b == b'=' {
+            encoded.extend_from_slice(format!("%{:02X}", b).as_bytes());
+        } else {
+            encoded.push(b);
+        }
+    }
+    encoded
+}
+
+/// Encodes standard base64 without padding, like libarchive does.
+fn base64_encode(value: &[u8]) -> Vec<u8> {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = Vec::with_capacity(value.len() * 4 / 3 + 2);
+    for chunk in value.chunks(3) {
+        let mut bytes = [0; 4];
+        bytes[1..=chunk.len()].copy_from_slice(chunk);
+        let bits = u32::from_be_bytes(bytes);
+        for i in 0..=chunk.len() {
+            encoded.push(ALPHABET[(bits >> (18 - 6 * i) & 63) as usize]);
+        }
+    }
+    encoded
+}
+
+/// Decodes standard base64, with or without padding.
+fn base64_decode(value: &[u8]) -> Option<Vec<u8>> {
+    fn sextet(b: u8) -> Option<u32> {
+        Some(match b {
+            b'A'..=b'Z' => b - b'A',
+            b'a'..=b'z' => b - b'a' + 26,
+            b'0'..=b'9' => b - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None,
+        } as u32)
+    }
+
+    let value = match value.iter().position(|b| *b == b'=') {
+        Some(i) => &value[..i],
+        None => value,
+    };
+    let mut decoded = Vec::with_capacity(value.len() * 3 / 4);
+    for chunk in value.chunks(4) {
+        if chunk.len() == 1 {
+            return None;
+        }
+        let mut bits = 0;
+        for &b in chunk {
+            bits = bits << 6 | sextet(b)?;
+        }
+        bits <<= 6 * (4 - chunk.len() as u32);
+        decoded.extend_from_slice(&bits.to_be_bytes()[1..chunk.len()]);
+    }
+    Some(decoded)
+}
+
 impl<'entry> Iterator for PaxExtensions<'entry> {
     type Item = io::Result<PaxExtension<'entry>>;
 
@@ -89,3 +313,173 @@ impl<'entry> PaxExtension<'entry> {
         self.value
     }
 }
+
+/// Splits the data of a pax extension entry into records as it is read.
+///
+/// Limits are enforced as soon as a record announces its length, but the
+/// records are not streamed: all of them are kept until the entry has been
+/// read, so the memory used is bounded only by the limit on their total size.
+/// Data which does not look like records is kept as it is, counting towards
+/// that limit, so that iterating over the records reports it like any other
+/// malformed record.
+#[derive(Debug)]
+pub(crate) struct PaxParser {
+    records: Vec<u8>,
+    /// The record being read, starting with its length.
+    partial: Vec<u8>,
+    /// Length of the record being read, once its length field is complete.
+    len: Option<usize>,
+    malformed: bool,
+    max_record: Option<u64>,
+    max_total: u64,
+}
+
+impl PaxParser {
+    pub(crate) fn new(max_record: Option<u64>, max_total: u64) -> PaxParser {
+        PaxParser {
+            records: Vec::new(),
+            partial: Vec::new(),
+            len: None,
+            malformed: false,
+            max_record,
+            max_total,
+        }
+    }
+
+    /// Parses the next chunk of data.
+    pub(crate) fn feed(&mut self, mut data: &[u8]) -> io::Result<()> {
+        while !data.is_empty() {
+            match self.len {
+                _ if self.malformed => {
+                    self.partial.extend_from_slice(data);
+                    data = &[];
+                }
+                None => {
+                    let b = data[0];
+                    data = &data[1..];
+                    self.partial.push(b);
+                    if b == b' ' {
+                        self.start_record()?;
+                    } else if !b.is_ascii_digit() || self.partial.len() > 20 {
+                        self.malformed = true;
+                    }
+                }
+                Some(len) => {
+                    let n = cmp::min(len - self.partial.len(), data.len());
+                    self.partial.extend_from_slice(&data[..n]);
+                    data = &data[n..];
+                    if self.partial.len() == len {
+                        self.records.append(&mut self.partial);
+                        self.len = None;
+                    }
+                }
+            }
+            if (self.records.len() + self.partial.len()) as u64 > self.max_total {
+                return Err(other(&format!(
+                    "pax extensions are larger than the limit of {} bytes",
+                    self.max_total
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the length field which has just been read.
+    fn start_record(&mut self) -> io::Result<()> {
+        let digits = &self.partial[..self.partial.len() - 1];
+        let len = match str::from_utf8(digits).ok().and_then(|d| d.parse().ok()) {
+            Some(len) if len > self.partial.len() => len,
+            _ => {
+                self.malformed = true;
+                return Ok(());
+            }
+        };
+        if let Some(max) = self.max_record {
+            if len as u64 > max {
+                return Err(other(&format!(
+                    "pax record of {} bytes is larger than the limit of {} bytes",
+                    len, max
+                )));
+            }
+        }
+        self.len = Some(len);
+        Ok(())
+    }
+
+    /// Returns the records read, including any incomplete trailing data.
+    pub(crate) fn finish(mut self) -> Vec<u8> {
+        self.records.append(&mut self.partial);
+        self.records
+    }
+}
+
+/// Formats a single `len key=value` pax record.
+pub(crate) fn pax_record(key: &[u8], value: &[u8]) -> Vec<u8> {
+    // The length includes its own digits, so grow it until it is stable.
+    let rest = key.len() + value.len() + 3;
+    let mut len = rest + 1;
+    while rest + len.to_string().len() != len {
+        len = rest + len.to_string().len();
+    }
+    let mut record = format!("{} ", len).into_bytes();
+    record.extend_from_slice(key);
+    record.push(b'=');
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+/// Encodes the extended attribute `name` as pax records in the given form.
+pub(crate) fn xattr_records(name: &[u8], value: &[u8], format: XattrFormat) -> Vec<u8> {
+    let mut records = Vec::new();
+    if let XattrFormat::Schily | XattrFormat::Both = format {
+        let mut key = b"SCHILY.xattr.".to_vec();
+        key.extend_from_slice(name);
+        records.extend(pax_record(&key, value));
+    }
+    if let XattrFormat::Libarchive | XattrFormat::Both = format {
+        let mut key = b"LIBARCHIVE.xattr.".to_vec();
+        key.extend(percent_encode(name));
+        records.extend(pax_record(&key, &base64_encode(value)));
+    }
+    records
+}
+
+/// Applies the records of a global extended header to `globals`, the
+/// records of the global headers seen before.
+///
+/// Later records override earlier ones with the same key, and a record with
+/// an empty value removes the key.
+pub(crate) fn merge_global_extensions(globals: &[u8], update: &[u8]) -> io::Result<Vec<u8>> {
+    let mut records = Vec::new();
+    for ext in pax_extensions(globals).chain(pax_extensions(update)) {
+        let ext = ext?;
+        records.retain(|(key, _)| *key != ext.key_bytes());
+        if !ext.value_bytes().is_empty() {
+            records.push((ext.key_bytes(), ext.value_bytes()));
+        }
+    }
+    Ok(records
+        .into_iter()
+        .flat_map(|(key, value)| pax_record(key, value))
+        .collect())
+}
+
+/// Combines the records of a local extended header with the global ones in
+/// effect, leaving out global records which the local ones override.
+pub(crate) fn apply_global_extensions(local: Option<Vec<u8>>, globals: &[u8]) -> Option<Vec<u8>> {
+    if globals.is_empty() {
+        return local;
+    }
+    let mut merged = local.unwrap_or_default();
+    let overridden = pax_extensions(&merged)
+        .filter_map(Result::ok)
+        .map(|ext| ext.key_bytes().to_vec())
+        .collect::<Vec<_>>();
+    for ext in pax_extensions(globals).filter_map(Result::ok) {
+        if !overridden.iter().any(|key| key == ext.key_bytes()) {
+            merged.extend(pax_record(ext.key_bytes(), ext.value_bytes()));
+        }
+    }
+    Some(merged)
+}
@@ -0,0 +1,89 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use futures_util::StreamExt;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+
+use crate::{error::TarError, other, Archive, Entry, EntryType, HeaderBuilder};
+
+/// Copies the entries of `src` into a new archive written to `dst`, letting
+/// `transform` filter and rename them on the way.
+///
+/// `transform` is called with every entry before its contents are read and
+/// returns the path to store the entry under, or `None` to leave it out. It
+/// can also change the owner, mode or times of the entry through
+/// `Entry::header_mut`.
+///
+/// Each entry is written with a header built from its own, extended with GNU
+/// long name or pax records as needed for its path, followed by its
+/// contents. Sparse files are written as regular files with their holes
+/// filled in. The pax records of an entry are carried over, including those
+/// of global extensions applying to it, except for the ones describing its
+/// name, size and sparse layout; records such as `mtime` or `uid` which are
+/// kept take precedence over the header when the archive is read. The new archive is ended by two zero
+/// blocks.
+pub async fn repack<R, W, F>(src: Archive<R>, mut dst: W, mut transform: F) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+    F: FnMut(&mut Entry<Archive<R>>) -> io::Result<Option<PathBuf>>,
+{
+    let mut entries = src.entries()?;
+    while let Some(entry) = entries.next().await {
+        let mut entry = entry.map_err(|e| TarError::new("failed to iterate over archive", e))?;
+        let path = match transform(&mut entry)? {
+            Some(path) => path,
+            None => continue,
+        };
+        repack_entry(&mut entry, &path, &mut dst)
+            .await
+            .map_err(|e| TarError::new(&format!("failed to repack `{}`", path.display()), e))?;
+    }
+    dst.write_all(&[0; 1024]).await?;
+    dst.flush().await
+}
+
+/// Writes `entry` to `dst` under `path`.
+async fn repack_entry<R, W>(
+    entry: &mut Entry<Archive<R>>,
+    path: &Path,
+    dst: &mut W,
+) -> io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let size = entry.logical_size()?;
+    let mut header = entry.header().clone();
+    if header.entry_type().is_gnu_sparse() {
+        header.set_entry_type(EntryType::Regular);
+    }
+    let mut builder = HeaderBuilder::keeping(header).path(path).size(size);
+    if let Some(link_name) = entry.link_name()? {
+        builder = builder.link_name(link_name);
+    }
+    if let Some(pax) = entry.pax_headers() {
+        for record in pax {
+            let record = record?;
+            let key = record.key_bytes();
+            let replaced = matches!(key, b"path" | b"linkpath" | b"size" | b"hdrcharset")
+                || key.starts_with(b"GNU.sparse.");
+            if !replaced {
+                builder = builder.pax_extension(key, record.value_bytes());
+            }
+        }
+    }
+    dst.write_all(&builder.build_with_extensions()?).await?;
+
+    let copied = tokio::io::copy(entry, dst).await?;
+    if copied != size {
+        return Err(other(&format!(
+            "entry has {} bytes of data but {} were expected",
+            copied, size
+        )));
+    }
+    let padding = ((size + 511) & !511) - size;
+    dst.write_all(&[0; 512][..padding as usize]).await
+}
@@ -0,0 +1,264 @@
+use std::io;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{archive::checksum_matches, other, EntryType, GnuExtSparseHeader, Header};
+
+/// Upper bound for the extension entries buffered before the entry they
+/// describe.
+const MAX_PENDING: usize = 1024 * 1024;
+
+/// Options for `repair`.
+#[derive(Clone, Debug, Default)]
+pub struct RepairOptions {
+    ignore_zeros: bool,
+    skip_damaged: bool,
+}
+
+impl RepairOptions {
+    /// Creates the default options, which stop at the first zero block and
+    /// fail on blocks which cannot be made sense of.
+    pub fn new() -> RepairOptions {
+        RepairOptions::default()
+    }
+
+    /// Indicate whether zero blocks in the middle of the input are dropped
+    /// instead of ending the archive, which joins concatenated archives.
+    pub fn set_ignore_zeros(mut self, ignore_zeros: bool) -> Self {
+        self.ignore_zeros = ignore_zeros;
+        self
+    }
+
+    /// Indicate whether blocks which neither are a valid header nor can be
+    /// repaired into one are dropped, continuing with the next block.
+    ///
+    /// By default such a block fails the repair.
+    pub fn set_skip_damaged(mut self, skip: bool) -> Self {
+        self.skip_damaged = skip;
+        self
+    }
+}
+
+/// A defect fixed by `repair`.
+///
+/// Offsets are positions in the input archive.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[non_exhaustive]
+pub enum RepairFix {
+    /// The checksum of the header at `offset` did not match and was
+    /// recomputed.
+    Checksum {
+        /// Position of the header.
+        offset: u64,
+    },
+    /// The padding after the data of the entry whose header is at `offset`
+    /// was not zeroed.
+    Padding {
+        /// Position of the header.
+        offset: u64,
+    },
+    /// The extension entry at `offset` was dropped, either because a later
+    /// one of the same kind superseded it or because no entry followed it.
+    DroppedExtension {
+        /// Position of the extension header.
+        offset: u64,
+    },
+    /// The block at `offset` was not a header and was dropped, see
+    /// `RepairOptions::set_skip_damaged`.
+    DamagedBlock {
+        /// Position of the block.
+        offset: u64,
+    },
+    /// The input ended inside the entry whose header is at `offset`, and the
+    /// `missing` bytes were filled with zeros.
+    Truncated {
+        /// Position of the header.
+        offset: u64,
+        /// Number of bytes of data and padding filled in.
+        missing: u64,
+    },
+    /// The end of archive marker was missing and has been added.
+    Terminator,
+}
+
+/// Describes the outcome of `repair`.
+#[derive(Clone, Debug, Default)]
+pub struct RepairReport {
+    fixes: Vec<RepairFix>,
+}
+
+impl RepairReport {
+    /// Returns the defects which were fixed, in the order they were found.
+    pub fn fixes(&self) -> &[RepairFix] {
+        &self.fixes
+    }
+
+    /// Returns `true` if the input did not need any repairs.
+    pub fn is_clean(&self) -> bool {
+        self.fixes.is_empty()
+    }
+}
+
+/// Copies the archive in `src` to `dst`, fixing recoverable defects on the
+/// way.
+///
+/// Header checksums are recomputed, the padding after entry data is zeroed,
+/// extension entries which would be ignored by readers are dropped, data cut
+/// off by the end of the input is filled with zeros, and the archive is
+/// always ended by two zero blocks. Every fix applied is listed in the
+/// returned report.
+///
+/// A block with a bad checksum is only treated as a header if its size, mode
+/// and modification time still parse. Otherwise the repair fails, unless
+/// `RepairOptions::set_skip_damaged` is enabled.
+pub async fn repair<R, W>(
+    mut src: R,
+    mut dst: W,
+    options: RepairOptions,
+) -> io::Result<RepairReport>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut report = RepairReport::default();
+    // Extension entries held back until the entry they describe is found.
+    let mut pending: Vec<(EntryType, u64, Vec<u8>)> = Vec::new();
+    let mut pos = 0;
+    let mut terminated = false;
+    let mut header = Header::new_old();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let offset = pos;
+        let n = read_full(&mut src, header.as_mut_bytes()).await?;
+        pos += n as u64;
+        if n == 0 {
+            break;
+        } else if n < 512 {
+            return Err(other("archive ends with a partial block"));
+        }
+
+        if header.as_bytes().iter().all(|b| *b == 0) {
+            if options.ignore_zeros {
+                continue;
+            }
+            terminated = true;
+            break;
+        }
+
+        if !checksum_matches(&header) {
+            let plausible =
+                header.entry_size().is_ok() && header.mode().is_ok() && header.mtime().is_ok();
+            if !plausible {
+                if options.skip_damaged {
+                    report.fixes.push(RepairFix::DamagedBlock { offset });
+                    continue;
+                }
+                return Err(other(&format!("damaged header at offset {}", offset)));
+            }
+            header.set_cksum();
+            report.fixes.push(RepairFix::Checksum { offset });
+        }
+
+        let kind = header.entry_type();
+        let is_extension = matches!(
+            kind,
+            EntryType::XHeader | EntryType::GNULongName | EntryType::GNULongLink
+        );
+        let mut out = header.as_bytes().to_vec();
+
+        // Old GNU sparse files list further chunks in the blocks following
+        // the header.
+        let mut extended = matches!(header.as_gnu(), Some(gnu) if gnu.is_extended());
+        while extended {
+            let mut ext = GnuExtSparseHeader::new();
+            let n = read_full(&mut src, ext.as_mut_bytes()).await?;
+            pos += n as u64;
+            if n < 512 {
+                return Err(other("archive ends inside of a sparse header"));
+            }
+            extended = ext.is_extended();
+            out.extend_from_slice(ext.as_bytes());
+        }
+
+        let size = header.entry_size()?;
+        if is_extension && size > MAX_PENDING as u64 {
+            return Err(other(&format!(
+                "extension entry at offset {} is too large",
+                offset
+            )));
+        }
+        if !is_extension {
+            for (_, _, bytes) in pending.drain(..) {
+                dst.write_all(&bytes).await?;
+            }
+            dst.write_all(&out).await?;
+            out.clear();
+        }
+
+        let padded = size.saturating_add(511) / 512 * 512;
+        let mut copied = 0;
+        let mut dirty_padding = false;
+        let mut truncated = false;
+        while copied < padded && !truncated {
+            let want = (padded - copied).min(buf.len() as u64) as usize;
+            let n = read_full(&mut src, &mut buf[..want]).await?;
+            pos += n as u64;
+            let data_end = size.saturating_sub(copied).min(n as u64) as usize;
+            if buf[data_end..n].iter().any(|b| *b != 0) {
+                dirty_padding = true;
+            }
+            buf[data_end..want].iter_mut().for_each(|b| *b = 0);
+            if n < want {
+                truncated = true;
+                report.fixes.push(RepairFix::Truncated {
+                    offset,
+                    missing: padded - copied - n as u64,
+                });
+            }
+            if is_extension {
+                out.extend_from_slice(&buf[..want]);
+            } else {
+                dst.write_all(&buf[..want]).await?;
+            }
+            copied += want as u64;
+        }
+        if dirty_padding {
+            report.fixes.push(RepairFix::Padding { offset });
+        }
+
+        if is_extension {
+            if let Some(i) = pending.iter().position(|(k, _, _)| *k == kind) {
+                let (_, dropped, _) = pending.remove(i);
+                report
+                    .fixes
+                    .push(RepairFix::DroppedExtension { offset: dropped });
+            }
+            pending.push((kind, offset, out));
+        }
+        if truncated {
+            break;
+        }
+    }
+
+    for (_, offset, _) in pending {
+        report.fixes.push(RepairFix::DroppedExtension { offset });
+    }
+    if !terminated {
+        report.fixes.push(RepairFix::Terminator);
+    }
+    dst.write_all(&[0; 1024]).await?;
+    dst.flush().await?;
+    Ok(report)
+}
+
+/// Reads until `buf` is full or the input ends, returning the bytes read.
+async fn read_full<R: AsyncRead + Unpin>(src: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match src.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
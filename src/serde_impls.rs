@@ -0,0 +1,69 @@
+use std::fmt;
+
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::{EntryType, Header};
+
+// Headers are stored as their raw bytes, so that they round-trip exactly,
+// checksum included.
+impl Serialize for Header {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(self.as_bytes())
+    }
+}
+
+impl<'de> Deserialize<'de> for Header {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Header, D::Error> {
+        struct HeaderVisitor;
+
+        impl<'de> Visitor<'de> for HeaderVisitor {
+            type Value = Header;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("the 512 bytes of a tar header")
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Header, E> {
+                let mut header = Header::new_old();
+                if v.len() != header.as_bytes().len() {
+                    return Err(E::invalid_length(v.len(), &self));
+                }
+                header.as_mut_bytes().copy_from_slice(v);
+                Ok(header)
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Header, A::Error> {
+                let mut header = Header::new_old();
+                let bytes = header.as_mut_bytes();
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = seq
+                        .next_element()?
+                        .ok_or_else(|| de::Error::invalid_length(i, &self))?;
+                }
+                if seq.next_element::<u8>()?.is_some() {
+                    return Err(de::Error::invalid_length(bytes.len() + 1, &self));
+                }
+                Ok(header)
+            }
+        }
+
+        deserializer.deserialize_bytes(HeaderVisitor)
+    }
+}
+
+// Entry types are stored by name, see `EntryType::as_str`.
+impl Serialize for EntryType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for EntryType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<EntryType, D::Error> {
+        let name = <std::borrow::Cow<'de, str>>::deserialize(deserializer)?;
+        name.parse().map_err(de::Error::custom)
+    }
+}
@@ -0,0 +1,446 @@
+//! Serving archive members over HTTP.
+//!
+//! This module maps request paths onto the members of a tarball and produces
+//! everything needed to answer the request: status code, `Content-Length`,
+//! `Content-Type`, `ETag`, `Content-Range` and a reader over the body. It does
+//! not depend on any particular HTTP implementation; plug the returned
+//! `Response` into whatever server framework is in use.
+//!
+//! The archive is indexed once up front, after which every request is answered
+//! by seeking directly to the member's data, so the source must implement
+//! `AsyncSeek`. The `ETag` of a member is the SHA-256 digest of its contents,
+//! computed the first time the member is requested.
+
+use std::{
+    collections::HashMap,
+    io,
+    path::{Component, Path, PathBuf},
+};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, Take};
+
+use crate::{digest::DigestState, ArchiveIndex, DigestAlgorithm, IndexEntry};
+
+/// Maximum number of links followed while resolving a request path.
+const MAX_LINK_DEPTH: usize = 8;
+
+/// Number of bytes inspected when sniffing the content type of a member.
+const SNIFF_LEN: u64 = 512;
+
+/// Serves the members of a seekable archive.
+#[derive(Debug)]
+pub struct Server<R> {
+    reader: R,
+    index: ArchiveIndex,
+    index_file: String,
+    /// Entity tags of the members requested so far, by header position.
+    etags: HashMap<u64, String>,
+}
+
+/// A request to be answered by a `Server`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Request<'a> {
+    path: &'a str,
+    range: Option<&'a str>,
+    if_none_match: Option<&'a str>,
+    head: bool,
+}
+
+/// The answer to a `Request`.
+#[derive(Debug)]
+pub struct Response<'a, R> {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    body: Option<Take<&'a mut R>>,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> Server<R> {
+    /// Creates a new server over the archive in `reader`.
+    ///
+    /// The reader is rewound to position 0, which must be the start of the
+    /// archive, and the whole archive is indexed before this returns.
+    pub async fn new(reader: R) -> io::Result<Server<R>> {
+        let (reader, index) = ArchiveIndex::build_seekable(reader).await?;
+        Ok(Server {
+            reader,
+            index,
+            index_file: "index.html".to_string(),
+            etags: HashMap::new(),
+        })
+    }
+
+    /// Sets the file name served when a directory is requested.
+    ///
+    /// Defaults to `index.html`.
+    pub fn set_index_file(&mut self, name: &str) {
+        self.index_file = name.to_string();
+    }
+
+    /// Returns the index of the served archive.
+    pub fn index(&self) -> &ArchiveIndex {
+        &self.index
+    }
+
+    /// Unwraps this server, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Answers `request`.
+    ///
+    /// Only regular files with contiguous data are served; directories are
+    /// answered with their index file, links are followed within the archive
+    /// and everything else results in a `404`.
+    pub async fn respond(&mut self, request: &Request<'_>) -> io::Result<Response<'_, R>> {
+        let entry = match self.resolve(request.path) {
+            Some(entry) => entry.clone(),
+            None => return Ok(Response::empty(404)),
+        };
+
+        let size = entry.size();
+        let etag = match self.etags.get(&entry.raw_header_position()) {
+            Some(etag) => etag.clone(),
+            None => {
+                let etag = content_etag(&entry, &mut self.reader).await?;
+                self.etags.insert(entry.raw_header_position(), etag.clone());
+                etag
+            }
+        };
+        if let Some(candidates) = request.if_none_match {
+            // `If-None-Match` uses the weak comparison, ignoring `W/`.
+            let matches = candidates
+                .split(',')
+                .map(str::trim)
+                .any(|c| c == "*" || opaque_tag(c) == opaque_tag(&etag));
+            if matches {
+                let mut response = Response::empty(304);
+                response.headers.push(("ETag", etag));
+                return Ok(response);
+            }
+        }
+
+        let content_type = match mime_from_extension(entry.path().to_str().unwrap_or("")) {
+            Some(mime) => mime,
+            None => {
+                let mut prefix = Vec::new();
+                entry
+                    .read_range(&mut self.reader, 0, SNIFF_LEN)
+                    .await?
+                    .read_to_end(&mut prefix)
+                    .await?;
+                sniff(&prefix)
+            }
+        };
+
+        let (status, start, len) = match request.range.map(|r| parse_range(r, size)) {
+            Some(RangeSpec::Satisfiable(start, end)) => (206, start, end - start + 1),
+            Some(RangeSpec::Unsatisfiable) => {
+                let mut response = Response::empty(416);
+                response
+                    .headers
+                    .push(("Content-Range", format!("bytes */{}", size)));
+                return Ok(response);
+            }
+            Some(RangeSpec::Ignored) | None => (200, 0, size),
+        };
+
+        let mut headers = vec![
+            ("Content-Length", len.to_string()),
+            ("Content-Type", content_type.to_string()),
+            ("ETag", etag),
+            ("Accept-Ranges", "bytes".to_string()),
+        ];
+        if status == 206 {
+            headers.push((
+                "Content-Range",
+                format!("bytes {}-{}/{}", start, start + len - 1, size),
+            ));
+        }
+
+        let body = if request.head {
+            None
+        } else {
+            Some(entry.read_range(&mut self.reader, start, len).await?)
+        };
+        Ok(Response {
+            status,
+            headers,
+            body,
+        })
+    }
+
+    fn resolve(&self, path: &str) -> Option<&IndexEntry> {
+        let mut path = decode_path(path)?;
+        for _ in 0..MAX_LINK_DEPTH {
+            let entry = match self.index.get(&path) {
+                Some(entry) => entry,
+                None if path.as_os_str().is_empty() => {
+                    path.push(&self.index_file);
+                    continue;
+                }
+                None => return None,
+            };
+            let kind = entry.header().entry_type();
+            if kind.is_dir() || kind.is_gnu_dumpdir() {
+                path.push(&self.index_file);
+            } else if kind.is_symlink() {
+                let base = path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+                path = clean(&base.join(entry.link_name()?))?;
+            } else if kind.is_hard_link() {
+                path = clean(entry.link_name()?)?;
+            } else if entry.is_contiguous() && (kind.is_file() || kind.is_contiguous()) {
+                return Some(entry);
+            } else {
+                return None;
+            }
+        }
+        None
+    }
+}
+
+impl<'a> Request<'a> {
+    /// Creates a `GET` request for `path`.
+    ///
+    /// The path may be percent-encoded and may include a query string, which
+    /// is ignored.
+    pub fn new(path: &'a str) -> Request<'a> {
+        Request {
+            path,
+            ..Request::default()
+        }
+    }
+
+    /// Sets the value of the `Range` request header.
+    pub fn range(mut self, range: &'a str) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Sets the value of the `If-None-Match` request header.
+    pub fn if_none_match(mut self, etags: &'a str) -> Self {
+        self.if_none_match = Some(etags);
+        self
+    }
+
+    /// Marks this as a `HEAD` request, in which case no body is produced.
+    pub fn head(mut self, head: bool) -> Self {
+        self.head = head;
+        self
+    }
+}
+
+impl<'a, R> Response<'a, R> {
+    fn empty(status: u16) -> Response<'a, R> {
+        Response {
+            status,
+            headers: vec![("Content-Length", "0".to_string())],
+            body: None,
+        }
+    }
+
+    /// Returns the HTTP status code of this response.
+    pub fn status(&self) -> u16 {
+        self.status
+    }
+
+    /// Returns the response headers as name/value pairs.
+    pub fn headers(&self) -> &[(&'static str, String)] {
+        &self.headers
+    }
+
+    /// Returns the value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Consumes the response, returning a reader over the body, if any.
+    pub fn into_body(self) -> Option<Take<&'a mut R>> {
+        self.body
+    }
+}
+
+enum RangeSpec {
+    Satisfiable(u64, u64),
+    Unsatisfiable,
+    Ignored,
+}
+
+/// Parses a single `bytes=` range against a member of `size` bytes.
+///
+/// Multiple ranges and malformed values are ignored, in which case the whole
+/// member is served as permitted by RFC 7233.
+fn parse_range(value: &str, size: u64) -> RangeSpec {
+    let spec = match value.trim().strip_prefix("bytes=") {
+        Some(spec) if !spec.contains(',') => spec.trim(),
+        _ => return RangeSpec::Ignored,
+    };
+    let (start, end) = match spec.split_once('-') {
+        Some(parts) => parts,
+        None => return RangeSpec::Ignored,
+    };
+    let range = match (start.parse::<u64>(), end.parse::<u64>()) {
+        (Ok(start), Ok(end)) if start <= end => (start, end.min(size.saturating_sub(1))),
+        (Ok(start), Err(_)) if end.is_empty() => (start, size.saturating_sub(1)),
+        (Err(_), Ok(suffix)) if start.is_empty() => {
+            if suffix == 0 {
+                return RangeSpec::Unsatisfiable;
+            }
+            (size.saturating_sub(suffix), size.saturating_sub(1))
+        }
+        _ => return RangeSpec::Ignored,
+    };
+    if size == 0 || range.0 >= size {
+        RangeSpec::Unsatisfiable
+    } else {
+        RangeSpec::Satisfiable(range.0, range.1)
+    }
+}
+
+/// Percent-decodes a request path, dropping any query string or fragment.
+///
+/// Returns `None` for paths which try to escape the archive root.
+fn decode_path(path: &str) -> Option<PathBuf> {
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut iter = path.bytes();
+    while let Some(b) = iter.next() {
+        if b == b'%' {
+            let hi = (iter.next()? as char).to_digit(16)?;
+            let lo = (iter.next()? as char).to_digit(16)?;
+            bytes.push((hi * 16 + lo) as u8);
+        } else {
+            bytes.push(b);
+        }
+    }
+    let decoded = String::from_utf8(bytes).ok()?;
+    if decoded.split('/').any(|part| part == "..") {
+        return None;
+    }
+    clean(Path::new(&decoded))
+}
+
+/// Lexically resolves `.` and `..` components of a path within the archive.
+///
+/// Returns `None` if the path would escape the archive root.
+fn clean(path: &Path) -> Option<PathBuf> {
+    let mut out = PathBuf::new();
+    for part in path.components() {
+        match part {
+            Component::Normal(part) => out.push(part),
+            Component::ParentDir => {
+                if !out.pop() {
+                    return None;
+                }
+            }
+            Component::Prefix(..) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    Some(out)
+}
+
+/// Computes a strong entity tag from the SHA-256 digest of the contents of
+/// `entry`, which are read from `reader` in full.
+async fn content_etag<R: AsyncRead + AsyncSeek + Unpin>(
+    entry: &IndexEntry,
+    reader: &mut R,
+) -> io::Result<String> {
+    let mut digest = DigestState::new(Some(DigestAlgorithm::Sha256));
+    let mut body = entry.read_range(reader, 0, entry.size()).await?;
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = body.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        digest.update(&buf[..n]);
+    }
+    digest.finish();
+    let hex: String = digest
+        .digest()
+        .unwrap_or_default()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    Ok(format!("\"{}\"", hex))
+}
+
+/// Strips the weakness indicator from an entity tag.
+fn opaque_tag(tag: &str) -> &str {
+    tag.strip_prefix("W/").unwrap_or(tag)
+}
+
+fn mime_from_extension(path: &str) -> Option<&'static str> {
+    let ext = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    Some(match ext.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" | "md" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "avif" => "image/avif",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        _ => return None,
+    })
+}
+
+/// Guesses a content type from the leading bytes of a member.
+fn sniff(prefix: &[u8]) -> &'static str {
+    const MAGIC: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+        (b"\0asm", "application/wasm"),
+    ];
+    if let Some((_, mime)) = MAGIC.iter().find(|(magic, _)| prefix.starts_with(magic)) {
+        return mime;
+    }
+    let start = prefix
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(prefix.len());
+    let text = &prefix[start..];
+    if text.len() >= 5 && text[..5].eq_ignore_ascii_case(b"<html")
+        || text.len() >= 9 && text[..9].eq_ignore_ascii_case(b"<!doctype")
+    {
+        return "text/html; charset=utf-8";
+    }
+    // A multi-byte character may straddle the end of the sniffed prefix.
+    let valid_utf8 = match std::str::from_utf8(prefix) {
+        Ok(_) => true,
+        Err(e) => e.error_len().is_none(),
+    };
+    if valid_utf8
+        && !prefix
+            .iter()
+            .any(|b| *b < 0x20 && !b"\t\n\r\x0c".contains(b))
+    {
+        "text/plain; charset=utf-8"
+    } else {
+        "application/octet-stream"
+    }
+}
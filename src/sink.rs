@@ -0,0 +1,265 @@
+use std::{
+    fs::Permissions,
+    future::Future,
+    io::{self, ErrorKind},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use filetime::{self, FileTime};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio_uring::fs;
+
+use crate::{entry::FixedBuffers, error::TarError, other, Archive, Entry, Header};
+
+/// A boxed future returned by the methods of `ExtractSink`.
+pub type SinkFuture<'a, T> = Pin<Box<dyn Future<Output = io::Result<T>> + 'a>>;
+
+/// A destination for the entries of an archive, used by
+/// `Archive::unpack_into`.
+///
+/// The archive takes care of parsing headers, long names, PAX extensions and
+/// sparse files, and of rejecting paths which would escape the destination.
+/// Implementors only receive sanitized paths relative to the root of the
+/// destination, so they can extract into object stores, databases or virtual
+/// filesystems.
+///
+/// `FsSink` implements this trait for the local filesystem, and is also what
+/// `Archive::unpack` extracts through.
+///
+/// Parent directories are always passed to `create_dir` before any of their
+/// children, with the exception of directories which are only implied by the
+/// paths of other entries. `set_metadata` for directories is deferred until
+/// all other entries have been written, so that restrictive permissions do
+/// not get in the way of extraction.
+pub trait ExtractSink {
+    /// Creates the directory at `path`.
+    fn create_dir<'a>(&'a mut self, path: &'a Path, header: &'a Header) -> SinkFuture<'a, ()>;
+
+    /// Creates a regular file at `path` with the contents read from `data`.
+    ///
+    /// Any existing file at `path` should be replaced.
+    fn write_file<'a>(
+        &'a mut self,
+        path: &'a Path,
+        header: &'a Header,
+        data: &'a mut (dyn AsyncRead + Unpin),
+    ) -> SinkFuture<'a, ()>;
+
+    /// Creates a symbolic link at `path` pointing to `target`.
+    ///
+    /// `target` is taken verbatim from the archive and may be absolute or
+    /// point outside of the destination.
+    fn symlink<'a>(
+        &'a mut self,
+        path: &'a Path,
+        target: &'a Path,
+        header: &'a Header,
+    ) -> SinkFuture<'a, ()>;
+
+    /// Creates a hard link at `path` to the previously extracted `target`.
+    ///
+    /// Both paths are relative to the root of the destination. By default
+    /// this returns an error.
+    fn hard_link<'a>(
+        &'a mut self,
+        path: &'a Path,
+        target: &'a Path,
+        header: &'a Header,
+    ) -> SinkFuture<'a, ()> {
+        let _ = (target, header);
+        Box::pin(async move {
+            Err(other(&format!(
+                "hard link `{}` is not supported by this sink",
+                path.display()
+            )))
+        })
+    }
+
+    /// Applies the permissions, timestamps and other metadata described by
+    /// `header` to the previously created `path`.
+    fn set_metadata<'a>(&'a mut self, path: &'a Path, header: &'a Header) -> SinkFuture<'a, ()>;
+}
+
+/// An `ExtractSink` writing to a directory on the local filesystem.
+///
+/// Through `Archive::unpack_into` only the methods of `ExtractSink` are used.
+/// `Archive::unpack` extracts through an `FsSink` as well, but hands it whole
+/// entries, so that extended attributes, sparse files, registered buffers and
+/// the other options of `ArchiveBuilder` are supported.
+#[derive(Debug)]
+pub struct FsSink {
+    root: PathBuf,
+    preserve_permissions: bool,
+    preserve_mtime: bool,
+}
+
+impl FsSink {
+    /// Creates a sink extracting into the directory `root`, which is created
+    /// if it does not exist yet.
+    pub fn new<P: AsRef<Path>>(root: P) -> FsSink {
+        FsSink {
+            root: root.as_ref().to_path_buf(),
+            preserve_permissions: false,
+            preserve_mtime: true,
+        }
+    }
+
+    /// Indicate whether extended permissions (like suid on Unix) are
+    /// preserved.
+    ///
+    /// This flag is disabled by default.
+    pub fn set_preserve_permissions(&mut self, preserve: bool) {
+        self.preserve_permissions = preserve;
+    }
+
+    /// Indicate whether modification times are preserved.
+    ///
+    /// This flag is enabled by default.
+    pub fn set_preserve_mtime(&mut self, preserve: bool) {
+        self.preserve_mtime = preserve;
+    }
+
+    /// Resolves `path` below the root, creating missing parent directories
+    /// and making sure that symlinks created earlier do not lead outside.
+    async fn prepare(&self, path: &Path) -> io::Result<PathBuf> {
+        let dst = self.root.join(path);
+        if let Some(parent) = dst.parent() {
+            tokio_uring::fs::create_dir_all(parent).await?;
+            let canon_root = self.root.canonicalize()?;
+            if !parent.canonicalize()?.starts_with(&canon_root) {
+                return Err(TarError::new(
+                    &format!(
+                        "trying to unpack outside of destination path: {}",
+                        canon_root.display()
+                    ),
+                    other("Invalid argument"),
+                )
+                .into());
+            }
+        }
+        Ok(dst)
+    }
+
+    /// Unpacks `entry` below the root with the options of its archive,
+    /// returning where it ended up, or `None` if it was skipped.
+    pub(crate) async fn unpack_entry<R: AsyncRead + Unpin>(
+        &self,
+        entry: &mut Entry<Archive<R>>,
+        buffers: Option<&FixedBuffers>,
+    ) -> io::Result<Option<PathBuf>> {
+        entry.unpack_in_with(&self.root, buffers).await
+    }
+}
+
+/// Removes whatever is at `dst` so that a new node can be created there.
+async fn remove_existing(dst: &Path) -> io::Result<()> {
+    match fs::remove_file(dst).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+impl ExtractSink for FsSink {
+    fn create_dir<'a>(&'a mut self, path: &'a Path, _header: &'a Header) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let dst = self.prepare(path).await?;
+            match fs::create_dir(&dst).await {
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => {
+                    let (is_dir, _) = fs::is_dir_regfile(&dst).await;
+                    if is_dir {
+                        Ok(())
+                    } else {
+                        Err(e)
+                    }
+                }
+                res => res,
+            }
+        })
+    }
+
+    fn write_file<'a>(
+        &'a mut self,
+        path: &'a Path,
+        _header: &'a Header,
+        data: &'a mut (dyn AsyncRead + Unpin),
+    ) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let dst = self.prepare(path).await?;
+            remove_existing(&dst).await?;
+            let f = fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&dst)
+                .await?;
+            let mut buf = vec![0; 64 * 1024];
+            let mut pos = 0;
+            loop {
+                let n = data.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                buf.truncate(n);
+                let (res, b) = f.write_all_at(buf, pos).await;
+                res?;
+                buf = b;
+                buf.resize(64 * 1024, 0);
+                pos += n as u64;
+            }
+            f.close().await
+        })
+    }
+
+    fn symlink<'a>(
+        &'a mut self,
+        path: &'a Path,
+        target: &'a Path,
+        _header: &'a Header,
+    ) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let dst = self.prepare(path).await?;
+            remove_existing(&dst).await?;
+            fs::symlink(target, &dst).await
+        })
+    }
+
+    fn hard_link<'a>(
+        &'a mut self,
+        path: &'a Path,
+        target: &'a Path,
+        _header: &'a Header,
+    ) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let dst = self.prepare(path).await?;
+            let src = self.prepare(target).await?;
+            remove_existing(&dst).await?;
+            tokio::fs::hard_link(&src, &dst).await
+        })
+    }
+
+    fn set_metadata<'a>(&'a mut self, path: &'a Path, header: &'a Header) -> SinkFuture<'a, ()> {
+        Box::pin(async move {
+            let dst = self.root.join(path);
+            // Symlinks keep the metadata they were created with.
+            if header.entry_type().is_symlink() {
+                return Ok(());
+            }
+            if self.preserve_mtime {
+                if let Ok(mtime) = header.mtime() {
+                    let mtime = FileTime::from_unix_time(mtime as i64, 0);
+                    filetime::set_file_times(&dst, mtime, mtime)?;
+                }
+            }
+            if let Ok(mode) = header.mode() {
+                let mode = if self.preserve_permissions {
+                    mode
+                } else {
+                    mode & 0o777
+                };
+                tokio::fs::set_permissions(&dst, Permissions::from_mode(mode)).await?;
+            }
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+/// Largest number of bytes a throttled read waits for before going ahead.
+const CHUNK: u64 = 64 * 1024;
+
+/// A reader which caps the rate at which bytes are read from its inner
+/// reader.
+///
+/// The rate is enforced with a token bucket: up to one second worth of bytes
+/// may be read in a burst, after which reads wait for the bucket to refill.
+/// Archives can be throttled directly with `ArchiveBuilder::set_max_read_rate`.
+#[derive(Debug)]
+pub struct ThrottledReader<R> {
+    inner: R,
+    throttle: Throttle,
+}
+
+impl<R: AsyncRead + Unpin> ThrottledReader<R> {
+    /// Wraps `inner`, reading at most `bytes_per_sec` bytes per second from
+    /// it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bytes_per_sec` is zero.
+    pub fn new(inner: R, bytes_per_sec: u64) -> ThrottledReader<R> {
+        ThrottledReader {
+            inner,
+            throttle: Throttle::new(bytes_per_sec),
+        }
+    }
+
+    /// Returns the maximum number of bytes read per second.
+    pub fn rate(&self) -> u64 {
+        self.throttle.rate
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ThrottledReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = &mut this.inner;
+        this.throttle
+            .poll_read(cx, into, |cx, buf| Pin::new(inner).poll_read(cx, buf))
+    }
+}
+
+/// Token bucket state shared by `ThrottledReader` and throttled archives.
+#[derive(Debug)]
+pub(crate) struct Throttle {
+    rate: u64,
+    tokens: f64,
+    refilled: Instant,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl Throttle {
+    pub(crate) fn new(rate: u64) -> Throttle {
+        assert!(rate > 0, "read rate must be positive");
+        Throttle {
+            rate,
+            tokens: rate as f64,
+            refilled: Instant::now(),
+            sleep: None,
+        }
+    }
+
+    /// Reads into `into` with `read`, limited to the bytes currently allowed
+    /// and waiting for the bucket to refill if none are.
+    pub(crate) fn poll_read<F>(
+        &mut self,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
+        read: F,
+    ) -> Poll<io::Result<()>>
+    where
+        F: FnOnce(&mut Context<'_>, &mut ReadBuf<'_>) -> Poll<io::Result<()>>,
+    {
+        if into.remaining() == 0 {
+            return read(cx, into);
+        }
+        let allowed = std::task::ready!(self.poll_acquire(cx, into.remaining()));
+        let mut buf = into.take(allowed);
+        let ptr = buf.filled().as_ptr();
+        std::task::ready!(read(cx, &mut buf))?;
+        assert_eq!(ptr, buf.filled().as_ptr());
+        let n = buf.filled().len();
+        // The bytes were initialized by the read into `buf`, which is a view
+        // of the unfilled part of `into`.
+        unsafe { into.assume_init(n) };
+        into.advance(n);
+        self.tokens -= n as f64;
+        Poll::Ready(Ok(()))
+    }
+
+    /// Waits until a read of up to `want` bytes may go ahead, returning how
+    /// many bytes it may read.
+    fn poll_acquire(&mut self, cx: &mut Context<'_>, want: usize) -> Poll<usize> {
+        loop {
+            if let Some(sleep) = self.sleep.as_mut() {
+                std::task::ready!(sleep.as_mut().poll(cx));
+                self.sleep = None;
+            }
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.rate as f64);
+            self.refilled = now;
+
+            // Wait for enough tokens to read a reasonable chunk at once,
+            // rather than waking up for every single byte.
+            let need = (want as u64).min((self.rate / 10).clamp(1, CHUNK)) as f64;
+            if self.tokens >= need {
+                return Poll::Ready((self.tokens as usize).min(want));
+            }
+            let wait = Duration::from_secs_f64((need - self.tokens) / self.rate as f64);
+            self.sleep = Some(Box::pin(tokio::time::sleep_until(now + wait)));
+        }
+    }
+}
@@ -0,0 +1,95 @@
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    time::{Instant, Sleep},
+};
+
+/// A reader which fails with `ErrorKind::TimedOut` when its inner reader
+/// makes no progress for too long.
+///
+/// The timeout applies to each read separately, so a slow but steady source
+/// never times out while a stalled one does. Archives can time out reads
+/// directly with `ArchiveBuilder::set_read_timeout`.
+#[derive(Debug)]
+pub struct TimeoutReader<R> {
+    inner: R,
+    timeout: ReadTimeout,
+}
+
+impl<R: AsyncRead + Unpin> TimeoutReader<R> {
+    /// Wraps `inner`, failing reads which take longer than `timeout`.
+    pub fn new(inner: R, timeout: Duration) -> TimeoutReader<R> {
+        TimeoutReader {
+            inner,
+            timeout: ReadTimeout::new(timeout),
+        }
+    }
+
+    /// Returns the time a single read may take.
+    pub fn timeout(&self) -> Duration {
+        self.timeout.duration
+    }
+
+    /// Unwraps this reader, returning the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TimeoutReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let inner = &mut this.inner;
+        this.timeout
+            .poll_read(cx, |cx| Pin::new(inner).poll_read(cx, into))
+    }
+}
+
+/// Timer state shared by `TimeoutReader` and archives with a read timeout.
+#[derive(Debug)]
+pub(crate) struct ReadTimeout {
+    duration: Duration,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl ReadTimeout {
+    pub(crate) fn new(duration: Duration) -> ReadTimeout {
+        ReadTimeout {
+            duration,
+            sleep: None,
+        }
+    }
+
+    /// Polls `read`, failing once it has been pending for longer than the
+    /// timeout since the last completed read.
+    pub(crate) fn poll_read<F>(&mut self, cx: &mut Context<'_>, read: F) -> Poll<io::Result<()>>
+    where
+        F: FnOnce(&mut Context<'_>) -> Poll<io::Result<()>>,
+    {
+        if let Poll::Ready(res) = read(cx) {
+            self.sleep = None;
+            return Poll::Ready(res);
+        }
+        let duration = self.duration;
+        let sleep = self
+            .sleep
+            .get_or_insert_with(|| Box::pin(tokio::time::sleep_until(Instant::now() + duration)));
+        std::task::ready!(sleep.as_mut().poll(cx));
+        self.sleep = None;
+        Poll::Ready(Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no data was read for {:?}", duration),
+        )))
+    }
+}
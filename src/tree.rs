@@ -0,0 +1,114 @@
+use std::{
+    collections::BTreeMap,
+    ffi::{OsStr, OsString},
+    path::{Component, Path},
+};
+
+use crate::{ArchiveIndex, IndexEntry};
+
+/// The contents of an archive arranged as a directory tree, built by
+/// `Archive::tree`.
+///
+/// This suits user interfaces which display an archive as an expandable tree
+/// without extracting anything. Directories which are only implied by the
+/// paths of other members appear as nodes without an entry. Members whose
+/// path contains a `..` component are left out.
+#[derive(Debug, Clone)]
+pub struct ArchiveTree {
+    root: TreeNode,
+}
+
+/// A file or directory in an `ArchiveTree`.
+#[derive(Debug, Clone, Default)]
+pub struct TreeNode {
+    name: OsString,
+    entry: Option<IndexEntry>,
+    children: BTreeMap<OsString, TreeNode>,
+}
+
+impl ArchiveTree {
+    /// Arranges the members recorded in `index` as a tree.
+    ///
+    /// When the same path is listed multiple times, the node describes the
+    /// last occurrence.
+    pub fn from_index(index: &ArchiveIndex) -> ArchiveTree {
+        let mut root = TreeNode::default();
+        'entries: for entry in index {
+            let mut node = &mut root;
+            for part in entry.path().components() {
+                match part {
+                    Component::Normal(name) => {
+                        node =
+                            node.children
+                                .entry(name.to_os_string())
+                                .or_insert_with(|| TreeNode {
+                                    name: name.to_os_string(),
+                                    ..TreeNode::default()
+                                });
+                    }
+                    _ => continue 'entries,
+                }
+            }
+            node.entry = Some(entry.clone());
+        }
+        ArchiveTree { root }
+    }
+
+    /// Returns the root of the tree, which has an empty name.
+    pub fn root(&self) -> &TreeNode {
+        &self.root
+    }
+
+    /// Looks up the node at `path`.
+    ///
+    /// Leading `/` and `./` components as well as trailing slashes are
+    /// ignored.
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&TreeNode> {
+        let mut node = &self.root;
+        for part in path.as_ref().components() {
+            match part {
+                Component::Normal(name) => node = node.child(name)?,
+                Component::ParentDir => return None,
+                _ => {}
+            }
+        }
+        Some(node)
+    }
+}
+
+impl TreeNode {
+    /// Returns the file name of this node.
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Returns the archive member describing this node, with its header and
+    /// position in the archive.
+    ///
+    /// This is `None` for directories which are not listed in the archive.
+    pub fn entry(&self) -> Option<&IndexEntry> {
+        self.entry.as_ref()
+    }
+
+    /// Returns whether this node is a directory, either listed as one or
+    /// implied by the paths of its children.
+    pub fn is_dir(&self) -> bool {
+        match self.entry {
+            Some(ref entry) => {
+                let kind = entry.header().entry_type();
+                kind.is_dir() || kind.is_gnu_dumpdir()
+            }
+            None => true,
+        }
+    }
+
+    /// Returns the child named `name`.
+    pub fn child<N: AsRef<OsStr>>(&self, name: N) -> Option<&TreeNode> {
+        self.children.get(name.as_ref())
+    }
+
+    /// Returns an iterator over the children of this node, sorted by name.
+    pub fn children(&self) -> impl Iterator<Item = &TreeNode> {
+        self.children.values()
+    }
+}
@@ -0,0 +1,277 @@
+use std::{
+    ffi::OsStr,
+    fmt,
+    future::Future,
+    io,
+    os::unix::ffi::OsStrExt,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::io::AsyncRead;
+
+use crate::{
+    archive::{Admission, Outcome, Unpacker},
+    Archive, Entries, Entry, UnpackReport,
+};
+
+/// Progress reported by the stream returned from `Archive::unpack_stream`.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum UnpackEvent {
+    /// Unpacking of the entry at `path` has started.
+    EntryStarted {
+        /// Path of the entry in the archive.
+        path: PathBuf,
+    },
+    /// Data of the entry at `path` is being written.
+    BytesWritten {
+        /// Path of the entry in the archive.
+        path: PathBuf,
+        /// Number of bytes of the entry written so far.
+        written: u64,
+    },
+    /// The entry at `path` has been unpacked.
+    EntryFinished {
+        /// Path of the entry in the archive.
+        path: PathBuf,
+    },
+    /// The entry at `path` was not unpacked because it would end up outside
+    /// of the destination, because its type is unknown and
+    /// `UnknownEntryTypes::Skip` is in effect, or because it repeats an
+    /// earlier path and `DuplicateEntries::FirstWins` is in effect.
+    Skipped {
+        /// Path of the entry in the archive.
+        path: PathBuf,
+    },
+    /// The entry at `path` could not be unpacked, but unpacking goes on
+    /// because of `ArchiveBuilder::set_continue_on_error`.
+    ///
+    /// The error is listed in `UnpackReport::failures`.
+    Failed {
+        /// Path of the entry in the archive.
+        path: PathBuf,
+    },
+    /// Unpacking failed, after which the stream ends.
+    Error {
+        /// Path of the entry which failed, if the error is specific to one.
+        path: Option<PathBuf>,
+        /// The error that occurred.
+        error: io::Error,
+    },
+}
+
+type PrepareFuture<'a, R> =
+    Pin<Box<dyn Future<Output = io::Result<(Unpacker<R>, Entries<R>)>> + 'a>>;
+
+type EntryFuture<'a, R> =
+    Pin<Box<dyn Future<Output = (Entry<Archive<R>>, io::Result<Option<PathBuf>>)> + 'a>>;
+
+enum State<'a, R: AsyncRead + Unpin> {
+    Prepare(PrepareFuture<'a, R>),
+    Next,
+    Unpacking {
+        path: PathBuf,
+        future: EntryFuture<'a, R>,
+        progress: Arc<AtomicU64>,
+        reported: u64,
+        result: Option<io::Result<Outcome>>,
+    },
+    Done,
+}
+
+/// A stream of `UnpackEvent`s, created by `Archive::unpack_stream`.
+///
+/// Entries are unpacked as the stream is polled, by the same code as with
+/// `Archive::unpack`, so all options of `ArchiveBuilder` apply. In
+/// particular directories are delayed until the end and unpacked
+/// subdirectories first, unless
+/// `ArchiveBuilder::set_delay_directory_restore` is disabled.
+pub struct UnpackStream<'a, R: AsyncRead + Unpin> {
+    entries: Option<Entries<R>>,
+    unpacker: Option<Unpacker<R>>,
+    directories: std::vec::IntoIter<Entry<Archive<R>>>,
+    state: State<'a, R>,
+}
+
+impl<'a, R: AsyncRead + Unpin + 'a> UnpackStream<'a, R> {
+    pub(crate) fn new(archive: Archive<R>, dst: PathBuf) -> UnpackStream<'a, R> {
+        UnpackStream {
+            entries: None,
+            unpacker: None,
+            directories: Vec::new().into_iter(),
+            state: State::Prepare(Box::pin(async move {
+                let unpacker = Unpacker::new(&archive, &dst).await?;
+                Ok((unpacker, archive.entries()?))
+            })),
+        }
+    }
+
+    /// Returns what has been unpacked so far, the same report that
+    /// `Archive::unpack_with_report` returns once the stream has ended.
+    ///
+    /// Returns `None` until the destination has been prepared, which happens
+    /// when the stream is first polled.
+    pub fn report(&self) -> Option<&UnpackReport> {
+        self.unpacker.as_ref().map(|unpacker| &unpacker.report)
+    }
+}
+
+/// Starts unpacking `entry`, returning the state to continue in.
+fn start<'a, R: AsyncRead + Unpin + 'a>(
+    unpacker: &Unpacker<R>,
+    mut entry: Entry<Archive<R>>,
+) -> (State<'a, R>, UnpackEvent) {
+    let path = event_path(&entry);
+    let progress = entry.track_progress();
+    let state = State::Unpacking {
+        path: path.clone(),
+        future: Box::pin(unpacker.unpack(entry)),
+        progress,
+        reported: 0,
+        result: None,
+    };
+    (state, UnpackEvent::EntryStarted { path })
+}
+
+/// The path an entry is reported with, even if it cannot be decoded.
+fn event_path<R: AsyncRead + Unpin>(entry: &Entry<Archive<R>>) -> PathBuf {
+    match entry.path() {
+        Ok(path) => path.into_owned(),
+        Err(_) => PathBuf::from(OsStr::from_bytes(&entry.path_bytes())),
+    }
+}
+
+impl<'a, R: AsyncRead + Unpin + 'a> Stream for UnpackStream<'a, R> {
+    type Item = UnpackEvent;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<UnpackEvent>> {
+        let this = self.get_mut();
+        loop {
+            let unpacker = match (&mut this.state, this.unpacker.as_mut()) {
+                (State::Prepare(future), _) => match std::task::ready!(future.as_mut().poll(cx)) {
+                    Ok((unpacker, entries)) => {
+                        this.unpacker = Some(unpacker);
+                        this.entries = Some(entries);
+                        this.state = State::Next;
+                        continue;
+                    }
+                    Err(error) => {
+                        this.state = State::Done;
+                        return Poll::Ready(Some(UnpackEvent::Error { path: None, error }));
+                    }
+                },
+                (State::Done, _) | (_, None) => return Poll::Ready(None),
+                (_, Some(unpacker)) => unpacker,
+            };
+            match this.state {
+                State::Next => {
+                    let next = match this.entries.as_mut() {
+                        Some(entries) => std::task::ready!(Pin::new(entries).poll_next(cx)),
+                        None => None,
+                    };
+                    let entry = match next {
+                        Some(Ok(entry)) => entry,
+                        Some(Err(error)) => {
+                            this.state = State::Done;
+                            return Poll::Ready(Some(UnpackEvent::Error { path: None, error }));
+                        }
+                        None => {
+                            if this.entries.take().is_some() {
+                                this.directories = unpacker.delayed_directories().into_iter();
+                            }
+                            match this.directories.next() {
+                                Some(dir) => {
+                                    let (state, event) = start(unpacker, dir);
+                                    this.state = state;
+                                    return Poll::Ready(Some(event));
+                                }
+                                None => {
+                                    this.state = State::Done;
+                                    continue;
+                                }
+                            }
+                        }
+                    };
+                    let path = event_path(&entry);
+                    match unpacker.admit(entry) {
+                        Ok(Admission::Unpack(entry)) => {
+                            let (state, event) = start(unpacker, *entry);
+                            this.state = state;
+                            return Poll::Ready(Some(event));
+                        }
+                        Ok(Admission::Delay) => continue,
+                        Ok(Admission::Skip) => {
+                            return Poll::Ready(Some(UnpackEvent::Skipped { path }))
+                        }
+                        Ok(Admission::Fail) => {
+                            return Poll::Ready(Some(UnpackEvent::Failed { path }))
+                        }
+                        Err(error) => {
+                            this.state = State::Done;
+                            return Poll::Ready(Some(UnpackEvent::Error {
+                                path: Some(path),
+                                error,
+                            }));
+                        }
+                    }
+                }
+                State::Unpacking {
+                    ref path,
+                    ref mut future,
+                    ref progress,
+                    ref mut reported,
+                    ref mut result,
+                } => {
+                    if result.is_none() {
+                        if let Poll::Ready((mut entry, res)) = future.as_mut().poll(cx) {
+                            *result = Some(unpacker.finish(&mut entry, res));
+                        }
+                    }
+                    // Report the bytes written since the last event, also
+                    // when the entry finished in one go.
+                    let written = progress.load(Ordering::Relaxed);
+                    if written != *reported {
+                        *reported = written;
+                        return Poll::Ready(Some(UnpackEvent::BytesWritten {
+                            path: path.clone(),
+                            written,
+                        }));
+                    }
+                    let path = path.clone();
+                    let event = match result.take() {
+                        None => return Poll::Pending,
+                        Some(Ok(Outcome::Unpacked)) => UnpackEvent::EntryFinished { path },
+                        Some(Ok(Outcome::Skipped)) => UnpackEvent::Skipped { path },
+                        Some(Ok(Outcome::Failed)) => UnpackEvent::Failed { path },
+                        Some(Err(error)) => {
+                            this.state = State::Done;
+                            return Poll::Ready(Some(UnpackEvent::Error {
+                                path: Some(path),
+                                error,
+                            }));
+                        }
+                    };
+                    this.state = State::Next;
+                    return Poll::Ready(Some(event));
+                }
+                State::Prepare(_) | State::Done => unreachable!(),
+            }
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> fmt::Debug for UnpackStream<'_, R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("UnpackStream")
+            .field("report", &self.report())
+            .field("directories", &self.directories.len())
+            .finish()
+    }
+}
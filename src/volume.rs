@@ -0,0 +1,250 @@
+use std::{
+    cmp, io, mem,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use crate::{other, EntryType, Header};
+
+/// A reader presenting a GNU multi-volume archive as one contiguous archive.
+///
+/// GNU tar splits archives which do not fit on a single medium into volumes.
+/// Each follow-up volume may start with a volume label, and a file which was
+/// split across the boundary continues after a `GNUMultiVolume` header
+/// describing the rest of it. This reader strips those headers while moving
+/// from one volume to the next, so that wrapping it in an `Archive` yields
+/// every split file as a single `Entry`. The offset recorded in a
+/// `GNUMultiVolume` header must match the part of the file read from the
+/// previous volume, otherwise reading fails, as it does when volumes are
+/// handed out of order.
+///
+/// Follow-up volumes are requested from `volumes` whenever the current one
+/// ends, which allows prompting for the next medium or opening it lazily. The
+/// archive ends once `volumes` is exhausted.
+#[derive(Debug)]
+pub struct MultiVolumeReader<R, S> {
+    current: R,
+    volumes: S,
+    volume: u32,
+    state: State,
+    position: Position,
+}
+
+/// Upper bound for the long names buffered at the start of a volume.
+const MAX_PENDING: usize = 1024 * 1024;
+
+/// Follows the entries in the data passed through, so that the continuation
+/// of a split file can be checked against the part read before.
+#[derive(Debug)]
+struct Position {
+    /// The 512 byte block being read, if it is a header.
+    block: Vec<u8>,
+    filled: usize,
+    /// Whether the next block is an extended sparse header.
+    sparse_ext: bool,
+    /// Bytes of data, including padding, left in the current entry.
+    data_left: u64,
+    /// Bytes of data of the current entry read so far.
+    data_read: u64,
+}
+
+impl Position {
+    fn new() -> Position {
+        Position {
+            block: vec![0; 512],
+            filled: 0,
+            sparse_ext: false,
+            data_left: 0,
+            data_read: 0,
+        }
+    }
+
+    fn feed(&mut self, mut data: &[u8]) {
+        while !data.is_empty() {
+            if self.data_left > 0 && !self.sparse_ext {
+                let n = cmp::min(self.data_left, data.len() as u64);
+                self.data_left -= n;
+                self.data_read += n;
+                data = &data[n as usize..];
+                continue;
+            }
+            let n = cmp::min(512 - self.filled, data.len());
+            self.block[self.filled..self.filled + n].copy_from_slice(&data[..n]);
+            self.filled += n;
+            data = &data[n..];
+            if self.filled < 512 {
+                break;
+            }
+            self.filled = 0;
+            if self.sparse_ext {
+                // The `isextended` flag follows the 21 sparse records.
+                self.sparse_ext = self.block[21 * 24] == 1;
+                continue;
+            }
+            let header = Header::from_byte_slice(&self.block);
+            let size = header.entry_size().unwrap_or(0);
+            self.data_left = size.saturating_add(511) & !511;
+            self.data_read = 0;
+            self.sparse_ext = header.entry_type().is_gnu_sparse()
+                && matches!(header.as_gnu(), Some(gnu) if gnu.is_extended());
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Reading,
+    /// Reading the headers at the start of a follow-up volume into `buf`.
+    ///
+    /// Long name entries are buffered until it is known whether they
+    /// describe the continued file or a new one. The next header starts at
+    /// `header_at` and `filled` bytes of `buf` have been read so far.
+    Headers {
+        buf: Vec<u8>,
+        filled: usize,
+        header_at: usize,
+    },
+    /// Passing headers which turned out to start a new entry through.
+    Emit(Vec<u8>, usize),
+}
+
+impl<R, S> MultiVolumeReader<R, S>
+where
+    R: AsyncRead + Unpin,
+    S: Stream<Item = io::Result<R>> + Unpin,
+{
+    /// Creates a reader starting with the `first` volume and continuing with
+    /// the readers produced by `volumes`.
+    pub fn new(first: R, volumes: S) -> MultiVolumeReader<R, S> {
+        MultiVolumeReader {
+            current: first,
+            volumes,
+            volume: 1,
+            state: State::Reading,
+            position: Position::new(),
+        }
+    }
+
+    /// Returns the number of the volume currently being read, starting at 1.
+    pub fn volume(&self) -> u32 {
+        self.volume
+    }
+}
+
+impl<R, S> AsyncRead for MultiVolumeReader<R, S>
+where
+    R: AsyncRead + Unpin,
+    S: Stream<Item = io::Result<R>> + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        into: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if into.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            match this.state {
+                State::Reading => {
+                    let filled = into.filled().len();
+                    std::task::ready!(Pin::new(&mut this.current).poll_read(cx, into))?;
+                    if into.filled().len() > filled {
+                        this.position.feed(&into.filled()[filled..]);
+                        return Poll::Ready(Ok(()));
+                    }
+                    match std::task::ready!(Pin::new(&mut this.volumes).poll_next(cx)) {
+                        Some(next) => {
+                            this.current = next?;
+                            this.volume += 1;
+                            this.state = State::Headers {
+                                buf: Vec::new(),
+                                filled: 0,
+                                header_at: 0,
+                            };
+                        }
+                        None => return Poll::Ready(Ok(())),
+                    }
+                }
+                State::Headers {
+                    ref mut buf,
+                    ref mut filled,
+                    ref mut header_at,
+                } => {
+                    if *filled < buf.len() {
+                        let mut read_buf = ReadBuf::new(&mut buf[*filled..]);
+                        std::task::ready!(Pin::new(&mut this.current).poll_read(cx, &mut read_buf))?;
+                        let n = read_buf.filled().len();
+                        if n == 0 {
+                            if *filled == 0 {
+                                // An empty volume, move on to the next one.
+                                this.state = State::Reading;
+                                continue;
+                            }
+                            return Poll::Ready(Err(other("volume ended inside of its headers")));
+                        }
+                        *filled += n;
+                        continue;
+                    }
+                    if buf.len() == *header_at {
+                        buf.resize(*header_at + 512, 0);
+                        continue;
+                    }
+
+                    let header = Header::from_byte_slice(&buf[*header_at..]);
+                    match header.entry_type() {
+                        EntryType::GNUVolumeLabel if header.as_gnu().is_some() => {
+                            buf.truncate(*header_at);
+                            *filled = *header_at;
+                        }
+                        EntryType::GNULongName | EntryType::GNULongLink
+                            if header.as_gnu().is_some() =>
+                        {
+                            let size = header.entry_size()?;
+                            let len = buf.len() as u64 + size.saturating_add(511) / 512 * 512;
+                            if len > MAX_PENDING as u64 {
+                                return Poll::Ready(Err(other(
+                                    "long name at the start of a volume is too long",
+                                )));
+                            }
+                            *header_at = len as usize;
+                            buf.resize(*header_at, 0);
+                        }
+                        // The rest of the file is passed through as if it
+                        // directly followed the data in the previous volume.
+                        EntryType::GNUMultiVolume if header.as_gnu().is_some() => {
+                            let offset = header.as_gnu().unwrap().offset()?;
+                            let position = &this.position;
+                            if position.data_left == 0 || offset != position.data_read {
+                                return Poll::Ready(Err(other(&format!(
+                                    "continuation of `{}` in volume {} starts at offset {}, \
+                                     but {} bytes of it were read before",
+                                    String::from_utf8_lossy(&header.path_bytes()),
+                                    this.volume,
+                                    offset,
+                                    position.data_read
+                                ))));
+                            }
+                            this.state = State::Reading;
+                        }
+                        _ => this.state = State::Emit(mem::take(buf), 0),
+                    }
+                }
+                State::Emit(ref pending, ref mut pos) => {
+                    let n = into.remaining().min(pending.len() - *pos);
+                    into.put_slice(&pending[*pos..*pos + n]);
+                    this.position.feed(&pending[*pos..*pos + n]);
+                    *pos += n;
+                    if *pos == pending.len() {
+                        this.state = State::Reading;
+                    }
+                    return Poll::Ready(Ok(()));
+                }
+            }
+        }
+    }
+}
@@ -4,17 +4,18 @@ extern crate tempfile;
 #[cfg(all(unix, feature = "xattr"))]
 extern crate xattr;
 
-use async_std::{
-    fs::{self, File},
-    io::{self, Cursor, Read, Write},
+use std::{
+    fs,
+    io::Cursor,
+    iter::repeat,
     path::{Path, PathBuf},
-    prelude::*,
 };
-use std::iter::repeat;
 
-use async_tar::{Archive, ArchiveBuilder, Builder, EntryType, Header};
+use async_tar::{Archive, ArchiveBuilder, EntryType, Header};
 use filetime::FileTime;
+use futures_util::StreamExt;
 use tempfile::{Builder as TempBuilder, TempDir};
+use tokio::io::{AsyncRead, AsyncReadExt};
 
 macro_rules! t {
     ($e:expr) => {
@@ -33,9 +34,46 @@ macro_rules! tar {
 
 mod header;
 
+/// Assembles an archive out of headers and the data of their entries,
+/// setting each header's size from its data.
+fn build_archive(entries: Vec<(async_tar::HeaderBuilder, &[u8])>) -> Vec<u8> {
+    let mut archive = Vec::new();
+    for (builder, data) in entries {
+        let size = data.len() as u64;
+        archive.extend(t!(builder.size(size).build_with_extensions()));
+        archive.extend_from_slice(data);
+        archive.resize((archive.len() + 511) & !511, 0);
+    }
+    archive.resize(archive.len() + 1024, 0);
+    archive
+}
+
+/// Appends `header` and `data`, padded to a whole block, to `archive`.
+///
+/// Unlike with `build_archive` the header is used as is, so it can disagree
+/// with the data which follows.
+fn append_raw(archive: &mut Vec<u8>, header: &Header, data: &[u8]) {
+    archive.extend_from_slice(header.as_bytes());
+    archive.extend_from_slice(data);
+    archive.resize((archive.len() + 511) & !511, 0);
+}
+
+/// Returns a GNU header for `path` of type `kind` which lists `size` bytes of
+/// data, with its checksum set, for use with `append_raw`.
+fn raw_header(path: &str, kind: EntryType, size: u64) -> Header {
+    let mut header = Header::new_gnu();
+    t!(header.set_path(path));
+    header.set_entry_type(kind);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_size(size);
+    header.set_cksum();
+    header
+}
+
 /// test that we can concatenate the simple.tar archive and extract the same entries twice when we
 /// use the ignore_zeros option.
-#[async_std::test]
+#[tokio::test]
 async fn simple_concat() {
     let bytes = tar!("simple.tar");
     let mut archive_bytes = Vec::new();
@@ -67,7 +105,7 @@ async fn simple_concat() {
 
     async fn decode_names<R>(ar: Archive<R>) -> Vec<String>
     where
-        R: Read + Unpin + Sync + Send,
+        R: AsyncRead + Unpin,
     {
         let mut names = Vec::new();
         let mut entries = t!(ar.entries());
@@ -81,7 +119,7 @@ async fn simple_concat() {
     }
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn header_impls() {
     let ar = Archive::new(Cursor::new(tar!("simple.tar")));
     let hn = Header::new_old();
@@ -97,7 +135,7 @@ async fn header_impls() {
     }
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn header_impls_missing_last_header() {
     let ar = Archive::new(Cursor::new(tar!("simple_missing_last_header.tar")));
     let hn = Header::new_old();
@@ -114,7 +152,7 @@ async fn header_impls_missing_last_header() {
     }
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn reading_files() {
     let rdr = Cursor::new(tar!("reading_files.tar"));
     let ar = Archive::new(rdr);
@@ -135,85 +173,7 @@ async fn reading_files() {
     assert!(entries.next().await.is_none());
 }
 
-#[async_std::test]
-async fn writing_files() {
-    let mut ar = Builder::new(Vec::new());
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let path = td.path().join("test");
-    t!(t!(File::create(&path).await).write_all(b"test").await);
-
-    t!(ar
-        .append_file("test2", &mut t!(File::open(&path).await))
-        .await);
-
-    let data = t!(ar.into_inner().await);
-    let ar = Archive::new(Cursor::new(data));
-    let mut entries = t!(ar.entries());
-    let mut f = t!(entries.next().await.unwrap());
-
-    assert_eq!(&*f.header().path_bytes(), b"test2");
-    assert_eq!(f.header().size().unwrap(), 4);
-    let mut s = String::new();
-    t!(f.read_to_string(&mut s).await);
-    assert_eq!(s, "test");
-
-    assert!(entries.next().await.is_none());
-}
-
-#[async_std::test]
-async fn large_filename() {
-    let mut ar = Builder::new(Vec::new());
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let path = td.path().join("test");
-    t!(t!(File::create(&path).await).write_all(b"test").await);
-
-    let filename = "abcd/".repeat(50);
-    let mut header = Header::new_ustar();
-    header.set_path(&filename).unwrap();
-    header.set_metadata(&t!(fs::metadata(&path).await));
-    header.set_cksum();
-    t!(ar.append(&header, &b"test"[..]).await);
-    let too_long = "abcd".repeat(200);
-    t!(ar
-        .append_file(&too_long, &mut t!(File::open(&path).await))
-        .await);
-    t!(ar.append_data(&mut header, &too_long, &b"test"[..]).await);
-
-    let rd = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rd);
-    let mut entries = t!(ar.entries());
-
-    // The short entry added with `append`
-    let mut f = entries.next().await.unwrap().unwrap();
-    assert_eq!(&*f.header().path_bytes(), filename.as_bytes());
-    assert_eq!(f.header().size().unwrap(), 4);
-    let mut s = String::new();
-    t!(f.read_to_string(&mut s).await);
-    assert_eq!(s, "test");
-
-    // The long entry added with `append_file`
-    let mut f = entries.next().await.unwrap().unwrap();
-    assert_eq!(&*f.path_bytes(), too_long.as_bytes());
-    assert_eq!(f.header().size().unwrap(), 4);
-    let mut s = String::new();
-    t!(f.read_to_string(&mut s).await);
-    assert_eq!(s, "test");
-
-    // The long entry added with `append_data`
-    let mut f = entries.next().await.unwrap().unwrap();
-    assert!(f.header().path_bytes().len() < too_long.len());
-    assert_eq!(&*f.path_bytes(), too_long.as_bytes());
-    assert_eq!(f.header().size().unwrap(), 4);
-    let mut s = String::new();
-    t!(f.read_to_string(&mut s).await);
-    assert_eq!(s, "test");
-
-    assert!(entries.next().await.is_none());
-}
-
-#[async_std::test]
+#[tokio::test]
 async fn reading_entries() {
     let rdr = Cursor::new(tar!("reading_files.tar"));
     let ar = Archive::new(rdr);
@@ -235,369 +195,208 @@ async fn reading_entries() {
     assert!(entries.next().await.is_none());
 }
 
-async fn check_dirtree(td: &TempDir) {
+fn check_dirtree(td: &TempDir) {
     let dir_a = td.path().join("a");
     let dir_b = td.path().join("a/b");
     let file_c = td.path().join("a/c");
-    assert!(fs::metadata(&dir_a)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-    assert!(fs::metadata(&dir_b)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-    assert!(fs::metadata(&file_c)
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
+    assert!(fs::metadata(dir_a).map(|m| m.is_dir()).unwrap_or(false));
+    assert!(fs::metadata(dir_b).map(|m| m.is_dir()).unwrap_or(false));
+    assert!(fs::metadata(file_c).map(|m| m.is_file()).unwrap_or(false));
 }
 
-#[async_std::test]
-async fn extracting_directories() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let rdr = Cursor::new(tar!("directory.tar"));
-    let ar = Archive::new(rdr);
-    t!(ar.unpack(td.path()).await);
-    check_dirtree(&td).await;
+#[test]
+fn extracting_directories() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = Cursor::new(tar!("directory.tar"));
+        let ar = Archive::new(rdr);
+        t!(ar.unpack(td.path()).await);
+        check_dirtree(&td);
+    })
 }
 
-#[async_std::test]
+#[test]
 #[cfg(all(unix, feature = "xattr"))]
-async fn xattrs() {
-    // If /tmp is a tmpfs, xattr will fail
-    // The xattr crate's unit tests also use /var/tmp for this reason
-    let td = t!(TempBuilder::new()
-        .prefix("async-tar")
-        .tempdir_in("/var/tmp"));
-    let rdr = Cursor::new(tar!("xattrs.tar"));
-    let builder = ArchiveBuilder::new(rdr).set_unpack_xattrs(true);
-    let ar = builder.build();
-    t!(ar.unpack(td.path()).await);
-
-    let val = xattr::get(td.path().join("a/b"), "user.pax.flags").unwrap();
-    assert_eq!(val.unwrap(), b"epm");
+fn xattrs() {
+    tokio_uring::start(async {
+        // If /tmp is a tmpfs, xattr will fail
+        // The xattr crate's unit tests also use /var/tmp for this reason
+        let td = t!(TempBuilder::new()
+            .prefix("async-tar")
+            .tempdir_in("/var/tmp"));
+        let rdr = Cursor::new(tar!("xattrs.tar"));
+        let builder = ArchiveBuilder::new(rdr).set_unpack_xattrs(true);
+        let ar = builder.build();
+        t!(ar.unpack(td.path()).await);
+
+        let val = xattr::get(td.path().join("a/b"), "user.pax.flags").unwrap();
+        assert_eq!(val.unwrap(), b"epm");
+    })
 }
 
-#[async_std::test]
+#[test]
 #[cfg(all(unix, feature = "xattr"))]
-async fn no_xattrs() {
-    // If /tmp is a tmpfs, xattr will fail
-    // The xattr crate's unit tests also use /var/tmp for this reason
-    let td = t!(TempBuilder::new()
-        .prefix("async-tar")
-        .tempdir_in("/var/tmp"));
-    let rdr = Cursor::new(tar!("xattrs.tar"));
-    let builder = ArchiveBuilder::new(rdr).set_unpack_xattrs(false);
-    let ar = builder.build();
-    t!(ar.unpack(td.path()).await);
-
-    assert_eq!(
-        xattr::get(td.path().join("a/b"), "user.pax.flags").unwrap(),
-        None
-    );
-}
-
-#[async_std::test]
-async fn writing_and_extracting_directories() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let mut ar = Builder::new(Vec::new());
-    let tmppath = td.path().join("tmpfile");
-    t!(t!(File::create(&tmppath).await).write_all(b"c").await);
-    t!(ar.append_dir("a", ".").await);
-    t!(ar.append_dir("a/b", ".").await);
-    t!(ar
-        .append_file("a/c", &mut t!(File::open(&tmppath).await))
-        .await);
-    t!(ar.finish().await);
-
-    let rdr = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rdr);
-    t!(ar.unpack(td.path()).await);
-    check_dirtree(&td).await;
-}
-
-#[async_std::test]
-async fn writing_directories_recursively() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let base_dir = td.path().join("base");
-    t!(fs::create_dir(&base_dir).await);
-    t!(t!(File::create(base_dir.join("file1")).await)
-        .write_all(b"file1")
-        .await);
-    let sub_dir = base_dir.join("sub");
-    t!(fs::create_dir(&sub_dir).await);
-    t!(t!(File::create(sub_dir.join("file2")).await)
-        .write_all(b"file2")
-        .await);
-
-    let mut ar = Builder::new(Vec::new());
-    t!(ar.append_dir_all("foobar", base_dir).await);
-    let data = t!(ar.into_inner().await);
-
-    let ar = Archive::new(Cursor::new(data));
-    t!(ar.unpack(td.path()).await);
-    let base_dir = td.path().join("foobar");
-    assert!(fs::metadata(&base_dir)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-    let file1_path = base_dir.join("file1");
-    assert!(fs::metadata(&file1_path)
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-    let sub_dir = base_dir.join("sub");
-    assert!(fs::metadata(&sub_dir)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-    let file2_path = sub_dir.join("file2");
-    assert!(fs::metadata(&file2_path)
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-}
-
-#[async_std::test]
-async fn append_dir_all_blank_dest() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let base_dir = td.path().join("base");
-    t!(fs::create_dir(&base_dir).await);
-    t!(t!(File::create(base_dir.join("file1")).await)
-        .write_all(b"file1")
-        .await);
-    let sub_dir = base_dir.join("sub");
-    t!(fs::create_dir(&sub_dir).await);
-    t!(t!(File::create(sub_dir.join("file2")).await)
-        .write_all(b"file2")
-        .await);
-
-    let mut ar = Builder::new(Vec::new());
-    t!(ar.append_dir_all("", base_dir).await);
-    let data = t!(ar.into_inner().await);
-
-    let ar = Archive::new(Cursor::new(data));
-    t!(ar.unpack(td.path()).await);
-    let base_dir = td.path();
-    assert!(fs::metadata(&base_dir)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-    let file1_path = base_dir.join("file1");
-    assert!(fs::metadata(&file1_path)
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-    let sub_dir = base_dir.join("sub");
-    assert!(fs::metadata(&sub_dir)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-    let file2_path = sub_dir.join("file2");
-    assert!(fs::metadata(&file2_path)
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-}
-
-#[async_std::test]
-async fn append_dir_all_does_not_work_on_non_directory() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let path = td.path().join("test");
-    t!(t!(File::create(&path).await).write_all(b"test").await);
-
-    let mut ar = Builder::new(Vec::new());
-    let result = ar.append_dir_all("test", path).await;
-    assert!(result.is_err());
+fn no_xattrs() {
+    tokio_uring::start(async {
+        // If /tmp is a tmpfs, xattr will fail
+        // The xattr crate's unit tests also use /var/tmp for this reason
+        let td = t!(TempBuilder::new()
+            .prefix("async-tar")
+            .tempdir_in("/var/tmp"));
+        let rdr = Cursor::new(tar!("xattrs.tar"));
+        let builder = ArchiveBuilder::new(rdr).set_unpack_xattrs(false);
+        let ar = builder.build();
+        t!(ar.unpack(td.path()).await);
+
+        assert_eq!(
+            xattr::get(td.path().join("a/b"), "user.pax.flags").unwrap(),
+            None
+        );
+    })
 }
 
-#[async_std::test]
-async fn extracting_duplicate_dirs() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let rdr = Cursor::new(tar!("duplicate_dirs.tar"));
-    let ar = Archive::new(rdr);
-    t!(ar.unpack(td.path()).await);
-
-    let some_dir = td.path().join("some_dir");
-    assert!(fs::metadata(&some_dir)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
+#[test]
+fn extracting_duplicate_dirs() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = Cursor::new(tar!("duplicate_dirs.tar"));
+        let ar = Archive::new(rdr);
+        t!(ar.unpack(td.path()).await);
+
+        let some_dir = td.path().join("some_dir");
+        assert!(fs::metadata(some_dir).map(|m| m.is_dir()).unwrap_or(false));
+    })
 }
 
-#[async_std::test]
-async fn unpack_old_style_bsd_dir() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let mut ar = Builder::new(Vec::new());
-
-    let mut header = Header::new_old();
-    header.set_entry_type(EntryType::Regular);
-    t!(header.set_path("testdir/"));
-    header.set_size(0);
-    header.set_cksum();
-    t!(ar.append(&header, &mut io::empty()).await);
-
-    // Extracting
-    let rdr = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rdr);
-    t!(ar.clone().unpack(td.path()).await);
-
-    // Iterating
-    let rdr = Cursor::new(ar.into_inner().map_err(|_| ()).unwrap().into_inner());
-    let ar = Archive::new(rdr);
-    assert!(t!(ar.entries()).all(|fr| fr.is_ok()).await);
-
-    assert!(td.path().join("testdir").is_dir());
+#[test]
+fn unpack_old_style_bsd_dir() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+
+        let mut data = Vec::new();
+        let mut header = Header::new_old();
+        header.set_entry_type(EntryType::Regular);
+        t!(header.set_path("testdir/"));
+        header.set_size(0);
+        header.set_cksum();
+        append_raw(&mut data, &header, b"");
+        data.resize(data.len() + 1024, 0);
+
+        // Extracting
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+
+        // Iterating
+        let ar = Archive::new(&data[..]);
+        assert!(t!(ar.entries()).all(|fr| async move { fr.is_ok() }).await);
+
+        assert!(td.path().join("testdir").is_dir());
+    })
 }
 
-#[async_std::test]
-async fn handling_incorrect_file_size() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let mut ar = Builder::new(Vec::new());
-
-    let path = td.path().join("tmpfile");
-    t!(File::create(&path).await);
-    let mut file = t!(File::open(&path).await);
-    let mut header = Header::new_old();
-    t!(header.set_path("somepath"));
-    header.set_metadata(&t!(file.metadata().await));
-    header.set_size(2048); // past the end of file null blocks
-    header.set_cksum();
-    t!(ar.append(&header, &mut file).await);
-
-    // Extracting
-    let rdr = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rdr);
-    assert!(ar.clone().unpack(td.path()).await.is_err());
-
-    // Iterating
-    let rdr = Cursor::new(ar.into_inner().map_err(|_| ()).unwrap().into_inner());
-    let ar = Archive::new(rdr);
-    assert!(t!(ar.entries()).any(|fr| fr.is_err()).await);
+#[test]
+fn handling_incorrect_file_size() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+
+        let mut data = Vec::new();
+        let mut header = Header::new_old();
+        t!(header.set_path("somepath"));
+        header.set_mode(0o644);
+        header.set_size(2048); // past the end of file null blocks
+        header.set_cksum();
+        append_raw(&mut data, &header, b"");
+        data.resize(data.len() + 1024, 0);
+
+        // Extracting
+        assert!(Archive::new(&data[..]).unpack(td.path()).await.is_err());
+
+        // Iterating
+        let ar = Archive::new(&data[..]);
+        assert!(t!(ar.entries()).any(|fr| async move { fr.is_err() }).await);
+    })
 }
 
-#[async_std::test]
-async fn extracting_malicious_tarball() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let mut evil_tar = Vec::new();
-
-    {
-        let mut a = Builder::new(&mut evil_tar);
-        async fn append<R: Write + Unpin + Send + Sync>(a: &mut Builder<R>, path: &'static str) {
-            let mut header = Header::new_gnu();
-            assert!(header.set_path(path).is_err(), "was ok: {:?}", path);
-            {
-                let h = header.as_gnu_mut().unwrap();
-                for (a, b) in h.name.iter_mut().zip(path.as_bytes()) {
-                    *a = *b;
-                }
+#[test]
+fn extracting_malicious_tarball() {
+    fn append(data: &mut Vec<u8>, path: &'static str) {
+        let mut header = Header::new_gnu();
+        assert!(header.set_path(path).is_err(), "was ok: {:?}", path);
+        {
+            let h = header.as_gnu_mut().unwrap();
+            for (a, b) in h.name.iter_mut().zip(path.as_bytes()) {
+                *a = *b;
             }
-            header.set_size(1);
-            header.set_cksum();
-            t!(a.append(&header, io::repeat(1).take(1)).await);
         }
-
-        append(&mut a, "/tmp/abs_evil.txt").await;
-        append(&mut a, "//tmp/abs_evil2.txt").await;
-        append(&mut a, "///tmp/abs_evil3.txt").await;
-        append(&mut a, "/./tmp/abs_evil4.txt").await;
-        append(&mut a, "//./tmp/abs_evil5.txt").await;
-        append(&mut a, "///./tmp/abs_evil6.txt").await;
-        append(&mut a, "/../tmp/rel_evil.txt").await;
-        append(&mut a, "../rel_evil2.txt").await;
-        append(&mut a, "./../rel_evil3.txt").await;
-        append(&mut a, "some/../../rel_evil4.txt").await;
-        append(&mut a, "").await;
-        append(&mut a, "././//./..").await;
-        append(&mut a, "..").await;
-        append(&mut a, "/////////..").await;
-        append(&mut a, "/////////").await;
-    }
-
-    let ar = Archive::new(&evil_tar[..]);
-    t!(ar.unpack(td.path()).await);
-
-    assert!(fs::metadata("/tmp/abs_evil.txt").await.is_err());
-    assert!(fs::metadata("/tmp/abs_evil.txt2").await.is_err());
-    assert!(fs::metadata("/tmp/abs_evil.txt3").await.is_err());
-    assert!(fs::metadata("/tmp/abs_evil.txt4").await.is_err());
-    assert!(fs::metadata("/tmp/abs_evil.txt5").await.is_err());
-    assert!(fs::metadata("/tmp/abs_evil.txt6").await.is_err());
-    assert!(fs::metadata("/tmp/rel_evil.txt").await.is_err());
-    assert!(fs::metadata("/tmp/rel_evil.txt").await.is_err());
-    assert!(fs::metadata(td.path().join("../tmp/rel_evil.txt"))
-        .await
-        .is_err());
-    assert!(fs::metadata(td.path().join("../rel_evil2.txt"))
-        .await
-        .is_err());
-    assert!(fs::metadata(td.path().join("../rel_evil3.txt"))
-        .await
-        .is_err());
-    assert!(fs::metadata(td.path().join("../rel_evil4.txt"))
-        .await
-        .is_err());
-
-    // The `some` subdirectory should not be created because the only
-    // filename that references this has '..'.
-    assert!(fs::metadata(td.path().join("some")).await.is_err());
-
-    // The `tmp` subdirectory should be created and within this
-    // subdirectory, there should be files named `abs_evil.txt` through
-    // `abs_evil6.txt`.
-    let tmp_root = td.path().join("tmp");
-
-    assert!(fs::metadata(&tmp_root)
-        .await
-        .map(|m| m.is_dir())
-        .unwrap_or(false));
-
-    let mut entries = fs::read_dir(&tmp_root).await.unwrap();
-    while let Some(entry) = entries.next().await {
-        let entry = entry.unwrap();
-        println!("- {:?}", entry.file_name());
+        header.set_size(1);
+        header.set_cksum();
+        append_raw(data, &header, &[1]);
     }
 
-    assert!(fs::metadata(tmp_root.join("abs_evil.txt"))
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-
-    // not present due to // being interpreted differently on windows
-    #[cfg(not(target_os = "windows"))]
-    assert!(fs::metadata(tmp_root.join("abs_evil2.txt"))
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-    assert!(fs::metadata(tmp_root.join("abs_evil3.txt"))
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-    assert!(fs::metadata(tmp_root.join("abs_evil4.txt"))
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-
-    // not present due to // being interpreted differently on windows
-    #[cfg(not(target_os = "windows"))]
-    assert!(fs::metadata(tmp_root.join("abs_evil5.txt"))
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
-    assert!(fs::metadata(tmp_root.join("abs_evil6.txt"))
-        .await
-        .map(|m| m.is_file())
-        .unwrap_or(false));
+    let mut evil_tar = Vec::new();
+    append(&mut evil_tar, "/tmp/abs_evil.txt");
+    append(&mut evil_tar, "//tmp/abs_evil2.txt");
+    append(&mut evil_tar, "///tmp/abs_evil3.txt");
+    append(&mut evil_tar, "/./tmp/abs_evil4.txt");
+    append(&mut evil_tar, "//./tmp/abs_evil5.txt");
+    append(&mut evil_tar, "///./tmp/abs_evil6.txt");
+    append(&mut evil_tar, "/../tmp/rel_evil.txt");
+    append(&mut evil_tar, "../rel_evil2.txt");
+    append(&mut evil_tar, "./../rel_evil3.txt");
+    append(&mut evil_tar, "some/../../rel_evil4.txt");
+    append(&mut evil_tar, "");
+    append(&mut evil_tar, "././//./..");
+    append(&mut evil_tar, "..");
+    append(&mut evil_tar, "/////////..");
+    append(&mut evil_tar, "/////////");
+    evil_tar.resize(evil_tar.len() + 1024, 0);
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = Archive::new(&evil_tar[..]);
+        t!(ar.unpack(td.path()).await);
+
+        assert!(fs::metadata("/tmp/abs_evil.txt").is_err());
+        assert!(fs::metadata("/tmp/abs_evil.txt2").is_err());
+        assert!(fs::metadata("/tmp/abs_evil.txt3").is_err());
+        assert!(fs::metadata("/tmp/abs_evil.txt4").is_err());
+        assert!(fs::metadata("/tmp/abs_evil.txt5").is_err());
+        assert!(fs::metadata("/tmp/abs_evil.txt6").is_err());
+        assert!(fs::metadata("/tmp/rel_evil.txt").is_err());
+        assert!(fs::metadata("/tmp/rel_evil.txt").is_err());
+        assert!(fs::metadata(td.path().join("../tmp/rel_evil.txt")).is_err());
+        assert!(fs::metadata(td.path().join("../rel_evil2.txt")).is_err());
+        assert!(fs::metadata(td.path().join("../rel_evil3.txt")).is_err());
+        assert!(fs::metadata(td.path().join("../rel_evil4.txt")).is_err());
+
+        // The `some` subdirectory should not be created because the only
+        // filename that references this has '..'.
+        assert!(fs::metadata(td.path().join("some")).is_err());
+
+        // The `tmp` subdirectory should be created and within this
+        // subdirectory, there should be files named `abs_evil.txt` through
+        // `abs_evil6.txt`.
+        let tmp_root = td.path().join("tmp");
+        assert!(fs::metadata(&tmp_root).map(|m| m.is_dir()).unwrap_or(false));
+        for name in [
+            "abs_evil.txt",
+            "abs_evil2.txt",
+            "abs_evil3.txt",
+            "abs_evil4.txt",
+            "abs_evil5.txt",
+            "abs_evil6.txt",
+        ] {
+            assert!(
+                fs::metadata(tmp_root.join(name))
+                    .map(|m| m.is_file())
+                    .unwrap_or(false),
+                "{}",
+                name
+            );
+        }
+    })
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn octal_spaces() {
     let rdr = Cursor::new(tar!("spaces.tar"));
     let ar = Archive::new(rdr);
@@ -611,104 +410,85 @@ async fn octal_spaces() {
     assert_eq!(entry.header().cksum().unwrap(), 0o4253);
 }
 
-#[async_std::test]
-async fn extracting_malformed_tar_null_blocks() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let mut ar = Builder::new(Vec::new());
-
-    let path1 = td.path().join("tmpfile1");
-    let path2 = td.path().join("tmpfile2");
-    t!(File::create(&path1).await);
-    t!(File::create(&path2).await);
-    t!(ar
-        .append_file("tmpfile1", &mut t!(File::open(&path1).await))
-        .await);
-    let mut data = t!(ar.into_inner().await);
-    let amt = data.len();
-    data.truncate(amt - 512);
-    let mut ar = Builder::new(data);
-    t!(ar
-        .append_file("tmpfile2", &mut t!(File::open(&path2).await))
-        .await);
-    t!(ar.finish().await);
-
-    let data = t!(ar.into_inner().await);
-    let ar = Archive::new(&data[..]);
-    assert!(ar.unpack(td.path()).await.is_ok());
+#[test]
+fn extracting_malformed_tar_null_blocks() {
+    let mut data = Vec::new();
+    let mut header = Header::new_gnu();
+    t!(header.set_path("tmpfile1"));
+    header.set_mode(0o644);
+    header.set_size(0);
+    header.set_cksum();
+    append_raw(&mut data, &header, b"");
+    // A single zero block, rather than the two ending an archive.
+    data.resize(data.len() + 512, 0);
+    t!(header.set_path("tmpfile2"));
+    header.set_cksum();
+    append_raw(&mut data, &header, b"");
+    data.resize(data.len() + 1024, 0);
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = Archive::new(&data[..]);
+        t!(ar.unpack(td.path()).await);
+    })
 }
 
-#[async_std::test]
-async fn empty_filename() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let rdr = Cursor::new(tar!("empty_filename.tar"));
-    let ar = Archive::new(rdr);
-    assert!(ar.unpack(td.path()).await.is_ok());
+#[test]
+fn empty_filename() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = Cursor::new(tar!("empty_filename.tar"));
+        let ar = Archive::new(rdr);
+        assert!(ar.unpack(td.path()).await.is_ok());
+    })
 }
 
-#[async_std::test]
-async fn file_times() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let rdr = Cursor::new(tar!("file_times.tar"));
-    let ar = Archive::new(rdr);
-    t!(ar.unpack(td.path()).await);
-
-    let meta = fs::metadata(td.path().join("a")).await.unwrap();
-    let mtime = FileTime::from_last_modification_time(&meta);
-    let atime = FileTime::from_last_access_time(&meta);
-    assert_eq!(mtime.unix_seconds(), 1_000_000_000);
-    assert_eq!(mtime.nanoseconds(), 0);
-    assert_eq!(atime.unix_seconds(), 1_000_000_000);
-    assert_eq!(atime.nanoseconds(), 0);
+#[test]
+fn file_times() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = Cursor::new(tar!("file_times.tar"));
+        let ar = Archive::new(rdr);
+        t!(ar.unpack(td.path()).await);
+
+        let meta = fs::metadata(td.path().join("a")).unwrap();
+        let mtime = FileTime::from_last_modification_time(&meta);
+        let atime = FileTime::from_last_access_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+        assert_eq!(mtime.nanoseconds(), 0);
+        assert_eq!(atime.unix_seconds(), 1_000_000_000);
+        assert_eq!(atime.nanoseconds(), 0);
+    })
 }
 
-#[async_std::test]
-async fn backslash_treated_well() {
-    // Insert a file into an archive with a backslash
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let mut ar = Builder::new(Vec::<u8>::new());
-    t!(ar.append_dir("foo\\bar", td.path()).await);
-    let ar = Archive::new(Cursor::new(t!(ar.into_inner().await)));
-    let f = t!(t!(ar.entries()).next().await.unwrap());
-    if cfg!(unix) {
-        assert_eq!(t!(f.header().path()).to_str(), Some("foo\\bar"));
-    } else {
-        assert_eq!(t!(f.header().path()).to_str(), Some("foo/bar"));
-    }
-
+#[test]
+fn backslash_treated_well() {
     // Unpack an archive with a backslash in the name
-    let mut ar = Builder::new(Vec::<u8>::new());
+    let mut data = Vec::new();
     let mut header = Header::new_gnu();
-    header.set_metadata(&t!(fs::metadata(td.path()).await));
+    header.set_entry_type(EntryType::Directory);
+    header.set_mode(0o755);
     header.set_size(0);
     for (a, b) in header.as_old_mut().name.iter_mut().zip(b"foo\\bar\x00") {
         *a = *b;
     }
     header.set_cksum();
-    t!(ar.append(&header, &mut io::empty()).await);
-    let data = t!(ar.into_inner().await);
-    let ar = Archive::new(&data[..]);
-    let f = t!(t!(ar.entries()).next().await.unwrap());
-    assert_eq!(t!(f.header().path()).to_str(), Some("foo\\bar"));
+    append_raw(&mut data, &header, b"");
+    data.resize(data.len() + 1024, 0);
 
-    let ar = Archive::new(&data[..]);
-    t!(ar.unpack(td.path()).await);
-    assert!(fs::metadata(td.path().join("foo\\bar")).await.is_ok());
-}
-
-#[cfg(unix)]
-#[async_std::test]
-async fn nul_bytes_in_path() {
-    use std::{ffi::OsStr, os::unix::prelude::*};
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = Archive::new(&data[..]);
+        let f = t!(t!(ar.entries()).next().await.unwrap());
+        assert_eq!(t!(f.header().path()).to_str(), Some("foo\\bar"));
 
-    let nul_path = OsStr::from_bytes(b"foo\0");
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let mut ar = Builder::new(Vec::<u8>::new());
-    let err = ar.append_dir(nul_path, td.path()).await.unwrap_err();
-    assert!(err.to_string().contains("contains a nul byte"));
+        let ar = Archive::new(&data[..]);
+        t!(ar.unpack(td.path()).await);
+        assert!(fs::metadata(td.path().join("foo\\bar")).is_ok());
+    })
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn links() {
     let ar = Archive::new(Cursor::new(tar!("link.tar")));
     let mut entries = t!(ar.entries());
@@ -721,23 +501,25 @@ async fn links() {
     assert!(t!(other.header().link_name()).is_none());
 }
 
-#[async_std::test]
+#[test]
 #[cfg(unix)] // making symlinks on windows is hard
-async fn unpack_links() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let ar = Archive::new(Cursor::new(tar!("link.tar")));
-    t!(ar.unpack(td.path()).await);
-
-    let md = t!(fs::symlink_metadata(td.path().join("lnk")).await);
-    assert!(md.file_type().is_symlink());
-    assert_eq!(
-        &*t!(fs::read_link(td.path().join("lnk")).await),
-        Path::new("file")
-    );
-    t!(File::open(td.path().join("lnk")).await);
+fn unpack_links() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = Archive::new(Cursor::new(tar!("link.tar")));
+        t!(ar.unpack(td.path()).await);
+
+        let md = t!(fs::symlink_metadata(td.path().join("lnk")));
+        assert!(md.file_type().is_symlink());
+        assert_eq!(
+            &*t!(fs::read_link(td.path().join("lnk"))),
+            Path::new("file")
+        );
+        t!(fs::File::open(td.path().join("lnk")));
+    })
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn pax_simple() {
     let ar = Archive::new(tar!("pax.tar"));
     let mut entries = t!(ar.entries());
@@ -757,7 +539,7 @@ async fn pax_simple() {
     assert_eq!(third.value(), Ok("1453146164.953123768"));
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn pax_path() {
     let ar = Archive::new(tar!("pax2.tar"));
     let mut entries = t!(ar.entries());
@@ -766,80 +548,53 @@ async fn pax_path() {
     assert!(first.path().unwrap().ends_with("aaaaaaaaaaaaaaa"));
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn long_name_trailing_nul() {
-    let mut b = Builder::new(Vec::<u8>::new());
-
+    let mut data = Vec::new();
     let mut h = Header::new_gnu();
     t!(h.set_path("././@LongLink"));
     h.set_size(4);
     h.set_entry_type(EntryType::new(b'L'));
     h.set_cksum();
-    t!(b.append(&h, "foo\0".as_bytes()).await);
-    let mut h = Header::new_gnu();
+    append_raw(&mut data, &h, b"foo\0");
 
+    let mut h = Header::new_gnu();
     t!(h.set_path("bar"));
     h.set_size(6);
     h.set_entry_type(EntryType::file());
     h.set_cksum();
-    t!(b.append(&h, b"foobar" as &[u8]).await);
-
-    let contents = t!(b.into_inner().await);
-    let a = Archive::new(&contents[..]);
+    append_raw(&mut data, &h, b"foobar");
+    data.resize(data.len() + 1024, 0);
 
+    let a = Archive::new(&data[..]);
     let e = t!(t!(a.entries()).next().await.unwrap());
     assert_eq!(&*e.path_bytes(), b"foo");
 }
 
-#[async_std::test]
+#[tokio::test]
 async fn long_linkname_trailing_nul() {
-    let mut b = Builder::new(Vec::<u8>::new());
-
+    let mut data = Vec::new();
     let mut h = Header::new_gnu();
     t!(h.set_path("././@LongLink"));
     h.set_size(4);
     h.set_entry_type(EntryType::new(b'K'));
     h.set_cksum();
-    t!(b.append(&h, "foo\0".as_bytes()).await);
-    let mut h = Header::new_gnu();
+    append_raw(&mut data, &h, b"foo\0");
 
+    let mut h = Header::new_gnu();
     t!(h.set_path("bar"));
     h.set_size(6);
     h.set_entry_type(EntryType::file());
     h.set_cksum();
-    t!(b.append(&h, b"foobar" as &[u8]).await);
-
-    let contents = t!(b.into_inner().await);
-    let a = Archive::new(&contents[..]);
+    append_raw(&mut data, &h, b"foobar");
+    data.resize(data.len() + 1024, 0);
 
+    let a = Archive::new(&data[..]);
     let e = t!(t!(a.entries()).next().await.unwrap());
     assert_eq!(&*e.link_name_bytes().unwrap(), b"foo");
 }
 
-#[async_std::test]
-async fn encoded_long_name_has_trailing_nul() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let path = td.path().join("foo");
-    t!(t!(File::create(&path).await).write_all(b"test").await);
-
-    let mut b = Builder::new(Vec::<u8>::new());
-    let long = "abcd".repeat(200);
-
-    t!(b.append_file(&long, &mut t!(File::open(&path).await)).await);
-
-    let contents = t!(b.into_inner().await);
-    let a = Archive::new(&contents[..]);
-
-    let mut e = t!(t!(a.entries_raw()).next().await.unwrap());
-    let mut name = Vec::new();
-    t!(e.read_to_end(&mut name).await);
-    assert_eq!(name[name.len() - 1], 0);
-
-    let header_name = &e.header().as_gnu().unwrap().name;
-    assert!(header_name.starts_with(b"././@LongLink\x00"));
-}
-
-#[async_std::test]
+#[tokio::test]
 async fn reading_sparse() {
     let rdr = Cursor::new(tar!("sparse.tar"));
     let ar = Archive::new(rdr);
@@ -863,57 +618,19 @@ async fn reading_sparse() {
     let mut s = String::new();
     assert_eq!(&*a.header().path_bytes(), b"sparse_ext.txt");
     t!(a.read_to_string(&mut s).await);
-    assert!(s[..0x1000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x1000..0x1000 + 5], "text\n");
-    assert!(s[0x1000 + 5..0x3000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x3000..0x3000 + 5], "text\n");
-    assert!(s[0x3000 + 5..0x5000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x5000..0x5000 + 5], "text\n");
-    assert!(s[0x5000 + 5..0x7000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x7000..0x7000 + 5], "text\n");
-    assert!(s[0x7000 + 5..0x9000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x9000..0x9000 + 5], "text\n");
-    assert!(s[0x9000 + 5..0xb000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0xb000..0xb000 + 5], "text\n");
+    check_sparse_ext(&s);
 
     let mut a = t!(entries.next().await.unwrap());
     let mut s = String::new();
     assert_eq!(&*a.header().path_bytes(), b"sparse.txt");
     t!(a.read_to_string(&mut s).await);
-    assert!(s[..0x1000].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x1000..0x1000 + 6], "hello\n");
-    assert!(s[0x1000 + 6..0x2fa0].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[0x2fa0..0x2fa0 + 6], "world\n");
-    assert!(s[0x2fa0 + 6..0x4000].chars().all(|x| x == '\u{0}'));
+    check_sparse(&s);
 
     assert!(entries.next().await.is_none());
 }
 
-#[async_std::test]
-async fn extract_sparse() {
-    let rdr = Cursor::new(tar!("sparse.tar"));
-    let ar = Archive::new(rdr);
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    t!(ar.unpack(td.path()).await);
-
-    let mut s = String::new();
-    t!(t!(File::open(td.path().join("sparse_begin.txt")).await)
-        .read_to_string(&mut s)
-        .await);
-    assert_eq!(&s[..5], "test\n");
-    assert!(s[5..].chars().all(|x| x == '\u{0}'));
-
-    s.truncate(0);
-    t!(t!(File::open(td.path().join("sparse_end.txt")).await)
-        .read_to_string(&mut s)
-        .await);
-    assert!(s[..s.len() - 9].chars().all(|x| x == '\u{0}'));
-    assert_eq!(&s[s.len() - 9..], "test_end\n");
-
-    s.truncate(0);
-    t!(t!(File::open(td.path().join("sparse_ext.txt")).await)
-        .read_to_string(&mut s)
-        .await);
+/// Checks the contents of `sparse_ext.txt` from `sparse.tar`.
+fn check_sparse_ext(s: &str) {
     assert!(s[..0x1000].chars().all(|x| x == '\u{0}'));
     assert_eq!(&s[0x1000..0x1000 + 5], "text\n");
     assert!(s[0x1000 + 5..0x3000].chars().all(|x| x == '\u{0}'));
@@ -926,11 +643,10 @@ async fn extract_sparse() {
     assert_eq!(&s[0x9000..0x9000 + 5], "text\n");
     assert!(s[0x9000 + 5..0xb000].chars().all(|x| x == '\u{0}'));
     assert_eq!(&s[0xb000..0xb000 + 5], "text\n");
+}
 
-    s.truncate(0);
-    t!(t!(File::open(td.path().join("sparse.txt")).await)
-        .read_to_string(&mut s)
-        .await);
+/// Checks the contents of `sparse.txt` from `sparse.tar`.
+fn check_sparse(s: &str) {
     assert!(s[..0x1000].chars().all(|x| x == '\u{0}'));
     assert_eq!(&s[0x1000..0x1000 + 6], "hello\n");
     assert!(s[0x1000 + 6..0x2fa0].chars().all(|x| x == '\u{0}'));
@@ -938,14 +654,29 @@ async fn extract_sparse() {
     assert!(s[0x2fa0 + 6..0x4000].chars().all(|x| x == '\u{0}'));
 }
 
-#[async_std::test]
-async fn path_separators() {
-    let mut ar = Builder::new(Vec::new());
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-
-    let path = td.path().join("test");
-    t!(t!(File::create(&path).await).write_all(b"test").await);
+#[test]
+fn extract_sparse() {
+    tokio_uring::start(async {
+        let rdr = Cursor::new(tar!("sparse.tar"));
+        let ar = Archive::new(rdr);
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(ar.unpack(td.path()).await);
+
+        let s = t!(fs::read_to_string(td.path().join("sparse_begin.txt")));
+        assert_eq!(&s[..5], "test\n");
+        assert!(s[5..].chars().all(|x| x == '\u{0}'));
+
+        let s = t!(fs::read_to_string(td.path().join("sparse_end.txt")));
+        assert!(s[..s.len() - 9].chars().all(|x| x == '\u{0}'));
+        assert_eq!(&s[s.len() - 9..], "test_end\n");
+
+        check_sparse_ext(&t!(fs::read_to_string(td.path().join("sparse_ext.txt"))));
+        check_sparse(&t!(fs::read_to_string(td.path().join("sparse.txt"))));
+    })
+}
 
+#[test]
+fn path_separators() {
     let short_path: PathBuf = repeat("abcd").take(2).collect();
     let long_path: PathBuf = repeat("abcd").take(50).collect();
 
@@ -959,163 +690,3350 @@ async fn path_separators() {
     t!(header.set_path(&long_path));
     assert_eq!(t!(header.path()), long_path);
     assert!(!header.path_bytes().contains(&b'\\'));
+}
 
-    // Make sure GNU headers normalize to Unix path separators,
-    // including the `@LongLink` fallback used by `append_file`.
-    t!(ar
-        .append_file(&short_path, &mut t!(File::open(&path).await))
-        .await);
-    t!(ar
-        .append_file(&long_path, &mut t!(File::open(&path).await))
-        .await);
+#[test]
+fn name_with_slash_doesnt_fool_long_link_and_bsd_compat() {
+    let mut data = Vec::new();
+    let mut h = Header::new_gnu();
+    t!(h.set_path("././@LongLink"));
+    h.set_size(4);
+    h.set_entry_type(EntryType::new(b'L'));
+    h.set_cksum();
+    append_raw(&mut data, &h, b"foo\0");
 
-    let rd = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rd);
-    let mut entries = t!(ar.entries());
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    t!(header.set_path("testdir/"));
+    header.set_mode(0o644);
+    header.set_size(0);
+    header.set_cksum();
+    append_raw(&mut data, &header, b"");
+    data.resize(data.len() + 1024, 0);
 
-    let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), short_path);
-    assert!(!entry.path_bytes().contains(&b'\\'));
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
 
-    let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), long_path);
-    assert!(!entry.path_bytes().contains(&b'\\'));
+        // Extracting
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
 
-    assert!(entries.next().await.is_none());
-}
+        // Iterating
+        let ar = Archive::new(&data[..]);
+        assert!(t!(ar.entries()).all(|fr| async move { fr.is_ok() }).await);
 
-#[async_std::test]
-#[cfg(unix)]
-async fn append_path_symlink() {
-    use std::{borrow::Cow, env, os::unix::fs::symlink};
+        assert!(td.path().join("foo").is_file());
+    })
+}
 
-    let mut ar = Builder::new(Vec::new());
-    ar.follow_symlinks(false);
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+#[test]
+fn long_path() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+        let rdr = Cursor::new(tar!("7z_long_path.tar"));
+        let ar = Archive::new(rdr);
+        ar.unpack(td.path()).await.unwrap();
+    })
+}
 
-    let long_linkname = "abcd".repeat(30);
-    let long_pathname = "dcba".repeat(30);
-    t!(env::set_current_dir(td.path()));
-    // "short" path name / short link name
-    t!(symlink("testdest", "test"));
-    t!(ar.append_path("test").await);
-    // short path name / long link name
-    t!(symlink(&long_linkname, "test2"));
-    t!(ar.append_path("test2").await);
-    // long path name / long link name
-    t!(symlink(&long_linkname, &long_pathname));
-    t!(ar.append_path(&long_pathname).await);
-
-    let rd = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rd);
-    let mut entries = t!(ar.entries());
+#[cfg(feature = "serve")]
+#[tokio::test]
+async fn serve_range_requests() {
+    use async_tar::serve::{Request, Server};
+    use tokio::io::AsyncReadExt;
 
-    let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), Path::new("test"));
-    assert_eq!(
-        t!(entry.link_name()),
-        Some(Cow::from(Path::new("testdest")))
-    );
-    assert_eq!(t!(entry.header().size()), 0);
+    let rdr = std::io::Cursor::new(tar!("reading_files.tar"));
+    let mut server = t!(Server::new(rdr).await);
 
-    let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), Path::new("test2"));
+    let resp = t!(server.respond(&Request::new("/a").range("bytes=2-3")).await);
+    assert_eq!(resp.status(), 206);
+    assert_eq!(resp.header("content-range"), Some("bytes 2-3/22"));
+    let etag = resp.header("etag").unwrap().to_string();
+    // SHA-256 of the contents.
     assert_eq!(
-        t!(entry.link_name()),
-        Some(Cow::from(Path::new(&long_linkname)))
+        etag,
+        "\"4f1057cce3b43df559170162abc16f7b72b14139ea974634dbb194b734c4a870\""
     );
-    assert_eq!(t!(entry.header().size()), 0);
+    let mut body = String::new();
+    t!(resp.into_body().unwrap().read_to_string(&mut body).await);
+    assert_eq!(body, "a\n");
 
-    let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), Path::new(&long_pathname));
+    let resp = t!(server.respond(&Request::new("/b")).await);
+    assert_eq!(resp.status(), 200);
     assert_eq!(
-        t!(entry.link_name()),
-        Some(Cow::from(Path::new(&long_linkname)))
+        resp.header("content-type"),
+        Some("text/plain; charset=utf-8")
     );
-    assert_eq!(t!(entry.header().size()), 0);
 
+    let resp = t!(server
+        .respond(&Request::new("/a").if_none_match(&etag))
+        .await);
+    assert_eq!(resp.status(), 304);
+    let weak = format!("W/{}", etag);
+    let resp = t!(server
+        .respond(&Request::new("/a").if_none_match(&weak))
+        .await);
+    assert_eq!(resp.status(), 304);
+    let resp = t!(server.respond(&Request::new("/a").range("bytes=30-")).await);
+    assert_eq!(resp.status(), 416);
+    let resp = t!(server.respond(&Request::new("/../a")).await);
+    assert_eq!(resp.status(), 404);
+}
+
+#[test]
+fn unpack_with_fixed_buffers() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = std::io::Cursor::new(tar!("reading_files.tar"));
+        let ar = ArchiveBuilder::new(rdr).set_fixed_buffers(2, 4).build();
+        t!(ar.unpack(td.path()).await);
+
+        let a = t!(std::fs::read_to_string(td.path().join("a")));
+        assert_eq!(a, "a\na\na\na\na\na\na\na\na\na\na\n");
+        let b = t!(std::fs::read_to_string(td.path().join("b")));
+        assert_eq!(b, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+    });
+}
+
+#[cfg(feature = "fuse")]
+#[tokio::test]
+async fn fuse_read_only_view() {
+    use async_tar::fuse::{ArchiveFs, NodeKind, ROOT_INODE};
+    use std::ffi::OsStr;
+
+    let rdr = std::io::Cursor::new(tar!("pax.tar"));
+    let mut fs = t!(ArchiveFs::new(rdr).await);
+
+    let src = fs.lookup(ROOT_INODE, OsStr::new("src")).unwrap();
+    assert_eq!(src.kind, NodeKind::Directory);
+    let names = fs
+        .readdir(src.ino)
+        .unwrap()
+        .into_iter()
+        .map(|e| e.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, [".", "..", "lib.rs"]);
+
+    let lib = fs.lookup(src.ino, OsStr::new("lib.rs")).unwrap();
+    assert_eq!(lib.kind, NodeKind::File);
+    assert_eq!(lib.size, 2390);
+    assert_eq!(lib.perm, 0o664);
+    let data = t!(fs.read(lib.ino, 2380, 100).await);
+    assert_eq!(data.len(), 10);
+
+    let rdr = std::io::Cursor::new(tar!("link.tar"));
+    let fs = t!(ArchiveFs::new(rdr).await);
+    let lnk = fs.lookup(ROOT_INODE, OsStr::new("lnk")).unwrap();
+    assert_eq!(lnk.kind, NodeKind::Symlink);
+    assert_eq!(fs.readlink(lnk.ino), Some(std::path::Path::new("file")));
+}
+
+#[cfg(feature = "fuse")]
+#[tokio::test]
+async fn fuse_read_sparse() {
+    use async_tar::fuse::{ArchiveFs, ROOT_INODE};
+    use tokio::io::AsyncReadExt;
+
+    let mut contents = Vec::new();
+    let mut entries = t!(Archive::new(tar!("sparse.tar")).entries());
+    while let Some(entry) = entries.next().await {
+        let mut entry = t!(entry);
+        let mut buf = Vec::new();
+        t!(entry.read_to_end(&mut buf).await);
+        contents.push((t!(entry.path()).into_owned(), buf));
+    }
+
+    let rdr = std::io::Cursor::new(tar!("sparse.tar"));
+    let mut fs = t!(ArchiveFs::new(rdr).await);
+    for (path, expected) in contents {
+        let node = fs.lookup(ROOT_INODE, path.as_os_str()).unwrap();
+        assert_eq!(node.size, expected.len() as u64);
+        for offset in (0..expected.len()).step_by(997) {
+            let data = t!(fs.read(node.ino, offset as u64, 1500).await);
+            let end = (offset + 1500).min(expected.len());
+            assert!(data == expected[offset..end], "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn unpack_with_preallocation() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = std::io::Cursor::new(tar!("sparse.tar"));
+        let ar = ArchiveBuilder::new(rdr).set_preallocate(true).build();
+        t!(ar.unpack(td.path()).await);
+
+        let meta = t!(std::fs::metadata(td.path().join("sparse_ext.txt")));
+        assert_eq!(meta.len(), 45061);
+        let s = t!(std::fs::read_to_string(td.path().join("sparse_begin.txt")));
+        assert_eq!(&s[..5], "test\n");
+
+        // Holes of sparse files are neither preallocated nor filled in.
+        use std::os::unix::fs::MetadataExt;
+        let plain = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(tar!("sparse.tar")).unpack(plain.path()).await);
+        let plain = t!(std::fs::metadata(plain.path().join("sparse_ext.txt")));
+        assert!(meta.blocks() * 512 < meta.len());
+        assert!(plain.blocks() * 512 < plain.len());
+    });
+}
+
+#[test]
+fn unpack_with_direct_io() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = std::io::Cursor::new(tar!("sparse.tar"));
+        let ar = ArchiveBuilder::new(rdr).set_direct_io(true).build();
+        t!(ar.unpack(td.path()).await);
+
+        let s = t!(std::fs::read_to_string(td.path().join("sparse_ext.txt")));
+        assert_eq!(s.len(), 45061);
+        assert!(s[..0x1000].chars().all(|x| x == '\u{0}'));
+        assert_eq!(&s[0x1000..0x1000 + 5], "text\n");
+        let s = t!(std::fs::read_to_string(td.path().join("sparse_begin.txt")));
+        assert_eq!(&s[..5], "test\n");
+    });
+}
+
+#[tokio::test]
+async fn logical_and_stored_sizes() {
+    let ar = Archive::new(tar!("sparse.tar"));
+    let mut entries = t!(ar.entries());
+    let a = t!(entries.next().await.unwrap());
+    assert_eq!(t!(a.logical_size()), 8096);
+    assert_eq!(t!(a.stored_data_size()), 512);
+
+    let record = b"27 GNU.sparse.realsize=100\n";
+    let mut data = Vec::new();
+    let mut header = Header::new_ustar();
+    t!(header.set_path("PaxHeaders/a"));
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(record.len() as u64);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(record);
+    data.resize(1024, 0);
+    let mut header = Header::new_ustar();
+    t!(header.set_path("a"));
+    header.set_size(4);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(b"abcd");
+    data.resize(3072, 0);
+
+    let ar = Archive::new(&data[..]);
+    let mut entries = t!(ar.entries());
+    let a = t!(entries.next().await.unwrap());
+    assert_eq!(t!(a.logical_size()), 100);
+    assert_eq!(t!(a.stored_data_size()), 4);
+}
+
+#[tokio::test]
+async fn read_file_with_fadvise() {
+    let file = t!(tokio::fs::File::open("tests/archives/reading_files.tar").await);
+    let ar = ArchiveBuilder::new(file).set_fadvise(true).build();
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().await.unwrap());
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+    assert!(entries.next().await.is_some());
+    assert!(entries.next().await.is_none());
+}
+
+#[tokio::test]
+async fn seek_backwards_with_fadvise() {
+    use tokio::io::{AsyncSeekExt, SeekFrom};
+
+    // Large enough for the advice window to be moved while reading.
+    let data: Vec<u8> = (0..20 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let header = async_tar::HeaderBuilder::new_gnu().path("big");
+    let archive = build_archive(vec![(header, &data[..])]);
+    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
+    let path = td.path().join("big.tar");
+    t!(fs::write(&path, archive));
+
+    let file = t!(tokio::fs::File::open(&path).await);
+    let ar = ArchiveBuilder::new(file).set_fadvise(true).build();
+    let mut entries = t!(ar.entries());
+    let mut entry = t!(entries.next().await.unwrap());
+    let mut buf = Vec::new();
+    t!(entry.read_to_end(&mut buf).await);
+    assert!(buf == data);
+
+    assert_eq!(t!(entry.seek(SeekFrom::Start(1000)).await), 1000);
+    let mut buf = Vec::new();
+    t!(entry.read_to_end(&mut buf).await);
+    assert!(buf[..] == data[1000..]);
+    assert!(entries.next().await.is_none());
+}
+
+#[tokio::test]
+async fn unpack_to_memory() {
+    let ar = Archive::new(tar!("directory.tar"));
+    let map = t!(ar.unpack_to_memory().await);
+    assert_eq!(map.len(), 3);
+    assert!(map[std::path::Path::new("a/b")]
+        .header
+        .entry_type()
+        .is_dir());
+    let c = &map[std::path::Path::new("a/c")];
+    assert_eq!(&c.data[..], b"c\n");
+    assert_eq!(t!(c.header.size()), 2);
+}
+
+#[test]
+fn unpack_symlink_depth_limit() {
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(std::fs::create_dir(td.path().join("real")));
+        t!(std::os::unix::fs::symlink("real", td.path().join("l2")));
+        t!(std::os::unix::fs::symlink("l2", td.path().join("l1")));
+        t!(std::os::unix::fs::symlink("l1", td.path().join("l0")));
+
+        let mut header = Header::new_gnu();
+        t!(header.set_path("l0/file"));
+        header.set_size(0);
+        header.set_cksum();
+        let mut data = header.as_bytes().to_vec();
+        data.resize(2048, 0);
+
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_max_symlink_depth(2)
+            .build();
+        let err = ar.unpack(td.path()).await.unwrap_err();
+        assert!(format!("{:?}", err).contains("levels of symbolic links"));
+        assert!(!td.path().join("real/file").exists());
+
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_max_symlink_depth(3)
+            .build();
+        t!(ar.unpack(td.path()).await);
+        assert!(td.path().join("real/file").exists());
+    });
+}
+
+#[test]
+fn unpack_into_sink() {
+    use async_tar::{ExtractSink, SinkFuture};
+    use std::path::Path;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl ExtractSink for Recorder {
+        fn create_dir<'a>(&'a mut self, path: &'a Path, _: &'a Header) -> SinkFuture<'a, ()> {
+            self.0.push(format!("dir {}", path.display()));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn write_file<'a>(
+            &'a mut self,
+            path: &'a Path,
+            _: &'a Header,
+            data: &'a mut (dyn tokio::io::AsyncRead + Unpin),
+        ) -> SinkFuture<'a, ()> {
+            Box::pin(async move {
+                let mut s = String::new();
+                tokio::io::AsyncReadExt::read_to_string(data, &mut s).await?;
+                self.0.push(format!("file {} {:?}", path.display(), s));
+                Ok(())
+            })
+        }
+
+        fn symlink<'a>(
+            &'a mut self,
+            path: &'a Path,
+            target: &'a Path,
+            _: &'a Header,
+        ) -> SinkFuture<'a, ()> {
+            self.0
+                .push(format!("symlink {} {}", path.display(), target.display()));
+            Box::pin(async { Ok(()) })
+        }
+
+        fn set_metadata<'a>(&'a mut self, path: &'a Path, _: &'a Header) -> SinkFuture<'a, ()> {
+            self.0.push(format!("meta {}", path.display()));
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    tokio_uring::start(async {
+        let mut sink = Recorder::default();
+        t!(Archive::new(tar!("directory.tar"))
+            .unpack_into(&mut sink)
+            .await);
+        assert_eq!(
+            sink.0,
+            [
+                "dir a",
+                "dir a/b",
+                "file a/c \"c\\n\"",
+                "meta a/c",
+                "meta a/b",
+                "meta a"
+            ]
+        );
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let mut sink = async_tar::FsSink::new(td.path());
+        t!(Archive::new(tar!("link.tar")).unpack_into(&mut sink).await);
+        let s = t!(fs::read_to_string(td.path().join("lnk")));
+        assert_eq!(s, t!(fs::read_to_string(td.path().join("file"))));
+
+        // Hard links out of the destination are not silently dropped.
+        let data = build_archive(vec![(
+            async_tar::HeaderBuilder::new_ustar()
+                .path("lnk")
+                .link_name("../file")
+                .entry_type(EntryType::Link),
+            &[],
+        )]);
+        let mut sink = Recorder::default();
+        let err = Archive::new(&data[..])
+            .unpack_into(&mut sink)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("lnk"), "{}", err);
+        assert!(sink.0.is_empty());
+    });
+}
+
+#[tokio::test]
+async fn max_input_bytes() {
+    use async_tar::InputLimitExceeded;
+
+    let bytes = tar!("simple.tar");
+    let ar = ArchiveBuilder::new(bytes)
+        .set_max_input_bytes(bytes.len() as u64)
+        .build();
+    let mut entries = t!(ar.entries());
+    while let Some(entry) = entries.next().await {
+        t!(entry);
+    }
+
+    let zeros = tokio::io::repeat(0);
+    let ar = ArchiveBuilder::new(zeros)
+        .set_ignore_zeros(true)
+        .set_max_input_bytes(10 * 512)
+        .build();
+    let mut entries = t!(ar.entries());
+    let err = entries.next().await.unwrap().unwrap_err();
+    let limit = err
+        .get_ref()
+        .and_then(|e| e.downcast_ref::<InputLimitExceeded>())
+        .unwrap();
+    assert_eq!(limit.limit(), 10 * 512);
+}
+
+#[tokio::test]
+async fn entries_termination() {
+    use async_tar::Termination;
+
+    async fn terminate(ar: Archive<&[u8]>) -> Option<Termination> {
+        let mut entries = t!(ar.entries());
+        while let Some(entry) = entries.next().await {
+            t!(entry);
+        }
+        entries.termination()
+    }
+
+    let bytes = tar!("simple.tar");
+    assert_eq!(
+        terminate(Archive::new(bytes)).await,
+        Some(Termination::CleanEof { zero_blocks: 1 })
+    );
+    let ar = ArchiveBuilder::new(bytes).set_ignore_zeros(true).build();
+    let zero_blocks = bytes.iter().rev().take_while(|b| **b == 0).count() as u64 / 512;
+    assert_eq!(
+        terminate(ar).await,
+        Some(Termination::CleanEof { zero_blocks })
+    );
+    assert_eq!(
+        terminate(Archive::new(&bytes[..1536])).await,
+        Some(Termination::TruncatedAfter { offset: 1536 })
+    );
+
+    let ar = ArchiveBuilder::new(bytes).set_max_input_bytes(600).build();
+    let mut entries = t!(ar.entries());
+    while let Some(Ok(_)) = entries.next().await {}
+    assert_eq!(entries.termination(), Some(Termination::StoppedByLimit));
+}
+
+#[cfg(feature = "sha2")]
+#[test]
+fn entry_digest() {
+    use async_tar::DigestAlgorithm;
+
+    const A: &str = "4f1057cce3b43df559170162abc16f7b72b14139ea974634dbb194b734c4a870";
+    const B: &str = "6c1b00c03e47115d53f9d48a6dd40119d109e245ef2186a167203b62da1d6bd4";
+
+    fn hex(digest: [u8; 32]) -> String {
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    tokio_uring::start(async {
+        let ar = ArchiveBuilder::new(tar!("reading_files.tar"))
+            .set_digest(DigestAlgorithm::Sha256)
+            .build();
+        let mut entries = t!(ar.entries());
+        let mut a = t!(entries.next().await.unwrap());
+        assert_eq!(a.digest(), None);
+        let mut s = String::new();
+        t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+        assert_eq!(hex(a.digest().unwrap()), A);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let mut b = t!(entries.next().await.unwrap());
+        t!(b.unpack_in(td.path()).await);
+        assert_eq!(hex(b.digest().unwrap()), B);
+    });
+}
+
+#[test]
+fn unpack_mangled_names() {
+    tokio_uring::start(async {
+        // Long names with a nul byte cannot be written by `HeaderBuilder`.
+        let mut data = Vec::new();
+        let longname = raw_header("././@LongLink", EntryType::GNULongName, 10);
+        append_raw(&mut data, &longname, b"dir/a\0b%c\0");
+        append_raw(&mut data, &raw_header("x", EntryType::Regular, 2), b"hi");
+        append_raw(
+            &mut data,
+            &raw_header("plain", EntryType::Regular, 2),
+            b"ok",
+        );
+        data.resize(data.len() + 1024, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        assert!(Archive::new(&data[..]).unpack(td.path()).await.is_err());
+
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_mangle_names(true)
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        let mangled = std::path::Path::new("dir/a%00b%25c");
+        assert_eq!(t!(std::fs::read_to_string(td.path().join(mangled))), "hi");
+        assert_eq!(t!(std::fs::read_to_string(td.path().join("plain"))), "ok");
+        assert_eq!(report.mangled_names().len(), 1);
+        let (original, unpacked) = &report.mangled_names()[0];
+        assert_eq!(
+            std::os::unix::ffi::OsStrExt::as_bytes(original.as_os_str()),
+            b"dir/a\0b%c"
+        );
+        assert_eq!(unpacked, mangled);
+    });
+}
+
+#[tokio::test]
+async fn verify() {
+    let bytes = tar!("reading_files.tar");
+    t!(Archive::new(bytes).verify().await);
+    t!(Archive::new(tar!("sparse.tar")).verify().await);
+    t!(Archive::new(tar!("pax.tar")).verify().await);
+
+    assert!(Archive::new(&bytes[..1024]).verify().await.is_err());
+    assert!(Archive::new(&bytes[..1536]).verify().await.is_err());
+
+    let mut corrupt = bytes.to_vec();
+    corrupt[600] ^= 1;
+    assert!(Archive::new(&corrupt[..]).verify().await.is_ok());
+    corrupt[100] ^= 1;
+    assert!(Archive::new(&corrupt[..]).verify().await.is_err());
+}
+
+#[tokio::test]
+async fn resync_headers() {
+    let mut bytes = tar!("reading_files.tar").to_vec();
+    bytes[100] ^= 1;
+    let mut entries = t!(Archive::new(&bytes[..]).entries());
+    assert!(entries.next().await.unwrap().is_err());
+
+    let ar = ArchiveBuilder::new(&bytes[..])
+        .set_resync_headers(true)
+        .build();
+    let mut entries = t!(ar.entries());
+    let mut b = t!(entries.next().await.unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert_eq!(b.skipped_bytes(), 1024);
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut b, &mut s).await);
+    assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+    assert!(entries.next().await.is_none());
+
+    let mut bytes = tar!("reading_files.tar").to_vec();
+    bytes[1024 + 100] ^= 1;
+    let ar = ArchiveBuilder::new(&bytes[..])
+        .set_resync_headers(true)
+        .build();
+    let mut entries = t!(ar.entries());
+    let a = t!(entries.next().await.unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    assert_eq!(a.skipped_bytes(), 0);
+    assert!(entries.next().await.is_none());
+    assert_eq!(
+        entries.termination(),
+        Some(async_tar::Termination::DamagedAfter {
+            offset: 1024,
+            skipped: bytes.len() as u64 - 1024,
+        })
+    );
+}
+
+#[tokio::test]
+async fn cat() {
+    let mut out = Vec::new();
+    t!(Archive::new(tar!("reading_files.tar"))
+        .cat::<&str, _>(&[], &mut out)
+        .await);
+    assert_eq!(
+        out,
+        "a\n"
+            .repeat(11)
+            .into_bytes()
+            .into_iter()
+            .chain("b\n".repeat(11).into_bytes())
+            .collect::<Vec<_>>()
+    );
+
+    let mut out = Vec::new();
+    t!(Archive::new(tar!("reading_files.tar"))
+        .cat_with(&["./b"], &mut out, |path, _| {
+            Some(format!("==> {} <==\n", path.display()).into_bytes())
+        })
+        .await);
+    assert_eq!(out, format!("==> b <==\n{}", "b\n".repeat(11)).into_bytes());
+
+    let mut out = Vec::new();
+    t!(Archive::new(tar!("directory.tar"))
+        .cat(&["a"], &mut out)
+        .await);
+    assert_eq!(out, b"c\n");
+}
+
+#[test]
+#[cfg(all(unix, feature = "xattr"))]
+fn unpack_metadata_order() {
+    use std::os::unix::fs::PermissionsExt;
+
+    use async_tar::MetadataOrder;
+
+    fn archive(mode: u32) -> Vec<u8> {
+        let record = b"30 SCHILY.xattr.user.test=yes\n";
+        let mut data = Vec::new();
+        let mut header = Header::new_ustar();
+        t!(header.set_path("PaxHeaders/f"));
+        header.set_entry_type(EntryType::XHeader);
+        header.set_size(record.len() as u64);
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+        data.extend_from_slice(record);
+        data.resize(1024, 0);
+        let mut header = Header::new_ustar();
+        t!(header.set_path("f"));
+        header.set_mode(mode);
+        header.set_mtime(1_000_000_000);
+        header.set_size(2);
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+        data.extend_from_slice(b"hi");
+        data.resize(3072, 0);
+        data
+    }
+
+    tokio_uring::start(async {
+        for &(mode, order) in &[
+            (0o400, MetadataOrder::XattrsFirst),
+            (0o4755, MetadataOrder::XattrsFirst),
+            (0o4755, MetadataOrder::ModeFirst),
+        ] {
+            // If /tmp is a tmpfs, xattr will fail
+            let td = t!(TempBuilder::new()
+                .prefix("async-tar")
+                .tempdir_in("/var/tmp"));
+            let data = archive(mode);
+            let ar = ArchiveBuilder::new(&data[..])
+                .set_unpack_xattrs(true)
+                .set_preserve_permissions(true)
+                .set_metadata_order(order)
+                .build();
+            t!(ar.unpack(td.path()).await);
+
+            let path = td.path().join("f");
+            let meta = t!(std::fs::metadata(&path));
+            assert_eq!(meta.permissions().mode() & 0o7777, mode);
+            let mtime = FileTime::from_last_modification_time(&meta);
+            assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+            let val = xattr::get(&path, "user.test").unwrap();
+            assert_eq!(val.unwrap(), b"yes");
+            t!(std::fs::set_permissions(
+                &path,
+                std::fs::Permissions::from_mode(0o644)
+            ));
+        }
+    });
+}
+
+#[tokio::test]
+async fn multi_volume() {
+    use async_tar::MultiVolumeReader;
+
+    // The first volume ends in the middle of `f`, which the second continues.
+    let contents = (0..1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let mut first = Vec::new();
+    let f = raw_header("f", EntryType::Regular, 1024);
+    append_raw(&mut first, &f, &contents[..512]);
+
+    let mut continued = raw_header("f", EntryType::GNUMultiVolume, 512);
+    continued.as_gnu_mut().unwrap().set_offset(512);
+    continued.set_cksum();
+    let mut second = Vec::new();
+    let label = raw_header("label", EntryType::GNUVolumeLabel, 0);
+    append_raw(&mut second, &label, &[]);
+    append_raw(&mut second, &continued, &contents[512..]);
+    append_raw(&mut second, &raw_header("g", EntryType::Regular, 2), b"g\n");
+    second.resize(second.len() + 1024, 0);
+
+    // A continuation which does not pick up where the first volume stopped.
+    let mut wrong = second.clone();
+    continued.as_gnu_mut().unwrap().set_offset(0);
+    continued.set_cksum();
+    wrong[512..1024].copy_from_slice(continued.as_bytes());
+    let volumes = futures_util::stream::iter(vec![Ok(&wrong[..])]);
+    let ar = Archive::new(MultiVolumeReader::new(&first[..], volumes));
+    let mut entries = t!(ar.entries());
+    let mut f = t!(entries.next().await.unwrap());
+    let mut data = Vec::new();
+    assert!(tokio::io::AsyncReadExt::read_to_end(&mut f, &mut data)
+        .await
+        .is_err());
+
+    let volumes = futures_util::stream::iter(vec![Ok(&second[..])]);
+    let ar = Archive::new(MultiVolumeReader::new(&first[..], volumes));
+    let mut entries = t!(ar.entries());
+    let mut f = t!(entries.next().await.unwrap());
+    assert_eq!(&*f.path_bytes(), b"f");
+    let mut data = Vec::new();
+    t!(tokio::io::AsyncReadExt::read_to_end(&mut f, &mut data).await);
+    assert_eq!(data, contents);
+    let g = t!(entries.next().await.unwrap());
+    assert_eq!(&*g.path_bytes(), b"g");
+    assert!(entries.next().await.is_none());
+}
+
+#[test]
+#[cfg(all(unix, feature = "xattr"))]
+fn unpack_unsupported_metadata() {
+    use async_tar::UnsupportedMetadata;
+
+    // Linux rejects extended attributes outside of the known namespaces with
+    // `EOPNOTSUPP`, like filesystems without xattr support do.
+    let record = b"31 SCHILY.xattr.bogus.test=yes\n";
+    let mut data = Vec::new();
+    let mut header = Header::new_ustar();
+    t!(header.set_path("PaxHeaders/f"));
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(record.len() as u64);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(record);
+    data.resize(1024, 0);
+    let mut header = Header::new_ustar();
+    t!(header.set_path("f"));
+    header.set_mode(0o644);
+    header.set_size(2);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(b"hi");
+    data.resize(3072, 0);
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_unpack_xattrs(true)
+            .build();
+        assert!(ar.unpack(td.path()).await.is_err());
+
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_unpack_xattrs(true)
+            .set_unsupported_metadata(UnsupportedMetadata::Warn)
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        assert_eq!(report.warnings().len(), 1);
+        assert_eq!(report.warnings()[0].0, std::path::Path::new("f"));
+        assert_eq!(t!(std::fs::read_to_string(td.path().join("f"))), "hi");
+    });
+}
+
+#[tokio::test]
+async fn volume_label() {
+    let mut data = Vec::new();
+    let mut header = Header::new_gnu();
+    t!(header.set_path("backup 2024-01-01"));
+    header.set_entry_type(EntryType::GNUVolumeLabel);
+    header.set_size(0);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(tar!("reading_files.tar"));
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    assert_eq!(entries.volume_label(), None);
+    let a = t!(entries.next().await.unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    assert_eq!(entries.volume_label(), Some(&b"backup 2024-01-01"[..]));
+    assert!(entries.next().await.is_some());
+    assert!(entries.next().await.is_none());
+}
+
+#[tokio::test]
+async fn pax_global_extensions() {
+    use async_tar::HeaderBuilder;
+
+    async fn records(entry: &mut async_tar::Entry<Archive<&[u8]>>) -> Vec<(String, String)> {
+        let exts = t!(entry.pax_extensions().await).unwrap();
+        exts.map(|e| {
+            let e = t!(e);
+            (t!(e.key()).to_string(), t!(e.value()).to_string())
+        })
+        .collect()
+    }
+
+    let global = || {
+        HeaderBuilder::new_ustar()
+            .path("g")
+            .entry_type(EntryType::XGlobalHeader)
+    };
+    let data = build_archive(vec![
+        (global(), b"14 foo=global\n14 bar=global\n"),
+        (
+            HeaderBuilder::new_ustar()
+                .path("a")
+                .pax_extension("bar", "local"),
+            b"a",
+        ),
+        (global(), b"7 foo=\n"),
+        (HeaderBuilder::new_ustar().path("b"), b"b"),
+    ]);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let mut a = t!(entries.next().await.unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+    assert_eq!(
+        records(&mut a).await,
+        vec![pair("bar", "local"), pair("foo", "global")]
+    );
+    let mut b = t!(entries.next().await.unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert_eq!(records(&mut b).await, vec![pair("bar", "global")]);
+    assert!(entries.next().await.is_none());
+    let globals = entries
+        .global_extensions()
+        .map(|e| t!(t!(e).key()).to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(globals, vec!["bar".to_string()]);
+}
+
+#[tokio::test]
+async fn sparse_pax_1_0() {
+    let mut contents = b"2\n512\n5\n3000\n3\n".to_vec();
+    contents.resize(512, 0);
+    contents.extend_from_slice(b"helloabc");
+    let data = build_archive(vec![(
+        async_tar::HeaderBuilder::new_ustar()
+            .path("GNUSparseFile.0/sparse.txt")
+            .pax_extensions([
+                ("GNU.sparse.major", "1"),
+                ("GNU.sparse.minor", "0"),
+                ("GNU.sparse.name", "sparse.txt"),
+                ("GNU.sparse.realsize", "3072"),
+            ]),
+        &contents,
+    )]);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let mut entry = t!(entries.next().await.unwrap());
+    assert_eq!(&*entry.path_bytes(), b"sparse.txt");
+    let mut read = Vec::new();
+    t!(tokio::io::AsyncReadExt::read_to_end(&mut entry, &mut read).await);
+    let mut expected = vec![0; 3072];
+    expected[512..517].copy_from_slice(b"hello");
+    expected[3000..3003].copy_from_slice(b"abc");
+    assert_eq!(read, expected);
+    assert!(entries.next().await.is_none());
+}
+
+#[test]
+fn unpack_clamp_future_mtime() {
+    use async_tar::FixedClock;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let mut header = Header::new_gnu();
+    t!(header.set_path("f"));
+    header.set_mtime(4_000_000_000);
+    header.set_size(0);
+    header.set_cksum();
+    let mut data = header.as_bytes().to_vec();
+    data.resize(2048, 0);
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_clamp_future_mtime(true)
+            .set_clock(FixedClock(UNIX_EPOCH + Duration::from_secs(1_000_000_000)))
+            .build();
+        t!(ar.unpack(td.path()).await);
+        let meta = t!(std::fs::metadata(td.path().join("f")));
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+
+        let ar = ArchiveBuilder::new(&data[..]).build();
+        t!(ar.unpack(td.path()).await);
+        let meta = t!(std::fs::metadata(td.path().join("f")));
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 4_000_000_000);
+    });
+}
+
+#[tokio::test]
+async fn entries_peek() {
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let mut entries = t!(ar.entries());
+    {
+        let peeked = t!(entries.peek().await.unwrap().as_ref());
+        assert_eq!(&*peeked.path_bytes(), b"a");
+    }
+    let peeked = t!(entries.peek().await.unwrap().as_ref()).header().clone();
+    assert_eq!(&*peeked.path_bytes(), b"a");
+
+    let mut a = t!(entries.next().await.unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+
+    assert_eq!(
+        &*t!(entries.peek().await.unwrap().as_ref()).path_bytes(),
+        b"b"
+    );
+    let b = t!(entries.next().await.unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert!(entries.peek().await.is_none());
     assert!(entries.next().await.is_none());
 }
 
-#[async_std::test]
-async fn name_with_slash_doesnt_fool_long_link_and_bsd_compat() {
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+#[tokio::test]
+async fn entries_from_offset() {
+    let data = tar!("reading_files.tar");
+    let mut entries = t!(Archive::new(data).entries());
+    let a = t!(entries.next().await.unwrap());
+    assert_eq!(&*a.path_bytes(), b"a");
+    let offset = entries.position();
+    let b = t!(entries.next().await.unwrap());
+    let b_pos = b.raw_header_position();
+    assert_eq!(b_pos, offset);
+
+    let ar = Archive::new(&data[offset as usize..]);
+    let mut entries = t!(ar.entries_from(offset));
+    let mut b = t!(entries.next().await.unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+    assert_eq!(b.raw_header_position(), b_pos);
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut b, &mut s).await);
+    assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+    assert!(entries.next().await.is_none());
+
+    let ar = Archive::new(&data[offset as usize..]);
+    let mut entries = t!(ar.entries_raw_from(offset));
+    let b = t!(entries.next().await.unwrap());
+    assert_eq!(&*b.path_bytes(), b"b");
+}
+
+#[tokio::test]
+async fn seek_skips() {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::io::{AsyncSeek, ReadBuf};
+
+    struct Counting<R> {
+        inner: R,
+        read: usize,
+    }
+
+    impl<R: tokio::io::AsyncRead + Unpin> tokio::io::AsyncRead for Counting<R> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let before = buf.filled().len();
+            let res = Pin::new(&mut self.inner).poll_read(cx, buf);
+            self.read += buf.filled().len() - before;
+            res
+        }
+    }
+
+    impl<R: AsyncSeek + Unpin> AsyncSeek for Counting<R> {
+        fn start_seek(mut self: Pin<&mut Self>, pos: std::io::SeekFrom) -> std::io::Result<()> {
+            Pin::new(&mut self.inner).start_seek(pos)
+        }
+
+        fn poll_complete(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<u64>> {
+            Pin::new(&mut self.inner).poll_complete(cx)
+        }
+    }
+
+    async fn list(seek: bool) -> (Vec<Vec<u8>>, usize) {
+        let reader = Counting {
+            inner: std::io::Cursor::new(tar!("reading_files.tar")),
+            read: 0,
+        };
+        let ar = ArchiveBuilder::new(reader).set_seek_skips(seek).build();
+        let mut entries = t!(ar.clone().entries());
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next().await {
+            paths.push(t!(entry).path_bytes().into_owned());
+        }
+        drop(entries);
+        (paths, ar.into_inner().ok().unwrap().read)
+    }
+
+    let (paths, read) = list(false).await;
+    let (seek_paths, seek_read) = list(true).await;
+    assert_eq!(paths, vec![b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(seek_paths, paths);
+    assert_eq!(read, 2560);
+    assert_eq!(seek_read, 1536);
+}
+
+#[tokio::test]
+async fn throttled_reads() {
+    use async_tar::ThrottledReader;
+    use std::time::{Duration, Instant};
+
+    let data = vec![1; 6144];
+    let mut reader = ThrottledReader::new(&data[..], 4096);
+    let start = Instant::now();
+    let mut read = Vec::new();
+    t!(tokio::io::AsyncReadExt::read_to_end(&mut reader, &mut read).await);
+    assert_eq!(read, data);
+    assert!(start.elapsed() >= Duration::from_millis(400));
+
+    let ar = ArchiveBuilder::new(tar!("reading_files.tar"))
+        .set_max_read_rate(1024 * 1024)
+        .build();
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().await.unwrap());
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+    assert!(entries.next().await.is_some());
+    assert!(entries.next().await.is_none());
+}
+
+#[tokio::test]
+async fn read_timeout() {
+    use async_tar::TimeoutReader;
+    use std::time::Duration;
+
+    struct Stalled;
+
+    impl tokio::io::AsyncRead for Stalled {
+        fn poll_read(
+            self: std::pin::Pin<&mut Self>,
+            _: &mut std::task::Context<'_>,
+            _: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            std::task::Poll::Pending
+        }
+    }
+
+    let mut reader = TimeoutReader::new(Stalled, Duration::from_millis(10));
+    let mut buf = [0; 16];
+    let err = tokio::io::AsyncReadExt::read(&mut reader, &mut buf)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+
+    // The first entry is complete, then the source stalls.
+    let data = tar!("reading_files.tar");
+    let rdr = tokio::io::AsyncReadExt::chain(&data[..1024], Stalled);
+    let ar = ArchiveBuilder::new(rdr)
+        .set_read_timeout(Duration::from_millis(10))
+        .build();
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().await.unwrap());
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+    let err = entries.next().await.unwrap().unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+}
+
+#[test]
+fn unpack_stream() {
+    use async_tar::{HeaderBuilder, UnpackEvent};
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = Archive::new(tar!("directory.tar"));
+        let mut events = t!(ar.unpack_stream(td.path()));
+        let mut log = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                UnpackEvent::EntryStarted { path } => log.push(format!("start {}", path.display())),
+                UnpackEvent::BytesWritten { .. } => {}
+                UnpackEvent::EntryFinished { path } => log.push(format!("done {}", path.display())),
+                event => panic!("unexpected {:?}", event),
+            }
+        }
+        assert_eq!(
+            log,
+            vec![
+                "start a/c",
+                "done a/c",
+                "start a/b/",
+                "done a/b/",
+                "start a/",
+                "done a/",
+            ]
+        );
+        assert!(td.path().join("a/c").is_file());
+        assert!(td.path().join("a/b").is_dir());
+
+        let mut header = Header::new_gnu();
+        t!(header.set_path("big"));
+        header.set_size(3 * 1024 * 1024);
+        header.set_cksum();
+        let mut data = header.as_bytes().to_vec();
+        data.resize(512 + 3 * 1024 * 1024 + 1024, 1);
+        let len = data.len();
+        data[len - 1024..].iter_mut().for_each(|b| *b = 0);
+        let ar = Archive::new(&data[..]);
+        let mut events = t!(ar.unpack_stream(td.path()));
+        let mut progress = Vec::new();
+        while let Some(event) = events.next().await {
+            if let UnpackEvent::BytesWritten { written, .. } = event {
+                progress.push(written);
+            }
+        }
+        assert!(!progress.is_empty());
+        assert!(progress.windows(2).all(|w| w[0] < w[1]));
+        assert_eq!(*progress.last().unwrap(), 3 * 1024 * 1024);
+
+        // The stream honors the same options as `unpack`.
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let data = build_archive(vec![
+            (HeaderBuilder::new_ustar().path("a"), b"first"),
+            (HeaderBuilder::new_ustar().path("a"), b"second"),
+            (
+                HeaderBuilder::new_ustar()
+                    .path("b")
+                    .pax_extension("path", "/b"),
+                b"third",
+            ),
+        ]);
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_duplicate_entries(async_tar::DuplicateEntries::FirstWins)
+            .build();
+        let mut events = t!(ar.unpack_stream(td.path()));
+        let mut skipped = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                UnpackEvent::Skipped { path } => skipped.push(path),
+                UnpackEvent::Error { error, .. } => panic!("{}", error),
+                _ => {}
+            }
+        }
+        assert_eq!(skipped, [std::path::PathBuf::from("a")]);
+        let report = events.report().unwrap();
+        assert_eq!(report.duplicates(), [std::path::PathBuf::from("a")]);
+        assert_eq!(report.sanitized().len(), 1);
+        assert_eq!(t!(std::fs::read(td.path().join("a"))), b"first");
+
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_duplicate_entries(async_tar::DuplicateEntries::Error)
+            .set_continue_on_error(true)
+            .build();
+        let mut events = t!(ar.unpack_stream(td.path()));
+        let mut log = Vec::new();
+        while let Some(event) = events.next().await {
+            match event {
+                UnpackEvent::EntryFinished { path } => log.push(format!("done {}", path.display())),
+                UnpackEvent::Failed { path } => log.push(format!("failed {}", path.display())),
+                _ => {}
+            }
+        }
+        assert_eq!(log, ["done a", "failed a", "done /b"]);
+        assert_eq!(events.report().unwrap().failures().len(), 1);
+    });
+}
+
+#[test]
+fn duplicate_entries() {
+    tokio_uring::start(async {
+        let mut data = Vec::new();
+        for (path, contents) in [("a", b"first"), ("./a", b"other"), ("a", b"third")] {
+            let mut header = Header::new_gnu();
+            t!(header.set_path(path));
+            header.set_size(contents.len() as u64);
+            header.set_cksum();
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(contents);
+            data.resize((data.len() + 511) & !511, 0);
+        }
+        data.resize(data.len() + 1024, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let report = t!(Archive::new(&data[..]).unpack_with_report(td.path()).await);
+        assert_eq!(
+            report.duplicates(),
+            &[std::path::PathBuf::from("a"), std::path::PathBuf::from("a")]
+        );
+        assert_eq!(t!(std::fs::read(td.path().join("a"))), b"third");
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_duplicate_entries(async_tar::DuplicateEntries::FirstWins)
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        assert_eq!(report.duplicates().len(), 2);
+        assert_eq!(t!(std::fs::read(td.path().join("a"))), b"first");
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_duplicate_entries(async_tar::DuplicateEntries::Error)
+            .build();
+        assert!(ar.unpack(td.path()).await.is_err());
+    })
+}
+
+#[test]
+fn duplicate_entries_normalized() {
+    use async_tar::HeaderBuilder;
+
+    // Pax names are used as stored, so `./a` and `/a` reach the unpacker.
+    let named = |path| {
+        HeaderBuilder::new_ustar()
+            .path("x")
+            .pax_extension("path", path)
+    };
+    let data = build_archive(vec![
+        (named("./a"), b"first"),
+        (HeaderBuilder::new_ustar().path("a"), b"second"),
+        (named("/a"), b"third"),
+    ]);
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_duplicate_entries(async_tar::DuplicateEntries::FirstWins)
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        assert_eq!(
+            report.duplicates(),
+            &[std::path::PathBuf::from("a"), std::path::PathBuf::from("a")]
+        );
+        assert_eq!(t!(std::fs::read(td.path().join("a"))), b"first");
+    })
+}
+
+#[test]
+fn skip_unchanged() {
+    tokio_uring::start(async {
+        let mut header = Header::new_gnu();
+        t!(header.set_path("a"));
+        header.set_size(5);
+        header.set_mtime(1_000_000_000);
+        header.set_mode(0o644);
+        header.set_cksum();
+        let mut data = header.as_bytes().to_vec();
+        data.extend_from_slice(b"hello");
+        data.resize(512 * 4, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let path = td.path().join("a");
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        let modify = |contents: &[u8], mtime: i64| {
+            t!(std::fs::write(&path, contents));
+            let mtime = FileTime::from_unix_time(mtime, 0);
+            t!(filetime::set_file_mtime(&path, mtime));
+        };
+        let unpack_skipping = || async {
+            let ar = ArchiveBuilder::new(&data[..])
+                .set_skip_unchanged(true)
+                .build();
+            t!(ar.unpack(td.path()).await);
+            t!(std::fs::read(&path))
+        };
+
+        // Same size and mtime, so the file is assumed to be unchanged.
+        modify(b"HELLO", 1_000_000_000);
+        assert_eq!(unpack_skipping().await, b"HELLO");
+        modify(b"HELLO", 1_000_000_001);
+        assert_eq!(unpack_skipping().await, b"hello");
+        modify(b"hello!", 1_000_000_000);
+        assert_eq!(unpack_skipping().await, b"hello");
+    })
+}
+
+#[test]
+fn incremental_dir() {
+    use async_tar::DumpDirKind;
+
+    tokio_uring::start(async {
+        let listing = b"Ydata.txt\0Nold.txt\0Dsub\0Rdir/a\0Tdir/b\0\0";
+        let mut header = Header::new_gnu();
+        t!(header.set_path("dir/"));
+        header.set_entry_type(EntryType::new(b'D'));
+        header.set_size(listing.len() as u64);
+        header.set_mode(0o755);
+        header.set_cksum();
+        let mut data = header.as_bytes().to_vec();
+        data.extend_from_slice(listing);
+        data.resize(512 * 4, 0);
+
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let mut entry = t!(entries.next().await.unwrap());
+        assert!(entry.header().entry_type().is_gnu_dumpdir());
+        let records = t!(entry.dumpdir_entries().await).unwrap();
+        let kinds = records.iter().map(|r| r.kind()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                DumpDirKind::Dumped,
+                DumpDirKind::Unchanged,
+                DumpDirKind::Directory,
+                DumpDirKind::RenameFrom,
+                DumpDirKind::RenameTo,
+            ]
+        );
+        assert_eq!(records[3].name(), "dir/a");
+
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let mut entry = t!(entries.next().await.unwrap());
+        let dir = t!(entry.incremental_dir().await).unwrap();
+        let contents = dir.contents().collect::<Vec<_>>();
+        assert_eq!(contents, ["data.txt", "old.txt", "sub"]);
+        assert!(dir.is_dumped("data.txt".as_ref()));
+        assert!(!dir.is_dumped("old.txt".as_ref()));
+        assert_eq!(dir.renames(), &[("dir/a".into(), "dir/b".into())]);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        let restored = td.path().join("dir");
+        assert!(restored.is_dir());
+        t!(std::fs::write(restored.join("old.txt"), b""));
+        t!(std::fs::write(restored.join("gone.txt"), b""));
+        assert_eq!(t!(dir.deleted_from(&restored).await), ["gone.txt"]);
+    })
+}
+
+#[tokio::test]
+async fn max_metadata_size() {
+    let mut header = Header::new_gnu();
+    t!(header.set_path("././@LongLink"));
+    header.set_entry_type(EntryType::GNULongName);
+    header.set_size(64 * 1024 * 1024 * 1024);
+    header.set_cksum();
+    let data = header.as_bytes().to_vec();
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    assert!(entries.next().await.unwrap().is_err());
+
+    let mut data = Vec::new();
+    let records = b"30 comment=a pax comment here\n";
+    let mut header = Header::new_ustar();
+    t!(header.set_path("x"));
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(records);
+    data.resize(512 * 2, 0);
+    let mut header = Header::new_ustar();
+    t!(header.set_path("a"));
+    header.set_size(0);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.resize(data.len() + 1024, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    assert_eq!(&*t!(entries.next().await.unwrap()).path_bytes(), b"a");
+    let ar = ArchiveBuilder::new(&data[..])
+        .set_max_metadata_size(16)
+        .build();
+    let mut entries = t!(ar.entries());
+    assert!(entries.next().await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn pax_streaming() {
+    use std::{
+        pin::Pin,
+        task::{Context, Poll},
+    };
+    use tokio::io::ReadBuf;
+
+    // Hands out a few bytes at a time, returning `Pending` in between.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        ready: bool,
+    }
+
+    impl tokio::io::AsyncRead for Trickle<'_> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            self.ready = !self.ready;
+            if !self.ready {
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            let n = self.data.len().min(buf.remaining()).min(7);
+            buf.put_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    let mut data = Vec::new();
+    let records = b"16 path=foo/bar\n30 comment=a pax comment here\n";
+    let mut header = Header::new_ustar();
+    t!(header.set_path("x"));
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(records);
+    data.resize(512 * 2, 0);
+    let mut header = Header::new_ustar();
+    t!(header.set_path("a"));
+    header.set_size(0);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.resize(data.len() + 1024, 0);
+
+    let reader = Trickle {
+        data: &data,
+        ready: true,
+    };
+    let mut entries = t!(Archive::new(reader).entries());
+    let entry = t!(entries.next().await.unwrap());
+    assert_eq!(&*entry.path_bytes(), b"foo/bar");
+    let comment = entry.pax_headers().unwrap().get("comment");
+    assert_eq!(comment, Some(&b"a pax comment here"[..]));
+    assert!(entries.next().await.is_none());
+
+    let ar = ArchiveBuilder::new(&data[..])
+        .set_max_pax_record_size(20)
+        .build();
+    let mut entries = t!(ar.entries());
+    assert!(entries.next().await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn pax_malformed_limit() {
+    use async_tar::HeaderBuilder;
+
+    let garbage = [b'x'; 100];
+    for kind in [EntryType::XHeader, EntryType::XGlobalHeader] {
+        let data = build_archive(vec![
+            (
+                HeaderBuilder::new_ustar().path("x").entry_type(kind),
+                &garbage,
+            ),
+            (HeaderBuilder::new_ustar().path("a"), &[]),
+        ]);
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_max_metadata_size(16)
+            .build();
+        let mut entries = t!(ar.entries());
+        let err = entries.next().await.unwrap().err().unwrap();
+        assert!(err.to_string().contains("limit of 16 bytes"), "{}", err);
+    }
+}
+
+#[test]
+fn unknown_entry_types() {
+    use async_tar::UnknownEntryTypes;
+    use std::sync::{Arc, Mutex};
+
+    tokio_uring::start(async {
+        let mut header = Header::new_ustar();
+        t!(header.set_path("acl"));
+        header.set_entry_type(EntryType::new(b'A'));
+        header.set_size(3);
+        header.set_cksum();
+        let mut data = header.as_bytes().to_vec();
+        data.extend_from_slice(b"acl");
+        data.resize(512 * 4, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        assert_eq!(t!(std::fs::read(td.path().join("acl"))), b"acl");
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let log = seen.clone();
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_unknown_entry_types(UnknownEntryTypes::Skip)
+            .set_unknown_entry_callback(move |path, kind| {
+                log.lock().unwrap().push((path.to_path_buf(), kind));
+            })
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        assert_eq!(report.skipped(), &[std::path::PathBuf::from("acl")]);
+        assert!(!td.path().join("acl").exists());
+        assert_eq!(
+            *seen.lock().unwrap(),
+            [("acl".into(), EntryType::new(b'A'))]
+        );
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_unknown_entry_types(UnknownEntryTypes::Error)
+            .build();
+        assert!(ar.unpack(td.path()).await.is_err());
+    })
+}
+
+#[test]
+fn nested_directory_mtimes() {
+    tokio_uring::start(async {
+        let entry = |path: &str, kind: EntryType, mtime: u64| {
+            let header = async_tar::HeaderBuilder::new_gnu()
+                .path(path)
+                .entry_type(kind)
+                .mode(0o755)
+                .mtime(mtime);
+            (header, &[][..])
+        };
+        // Parents are listed before their children, which themselves are
+        // only created when the directories are unpacked.
+        let data = build_archive(vec![
+            entry("a/", EntryType::Directory, 1_000_000_000),
+            entry("a/b/", EntryType::Directory, 1_100_000_000),
+            entry("a/b/c/", EntryType::Directory, 1_200_000_000),
+            entry("a/d/", EntryType::Directory, 1_300_000_000),
+            entry("a/d/file", EntryType::Regular, 1_400_000_000),
+        ]);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        for (path, mtime) in [
+            ("a", 1_000_000_000),
+            ("a/b", 1_100_000_000),
+            ("a/b/c", 1_200_000_000),
+            ("a/d", 1_300_000_000),
+            ("a/d/file", 1_400_000_000),
+        ] {
+            let meta = t!(std::fs::metadata(td.path().join(path)));
+            let actual = FileTime::from_last_modification_time(&meta);
+            assert_eq!(actual.unix_seconds(), mtime, "{}", path);
+        }
+    })
+}
+
+#[test]
+fn delay_directory_restore() {
+    use std::os::unix::fs::PermissionsExt;
+
+    tokio_uring::start(async {
+        let entry = |path: &str, kind: EntryType, mode: u32| {
+            let header = async_tar::HeaderBuilder::new_gnu()
+                .path(path)
+                .entry_type(kind)
+                .mode(mode)
+                .mtime(1_000_000_000);
+            (header, &[][..])
+        };
+        let data = build_archive(vec![
+            entry("ro/", EntryType::Directory, 0o500),
+            entry("ro/file", EntryType::Regular, 0o644),
+        ]);
+
+        // By default the read-only directory is restored after its contents.
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        let meta = t!(std::fs::metadata(td.path().join("ro")));
+        assert_eq!(meta.permissions().mode() & 0o777, 0o500);
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+        assert!(td.path().join("ro/file").exists());
+        t!(std::fs::set_permissions(
+            td.path().join("ro"),
+            std::fs::Permissions::from_mode(0o755)
+        ));
+
+        // Otherwise it is restored in archive order, so writing its contents
+        // afterwards updates its mtime.
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_delay_directory_restore(false)
+            .build();
+        t!(ar.unpack(td.path()).await);
+        let meta = t!(std::fs::metadata(td.path().join("ro")));
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_ne!(mtime.unix_seconds(), 1_000_000_000);
+        assert!(td.path().join("ro/file").exists());
+        t!(std::fs::set_permissions(
+            td.path().join("ro"),
+            std::fs::Permissions::from_mode(0o755)
+        ));
+    })
+}
+
+#[tokio::test]
+async fn total_size() {
+    let mut expected = 0;
+    let mut entries = t!(Archive::new(tar!("sparse.tar")).entries());
+    while let Some(entry) = entries.next().await {
+        let entry = t!(entry);
+        if entry.header().entry_type().is_gnu_sparse() {
+            assert!(t!(entry.logical_size()) > t!(entry.stored_data_size()));
+        }
+        expected += t!(entry.logical_size());
+    }
+    let total = t!(Archive::new(tar!("sparse.tar")).total_size().await);
+    assert_eq!(total, expected);
+
+    // Only files count towards the total.
+    let mut data = Vec::new();
+    for (path, kind, size) in [
+        ("dir/", EntryType::Directory, 0),
+        ("dir/file", EntryType::Regular, 5),
+        ("dir/link", EntryType::Symlink, 0),
+    ] {
+        let mut header = Header::new_gnu();
+        t!(header.set_path(path));
+        header.set_entry_type(kind);
+        header.set_size(size);
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+        data.resize(data.len() + if size > 0 { 512 } else { 0 }, 0);
+    }
+    data.resize(data.len() + 1024, 0);
+    assert_eq!(t!(Archive::new(&data[..]).total_size().await), 5);
+}
+
+#[tokio::test]
+async fn count() {
+    for data in [
+        tar!("simple.tar"),
+        tar!("pax.tar"),
+        tar!("sparse.tar"),
+        tar!("7z_long_path.tar"),
+        tar!("xattrs.tar"),
+    ] {
+        let mut expected = 0;
+        let mut entries = t!(Archive::new(data).entries());
+        while let Some(entry) = entries.next().await {
+            t!(entry);
+            expected += 1;
+        }
+        assert_eq!(t!(Archive::new(data).count().await), expected);
+    }
+}
+
+#[test]
+fn check_free_space() {
+    tokio_uring::start(async {
+        let archive = |realsize: u64| {
+            let record = format!("GNU.sparse.realsize={}\n", realsize);
+            let record = format!("{} {}", record.len() + 3, record);
+            let mut data = Vec::new();
+            let mut header = Header::new_ustar();
+            t!(header.set_path("PaxHeaders/a"));
+            header.set_entry_type(EntryType::XHeader);
+            header.set_size(record.len() as u64);
+            header.set_cksum();
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(record.as_bytes());
+            data.resize(1024, 0);
+            let mut header = Header::new_ustar();
+            t!(header.set_path("a"));
+            header.set_size(4);
+            header.set_cksum();
+            data.extend_from_slice(header.as_bytes());
+            data.extend_from_slice(b"abcd");
+            data.resize(data.len() + 508 + 1024, 0);
+            std::io::Cursor::new(data)
+        };
+
+        // The archive is read again after checking.
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(archive(4))
+            .set_check_free_space(true)
+            .build();
+        t!(ar.unpack(td.path()).await);
+        assert_eq!(t!(std::fs::read(td.path().join("a"))), b"abcd");
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(archive(1 << 62))
+            .set_check_free_space(true)
+            .build();
+        let err = ar.unpack(td.path()).await.unwrap_err();
+        assert!(err.to_string().contains("not enough space"), "{}", err);
+        assert!(!td.path().join("a").exists());
+    })
+}
+
+#[test]
+fn continue_on_error() {
+    tokio_uring::start(async {
+        use async_tar::HeaderBuilder;
+
+        let data = build_archive(vec![
+            (HeaderBuilder::new_gnu().path("a"), b"a"),
+            (
+                HeaderBuilder::new_gnu()
+                    .path("broken")
+                    .entry_type(EntryType::Link)
+                    .link_name("missing"),
+                b"",
+            ),
+            (HeaderBuilder::new_gnu().path("b"), b"b"),
+        ]);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        assert!(Archive::new(&data[..]).unpack(td.path()).await.is_err());
+        assert!(!td.path().join("b").exists());
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_continue_on_error(true)
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        assert!(!report.is_complete());
+        let failures = report.failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].0, std::path::Path::new("broken"));
+        assert_eq!(t!(std::fs::read(td.path().join("a"))), b"a");
+        assert_eq!(t!(std::fs::read(td.path().join("b"))), b"b");
+
+        // Plain unpacking still reports the failure at the end.
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_continue_on_error(true)
+            .build();
+        assert!(ar.unpack(td.path()).await.is_err());
+        assert!(td.path().join("b").exists());
+    })
+}
+
+#[test]
+fn unpack_report_skipped() {
+    tokio_uring::start(async {
+        let mut data = Vec::new();
+        for path in ["../escape", "/abs/file", "./ok"] {
+            let mut header = Header::new_gnu();
+            // `set_path` refuses `..`, so write the name directly.
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(0);
+            header.set_cksum();
+            data.extend_from_slice(header.as_bytes());
+        }
+        data.resize(data.len() + 1024, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let report = t!(Archive::new(&data[..]).unpack_with_report(td.path()).await);
+        assert_eq!(report.skipped(), [std::path::PathBuf::from("../escape")]);
+        assert_eq!(
+            report.sanitized(),
+            [(
+                std::path::PathBuf::from("/abs/file"),
+                std::path::PathBuf::from("abs/file")
+            )]
+        );
+        assert!(td.path().join("abs/file").exists());
+        assert!(td.path().join("ok").exists());
+    })
+}
+
+#[test]
+fn preserve_atime() {
+    tokio_uring::start(async {
+        let mut header = Header::new_gnu();
+        t!(header.set_path("a"));
+        header.set_size(0);
+        header.set_mtime(1_000_000_000);
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.set_atime(1_100_000_000);
+        gnu.set_ctime(1_200_000_000);
+        header.set_cksum();
+        let mut data = header.as_bytes().to_vec();
+        data.resize(data.len() + 1024, 0);
+
+        let ar = Archive::new(&data[..]);
+        let mut entries = t!(ar.entries());
+        let entry = t!(entries.next().await.unwrap());
+        assert_eq!(t!(entry.atime()), Some(1_100_000_000));
+        assert_eq!(t!(entry.ctime()), Some(1_200_000_000));
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        let meta = t!(std::fs::metadata(td.path().join("a")));
+        let atime = FileTime::from_last_access_time(&meta);
+        assert_eq!(atime.unix_seconds(), 1_000_000_000);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_preserve_atime(true)
+            .build();
+        t!(ar.unpack(td.path()).await);
+        let meta = t!(std::fs::metadata(td.path().join("a")));
+        let atime = FileTime::from_last_access_time(&meta);
+        assert_eq!(atime.unix_seconds(), 1_100_000_000);
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+    })
+}
+
+#[test]
+fn unpack_in_returns_path() {
+    tokio_uring::start(async {
+        let mut data = Vec::new();
+        for path in ["/abs//./file", "../escape"] {
+            let mut header = Header::new_gnu();
+            header.as_old_mut().name[..path.len()].copy_from_slice(path.as_bytes());
+            header.set_size(0);
+            header.set_cksum();
+            data.extend_from_slice(header.as_bytes());
+        }
+        data.resize(data.len() + 1024, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = Archive::new(&data[..]);
+        let mut entries = t!(ar.entries());
+        let mut entry = t!(entries.next().await.unwrap());
+        let path = t!(entry.unpack_in(td.path()).await);
+        assert_eq!(path, Some(td.path().join("abs/file")));
+        assert!(td.path().join("abs/file").exists());
+        let mut entry = t!(entries.next().await.unwrap());
+        assert_eq!(t!(entry.unpack_in(td.path()).await), None);
+    })
+}
+
+#[tokio::test]
+async fn entry_copy_to() {
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().await.unwrap());
+    let mut out = Vec::new();
+    t!(a.copy_to(&mut out).await);
+    assert_eq!(out, b"a\na\na\na\na\na\na\na\na\na\na\n");
+
+    let mut b = t!(entries.next().await.unwrap());
+    let mut out = std::io::Cursor::new(Vec::new());
+    assert_eq!(t!(b.copy_to_counted(&mut out).await), 22);
+    assert_eq!(out.into_inner(), b"b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+}
+
+#[tokio::test]
+async fn entry_buf_read() {
+    use tokio::io::AsyncBufReadExt;
+
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let mut entries = t!(ar.entries());
+    let a = t!(entries.next().await.unwrap());
+    let mut lines = a.lines();
+    let mut count = 0;
+    while let Some(line) = t!(lines.next_line().await) {
+        assert_eq!(line, "a");
+        count += 1;
+    }
+    assert_eq!(count, 11);
+
+    // Buffered and unbuffered reads can be mixed.
+    let mut b = t!(entries.next().await.unwrap());
+    let mut line = Vec::new();
+    t!(b.read_until(b'\n', &mut line).await);
+    assert_eq!(line, b"b\n");
+    let mut rest = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut b, &mut rest).await);
+    assert_eq!(rest, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+}
+
+#[tokio::test]
+async fn entry_read_to_vec() {
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().await.unwrap());
+    assert!(a.read_to_vec(21).await.is_err());
+    assert_eq!(
+        t!(a.read_to_vec(22).await),
+        b"a\na\na\na\na\na\na\na\na\na\na\n"
+    );
+
+    let mut b = t!(entries.next().await.unwrap());
+    let s = t!(b.read_to_string_limited(1024).await);
+    assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+
+    let mut header = Header::new_gnu();
+    t!(header.set_path("bin"));
+    header.set_size(2);
+    header.set_cksum();
+    let mut data = header.as_bytes().to_vec();
+    data.extend_from_slice(&[0xff, 0xfe]);
+    data.resize(data.len() + 510 + 1024, 0);
+    let ar = Archive::new(&data[..]);
+    let mut entries = t!(ar.entries());
+    let mut entry = t!(entries.next().await.unwrap());
+    assert!(entry.read_to_string_limited(1024).await.is_err());
+}
+
+#[tokio::test]
+async fn entry_into_bytes() {
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let mut entries = t!(ar.entries());
+    let a = t!(entries.next().await.unwrap());
+    let bytes = t!(a.into_bytes().await);
+    assert_eq!(&bytes[..], b"a\na\na\na\na\na\na\na\na\na\na\n");
+    let shared = bytes.clone();
+    assert_eq!(shared.as_ptr(), bytes.as_ptr());
+
+    let b = t!(entries.next().await.unwrap());
+    assert_eq!(&t!(b.into_bytes().await)[..2], b"b\n");
+}
+
+#[tokio::test]
+async fn entry_into_owned() {
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let mut entries = t!(ar.entries());
+    let mut owned = Vec::new();
+    while let Some(entry) = entries.next().await {
+        owned.push(t!(t!(entry).into_owned().await));
+    }
+    assert_eq!(owned.len(), 2);
+
+    // Read out of order, and on another task.
+    let b = owned.pop().unwrap();
+    let b = t!(tokio::spawn(async move { b.into_bytes().await }).await);
+    assert_eq!(&t!(b)[..], b"b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+    let mut a = owned.pop().unwrap();
+    assert_eq!(&*a.path_bytes(), b"a");
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+    assert_eq!(s, "a\na\na\na\na\na\na\na\na\na\na\n");
+}
+
+#[tokio::test]
+async fn entry_seek() {
+    use tokio::io::{AsyncReadExt, AsyncSeekExt, SeekFrom};
+
+    let ar = Archive::new(std::io::Cursor::new(tar!("reading_files.tar").to_vec()));
+    let mut entries = t!(ar.entries());
+    let mut a = t!(entries.next().await.unwrap());
+    let mut buf = [0; 3];
+    assert_eq!(t!(a.seek(SeekFrom::Start(4)).await), 4);
+    t!(a.read_exact(&mut buf).await);
+    assert_eq!(&buf, b"a\na");
+    assert_eq!(t!(a.seek(SeekFrom::End(-2)).await), 20);
+    let mut rest = Vec::new();
+    t!(a.read_to_end(&mut rest).await);
+    assert_eq!(rest, b"a\n");
+    assert_eq!(t!(a.seek(SeekFrom::Current(-22)).await), 0);
+    assert!(a.seek(SeekFrom::Current(-1)).await.is_err());
+
+    // The following entry is still read correctly.
+    let mut b = t!(entries.next().await.unwrap());
+    let mut s = String::new();
+    t!(b.read_to_string(&mut s).await);
+    assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+
+    // Seeking within sparse files lands in holes and data alike.
+    let data = tar!("sparse.tar").to_vec();
+    let mut contents = Vec::new();
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    while let Some(entry) = entries.next().await {
+        let mut buf = Vec::new();
+        t!(t!(entry).read_to_end(&mut buf).await);
+        contents.push(buf);
+    }
+    let ar = Archive::new(std::io::Cursor::new(data));
+    let mut entries = t!(ar.entries());
+    for expected in contents {
+        let mut entry = t!(entries.next().await.unwrap());
+        for offset in (0..expected.len()).step_by(997).rev() {
+            t!(entry.seek(SeekFrom::Start(offset as u64)).await);
+            let mut buf = Vec::new();
+            t!((&mut entry).take(600).read_to_end(&mut buf).await);
+            let end = (offset + 600).min(expected.len());
+            assert_eq!(buf, &expected[offset..end]);
+        }
+    }
+}
+
+#[tokio::test]
+async fn entry_raw_span() {
+    let long = "long/".repeat(40) + "name";
+    let mut data = Vec::new();
+    for path in ["short", &long[..]] {
+        let mut header = Header::new_gnu();
+        header.set_size(3);
+        header.set_entry_type(EntryType::Regular);
+        if path.len() > 100 {
+            let mut ext = Header::new_gnu();
+            t!(ext.set_path("././@LongLink"));
+            ext.set_entry_type(EntryType::GNULongName);
+            ext.set_size(path.len() as u64 + 1);
+            ext.set_cksum();
+            data.extend_from_slice(ext.as_bytes());
+            data.extend_from_slice(path.as_bytes());
+            data.push(0);
+            data.resize((data.len() + 511) & !511, 0);
+            t!(header.set_path("long"));
+        } else {
+            t!(header.set_path(path));
+        }
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+        data.extend_from_slice(b"abc");
+        data.resize((data.len() + 511) & !511, 0);
+    }
+    data.resize(data.len() + 1024, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let short = t!(entries.next().await.unwrap());
+    assert_eq!(short.raw_span(), 0..1024);
+    let long_entry = t!(entries.next().await.unwrap());
+    assert_eq!(long_entry.raw_span(), 1024..data.len() as u64 - 1024);
+    assert!(long_entry.raw_header_position() > 1024);
+
+    // Copying the span verbatim reproduces the entry.
+    let mut copy =
+        data[long_entry.raw_span().start as usize..long_entry.raw_span().end as usize].to_vec();
+    copy.resize(copy.len() + 1024, 0);
+    let mut entries = t!(Archive::new(&copy[..]).entries());
+    let entry = t!(entries.next().await.unwrap());
+    assert_eq!(&*entry.path_bytes(), long.as_bytes());
+}
 
-    let mut ar = Builder::new(Vec::new());
+#[test]
+fn entry_unpack_overrides() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let mut data = Vec::new();
+    for path in ["a", "b"] {
+        let mut header = Header::new_gnu();
+        t!(header.set_path(path));
+        header.set_mode(0o2755);
+        header.set_mtime(1_000_000_000);
+        header.set_size(2);
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+        data.extend_from_slice(b"hi");
+        data.resize(data.len() + 510, 0);
+    }
+    data.resize(data.len() + 1024, 0);
 
-    let mut h = Header::new_gnu();
-    t!(h.set_path("././@LongLink"));
-    h.set_size(4);
-    h.set_entry_type(EntryType::new(b'L'));
-    h.set_cksum();
-    t!(ar.append(&h, "foo\0".as_bytes()).await);
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        while let Some(entry) = entries.next().await {
+            let mut entry = t!(entry);
+            if &*entry.path_bytes() == b"a" {
+                entry.set_preserve_mtime(false);
+                entry.set_preserve_permissions(true);
+            }
+            t!(entry.unpack_in(td.path()).await);
+        }
+
+        let meta = t!(std::fs::metadata(td.path().join("a")));
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o2755);
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_ne!(mtime.unix_seconds(), 1_000_000_000);
+
+        let meta = t!(std::fs::metadata(td.path().join("b")));
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o755);
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+    });
+}
+
+#[test]
+fn unpack_mask() {
+    use std::os::unix::fs::PermissionsExt;
 
+    let mut data = Vec::new();
     let mut header = Header::new_gnu();
-    header.set_entry_type(EntryType::Regular);
-    t!(header.set_path("testdir/"));
+    t!(header.set_path("d"));
+    header.set_entry_type(EntryType::Directory);
+    header.set_mode(0o777);
     header.set_size(0);
     header.set_cksum();
-    t!(ar.append(&header, &mut io::empty()).await);
+    data.extend_from_slice(header.as_bytes());
+    let mut header = Header::new_gnu();
+    t!(header.set_path("d/f"));
+    header.set_mode(0o666);
+    header.set_size(2);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(b"hi");
+    data.resize(data.len() + 510 + 1024, 0);
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..]).set_mask(0o022).build();
+        t!(ar.unpack(td.path()).await);
+
+        let meta = t!(std::fs::metadata(td.path().join("d")));
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o755);
+        let meta = t!(std::fs::metadata(td.path().join("d/f")));
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o644);
+    });
+}
 
-    // Extracting
-    let rdr = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rdr);
-    t!(ar.clone().unpack(td.path()).await);
+#[tokio::test]
+async fn entry_metadata() {
+    let records: &[&str] = &[
+        "uid=4000000000",
+        "uname=alice",
+        "mtime=1000000000.5",
+        "SCHILY.xattr.user.test=yes",
+    ];
+    let mut pax = Vec::new();
+    for record in records {
+        let mut len = record.len() + 3;
+        while len != record.len() + 2 + len.to_string().len() {
+            len = record.len() + 2 + len.to_string().len();
+        }
+        pax.extend_from_slice(format!("{} {}\n", len, record).as_bytes());
+    }
 
-    // Iterating
-    let rdr = Cursor::new(ar.into_inner().map_err(|_| ()).unwrap().into_inner());
-    let ar = Archive::new(rdr);
-    assert!(t!(ar.entries()).all(|fr| fr.is_ok()).await);
+    let mut data = Vec::new();
+    let mut header = Header::new_ustar();
+    t!(header.set_path("PaxHeaders/f"));
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(pax.len() as u64);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(&pax);
+    data.resize(1024, 0);
+    let mut header = Header::new_ustar();
+    t!(header.set_path("f"));
+    header.set_mode(0o640);
+    header.set_uid(1);
+    header.set_gid(2);
+    t!(header.set_groupname("staff"));
+    header.set_mtime(5);
+    header.set_size(2);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(b"hi");
+    data.resize(3072, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let entry = t!(entries.next().await.unwrap());
+    let meta = t!(entry.metadata());
+    assert_eq!(meta.entry_type(), EntryType::Regular);
+    assert_eq!(meta.path(), std::path::Path::new("f"));
+    assert_eq!(meta.link_name(), None);
+    assert_eq!(meta.size(), 2);
+    assert_eq!(meta.mode(), 0o640);
+    assert_eq!(meta.uid(), 4_000_000_000);
+    assert_eq!(meta.gid(), 2);
+    assert_eq!(meta.username(), Ok(Some("alice")));
+    assert_eq!(meta.groupname(), Ok(Some("staff")));
+    let mtime = t!(meta.mtime().duration_since(std::time::UNIX_EPOCH));
+    assert_eq!(mtime.as_millis(), 1_000_000_000_500);
+    assert_eq!(meta.xattrs().len(), 1);
+    assert_eq!(meta.xattrs()[0].1, b"yes");
+}
 
-    assert!(td.path().join("foo").is_file());
+#[test]
+fn link_target_validation() {
+    use async_tar::link_stays_within;
+    use std::path::Path;
+
+    let root = Path::new("/dst");
+    let symlink = EntryType::Symlink;
+    let hard = EntryType::Link;
+    assert!(link_stays_within(
+        root,
+        Path::new("a/b"),
+        Path::new("../c"),
+        symlink
+    ));
+    assert!(!link_stays_within(
+        root,
+        Path::new("a/b"),
+        Path::new("../../c"),
+        symlink
+    ));
+    assert!(!link_stays_within(
+        root,
+        Path::new("a/b"),
+        Path::new("../c"),
+        hard
+    ));
+    assert!(link_stays_within(
+        root,
+        Path::new("a/b"),
+        Path::new("a/c"),
+        hard
+    ));
+    assert!(link_stays_within(
+        root,
+        Path::new("/a/b"),
+        Path::new("./c/../d"),
+        symlink
+    ));
+    assert!(link_stays_within(
+        root,
+        Path::new("a"),
+        Path::new("/dst/c"),
+        symlink
+    ));
+    assert!(!link_stays_within(
+        root,
+        Path::new("a"),
+        Path::new("/etc/passwd"),
+        symlink
+    ));
+    assert!(!link_stays_within(
+        root,
+        Path::new("a"),
+        Path::new("/dst/../etc"),
+        hard
+    ));
+
+    let mut data = Vec::new();
+    let mut header = Header::new_gnu();
+    t!(header.set_path("link"));
+    header.set_entry_type(EntryType::Link);
+    t!(header.set_link_name("../outside"));
+    header.set_size(0);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.resize(data.len() + 1024, 0);
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let err = Archive::new(&data[..]).unpack(td.path()).await.unwrap_err();
+        assert!(
+            format!("{:?}", err).contains("outside of the destination"),
+            "{:?}",
+            err
+        );
+    });
 }
 
-#[async_std::test]
-async fn insert_local_file_different_name() {
-    let mut ar = Builder::new(Vec::new());
+#[tokio::test]
+async fn entry_save_to() {
     let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let path = td.path().join("directory");
-    t!(fs::create_dir(&path).await);
-    ar.append_path_with_name(&path, "archive/dir")
-        .await
-        .unwrap();
-    let path = td.path().join("file");
-    t!(t!(File::create(&path).await).write_all(b"test").await);
-    ar.append_path_with_name(&path, "archive/dir/f")
-        .await
-        .unwrap();
+    let rdr = std::io::Cursor::new(tar!("reading_files.tar"));
+    let mut entries = t!(Archive::new(rdr).entries());
+    let mut a = t!(entries.next().await.unwrap());
+    let dst = td.path().join("x/y/a");
+    assert!(a.save_to(&dst, false).await.is_err());
+    assert_eq!(t!(a.save_to(&dst, true).await), 22);
+    assert_eq!(
+        t!(std::fs::read_to_string(&dst)),
+        "a\na\na\na\na\na\na\na\na\na\na\n"
+    );
+}
+
+#[test]
+fn entry_header_mut() {
+    use std::os::unix::fs::PermissionsExt;
+
+    tokio_uring::start(async {
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let rdr = std::io::Cursor::new(tar!("reading_files.tar"));
+        let mut entries = t!(Archive::new(rdr).entries());
+        let mut a = t!(entries.next().await.unwrap());
+        a.header_mut().set_mode(0o600);
+        a.header_mut().set_mtime(1_000_000_000);
+        a.header_mut().set_cksum();
+        assert_eq!(t!(a.header().mode()), 0o600);
+        t!(a.unpack_in(td.path()).await);
+
+        let meta = t!(std::fs::metadata(td.path().join("a")));
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o600);
+        let mtime = FileTime::from_last_modification_time(&meta);
+        assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+        assert_eq!(meta.len(), 22);
+    });
+}
+
+#[tokio::test]
+async fn raw_offsets() {
+    use async_tar::ArchiveIndex;
+
+    let data = tar!("reading_files.tar");
+    let mut entries = t!(Archive::new(data).entries_raw());
+    let mut offsets = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let entry = t!(entry);
+        offsets.push((
+            entry.raw_header_position(),
+            entry.raw_file_position(),
+            entry.raw_padded_size(),
+        ));
+    }
+    assert_eq!(offsets, [(0, 512, 512), (1024, 1536, 512)]);
+
+    let (_, index) = t!(ArchiveIndex::build_seekable(std::io::Cursor::new(data)).await);
+    let b = index.get("b").unwrap();
+    assert_eq!(b.raw_span(), 1024..2048);
+    assert_eq!(b.raw_padded_size(), 512);
+}
+
+#[tokio::test]
+async fn index_sparse_entries() {
+    use async_tar::{ArchiveIndex, HeaderBuilder};
+
+    let rdr = std::io::Cursor::new(tar!("sparse.tar"));
+    let (_, index) = t!(ArchiveIndex::build_seekable(rdr).await);
+    let entry = index.get("sparse_ext.txt").unwrap();
+    assert_eq!(entry.size(), 45061);
+    assert!(!entry.is_contiguous());
+
+    let mut contents = b"2\n512\n5\n3000\n3\n".to_vec();
+    contents.resize(512, 0);
+    contents.extend_from_slice(b"helloabc");
+    let sparse = HeaderBuilder::new_ustar()
+        .path("GNUSparseFile.0/sparse.txt")
+        .pax_extension("GNU.sparse.major", "1")
+        .pax_extension("GNU.sparse.minor", "0")
+        .pax_extension("GNU.sparse.name", "sparse.txt")
+        .pax_extension("GNU.sparse.realsize", "3072");
+    let data = build_archive(vec![
+        (sparse, &contents),
+        (HeaderBuilder::new_ustar().path("plain"), b"abc"),
+    ]);
+    let (_, index) = t!(ArchiveIndex::build_seekable(std::io::Cursor::new(data)).await);
+    let entry = index.get("sparse.txt").unwrap();
+    assert_eq!(entry.size(), 3072);
+    assert!(!entry.is_contiguous());
+    let plain = index.get("plain").unwrap();
+    assert_eq!(plain.size(), 3);
+    assert!(plain.is_contiguous());
+}
+
+#[tokio::test]
+async fn entries_fused() {
+    use futures_core::FusedStream;
+
+    let data = tar!("reading_files.tar");
+    let mut entries = t!(Archive::new(data).entries());
+    assert!(!entries.is_terminated());
+    while let Some(entry) = entries.next().await {
+        t!(entry);
+    }
+    assert!(entries.is_terminated());
+    assert!(entries.next().await.is_none());
+    assert!(entries.next().await.is_none());
+
+    let mut entries = t!(Archive::new(data).entries_raw());
+    assert_eq!(entries.by_ref().count().await, 2);
+    assert!(entries.is_terminated());
+    assert!(entries.next().await.is_none());
+}
+
+#[test]
+fn entry_type_names() {
+    for byte in 0..=255u8 {
+        let kind = EntryType::new(byte);
+        let name = kind.to_string();
+        let parsed: EntryType = t!(name.parse());
+        // `\0` is read as a regular file, which is written as `0`.
+        assert_eq!(parsed.as_byte(), kind.as_byte());
+    }
+    assert_eq!(EntryType::Char.as_str(), "character-device");
+    assert_eq!(EntryType::Other(b'A').to_string(), "other(65)");
+    assert_eq!("symlink".parse(), Ok(EntryType::Symlink));
+    assert!("other(50)".parse::<EntryType>().is_err());
+    assert!("file".parse::<EntryType>().is_err());
+}
+
+#[tokio::test]
+async fn oci_whiteouts() {
+    let mut data = Vec::new();
+    for path in [
+        "etc/.wh.passwd",
+        "var/cache/.wh..wh..opq",
+        "a/.wh..wh.plnk",
+        "a/b",
+    ] {
+        let mut header = Header::new_gnu();
+        t!(header.set_path(path));
+        header.set_size(0);
+        header.set_cksum();
+        data.extend_from_slice(header.as_bytes());
+    }
+    data.resize(data.len() + 1024, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let mut whiteouts = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let entry = t!(entry);
+        whiteouts.push((
+            entry.is_whiteout(),
+            entry.is_opaque_whiteout(),
+            entry.whiteout_target(),
+        ));
+    }
+    assert_eq!(
+        whiteouts,
+        [
+            (true, false, Some(std::path::PathBuf::from("etc/passwd"))),
+            (true, true, Some(std::path::PathBuf::from("var/cache"))),
+            (false, false, None),
+            (false, false, None),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn header_builder() {
+    use async_tar::HeaderBuilder;
+
+    assert!(HeaderBuilder::new_gnu().size(1).build().is_err());
+    assert!(HeaderBuilder::new_gnu()
+        .path("l")
+        .entry_type(EntryType::Symlink)
+        .build()
+        .is_err());
+    assert!(HeaderBuilder::new_old()
+        .path("a/".repeat(60))
+        .build()
+        .is_err());
+
+    let dir = t!(HeaderBuilder::new_ustar()
+        .path("d")
+        .entry_type(EntryType::Directory)
+        .build());
+    assert_eq!(t!(dir.mode()), 0o755);
+
+    let header = t!(HeaderBuilder::new_gnu()
+        .path("d/f")
+        .size(2)
+        .mode(0o600)
+        .mtime(1_000_000_000)
+        .uid(1000)
+        .gid(100)
+        .username("alice")
+        .groupname("users")
+        .build());
+    let mut data = Vec::new();
+    data.extend_from_slice(dir.as_bytes());
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(b"hi");
+    data.resize(data.len() + 510 + 1024, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    t!(entries.next().await.unwrap());
+    let f = t!(entries.next().await.unwrap());
+    let header = f.header();
+    assert_eq!(&*f.path_bytes(), b"d/f");
+    assert_eq!(t!(header.mode()), 0o600);
+    assert_eq!(t!(header.mtime()), 1_000_000_000);
+    assert_eq!(t!(header.uid()), 1000);
+    assert_eq!(t!(header.gid()), 100);
+    assert_eq!(t!(header.username()), Some("alice"));
+    assert_eq!(t!(header.groupname()), Some("users"));
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+async fn serde_round_trip() {
+    let data = tar!("reading_files.tar");
+    let mut entries = t!(Archive::new(data).entries());
+    let entry = t!(entries.next().await.unwrap());
+
+    let json = t!(serde_json::to_string(entry.header()));
+    let header: Header = t!(serde_json::from_str(&json));
+    assert_eq!(&header.as_bytes()[..], &entry.header().as_bytes()[..]);
+
+    assert_eq!(
+        t!(serde_json::to_string(&EntryType::Symlink)),
+        "\"symlink\""
+    );
+    let kind: EntryType = t!(serde_json::from_str("\"other(65)\""));
+    assert_eq!(kind, EntryType::Other(b'A'));
+
+    let meta = t!(entry.metadata());
+    let json = t!(serde_json::to_string(&meta));
+    let parsed: async_tar::EntryMetadata = t!(serde_json::from_str(&json));
+    assert_eq!(parsed, meta);
+}
+
+#[tokio::test]
+async fn base256_fields() {
+    use async_tar::HeaderBuilder;
+
+    let size = 10 * 1024 * 1024 * 1024;
+    let header = t!(HeaderBuilder::new_ustar()
+        .path("big")
+        .size(size)
+        .uid(1 << 24)
+        .build());
+    assert!(header.uses_base256());
+    assert_eq!(t!(header.size()), size);
+    assert_eq!(t!(header.uid()), 1 << 24);
+
+    let small = t!(HeaderBuilder::new_ustar().path("small").size(1).build());
+    assert!(!small.uses_base256());
+
+    let err = HeaderBuilder::new_ustar()
+        .path("big")
+        .size(size)
+        .strict_ustar(true)
+        .build()
+        .unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[tokio::test]
+async fn header_long_names() {
+    use async_tar::HeaderBuilder;
+
+    let long = "dir/".repeat(70) + "file";
+    let target = "target/".repeat(30);
+    let short = t!(HeaderBuilder::new_gnu().path("f").build());
+    let blocks = t!(HeaderBuilder::new_gnu().path("f").build_with_extensions());
+    assert_eq!(&blocks[..], &short.as_bytes()[..]);
+    assert!(HeaderBuilder::new_gnu().path(&long).build().is_err());
+    assert!(HeaderBuilder::new_old()
+        .path(&long)
+        .build_with_extensions()
+        .is_err());
+
+    for builder in [HeaderBuilder::new_gnu(), HeaderBuilder::new_ustar()] {
+        let mut data = t!(builder
+            .path(&long)
+            .entry_type(EntryType::Symlink)
+            .link_name(&target)
+            .build_with_extensions());
+        data.resize(data.len() + 1024, 0);
+
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let entry = t!(entries.next().await.unwrap());
+        assert_eq!(&*entry.path_bytes(), long.as_bytes());
+        assert_eq!(&*entry.link_name_bytes().unwrap(), target.as_bytes());
+        assert_eq!(entry.header().entry_type(), EntryType::Symlink);
+        assert!(entries.next().await.is_none());
+    }
+}
+
+#[tokio::test]
+async fn gnu_sparse_construction() {
+    let blocks: Vec<(u64, u64)> = (0..30).map(|i| (i * 1024, 512)).collect();
+    let mut header = Header::new_gnu();
+    t!(header.set_path("sparse.txt"));
+    header.set_entry_type(EntryType::GNUSparse);
+    header.set_size(30 * 512);
+    let extensions = {
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.set_real_size(29 * 1024 + 512);
+        gnu.set_sparse_blocks(&blocks)
+    };
+    header.set_cksum();
+    assert_eq!(extensions.len(), 2);
+    assert!(header.as_gnu().unwrap().is_extended());
+    assert!(extensions[0].is_extended());
+    assert!(!extensions[1].is_extended());
+    assert_eq!(t!(extensions[1].sparse()[4].offset()), 29 * 1024);
+    assert!(extensions[1].sparse()[5].is_empty());
+
+    let mut full = async_tar::GnuExtSparseHeader::new();
+    for i in 0..21 {
+        t!(full.add_sparse_block(i, 1));
+    }
+    assert!(full.add_sparse_block(21, 1).is_err());
+
+    let mut data = header.as_bytes().to_vec();
+    for ext in &extensions {
+        data.extend_from_slice(ext.as_bytes());
+    }
+    for i in 0..30u8 {
+        data.extend_from_slice(&[b'a' + i % 26; 512]);
+    }
+    data.resize(((data.len() + 511) & !511) + 1024, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let mut entry = t!(entries.next().await.unwrap());
+    assert_eq!(t!(entry.logical_size()), 29 * 1024 + 512);
+    let mut read = Vec::new();
+    t!(tokio::io::AsyncReadExt::read_to_end(&mut entry, &mut read).await);
+    let mut expected = vec![0; 29 * 1024 + 512];
+    for (i, block) in expected.chunks_mut(1024).enumerate() {
+        block[..512].copy_from_slice(&[b'a' + i as u8 % 26; 512]);
+    }
+    assert_eq!(read, expected);
+    assert!(entries.next().await.is_none());
+}
+
+#[tokio::test]
+async fn gnu_sparse_large_offsets() {
+    let big = 8 * 1024 * 1024 * 1024 + 512;
+    let mut header = Header::new_gnu();
+    t!(header.set_path("sparse.img"));
+    header.set_entry_type(EntryType::GNUSparse);
+    header.set_size(512);
+    let extensions = {
+        let gnu = header.as_gnu_mut().unwrap();
+        gnu.set_real_size(big + 512);
+        gnu.set_sparse_blocks(&[(big, 512)])
+    };
+    header.set_cksum();
+    assert!(extensions.is_empty());
+    assert!(header.uses_base256());
+    let gnu = header.as_gnu().unwrap();
+    assert_eq!(t!(gnu.real_size()), big + 512);
+    assert_eq!(t!(gnu.sparse[0].offset()), big);
+    assert_eq!(t!(gnu.sparse[0].length()), 512);
+
+    let mut ext = async_tar::GnuExtSparseHeader::new();
+    t!(ext.add_sparse_block(0, big));
+    assert_eq!(t!(ext.sparse()[0].length()), big);
+
+    let mut data = header.as_bytes().to_vec();
+    data.extend_from_slice(&[b'x'; 512]);
+    data.resize(data.len() + 1024, 0);
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let entry = t!(entries.next().await.unwrap());
+    assert_eq!(t!(entry.logical_size()), big + 512);
+}
+
+#[test]
+fn header_builder_old() {
+    tokio_uring::start(async {
+        use async_tar::HeaderBuilder;
+
+        assert!(HeaderBuilder::new_old()
+            .path("dev")
+            .entry_type(EntryType::Char)
+            .build()
+            .is_err());
+        assert!(HeaderBuilder::new_old()
+            .path("f")
+            .username("root")
+            .build()
+            .is_err());
+
+        let dir = HeaderBuilder::new_old()
+            .path("d")
+            .entry_type(EntryType::Directory);
+        let header = t!(dir.clone().build());
+        assert!(header.as_ustar().is_none() && header.as_gnu().is_none());
+        assert_eq!(&*header.path_bytes(), b"d/");
+        assert_eq!(header.entry_type(), EntryType::Regular);
+        assert_eq!(t!(header.mode()), 0o755);
+
+        let data = build_archive(vec![
+            (dir, &[]),
+            (HeaderBuilder::new_old().path("d/f"), b"hello"),
+        ]);
+        assert_eq!(data.len(), 512 * 3 + 1024);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        assert!(td.path().join("d").is_dir());
+        let s = t!(std::fs::read_to_string(td.path().join("d/f")));
+        assert_eq!(s, "hello");
+    })
+}
+
+#[tokio::test]
+async fn header_builder_pax_numbers() {
+    use async_tar::HeaderBuilder;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let gnu = t!(HeaderBuilder::new_gnu()
+        .path("f")
+        .uid(1 << 33)
+        .build_with_extensions());
+    assert_eq!(gnu.len(), 512);
+    assert!(HeaderBuilder::new_ustar()
+        .path("f")
+        .size(1 << 34)
+        .strict_ustar(true)
+        .build_with_extensions()
+        .is_err());
+
+    let builder = HeaderBuilder::new_ustar()
+        .path("f")
+        .uid(1 << 33)
+        .gid(5)
+        .mtime(1 << 34)
+        .strict_ustar(true);
+    let data = build_archive(vec![(builder, b"abc")]);
+    assert_eq!(data.len(), 4 * 512 + 1024);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let mut entry = t!(entries.next().await.unwrap());
+    assert!(!entry.header().uses_base256());
+    assert_eq!(t!(entry.header().uid()), 0);
+    let metadata = t!(entry.metadata());
+    assert_eq!(metadata.uid(), 1 << 33);
+    assert_eq!(metadata.gid(), 5);
+    assert_eq!(metadata.mtime(), UNIX_EPOCH + Duration::from_secs(1 << 34));
+    let mut read = Vec::new();
+    t!(tokio::io::AsyncReadExt::read_to_end(&mut entry, &mut read).await);
+    assert_eq!(read, b"abc");
+}
+
+#[tokio::test]
+async fn header_builder_pax_extensions() {
+    use async_tar::HeaderBuilder;
+
+    assert!(HeaderBuilder::new_old()
+        .path("f")
+        .pax_extension("MYAPP.checksum", "1234")
+        .build_with_extensions()
+        .is_err());
+
+    for builder in [HeaderBuilder::new_gnu(), HeaderBuilder::new_ustar()] {
+        let builder = builder
+            .path("f")
+            .pax_extension("MYAPP.checksum", "1234")
+            .pax_extensions(vec![("SCHILY.xattr.user.comment", &b"hi"[..])]);
+        let data = build_archive(vec![(builder, &[])]);
+        assert_eq!(data.len(), 3 * 512 + 1024);
+
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let mut entry = t!(entries.next().await.unwrap());
+        assert_eq!(&*entry.path_bytes(), b"f");
+        let pax = t!(entry.pax_extensions().await).unwrap();
+        let records: Vec<_> = pax
+            .map(|ext| {
+                let ext = t!(ext);
+                (t!(ext.key()).to_string(), ext.value_bytes().to_vec())
+            })
+            .collect();
+        assert_eq!(
+            records,
+            [
+                ("MYAPP.checksum".to_string(), b"1234".to_vec()),
+                ("SCHILY.xattr.user.comment".to_string(), b"hi".to_vec()),
+            ]
+        );
+        assert!(entries.next().await.is_none());
+    }
+}
+
+#[test]
+fn unpack_file_flags() {
+    use async_tar::{HeaderBuilder, UnsupportedMetadata};
+    use std::os::unix::io::AsRawFd;
+
+    // Reads the inode flags, or `None` if the filesystem has none.
+    fn inode_flags(path: &std::path::Path) -> Option<libc::c_int> {
+        let f = t!(std::fs::File::open(path));
+        let mut flags: libc::c_int = 0;
+        match unsafe { libc::ioctl(f.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) } {
+            0 => Some(flags),
+            _ => None,
+        }
+    }
+    const FS_APPEND_FL: libc::c_int = 0x20;
+    const FS_NODUMP_FL: libc::c_int = 0x40;
+
+    let builder = HeaderBuilder::new_ustar()
+        .path("f")
+        .pax_extension("SCHILY.fflags", "nodump,uappnd,nouappnd,hidden");
+    let data = build_archive(vec![(builder, b"hi")]);
+
+    tokio_uring::start(async {
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let mut entry = t!(entries.next().await.unwrap());
+        let pax = t!(entry.pax_extensions().await).unwrap();
+        assert_eq!(
+            pax.file_flags().collect::<Vec<_>>(),
+            ["nodump", "uappnd", "nouappnd", "hidden"]
+        );
+
+        // Without the option the flags are not touched at all.
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        t!(Archive::new(&data[..]).unpack(td.path()).await);
+        let flags = inode_flags(&td.path().join("f")).unwrap_or(0);
+        assert_eq!(flags & FS_NODUMP_FL, 0);
+
+        let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_unpack_file_flags(true)
+            .set_unsupported_metadata(UnsupportedMetadata::Warn)
+            .build();
+        let report = t!(ar.unpack_with_report(td.path()).await);
+        match inode_flags(&td.path().join("f")) {
+            Some(flags) => {
+                assert!(report.warnings().is_empty());
+                assert_eq!(flags & (FS_NODUMP_FL | FS_APPEND_FL), FS_NODUMP_FL);
+            }
+            None => assert_eq!(report.warnings().len(), 1),
+        }
+        assert_eq!(t!(std::fs::read_to_string(td.path().join("f"))), "hi");
+    })
+}
+
+#[tokio::test]
+async fn header_builder_xattrs() {
+    use async_tar::{HeaderBuilder, XattrFormat};
+
+    let formats = [
+        (XattrFormat::Schily, &b"SCHILY.xattr.user.a b"[..]),
+        (XattrFormat::Libarchive, &b"LIBARCHIVE.xattr.user.a%20b"[..]),
+        (XattrFormat::Both, &b"SCHILY.xattr.user.a b"[..]),
+    ];
+    for (format, key) in formats.iter() {
+        let builder = HeaderBuilder::new_ustar()
+            .path("f")
+            .xattr("user.a b", b"\x00value")
+            .xattr_format(*format);
+        let data = build_archive(vec![(builder, &[])]);
+
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let mut entry = t!(entries.next().await.unwrap());
+        let pax = t!(entry.pax_extensions().await).unwrap();
+        let keys: Vec<_> = pax
+            .clone()
+            .map(|ext| t!(ext).key_bytes().to_vec())
+            .collect();
+        assert_eq!(keys[0], *key);
+        assert_eq!(keys.len(), if *format == XattrFormat::Both { 2 } else { 1 });
+        let xattrs: Vec<_> = pax.xattrs().collect();
+        assert_eq!(xattrs, [("user.a b".into(), b"\x00value".to_vec())]);
+    }
+}
+
+#[tokio::test]
+async fn header_builder_large_ids() {
+    use async_tar::HeaderBuilder;
+
+    // IDs like those of systemd-homed users overflow the 7 octal digits.
+    let (uid, gid) = (60_514_u64 << 10, 4_294_967_294);
+    for builder in [HeaderBuilder::new_gnu(), HeaderBuilder::new_ustar()] {
+        let data = build_archive(vec![(builder.path("f").uid(uid).gid(gid), &[])]);
+
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let entry = t!(entries.next().await.unwrap());
+        let metadata = t!(entry.metadata());
+        assert_eq!((metadata.uid(), metadata.gid()), (uid, gid));
+    }
+}
+
+#[test]
+fn header_builder_clamp_mtime() {
+    use async_tar::HeaderBuilder;
+
+    let template = HeaderBuilder::new_ustar().clamp_mtime(1_000);
+    let old = t!(template.clone().path("old").mtime(10).build());
+    let new = t!(template.path("new").mtime(5_000).build());
+    assert_eq!(t!(old.mtime()), 10);
+    assert_eq!(t!(new.mtime()), 1_000);
+
+    let late = t!(HeaderBuilder::new_gnu()
+        .path("f")
+        .mtime(5_000)
+        .clamp_mtime(1_000)
+        .build());
+    assert_eq!(t!(late.mtime()), 1_000);
+}
+
+#[tokio::test]
+async fn entry_bytes_stream() {
+    let mut header = Header::new_gnu();
+    t!(header.set_path("big"));
+    header.set_size(200 * 1024);
+    header.set_cksum();
+    let mut data = header.as_bytes().to_vec();
+    data.extend((0..200 * 1024).map(|i| (i % 251) as u8));
+    data.extend_from_slice(&[0; 1024]);
+
+    let ar = Archive::new(&data[..]);
+    let mut entries = t!(ar.entries());
+    let entry = t!(entries.next().await.unwrap());
+    let mut stream = entry.into_bytes_stream();
+    assert_eq!(stream.entry().header().entry_size().unwrap(), 200 * 1024);
+    let mut contents = Vec::new();
+    while let Some(chunk) = stream.next().await {
+        let chunk = t!(chunk);
+        assert!(!chunk.is_empty() && chunk.len() <= 64 * 1024);
+        contents.extend_from_slice(&chunk);
+    }
+    assert_eq!(&contents[..], &data[512..512 + 200 * 1024]);
+    assert!(entries.next().await.is_none());
+}
+
+#[tokio::test]
+async fn pax_hdrcharset() {
+    use async_tar::HeaderBuilder;
+
+    let data = build_archive(vec![
+        (
+            HeaderBuilder::new_ustar()
+                .path("link")
+                .link_name("x")
+                .entry_type(EntryType::Symlink)
+                .pax_extension("path", "caf\u{e9}.txt")
+                .pax_extension("linkpath", "\u{fc}ber.txt"),
+            &[],
+        ),
+        (
+            HeaderBuilder::new_ustar()
+                .path("file")
+                .pax_extension("hdrcharset", "BINARY")
+                .pax_extension("path", b"caf\xe9.txt"),
+            &[],
+        ),
+        (
+            HeaderBuilder::new_ustar()
+                .path("file")
+                .pax_extension("path", b"caf\xe9.txt"),
+            &[],
+        ),
+    ]);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let link = t!(entries.next().await.unwrap());
+    assert!(!link.pax_headers().unwrap().binary_names());
+    assert_eq!(&*t!(link.path()), Path::new("caf\u{e9}.txt"));
+    assert_eq!(&*t!(link.link_name()).unwrap(), Path::new("\u{fc}ber.txt"));
+    let file = t!(entries.next().await.unwrap());
+    assert!(file.pax_headers().unwrap().binary_names());
+    assert_eq!(&*file.path_bytes(), b"caf\xe9.txt");
+    assert_eq!(
+        std::os::unix::ffi::OsStrExt::as_bytes(t!(file.path()).as_os_str()),
+        b"caf\xe9.txt"
+    );
+    // Without `hdrcharset=BINARY` pax names have to be UTF-8.
+    let invalid = t!(entries.next().await.unwrap());
+    assert_eq!(&*invalid.path_bytes(), b"caf\xe9.txt");
+    assert!(invalid.path().is_err());
+    assert!(entries.next().await.is_none());
+
+    // Binary names are decoded like names in the header.
+    #[cfg(feature = "encoding_rs")]
+    {
+        let ar = ArchiveBuilder::new(&data[..])
+            .set_name_encoding(encoding_rs::WINDOWS_1252)
+            .build();
+        let mut entries = t!(ar.entries());
+        t!(entries.next().await.unwrap());
+        let file = t!(entries.next().await.unwrap());
+        assert_eq!(&*t!(file.path()), Path::new("caf\u{e9}.txt"));
+        assert_eq!(&*file.path_bytes(), b"caf\xe9.txt");
+    }
+}
+
+#[cfg(feature = "encoding_rs")]
+#[tokio::test]
+async fn name_encoding() {
+    let mut data = Vec::new();
+    let mut header = Header::new_gnu();
+    header.as_gnu_mut().unwrap().name[..8].copy_from_slice(b"caf\xe9.txt");
+    header.set_entry_type(EntryType::Symlink);
+    header.as_gnu_mut().unwrap().linkname[..8].copy_from_slice(b"\x93\xfa\x96\x7b.txt");
+    header.set_size(0);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.resize(data.len() + 1024, 0);
+
+    let ar = ArchiveBuilder::new(&data[..])
+        .set_name_encoding(encoding_rs::WINDOWS_1252)
+        .build();
+    let mut entries = t!(ar.entries());
+    let entry = t!(entries.next().await.unwrap());
+    assert_eq!(&*entry.path_bytes(), "caf\u{e9}.txt".as_bytes());
+    assert_eq!(entry.header().path_bytes().len(), 8);
 
-    let rd = Cursor::new(t!(ar.into_inner().await));
-    let ar = Archive::new(rd);
+    let ar = ArchiveBuilder::new(&data[..])
+        .set_name_encoding(encoding_rs::SHIFT_JIS)
+        .build();
     let mut entries = t!(ar.entries());
     let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), Path::new("archive/dir"));
+    assert_eq!(
+        &*t!(entry.link_name()).unwrap(),
+        std::path::Path::new("\u{65e5}\u{672c}.txt")
+    );
+}
+
+#[tokio::test]
+async fn signed_checksum() {
+    let mut header = Header::new_gnu();
+    t!(header.set_path("caf\u{e9}"));
+    header.set_size(0);
+    header.as_old_mut().cksum = *b"        ";
+    let sum: i32 = header.as_bytes().iter().map(|b| *b as i8 as i32).sum();
+    let unsigned: u32 = header.as_bytes().iter().map(|b| *b as u32).sum();
+    let field = format!("{:06o}\0 ", sum);
+    header.as_old_mut().cksum.copy_from_slice(field.as_bytes());
+    assert_ne!(t!(header.cksum()), unsigned);
+    let mut data = header.as_bytes().to_vec();
+    data.resize(2048, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
     let entry = t!(entries.next().await.unwrap());
-    assert_eq!(t!(entry.path()), Path::new("archive/dir/f"));
+    assert_eq!(&*entry.path_bytes(), "caf\u{e9}".as_bytes());
     assert!(entries.next().await.is_none());
 }
 
-#[async_std::test]
-#[cfg(unix)]
-async fn tar_directory_containing_symlink_to_directory() {
-    use std::os::unix::fs::symlink;
+#[tokio::test]
+async fn repair() {
+    use async_tar::{RepairFix, RepairOptions};
+
+    // A bad checksum, garbage padding, a dangling long name and no
+    // end-of-archive marker.
+    let mut data = Vec::new();
+    append_raw(&mut data, &raw_header("a", EntryType::Regular, 5), b"hello");
+    data[0] = b'c';
+    data[600] = 0xff;
+    let longname = |size| raw_header("././@LongLink", EntryType::GNULongName, size);
+    append_raw(&mut data, &longname(6), b"first\0");
+    append_raw(&mut data, &longname(7), b"second\0");
+    append_raw(&mut data, &raw_header("b", EntryType::Regular, 1), b"b");
+
+    let mut repaired = Vec::new();
+    let report = t!(async_tar::repair(&data[..], &mut repaired, RepairOptions::new()).await);
+    assert_eq!(
+        report.fixes(),
+        &[
+            RepairFix::Checksum { offset: 0 },
+            RepairFix::Padding { offset: 0 },
+            RepairFix::DroppedExtension { offset: 1024 },
+            RepairFix::Terminator,
+        ][..]
+    );
 
-    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
-    let dummy_src = t!(TempBuilder::new().prefix("dummy_src").tempdir());
-    let dummy_dst = td.path().join("dummy_dst");
-    let mut ar = Builder::new(Vec::new());
-    t!(symlink(dummy_src.path().display().to_string(), &dummy_dst));
+    let mut entries = t!(Archive::new(&repaired[..]).entries());
+    let mut a = t!(entries.next().await.unwrap());
+    assert_eq!(&*a.path_bytes(), b"c");
+    let mut s = String::new();
+    t!(tokio::io::AsyncReadExt::read_to_string(&mut a, &mut s).await);
+    assert_eq!(s, "hello");
+    let b = t!(entries.next().await.unwrap());
+    assert_eq!(&*b.path_bytes(), b"second");
+    assert!(entries.next().await.is_none());
 
-    assert!(dummy_dst.read_link().is_ok());
-    assert!(dummy_dst.read_link().unwrap().is_dir());
-    ar.append_dir_all("symlinks", td.path()).await.unwrap();
-    ar.finish().await.unwrap();
+    let mut again = Vec::new();
+    let report = t!(async_tar::repair(&repaired[..], &mut again, RepairOptions::new()).await);
+    assert!(report.is_clean());
+    assert_eq!(again, repaired);
+
+    let mut truncated = Vec::new();
+    let report = t!(async_tar::repair(&data[..700], &mut truncated, RepairOptions::new()).await);
+    assert_eq!(
+        report.fixes(),
+        &[
+            RepairFix::Checksum { offset: 0 },
+            RepairFix::Truncated {
+                offset: 0,
+                missing: 324
+            },
+            RepairFix::Padding { offset: 0 },
+            RepairFix::Terminator,
+        ][..]
+    );
+    assert_eq!(truncated.len(), 2048);
 }
 
-#[async_std::test]
-async fn long_path() {
-    let td = t!(TempBuilder::new().prefix("tar-rs").tempdir());
-    let rdr = Cursor::new(tar!("7z_long_path.tar"));
-    let ar = Archive::new(rdr);
-    ar.unpack(td.path()).await.unwrap();
+/// Reads the paths, types and contents of all entries of `data`.
+async fn read_entries(data: &[u8]) -> Vec<(PathBuf, EntryType, u32, Vec<u8>)> {
+    let mut entries = t!(Archive::new(data).entries());
+    let mut read = Vec::new();
+    while let Some(entry) = entries.next().await {
+        let mut entry = t!(entry);
+        let mut contents = Vec::new();
+        t!(entry.read_to_end(&mut contents).await);
+        let header = entry.header();
+        read.push((
+            t!(entry.path()).into_owned(),
+            header.entry_type(),
+            t!(header.mode()),
+            contents,
+        ));
+    }
+    read
+}
+
+#[tokio::test]
+async fn repack() {
+    use async_tar::HeaderBuilder;
+
+    let long = format!("{}/a", "d".repeat(120));
+    let data = build_archive(vec![
+        (HeaderBuilder::new_gnu().path("a").mode(0o644), b"aaa"),
+        (HeaderBuilder::new_gnu().path("b").mode(0o644), b"bb"),
+        (
+            HeaderBuilder::new_gnu()
+                .path("l")
+                .entry_type(EntryType::Symlink)
+                .link_name("a")
+                .mode(0o777),
+            b"",
+        ),
+    ]);
+    let mut repacked = Vec::new();
+    t!(
+        async_tar::repack(Archive::new(&data[..]), &mut repacked, |entry| {
+            let path = entry.path()?.into_owned();
+            if path == Path::new("b") {
+                return Ok(None);
+            }
+            if path == Path::new("a") {
+                entry.header_mut().set_mode(0o600);
+                return Ok(Some(PathBuf::from(&long)));
+            }
+            Ok(Some(path))
+        })
+        .await
+    );
+    let read = read_entries(&repacked).await;
+    assert_eq!(
+        read,
+        vec![
+            (
+                PathBuf::from(&long),
+                EntryType::Regular,
+                0o600,
+                b"aaa".to_vec()
+            ),
+            (PathBuf::from("l"), EntryType::Symlink, 0o777, Vec::new()),
+        ]
+    );
+    let mut entries = t!(Archive::new(&repacked[..]).entries());
+    entries.next().await;
+    let link = t!(entries.next().await.unwrap());
+    assert_eq!(t!(link.link_name()).unwrap(), Path::new("a"));
+
+    let mut repacked = Vec::new();
+    t!(
+        async_tar::repack(Archive::new(tar!("sparse.tar")), &mut repacked, |entry| {
+            Ok(Some(entry.path()?.into_owned()))
+        })
+        .await
+    );
+    let original = read_entries(tar!("sparse.tar")).await;
+    let read = read_entries(&repacked).await;
+    assert_eq!(read.len(), original.len());
+    for (read, original) in read.iter().zip(&original) {
+        assert_eq!(read.0, original.0);
+        assert_eq!(read.3, original.3);
+        assert!(!read.1.is_gnu_sparse());
+    }
+}
+
+#[test]
+fn unpack_star_compat() {
+    let mut header = Header::new_ustar();
+    t!(header.set_path("f"));
+    header.set_mtime(1_000_000_000);
+    header.set_size(0);
+    {
+        let ustar = header.as_ustar_mut().unwrap();
+        ustar.prefix[..131].copy_from_slice(&[b'd'; 131]);
+        ustar.prefix[131..143].copy_from_slice(b"05753604000 ");
+        ustar.prefix[143..].copy_from_slice(b"05753604000 ");
+        ustar.pad[8..].copy_from_slice(b"tar\0");
+    }
+    header.set_cksum();
+    let mut data = header.as_bytes().to_vec();
+    data.resize(2048, 0);
+
+    tokio_uring::start(async {
+        let mut entries = t!(Archive::new(&data[..]).entries());
+        let entry = t!(entries.next().await.unwrap());
+        let path = format!("{}/f", "d".repeat(131));
+        assert_ne!(&*entry.path_bytes(), path.as_bytes());
+
+        for preserve_atime in [false, true] {
+            let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+            let ar = ArchiveBuilder::new(&data[..])
+                .set_star_compat(true)
+                .set_preserve_atime(preserve_atime)
+                .build();
+            let mut entries = t!(ar.entries());
+            let mut entry = t!(entries.next().await.unwrap());
+            assert!(entry.header().as_ustar().unwrap().is_star());
+            assert_eq!(&*entry.path_bytes(), path.as_bytes());
+            t!(entry.unpack_in(td.path()).await);
+            let meta = t!(std::fs::metadata(td.path().join(&path)));
+            let atime = FileTime::from_last_access_time(&meta);
+            let mtime = FileTime::from_last_modification_time(&meta);
+            let expected = if preserve_atime {
+                800_000_000
+            } else {
+                1_000_000_000
+            };
+            assert_eq!(atime.unix_seconds(), expected);
+            assert_eq!(mtime.unix_seconds(), 1_000_000_000);
+        }
+    });
+}
+
+#[tokio::test]
+async fn tree() {
+    let tree = t!(Archive::new(tar!("directory.tar")).tree().await);
+    let root = tree.root();
+    assert_eq!(
+        root.children()
+            .map(|n| n.name().to_owned())
+            .collect::<Vec<_>>(),
+        vec!["a"]
+    );
+    let a = root.child("a").unwrap();
+    assert!(a.is_dir());
+    let names = a
+        .children()
+        .map(|n| n.name().to_owned())
+        .collect::<Vec<_>>();
+    assert_eq!(names, vec!["b", "c"]);
+
+    let c = tree.get("./a/c").unwrap();
+    assert!(!c.is_dir());
+    let entry = c.entry().unwrap();
+    assert_eq!(entry.size(), 2);
+    assert_eq!(entry.raw_file_position(), entry.raw_header_position() + 512);
+    assert!(tree.get("a/b/").unwrap().is_dir());
+    assert!(tree.get("a/d").is_none());
+}
+
+#[tokio::test]
+async fn gnu_dumpdir_is_directory() {
+    use async_tar::HeaderBuilder;
+
+    let data = build_archive(vec![
+        (
+            HeaderBuilder::new_gnu()
+                .path("dir/")
+                .entry_type(EntryType::GNUDumpDir)
+                .mode(0o755),
+            b"Yindex.html\0\0",
+        ),
+        (HeaderBuilder::new_gnu().path("dir/index.html"), b"hi"),
+    ]);
+
+    let tree = t!(Archive::new(&data[..]).tree().await);
+    assert!(tree.get("dir").unwrap().is_dir());
+
+    #[cfg(feature = "fuse")]
+    {
+        use async_tar::fuse::{ArchiveFs, NodeKind, ROOT_INODE};
+
+        let fs = t!(ArchiveFs::new(std::io::Cursor::new(&data[..])).await);
+        let dir = fs.lookup(ROOT_INODE, std::ffi::OsStr::new("dir")).unwrap();
+        assert_eq!(dir.kind, NodeKind::Directory);
+        let index = fs.lookup(dir.ino, std::ffi::OsStr::new("index.html"));
+        assert_eq!(index.unwrap().size, 2);
+    }
+
+    #[cfg(feature = "serve")]
+    {
+        use async_tar::serve::{Request, Server};
+
+        let mut server = t!(Server::new(std::io::Cursor::new(&data[..])).await);
+        let resp = t!(server.respond(&Request::new("/dir")).await);
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.header("content-length"), Some("2"));
+    }
+}
+
+#[tokio::test]
+async fn pax_typed_accessors() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let records: &[u8] = b"17 path=dir/file\n9 size=2\n22 mtime=1234567890.5\n\
+        25 SCHILY.xattr.user.a=1\n34 LIBARCHIVE.xattr.user.%62=Mg==\n\
+        32 LIBARCHIVE.xattr.user.a=Mw==\n";
+    let mut data = Vec::new();
+    let mut header = Header::new_ustar();
+    t!(header.set_path("x"));
+    header.set_entry_type(EntryType::XHeader);
+    header.set_size(records.len() as u64);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(records);
+    data.resize(1024, 0);
+    let mut header = Header::new_ustar();
+    t!(header.set_path("f"));
+    header.set_size(2);
+    header.set_cksum();
+    data.extend_from_slice(header.as_bytes());
+    data.extend_from_slice(b"hi");
+    data.resize(3072, 0);
+
+    let mut entries = t!(Archive::new(&data[..]).entries());
+    let mut f = t!(entries.next().await.unwrap());
+    assert_eq!(&*f.path_bytes(), b"dir/file");
+    let pax = t!(f.pax_extensions().await).unwrap();
+    assert_eq!(pax.path(), Some(std::path::Path::new("dir/file")));
+    assert_eq!(pax.link_path(), None);
+    assert_eq!(pax.size(), Some(2));
+    assert_eq!(
+        pax.mtime(),
+        Some(UNIX_EPOCH + Duration::new(1_234_567_890, 500_000_000))
+    );
+    let xattrs = pax.xattrs().collect::<Vec<_>>();
+    assert_eq!(
+        xattrs,
+        vec![
+            ("user.a".into(), b"1".to_vec()),
+            ("user.b".into(), b"2".to_vec()),
+        ]
+    );
+}
+
+#[test]
+fn follow_growing_archive() {
+    use async_tar::FollowReader;
+    use std::{cell::Cell, io::Write as _, rc::Rc};
+
+    let bytes = tar!("reading_files.tar");
+    let td = t!(TempBuilder::new().prefix("async-tar").tempdir());
+    let path = td.path().join("growing.tar");
+    t!(std::fs::write(&path, &bytes[..1024 + 512]));
+
+    tokio_uring::start(async {
+        let calls = Rc::new(Cell::new(0));
+        let file = t!(tokio::fs::File::open(&path).await);
+        let reader = FollowReader::new(file, {
+            let calls = calls.clone();
+            let path = path.clone();
+            move || {
+                // Simulate the writer appending the rest of the archive.
+                calls.set(calls.get() + 1);
+                let mut f = t!(std::fs::OpenOptions::new().append(true).open(&path));
+                t!(f.write_all(&bytes[1024 + 512..]));
+                Box::pin(async { true })
+            }
+        });
+        let mut entries = t!(Archive::new(reader).entries());
+        let a = t!(entries.next().await.unwrap());
+        assert_eq!(&*a.path_bytes(), b"a");
+        let mut b = t!(entries.next().await.unwrap());
+        assert_eq!(&*b.path_bytes(), b"b");
+        let mut s = String::new();
+        t!(tokio::io::AsyncReadExt::read_to_string(&mut b, &mut s).await);
+        assert_eq!(s, "b\nb\nb\nb\nb\nb\nb\nb\nb\nb\nb\n");
+        assert!(entries.next().await.is_none());
+        assert_eq!(calls.get(), 1);
+    });
+}
+
+#[tokio::test]
+async fn pax_headers_without_reading() {
+    let mut entries = t!(Archive::new(tar!("pax.tar")).entries());
+    let mut first = t!(entries.next().await.unwrap());
+    let keys = first
+        .pax_headers()
+        .unwrap()
+        .map(|e| t!(t!(e).key()).to_string())
+        .collect::<Vec<_>>();
+    assert!(keys.iter().any(|k| k == "mtime"));
+    let mut data = Vec::new();
+    t!(tokio::io::AsyncReadExt::read_to_end(&mut first, &mut data).await);
+    assert_eq!(data.len() as u64, t!(first.header().size()));
+
+    let ar = Archive::new(tar!("reading_files.tar"));
+    let a = t!(t!(ar.entries()).next().await.unwrap());
+    assert!(a.pax_headers().is_none());
 }
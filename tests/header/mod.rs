@@ -10,7 +10,7 @@ use std::{
 
 use tempfile::Builder;
 
-use async_tar::{GnuHeader, Header, HeaderMode};
+use async_tar::{ChecksumFormat, GnuHeader, Header, HeaderMode};
 
 #[test]
 fn default_gnu() {
@@ -167,13 +167,26 @@ fn set_path() {
     assert_eq!(t!(h.path()).to_str(), Some(&medium2[..]));
 }
 
+#[test]
+fn set_ustar_path_limit() {
+    // The longest paths fit a 155 byte prefix, a `/` and a 100 byte name.
+    let mut h = Header::new_ustar();
+    let p = format!("{}/{}", "p".repeat(155), "n".repeat(100));
+    t!(h.set_path(&p));
+    assert_eq!(t!(h.path()).to_str(), Some(&p[..]));
+    assert!(h.as_ustar().unwrap().prefix.iter().all(|b| *b == b'p'));
+
+    assert!(h.set_path(format!("{}/{}", "p".repeat(156), "n")).is_err());
+    assert!(h.set_path(format!("{}/{}", "p", "n".repeat(101))).is_err());
+}
+
 #[test]
 fn set_ustar_path_hard() {
     let mut h = Header::new_ustar();
     let p = Path::new("a").join(vec!["a"; 100].join(""));
     t!(h.set_path(&p));
     let path = t!(h.path());
-    let actual: &Path = path.as_ref().into();
+    let actual: &Path = path.as_ref();
     assert_eq!(actual, p);
 }
 
@@ -245,3 +258,34 @@ fn byte_slice_conversion() {
     let b_conv: &[u8] = Header::from_byte_slice(h.as_bytes()).as_bytes();
     assert_eq!(b, b_conv);
 }
+
+#[test]
+fn checksum_format() {
+    let mut h = Header::new_gnu();
+    h.set_size(42);
+    h.set_cksum();
+    let cksum = t!(h.cksum());
+    assert_eq!(&h.as_old().cksum[6..], b"\0 ");
+
+    h.set_cksum_with(ChecksumFormat::Compat);
+    assert_eq!(h.as_old().cksum[7], 0);
+    assert_eq!(t!(h.cksum()), cksum);
+    assert_eq!(&h.as_old().cksum[..7], format!("{:07o}", cksum).as_bytes());
+}
+
+#[test]
+fn padding() {
+    let mut h = Header::new_gnu();
+    assert_eq!(h.padding(), [0; 17]);
+    h.padding_mut()[0] = 1;
+    assert_eq!(h.as_bytes()[495], 1);
+
+    let mut h = Header::new_ustar();
+    h.padding_mut().copy_from_slice(b"hidden data!");
+    assert_eq!(&h.as_bytes()[500..], b"hidden data!");
+    let copy = Header::from_byte_slice(h.as_bytes());
+    assert_eq!(copy.padding(), b"hidden data!");
+
+    let h = Header::new_old();
+    assert_eq!(h.padding().len(), 255);
+}